@@ -4,7 +4,7 @@ use splines::{Interpolate, Key};
 #[cfg(not(target_arch = "wasm32"))]
 use std::time::Duration;
 
-use cgmath::{EuclideanSpace, InnerSpace, Point3, Quaternion, Rad, VectorSpace};
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Quaternion, Rad, Vector4, VectorSpace};
 
 use crate::{camera::PerspectiveCamera, PerspectiveProjection};
 
@@ -82,6 +82,114 @@ impl Sampler for TrackingShot {
     }
 }
 
+/// Smooth camera path through keyframes for fly-throughs.
+/// Positions, field of view and near/far planes are interpolated with (uniform) Catmull-Rom splines,
+/// rotations with slerp between the keyframes.
+/// In contrast to [`TrackingShot`] the path can be open (`looping = false`).
+pub struct CameraPath {
+    cameras: Vec<PerspectiveCamera>,
+    looping: bool,
+}
+
+impl CameraPath {
+    /// panics if no cameras are given
+    pub fn new<C>(cameras: Vec<C>, looping: bool) -> Self
+    where
+        C: Into<PerspectiveCamera>,
+    {
+        assert!(
+            !cameras.is_empty(),
+            "a camera path needs at least one camera"
+        );
+        Self {
+            cameras: cameras.into_iter().map(|c| c.into()).collect(),
+            looping,
+        }
+    }
+
+    pub fn looping(&self) -> bool {
+        self.looping
+    }
+
+    /// camera at `t` in [0,1]. `t` wraps around for looping paths.
+    /// 0 and 1 return the first and last keyframe (first again for looping paths).
+    pub fn sample(&self, t: f32) -> PerspectiveCamera {
+        let n = self.cameras.len();
+        let segments = if self.looping { n } else { n - 1 };
+        let t = if self.looping {
+            t.rem_euclid(1.)
+        } else {
+            t.clamp(0., 1.)
+        };
+        if segments == 0 || t == 0. {
+            return self.cameras[0];
+        }
+        if t == 1. {
+            return self.cameras[n - 1];
+        }
+        let x = t * segments as f32;
+        let i = (x.floor() as usize).min(segments - 1);
+        let u = x - i as f32;
+        // open paths repeat the first and last keyframe
+        let key = |j: isize| -> &PerspectiveCamera {
+            if self.looping {
+                &self.cameras[j.rem_euclid(n as isize) as usize]
+            } else {
+                &self.cameras[j.clamp(0, n as isize - 1) as usize]
+            }
+        };
+        let i = i as isize;
+        let c = [key(i - 1), key(i), key(i + 1), key(i + 2)];
+        if u == 0. {
+            return *c[1];
+        }
+
+        let position = catmull_rom(c.map(|c| c.position.to_vec()), u);
+        let proj = catmull_rom(
+            c.map(|c| {
+                let p = &c.projection;
+                Vector4::new(p.fovx.0, p.fovy.0, p.znear, p.zfar)
+            }),
+            u,
+        );
+        let (a, b) = (c[1].rotation, c[2].rotation);
+        // slerp along the shortest path
+        let b = if a.dot(b) < 0. { -b } else { b };
+        return PerspectiveCamera {
+            position: Point3::from_vec(position),
+            rotation: a.slerp(b, u).normalize(),
+            projection: PerspectiveProjection {
+                fovx: Rad(proj.x),
+                fovy: Rad(proj.y),
+                // the spline may overshoot
+                znear: proj.z.max(1e-4),
+                zfar: proj.w.max(proj.z.max(1e-4) * 2.),
+                fov2view_ratio: c[1].projection.fov2view_ratio * (1. - u)
+                    + c[2].projection.fov2view_ratio * u,
+                kind: c[1].projection.lerp(&c[2].projection, u).kind,
+            },
+        };
+    }
+}
+
+impl Sampler for CameraPath {
+    type Sample = PerspectiveCamera;
+    fn sample(&self, v: f32) -> Self::Sample {
+        CameraPath::sample(self, v)
+    }
+}
+
+/// uniform Catmull-Rom spline between p[1] (u = 0) and p[2] (u = 1)
+fn catmull_rom<V: VectorSpace<Scalar = f32>>(p: [V; 4], u: f32) -> V {
+    let u2 = u * u;
+    let u3 = u2 * u;
+    return (p[1] * 2.
+        + (p[2] - p[0]) * u
+        + (p[0] * 2. - p[1] * 5. + p[2] * 4. - p[3]) * u2
+        + (p[1] * 3. - p[0] - p[2] * 3. + p[3]) * u3)
+        * 0.5;
+}
+
 impl Interpolate<f32> for PerspectiveCamera {
     fn step(t: f32, threshold: f32, a: Self, b: Self) -> Self {
         if t < threshold {
@@ -212,6 +320,7 @@ impl Interpolate<f32> for PerspectiveProjection {
                 (b.0, b.1.fov2view_ratio),
                 (y.0, y.1.fov2view_ratio),
             ),
+            kind: a.1.lerp(&b.1, t).kind,
         }
     }
 
@@ -302,3 +411,48 @@ fn unroll(rot: [Quaternion<f32>; 4]) -> [Quaternion<f32>; 4] {
     }
     return rot;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Deg, Rotation3, Vector2};
+
+    fn keyframes() -> Vec<PerspectiveCamera> {
+        return (0..3)
+            .map(|i| {
+                PerspectiveCamera::new(
+                    Point3::new(i as f32, 0., -2. * i as f32),
+                    Quaternion::from_angle_y(Deg(30. * i as f32)),
+                    PerspectiveProjection::new(
+                        Vector2::new(800, 600),
+                        Vector2::new(Deg(60. + i as f32), Deg(45.)),
+                        0.1 * (i + 1) as f32,
+                        100.,
+                    ),
+                )
+            })
+            .collect();
+    }
+
+    #[test]
+    fn camera_path_endpoints() {
+        let cameras = keyframes();
+
+        let open = CameraPath::new(cameras.clone(), false);
+        assert_eq!(open.sample(0.), cameras[0]);
+        assert_eq!(open.sample(1.), cameras[2]);
+        // keyframes are evenly spaced
+        assert_eq!(open.sample(0.5), cameras[1]);
+        // out of range values are clamped
+        assert_eq!(open.sample(-0.5), cameras[0]);
+        assert_eq!(open.sample(1.5), cameras[2]);
+
+        let looping = CameraPath::new(cameras.clone(), true);
+        assert_eq!(looping.sample(0.), cameras[0]);
+        assert_eq!(looping.sample(1.), cameras[0]);
+
+        // between the keyframes the path stays close to them
+        let mid = open.sample(0.25);
+        assert!(mid.position.x > 0. && mid.position.x < 1.);
+    }
+}
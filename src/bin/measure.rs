@@ -4,7 +4,7 @@ use clap::Parser;
 use std::{fs::File, path::PathBuf, time::{Duration, Instant}};
 #[allow(unused_imports)]
 use web_splats::{
-    io, GaussianRenderer, PerspectiveCamera, PointCloud, Scene, SceneCamera, SplattingArgs, Split, WGPUContext
+    io, GaussianRenderer, PerspectiveCamera, PointCloud, RenderMode, Scene, SceneCamera, SplattingArgs, Split, WGPUContext
 };
 
 #[derive(Debug, Parser)]
@@ -68,6 +68,12 @@ async fn render_views(
             walltime: Duration::from_secs(100),
             scene_center: None,
             scene_extend: None,
+            clip_sphere: None,
+            render_mode: RenderMode::Color,
+            preview_quality: None,
+            max_splat_size: None,
+            max_layers_per_pixel: None,
+            region: None,
         },
         &mut None,
     );
@@ -116,6 +122,12 @@ async fn render_views(
                     walltime: Duration::from_secs(100),
                     scene_center: None,
                     scene_extend: None,
+                    clip_sphere: None,
+                    render_mode: RenderMode::Color,
+                    preview_quality: None,
+                    max_splat_size: None,
+                    max_layers_per_pixel: None,
+                    region: None,
                 },
                 &mut None,
             );
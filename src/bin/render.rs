@@ -1,14 +1,30 @@
-use cgmath::Vector2;
-use clap::Parser;
-use half::f16;
-use image::{ImageBuffer, Rgba};
+use anyhow::Context;
+use cgmath::{
+    Angle, Deg, Matrix4, MetricSpace, Point3, Quaternion, Rotation, SquareMatrix, Vector2, Vector3,
+};
+use clap::{CommandFactory, Parser, ValueEnum};
+use image::{
+    codecs::{avif::AvifEncoder, jpeg::JpegEncoder, openexr::OpenExrEncoder, png::PngEncoder},
+    GrayImage, ImageBuffer, Luma, Rgba, Rgba32FImage, RgbaImage,
+};
 use indicatif::{ProgressBar, ProgressIterator, ProgressStyle};
 #[allow(unused_imports)]
-use std::{fs::File, path::PathBuf, time::Duration};
+use std::{
+    fs::File,
+    io::Write,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 #[allow(unused_imports)]
 use web_splats::{
-    io::GenericGaussianPointCloud, GaussianRenderer, PerspectiveCamera, PointCloud, Scene,
-    SceneCamera, SplattingArgs, Split, WGPUContext,
+    apply_colormap,
+    depth_sort::{CpuSorter, DepthSorter},
+    download_texture,
+    gpu_rs::GPURSSorter,
+    io::{AxisConvention, FrameSequence, GenericGaussianPointCloud, PCDataType},
+    render_to_texture, AutoExposure, Camera, CameraOverrides, CameraPath, ClipSide, Colormap,
+    GPUStopwatch, GaussianRenderer, PerspectiveCamera, PerspectiveProjection, PointCloud,
+    Projection, RenderMode, Scene, SceneCamera, SplattingArgs, Split, WGPUContext,
 };
 
 #[derive(Debug, Parser)]
@@ -18,15 +34,512 @@ struct Opt {
     /// input file
     input: PathBuf,
 
-    /// scene json file
+    /// scene json file, Nerfstudio transforms.json file
+    /// or COLMAP reconstruction directory (containing cameras.bin and images.bin)
     scene: PathBuf,
 
     /// image output directory
     img_out: PathBuf,
 
-    /// maximum allowed Spherical Harmonics (SH) degree
-    #[arg(long, default_value_t = 3)]
-    max_sh_deg: u32,
+    /// additional point cloud rendered together with the input (same coordinate system),
+    /// can be given multiple times
+    #[arg(long)]
+    merge: Vec<PathBuf>,
+
+    /// Spherical Harmonics (SH) degree used for rendering (e.g. 0 for view independent colors).
+    /// Clamped to the degree of the point cloud
+    #[arg(
+        long,
+        alias = "max-sh-deg",
+        default_value_t = 3,
+        value_parser = clap::value_parser!(u32).range(0..=3)
+    )]
+    render_sh_deg: u32,
+
+    /// only render gaussians within the sphere given as cx,cy,cz,r
+    #[arg(
+        long,
+        value_delimiter = ',',
+        num_args = 4,
+        allow_negative_numbers = true
+    )]
+    clip_sphere: Option<Vec<f32>>,
+
+    /// keep the gaussians outside of the clipping sphere instead
+    #[arg(long, requires = "clip_sphere")]
+    clip_outside: bool,
+
+    /// abort if the spherical harmonics of the input look malformed instead of only warning
+    #[arg(long)]
+    strict_sh: bool,
+
+    /// sort with 64 bit keys so that gaussians at the same depth keep their order
+    /// (slower, falls back to the unstable CPU sort if the GPU sort is not available)
+    #[arg(long)]
+    stable_sort: bool,
+
+    /// what to render
+    #[arg(long, value_enum, default_value_t = Mode::Color)]
+    mode: Mode,
+
+    /// gaussian for --mode contribution
+    #[arg(long, required_if_eq("mode", "contribution"))]
+    gaussian: Option<u32>,
+
+    /// view space depth range (near,far) for --mode depth-color, defaults to the camera's clipping planes
+    #[arg(long, value_delimiter = ',', num_args = 2)]
+    depth_range: Option<Vec<f32>>,
+
+    /// colormap for --mode depth-color and --depth-format colormap
+    #[arg(long, value_enum, default_value_t = ColormapArg::Turbo)]
+    colormap: ColormapArg,
+
+    /// use an orthographic projection.
+    /// The view height matches the perspective view at the center of the scene
+    #[arg(long)]
+    ortho: bool,
+
+    /// render with coarse settings for quick previews (0 is fastest, 3 is full quality)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(0..=3))]
+    preview_quality: Option<u32>,
+
+    /// write a json file with camera, settings, visible gaussians and timing next to each image
+    #[arg(long)]
+    metadata: bool,
+
+    /// clamp the screen space extent of each splat to this many pixels (of the output image)
+    #[arg(long)]
+    max_splat_size: Option<f32>,
+
+    /// only blend the k front-most gaussians per pixel
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=255))]
+    max_layers: Option<u32>,
+
+    /// adapt the exposure to this average luminance (derived from the previous frame)
+    #[arg(long)]
+    auto_exposure: Option<f32>,
+
+    /// adaption speed of the auto exposure per frame (0-1)
+    #[arg(long, default_value_t = 0.5, requires = "auto_exposure")]
+    exposure_speed: f32,
+
+    /// darken gaussians in dense regions by this strength (0-1), a crude ambient occlusion approximation
+    #[arg(long)]
+    ambient_occlusion: Option<f32>,
+
+    /// render all views with this resolution (WxH) instead of the resolution of the scene cameras
+    /// (e.g. for benchmarking). The field of view is adapted to the aspect ratio
+    #[arg(long, value_parser = parse_resolution)]
+    resolution: Option<Vector2<u32>>,
+
+    /// supersampling factor, renders at n times the resolution and downsamples in linear space
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=4))]
+    ssaa: u32,
+
+    /// keep gaussians whose center is up to this many pixels outside of the image
+    #[arg(long)]
+    cull_margin: Option<f32>,
+
+    /// skip gaussians with a lower opacity
+    #[arg(long)]
+    min_opacity: Option<f32>,
+
+    /// skip gaussians smaller than this many pixels on screen
+    #[arg(long)]
+    min_screen_size: Option<f32>,
+
+    /// pack the images into atlas pages with this many columns (plus atlas.json with the frame rectangles)
+    /// instead of writing one file per image
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    atlas_columns: Option<u32>,
+
+    /// sort bias relative to the gaussian size to separate coplanar gaussians
+    #[arg(long)]
+    depth_bias: Option<f32>,
+
+    /// background color (r,g,b,a in [0,1], sRGB) the gaussians are rendered on.
+    /// Default is transparent
+    #[arg(long, value_delimiter = ',', num_args = 4)]
+    background: Option<Vec<f64>>,
+
+    /// directory with per-frame position/opacity deltas (see `FrameSequence`).
+    /// Renders one image per frame into img_out/animation, cycling through the scene cameras
+    #[arg(long)]
+    frame_sequence: Option<PathBuf>,
+
+    /// render this many frames along a smooth path through the cameras of the selected splits
+    /// (sorted by id) into img_out/path instead of the cameras themselves
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    interpolate: Option<u32>,
+
+    /// close the --interpolate path by returning to the first camera
+    #[arg(long, requires = "interpolate")]
+    loop_path: bool,
+
+    /// render a 360° equirectangular light probe from this position (x,y,z) into img_out/light_probe.exr
+    /// instead of the scene cameras. The colors are converted to linear values (not clamped)
+    #[arg(
+        long,
+        value_delimiter = ',',
+        num_args = 3,
+        allow_negative_numbers = true
+    )]
+    light_probe: Option<Vec<f32>>,
+
+    /// width of the light probe panorama (the height is half of it)
+    #[arg(long, default_value_t = 2048, requires = "light_probe")]
+    light_probe_width: u32,
+
+    /// render the point cloud from the six axis directions (framed to the bounding box)
+    /// into img_out/axis_views instead of the scene cameras
+    #[arg(long)]
+    axis_views: bool,
+
+    /// directory with ground truth images (matched by the image name of the camera).
+    /// Writes a strip with ground truth, render and absolute difference next to every image
+    #[arg(long)]
+    compare_strip: Option<PathBuf>,
+
+    /// remove gaussians further than this distance from the center of the point cloud (floaters)
+    #[arg(long)]
+    remove_outliers: Option<f32>,
+
+    /// sort the gaussians along a Morton curve after loading (better memory locality)
+    #[arg(long)]
+    morton_order: bool,
+
+    /// encode the rendered views into this video file (e.g. out.mp4) instead of writing images.
+    /// Requires ffmpeg in the PATH, views with another resolution than the first one are scaled to it
+    #[arg(long)]
+    video: Option<PathBuf>,
+
+    /// frame rate of the --video file
+    #[arg(long, default_value_t = 30, requires = "video", value_parser = clap::value_parser!(u32).range(1..))]
+    fps: u32,
+
+    /// which cameras of the scene to render (each split is written into its own subdirectory)
+    #[arg(long, value_enum, default_value_t = SplitArg::All)]
+    split: SplitArg,
+
+    /// index of the first camera to render (per split, the image names keep the camera index)
+    #[arg(long, default_value_t = 0)]
+    start: usize,
+
+    /// renders at most this many cameras (per split)
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    limit: Option<u64>,
+
+    /// write the GPU time of the render stages and the total time (render and download) per view into this csv file
+    #[arg(long)]
+    timings: Option<PathBuf>,
+
+    /// image file format of the rendered views
+    #[arg(long, value_enum, default_value_t = ImageFormat::Png)]
+    format: ImageFormat,
+
+    /// also render the expected (alpha weighted) view space depth of every view into this directory.
+    /// Pixels without gaussians are set to the far plane
+    #[arg(long)]
+    depth_out: Option<PathBuf>,
+
+    /// file format of the --depth-out images
+    #[arg(long, value_enum, default_value_t = DepthFormat::Exr, requires = "depth_out")]
+    depth_format: DepthFormat,
+
+    /// tone mapping of the rendered colors before they are written
+    #[arg(long, value_enum, default_value_t = TonemapArg::None)]
+    tonemap: TonemapArg,
+
+    /// exponent for --tonemap gamma (the linear colors are encoded with 1/gamma)
+    #[arg(long, default_value_t = 2.2)]
+    gamma: f32,
+
+    /// alpha convention of the PNG, AVIF and atlas images.
+    /// Formats without alpha channel always show the render over black
+    #[arg(long, value_enum, default_value_t = AlphaMode::Premultiplied)]
+    alpha: AlphaMode,
+
+    /// write single channel luminance images (Rec. 709 weights) instead of RGBA
+    #[arg(long)]
+    grayscale: bool,
+
+    /// quality (1-100) for the lossy formats
+    #[arg(long, default_value_t = 90, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: u8,
+
+    /// bits per channel of the PNG images (16 avoids banding in smooth gradients)
+    #[arg(long, value_enum, default_value_t = BitDepth::Eight)]
+    bit_depth: BitDepth,
+
+    /// threads encoding and writing the images while the next views are rendered
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u64).range(1..))]
+    writer_threads: u64,
+
+    /// limit the staging buffer for reading back frames to this many MiB
+    /// (frames are downloaded in strips of rows)
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    download_buffer_mb: Option<u64>,
+
+    /// number of MSAA samples. Falls back to supersampling (see --ssaa) if the
+    /// render format does not support multisampling with this count
+    #[arg(long, default_value_t = 1)]
+    msaa: u32,
+
+    /// serve rendered frames to a remote viewer on host:port instead of writing files
+    #[cfg(feature = "stream")]
+    #[arg(long)]
+    stream: Option<String>,
+
+    /// prefer the low power (integrated) or high performance (discrete) GPU
+    #[arg(long, value_enum)]
+    gpu: Option<GpuArg>,
+
+    /// graphics backend to render with
+    #[arg(long, value_enum)]
+    backend: Option<BackendArg>,
+
+    /// coordinate system convention of the input file
+    #[arg(long, value_enum, default_value_t = Axis::YUp)]
+    axis: Axis,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Axis {
+    YUp,
+    ZUp,
+}
+
+impl From<Axis> for AxisConvention {
+    fn from(axis: Axis) -> Self {
+        match axis {
+            Axis::YUp => AxisConvention::YUp,
+            Axis::ZUp => AxisConvention::ZUp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Mode {
+    /// alpha blended colors
+    Color,
+    /// color of the front-most gaussian with an alpha of at least 0.5 per pixel
+    FrontOpaqueColor,
+    /// color of the gaussian with the highest blending weight per pixel
+    /// (searches the front-most --max-layers gaussians, 32 by default)
+    DominantColor,
+    /// gaussians colored by their depth (see --depth-range)
+    DepthColor,
+    /// per pixel weight of the gaussian selected with --gaussian
+    Contribution,
+    /// heatmap of pixels where splats were blended out of order (debug builds only)
+    #[cfg(debug_assertions)]
+    SortViolations,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum SplitArg {
+    Test,
+    Train,
+    /// test and train split
+    All,
+}
+
+impl SplitArg {
+    fn splits(&self) -> &'static [Split] {
+        match self {
+            SplitArg::Test => &[Split::Test],
+            SplitArg::Train => &[Split::Train],
+            SplitArg::All => &[Split::Test, Split::Train],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum BitDepth {
+    #[value(name = "8")]
+    Eight,
+    #[value(name = "16")]
+    Sixteen,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ImageFormat {
+    /// lossless with alpha channel
+    Png,
+    /// baseline JPEG, the alpha channel is dropped (the background is black)
+    Jpeg,
+    /// AV1 image, much smaller than PNG for photographic renders
+    Avif,
+    /// OpenEXR with linear (premultiplied) float colors and alpha, values are not clamped
+    Exr,
+}
+
+impl ImageFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Avif => "avif",
+            ImageFormat::Exr => "exr",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum GpuArg {
+    Low,
+    High,
+}
+
+impl From<GpuArg> for wgpu::PowerPreference {
+    fn from(gpu: GpuArg) -> Self {
+        match gpu {
+            GpuArg::Low => wgpu::PowerPreference::LowPower,
+            GpuArg::High => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum BackendArg {
+    Vulkan,
+    Dx12,
+    Metal,
+    Gl,
+}
+
+impl From<BackendArg> for wgpu::Backends {
+    fn from(backend: BackendArg) -> Self {
+        match backend {
+            BackendArg::Vulkan => wgpu::Backends::VULKAN,
+            BackendArg::Dx12 => wgpu::Backends::DX12,
+            BackendArg::Metal => wgpu::Backends::METAL,
+            BackendArg::Gl => wgpu::Backends::GL,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum DepthFormat {
+    /// OpenEXR with the view space depth in all color channels
+    Exr,
+    /// 16 bit grayscale PNG with the depth divided by the far plane (65535 is the far plane)
+    Png16,
+    /// 8 bit RGB PNG with the depth divided by the far plane mapped with --colormap
+    Colormap,
+}
+
+impl DepthFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            DepthFormat::Exr => "exr",
+            DepthFormat::Png16 | DepthFormat::Colormap => "png",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum AlphaMode {
+    /// colors multiplied by alpha (as rendered)
+    Premultiplied,
+    /// colors divided by alpha, as expected by most image editors and browsers
+    Straight,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum TonemapArg {
+    /// the colors are written as rendered (sRGB encoded)
+    None,
+    /// power curve instead of the sRGB curve (exponent set with --gamma)
+    Gamma,
+    /// Reinhard operator on the linear colors
+    Reinhard,
+    /// ACES filmic curve on the linear colors
+    Aces,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ColormapArg {
+    Turbo,
+    Viridis,
+    Gray,
+}
+
+impl From<ColormapArg> for Colormap {
+    fn from(colormap: ColormapArg) -> Self {
+        match colormap {
+            ColormapArg::Turbo => Colormap::Turbo,
+            ColormapArg::Viridis => Colormap::Viridis,
+            ColormapArg::Gray => Colormap::Gray,
+        }
+    }
+}
+
+impl Opt {
+    fn render_mode(&self, camera: &PerspectiveCamera) -> RenderMode {
+        match self.mode {
+            Mode::Color => RenderMode::Color,
+            Mode::FrontOpaqueColor => RenderMode::FrontOpaqueColor,
+            Mode::DominantColor => RenderMode::DominantColor,
+            Mode::Contribution => RenderMode::Contribution {
+                index: self.gaussian.unwrap(),
+            },
+            #[cfg(debug_assertions)]
+            Mode::SortViolations => RenderMode::SortViolations,
+            Mode::DepthColor => {
+                let (near, far) = self
+                    .depth_range
+                    .as_ref()
+                    .map_or((camera.projection.znear, camera.projection.zfar), |r| {
+                        (r[0], r[1])
+                    });
+                RenderMode::DepthColor {
+                    near,
+                    far,
+                    colormap: self.colormap.into(),
+                }
+            }
+        }
+    }
+
+    fn max_download_size(&self) -> Option<u64> {
+        self.download_buffer_mb.map(|mb| mb << 20)
+    }
+
+    fn tonemap(&self) -> Tonemap {
+        match self.tonemap {
+            TonemapArg::None => Tonemap::None,
+            TonemapArg::Gamma => Tonemap::Gamma(self.gamma),
+            TonemapArg::Reinhard => Tonemap::Reinhard,
+            TonemapArg::Aces => Tonemap::Aces,
+        }
+    }
+
+    fn clip_sphere(&self) -> Option<(Point3<f32>, f32, ClipSide)> {
+        let side = if self.clip_outside {
+            ClipSide::Outside
+        } else {
+            ClipSide::Inside
+        };
+        self.clip_sphere
+            .as_ref()
+            .map(|s| (Point3::new(s[0], s[1], s[2]), s[3], side))
+    }
+}
+
+/// exits with a usage error if --start skips all views
+fn check_start(opt: &Opt, num_views: usize, name: &str) {
+    if opt.start >= num_views {
+        Opt::command()
+            .error(
+                clap::error::ErrorKind::ValueValidation,
+                format!(
+                    "--start {} is out of range, {name} has {num_views} views",
+                    opt.start
+                ),
+            )
+            .exit();
+    }
 }
 
 #[allow(unused)]
@@ -35,15 +548,31 @@ async fn render_views(
     queue: &wgpu::Queue,
     renderer: &mut GaussianRenderer,
     pc: &mut PointCloud,
-    cameras: Vec<SceneCamera>,
-    img_out: &PathBuf,
+    cameras: impl Iterator<Item = &SceneCamera> + Clone,
+    opt: &Opt,
     split: &str,
+    timings: &mut Option<std::io::BufWriter<File>>,
+    video: &mut Option<VideoEncoder>,
 ) {
-    let img_out = img_out.join(&split);
+    let num_views = cameras
+        .clone()
+        .skip(opt.start)
+        .take(opt.limit.map_or(usize::MAX, |n| n as usize))
+        .count();
+    let cameras = cameras
+        .enumerate()
+        .skip(opt.start)
+        .take(opt.limit.map_or(usize::MAX, |n| n as usize));
+
+    let img_out = opt.img_out.join(&split);
     println!("saving images to '{}'", img_out.to_string_lossy());
     std::fs::create_dir_all(img_out.clone()).unwrap();
+    let depth_out = opt.depth_out.as_ref().map(|dir| dir.join(&split));
+    if let Some(dir) = &depth_out {
+        std::fs::create_dir_all(dir).unwrap();
+    }
 
-    let pb = ProgressBar::new(cameras.len() as u64);
+    let pb = ProgressBar::new(num_views as u64);
     let pb_style = ProgressStyle::with_template(
         "{msg} {spinner:.green} [{bar:.cyan/blue}] {pos}/{len} [{elapsed}/{duration}]",
     )
@@ -52,78 +581,721 @@ async fn render_views(
     pb.set_style(pb_style);
     pb.set_message(format!("rendering {split}"));
 
-    for (i, s) in cameras.iter().enumerate().progress_with(pb) {
-        let mut resolution: Vector2<u32> = Vector2::new(s.width, s.height);
+    let mut stopwatch = timings
+        .is_some()
+        .then(|| GPUStopwatch::new(device, Some(TIMING_STAGES.len() as u32)));
+    let mut atlas_frames = Vec::new();
+    let image_writer = ImageWriter::new(opt);
+    let mut failed = 0;
+    for (i, s) in cameras.progress_with(pb.clone()) {
+        // a failing camera (e.g. an empty resolution) is skipped instead of aborting the batch
+        device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let result: anyhow::Result<()> = async {
+            let start = Instant::now();
+            if pc.num_frames() > 0 {
+                pc.set_frame(device, queue, i)?;
+            }
+            let (target, settings) =
+                render_frame(device, queue, renderer, pc, s, opt, &mut stopwatch)?;
+            let resolution = settings.viewport / opt.ssaa;
+            let img = opt.tonemap().apply(downsample(
+                composite_background(
+                    download_texture(&target, device, queue, opt.max_download_size()).await?,
+                    renderer,
+                ),
+                opt.ssaa,
+            ));
+            let render_time = start.elapsed();
+            if let (Some(stopwatch), Some(writer)) = (&mut stopwatch, timings.as_mut()) {
+                // the measurements have to be taken before the next frame starts the stopwatch again
+                let durations = stopwatch.take_measurements(device, queue).await;
+                let ms = |d: &Duration| d.as_secs_f64() * 1000.;
+                write!(writer, "{split},{i},{}", s.img_name)?;
+                for stage in TIMING_STAGES {
+                    write!(writer, ",{}", durations.get(stage).map_or(0., ms))?;
+                }
+                writeln!(writer, ",{}", ms(&render_time))?;
+            }
+            if let Some(dir) = &depth_out {
+                let depth = render_depth(device, queue, renderer, pc, settings, opt).await?;
+                let file = dir.join(format!("{i:0>5}.{}", opt.depth_format.extension()));
+                save_depth(
+                    &depth,
+                    &file,
+                    opt.depth_format,
+                    settings.camera.projection.zfar,
+                    opt.colormap.into(),
+                )?;
+            }
 
-        if resolution.x > 1600 {
-            let s = resolution.x as f32 / 1600.;
-            resolution.x = 1600;
-            resolution.y = (resolution.y as f32 / s) as u32;
+            if let Some(gt_dir) = &opt.compare_strip {
+                let strip = find_gt_image(gt_dir, &s.img_name)
+                    .and_then(|file| Ok(image::open(file)?.into_rgba8()))
+                    .and_then(|gt| compare_strip(&gt, &to_rgba8(&img)))
+                    .context("comparing with the ground truth failed")?;
+                strip.save(img_out.join(format!("{i:0>5}_compare.png")))?;
+            }
+
+            if let Some(video) = video {
+                video.write_frame(&to_rgba8(&img))?;
+            } else if opt.atlas_columns.is_some() {
+                atlas_frames.push((
+                    i,
+                    s.img_name.clone(),
+                    to_rgba8(&with_alpha_mode(&img, opt.alpha)),
+                ));
+            } else {
+                let file = img_out.join(format!("{i:0>5}.{}", opt.format.extension()));
+                image_writer.write(file, img);
+            }
+
+            if opt.metadata {
+                let metadata = serde_json::json!({
+                    "camera_index": i,
+                    "split": split,
+                    "camera": s,
+                    "resolution": [resolution.x, resolution.y],
+                    "render_settings": {
+                        "max_sh_deg": settings.max_sh_deg,
+                        "gaussian_scaling": settings.gaussian_scaling,
+                        "max_splat_size": settings.max_splat_size,
+                        "render_mode": format!("{:?}", settings.render_mode),
+                        "preview_quality": settings.preview_quality,
+                        "exposure": renderer.exposure(),
+                        "ambient_occlusion": renderer.ambient_occlusion(),
+                        "max_layers_per_pixel": settings.max_layers_per_pixel,
+                        "ssaa": opt.ssaa,
+                        "msaa": renderer.msaa_samples(),
+                        "clip_sphere": settings.clip_sphere.map(|(c, r, side)| {
+                            serde_json::json!({
+                                "center": [c.x, c.y, c.z],
+                                "radius": r,
+                                "side": format!("{:?}", side),
+                            })
+                        }),
+                    },
+                    "far_plane": settings.camera.projection.zfar,
+                    "visible_gaussians": renderer.num_visible_points(device, queue).await,
+                    "render_time_ms": render_time.as_secs_f64() * 1000.,
+                });
+                let file = File::create(img_out.join(format!("{i:0>5}.json")))?;
+                serde_json::to_writer_pretty(file, &metadata)?;
+            }
+            renderer.update_exposure(device, queue, &target).await;
+            Ok(())
         }
+        .await;
+        let validation = device.pop_error_scope().await;
+        let out_of_memory = device.pop_error_scope().await;
+        let result = match (result, validation.or(out_of_memory)) {
+            (Ok(()), Some(err)) => Err(anyhow::anyhow!("{err}")),
+            (result, _) => result,
+        };
+        if let Err(err) = result {
+            failed += 1;
+            // println of the progress bar is swallowed if the output is not a terminal
+            pb.suspend(|| eprintln!("warning: skipping camera {i} ('{}'): {:#}", s.img_name, err));
+        }
+    }
+    failed += image_writer.finish();
+    println!(
+        "{split}: rendered {} of {num_views} views, {failed} failed",
+        num_views - failed
+    );
+    if let Some(columns) = opt.atlas_columns {
+        write_atlas(&img_out, atlas_frames, columns).unwrap();
+    }
+}
 
-        let target = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("render texture"),
-            size: wgpu::Extent3d {
-                width: resolution.x,
-                height: resolution.y,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: renderer.color_format(),
-            usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::RENDER_ATTACHMENT,
-            view_formats: &[],
-        });
+/// Encodes frames into a video by piping raw RGBA data into an ffmpeg process.
+/// The process is started with the size of the first frame, so frames are encoded as they are rendered.
+/// Frames of another size (cameras with a different resolution) are scaled to the size of the first frame.
+struct VideoEncoder {
+    file: PathBuf,
+    fps: u32,
+    process: Option<(std::process::Child, (u32, u32))>,
+}
 
-        let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+impl VideoEncoder {
+    fn new(file: PathBuf, fps: u32) -> anyhow::Result<Self> {
+        let available = std::process::Command::new("ffmpeg")
+            .arg("-version")
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .map_or(false, |status| status.success());
+        if !available {
+            return Err(anyhow::anyhow!(
+                "--video requires ffmpeg, but it could not be run (is it installed and in the PATH?)"
+            ));
+        }
+        if let Some(dir) = file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        return Ok(Self {
+            file,
+            fps,
+            process: None,
+        });
+    }
 
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("render encoder"),
+    fn write_frame(&mut self, img: &RgbaImage) -> anyhow::Result<()> {
+        if self.process.is_none() {
+            println!("encoding video to '{}'", self.file.to_string_lossy());
+            let child = std::process::Command::new("ffmpeg")
+                .args([
+                    "-y",
+                    "-loglevel",
+                    "error",
+                    "-f",
+                    "rawvideo",
+                    "-pix_fmt",
+                    "rgba",
+                ])
+                .args(["-s", &format!("{}x{}", img.width(), img.height())])
+                .args(["-r", &self.fps.to_string(), "-i", "-"])
+                // yuv420p needs even dimensions
+                .args([
+                    "-vf",
+                    "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+                    "-pix_fmt",
+                    "yuv420p",
+                ])
+                .arg(&self.file)
+                .stdin(std::process::Stdio::piped())
+                .spawn()?;
+            self.process = Some((child, img.dimensions()));
+        }
+        let (child, (width, height)) = self.process.as_mut().unwrap();
+        let scaled = (img.dimensions() != (*width, *height)).then(|| {
+            image::imageops::resize(img, *width, *height, image::imageops::FilterType::Triangle)
         });
+        child
+            .stdin
+            .as_mut()
+            .unwrap()
+            .write_all(scaled.as_ref().unwrap_or(img).as_raw())
+            .map_err(|err| anyhow::anyhow!("writing to ffmpeg failed: {err}"))?;
+        return Ok(());
+    }
 
-        let mut camera: PerspectiveCamera = s.clone().into();
-        camera.fit_near_far(pc.bbox());
-        renderer.prepare(
-            &mut encoder,
-            device,
-            queue,
-            &pc,
-            SplattingArgs {
-                camera: camera,
-                viewport: resolution,
-                gaussian_scaling: 1.,
-                max_sh_deg: pc.sh_deg(),
-                show_env_map: false,
-                mip_splatting: None,
-                kernel_size: None,
-                clipping_box: None,
-                walltime: Duration::from_secs(100),
-                scene_center: None,
-                scene_extend: None,
-            },
-            &mut None,
+    /// closes the input of ffmpeg and waits until the video is written
+    fn finish(self) -> anyhow::Result<()> {
+        if let Some((mut child, _)) = self.process {
+            drop(child.stdin.take());
+            let status = child.wait()?;
+            if !status.success() {
+                return Err(anyhow::anyhow!("ffmpeg failed ({status})"));
+            }
+        }
+        return Ok(());
+    }
+}
+
+fn finish_video(video: Option<VideoEncoder>) {
+    if let Some(video) = video {
+        video.finish().unwrap();
+    }
+}
+
+/// Saves images with [`save_image`] on worker threads, so the next views can be rendered meanwhile.
+/// At most one image per thread waits in the queue, [`ImageWriter::write`] blocks while it is full.
+struct ImageWriter {
+    sender: std::sync::mpsc::SyncSender<(PathBuf, Rgba32FImage)>,
+    /// each worker returns the number of images it failed to save
+    workers: Vec<std::thread::JoinHandle<usize>>,
+}
+
+impl ImageWriter {
+    fn new(opt: &Opt) -> Self {
+        let threads = opt.writer_threads as usize;
+        let (sender, receiver) = std::sync::mpsc::sync_channel(threads);
+        let receiver = std::sync::Arc::new(std::sync::Mutex::new(receiver));
+        let (format, quality, grayscale, alpha, bit_depth) = (
+            opt.format,
+            opt.quality,
+            opt.grayscale,
+            opt.alpha,
+            opt.bit_depth,
         );
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("render pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &target_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
+        let workers = (0..threads)
+            .map(|_| {
+                let receiver = receiver.clone();
+                std::thread::spawn(move || {
+                    let mut failed = 0;
+                    loop {
+                        // the lock is released before saving
+                        let job = receiver.lock().unwrap().recv();
+                        let Ok((file, img)): Result<(PathBuf, Rgba32FImage), _> = job else {
+                            return failed;
+                        };
+                        if let Err(err) =
+                            save_image(&img, &file, format, quality, grayscale, alpha, bit_depth)
+                        {
+                            failed += 1;
+                            eprintln!(
+                                "warning: cannot save '{}': {:#}",
+                                file.to_string_lossy(),
+                                err
+                            );
+                        }
+                    }
+                })
+            })
+            .collect();
+        return Self { sender, workers };
+    }
+
+    fn write(&self, file: PathBuf, img: Rgba32FImage) {
+        self.sender
+            .send((file, img))
+            .expect("all image writer threads exited");
+    }
+
+    /// waits until all images are saved and returns the number of failed ones
+    fn finish(self) -> usize {
+        drop(self.sender);
+        return self
+            .workers
+            .into_iter()
+            .map(|worker| worker.join().expect("image writer thread panicked"))
+            .sum();
+    }
+}
+
+/// saves the (sRGB encoded) image in the given format
+/// (16 bit depth is only supported for PNG)
+fn save_image(
+    img: &Rgba32FImage,
+    file: &std::path::Path,
+    format: ImageFormat,
+    quality: u8,
+    grayscale: bool,
+    alpha: AlphaMode,
+    bit_depth: BitDepth,
+) -> anyhow::Result<()> {
+    let writer = std::io::BufWriter::new(File::create(file)?);
+    if let ImageFormat::Exr = format {
+        if grayscale {
+            return Err(anyhow::anyhow!("grayscale output is not supported for exr"));
+        }
+        return Ok(to_linear(img).write_with_encoder(OpenExrEncoder::new(writer))?);
+    }
+    if bit_depth == BitDepth::Sixteen {
+        if !matches!(format, ImageFormat::Png) {
+            return Err(anyhow::anyhow!("16 bit output is only supported for png"));
+        }
+        let img = with_alpha_mode(img, alpha);
+        if grayscale {
+            luminance16(&img).write_with_encoder(PngEncoder::new(writer))?;
+        } else {
+            to_rgba16(&img).write_with_encoder(PngEncoder::new(writer))?;
+        }
+        return Ok(());
+    }
+    if grayscale {
+        let gray = luminance(&to_rgba8(img));
+        match format {
+            ImageFormat::Png => gray.write_with_encoder(PngEncoder::new(writer))?,
+            ImageFormat::Jpeg => {
+                gray.write_with_encoder(JpegEncoder::new_with_quality(writer, quality))?
+            }
+            ImageFormat::Avif => {
+                gray.write_with_encoder(AvifEncoder::new_with_speed_quality(writer, 6, quality))?
+            }
+            ImageFormat::Exr => unreachable!(),
+        }
+        return Ok(());
+    }
+    match format {
+        ImageFormat::Png => {
+            to_rgba8(&with_alpha_mode(img, alpha)).write_with_encoder(PngEncoder::new(writer))?
+        }
+        ImageFormat::Jpeg => {
+            // colors are premultiplied, so dropping alpha composites over black
+            let rgb = image::DynamicImage::ImageRgba8(to_rgba8(img)).into_rgb8();
+            rgb.write_with_encoder(JpegEncoder::new_with_quality(writer, quality))?
+        }
+        ImageFormat::Avif => to_rgba8(&with_alpha_mode(img, alpha))
+            .write_with_encoder(AvifEncoder::new_with_speed_quality(writer, 6, quality))?,
+        ImageFormat::Exr => unreachable!(),
+    }
+    return Ok(());
+}
+
+/// converts the premultiplied colors to the alpha mode.
+/// Transparent pixels are kept as they are for straight alpha
+fn with_alpha_mode(img: &Rgba32FImage, alpha: AlphaMode) -> Rgba32FImage {
+    let mut img = img.clone();
+    if alpha == AlphaMode::Straight {
+        for p in img.pixels_mut() {
+            let a = p.0[3];
+            if a > 0. {
+                for c in 0..3 {
+                    p.0[c] /= a;
+                }
+            }
+        }
+    }
+    return img;
+}
+
+/// Renders the expected view space depth per pixel with the settings of a color render.
+/// Pixels without gaussians are set to the far plane.
+async fn render_depth(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    renderer: &mut GaussianRenderer,
+    pc: &PointCloud,
+    settings: SplattingArgs,
+    opt: &Opt,
+) -> anyhow::Result<ImageBuffer<Luma<f32>, Vec<f32>>> {
+    let settings = SplattingArgs {
+        render_mode: RenderMode::Depth,
+        ..settings
+    };
+    let target = render_to_texture(device, queue, renderer, pc, settings, &mut None)?;
+    let img = download_texture(&target, device, queue, opt.max_download_size()).await?;
+    let far = settings.camera.projection.zfar;
+    let f = opt.ssaa;
+    return Ok(ImageBuffer::from_fn(
+        img.width() / f,
+        img.height() / f,
+        |x, y| {
+            // the alpha weighted depth of the samples is averaged before normalizing
+            let (mut depth, mut alpha) = (0., 0.);
+            for dy in 0..f {
+                for dx in 0..f {
+                    let [d, _, _, a] = img.get_pixel(x * f + dx, y * f + dy).0;
+                    depth += d;
+                    alpha += a;
+                }
+            }
+            if alpha > 1e-4 {
+                Luma([depth / alpha])
+            } else {
+                Luma([far])
+            }
+        },
+    ));
+}
+
+fn save_depth(
+    depth: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    file: &std::path::Path,
+    format: DepthFormat,
+    far: f32,
+    colormap: Colormap,
+) -> anyhow::Result<()> {
+    let writer = std::io::BufWriter::new(File::create(file)?);
+    match format {
+        DepthFormat::Exr => {
+            let rgb = image::Rgb32FImage::from_fn(depth.width(), depth.height(), |x, y| {
+                image::Rgb([depth.get_pixel(x, y).0[0]; 3])
             });
-            renderer.render(&mut render_pass, &pc);
+            rgb.write_with_encoder(OpenExrEncoder::new(writer))?
+        }
+        DepthFormat::Png16 => {
+            let img =
+                ImageBuffer::<Luma<u16>, _>::from_fn(depth.width(), depth.height(), |x, y| {
+                    let d = depth.get_pixel(x, y).0[0] / far;
+                    Luma([(d.clamp(0., 1.) * 65535.).round() as u16])
+                });
+            img.write_with_encoder(PngEncoder::new(writer))?
+        }
+        DepthFormat::Colormap => {
+            let values: Vec<f32> = depth.pixels().map(|p| p.0[0] / far).collect();
+            let img = apply_colormap(&values, depth.width(), depth.height(), colormap)?;
+            img.write_with_encoder(PngEncoder::new(writer))?
+        }
+    }
+    return Ok(());
+}
+
+/// Rec. 709 luma of the (premultiplied) colors, the alpha channel is dropped
+fn luminance(img: &RgbaImage) -> GrayImage {
+    return GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        let [r, g, b, _] = img.get_pixel(x, y).0.map(|c| c as f32);
+        Luma([(0.2126 * r + 0.7152 * g + 0.0722 * b).round().min(255.) as u8])
+    });
+}
+
+/// same as [`luminance`] with 16 bits (computed from the float colors)
+fn luminance16(img: &Rgba32FImage) -> ImageBuffer<Luma<u16>, Vec<u16>> {
+    return ImageBuffer::from_fn(img.width(), img.height(), |x, y| {
+        let [r, g, b, _] = img.get_pixel(x, y).0;
+        Luma([to_u16(0.2126 * r + 0.7152 * g + 0.0722 * b)])
+    });
+}
+
+/// finds the image with the given name (with or without file extension) in the directory
+fn find_gt_image(dir: &std::path::Path, name: &str) -> anyhow::Result<PathBuf> {
+    let file = dir.join(name);
+    if file.is_file() {
+        return Ok(file);
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.file_stem().map_or(false, |s| s == name) {
+            return Ok(path);
+        }
+    }
+    return Err(anyhow::anyhow!(
+        "no ground truth image for '{}' in '{}'",
+        name,
+        dir.to_string_lossy()
+    ));
+}
+
+/// puts ground truth, render (over black) and their absolute difference next to each other
+fn compare_strip(gt: &RgbaImage, render: &RgbaImage) -> anyhow::Result<RgbaImage> {
+    if gt.dimensions() != render.dimensions() {
+        return Err(anyhow::anyhow!(
+            "ground truth has resolution {:?} but the render has {:?}",
+            gt.dimensions(),
+            render.dimensions()
+        ));
+    }
+    let (width, height) = render.dimensions();
+    return Ok(RgbaImage::from_fn(width * 3, height, |x, y| {
+        let gt = gt.get_pixel(x % width, y).0;
+        let render = render.get_pixel(x % width, y).0;
+        let [r, g, b] = match x / width {
+            0 => [gt[0], gt[1], gt[2]],
+            1 => [render[0], render[1], render[2]],
+            _ => [0, 1, 2].map(|c| gt[c].abs_diff(render[c])),
+        };
+        Rgba([r, g, b, 255])
+    }));
+}
+
+/// maximum width or height of an atlas page
+const MAX_ATLAS_SIZE: u32 = 16384;
+
+/// Packs the frames (camera index, image name, image) into a grid with the given number of columns.
+/// Every frame occupies a cell of the size of the largest frame (aligned to the top left corner).
+/// Rows that do not fit into a page of at most MAX_ATLAS_SIZE pixels start a new page.
+/// The pages are saved as atlas_XXX.png and the rectangle of every frame is written to atlas.json.
+fn write_atlas(
+    img_out: &std::path::Path,
+    frames: Vec<(usize, String, RgbaImage)>,
+    columns: u32,
+) -> anyhow::Result<()> {
+    let cell_width = frames.iter().map(|(_, _, f)| f.width()).max().unwrap_or(1);
+    let cell_height = frames.iter().map(|(_, _, f)| f.height()).max().unwrap_or(1);
+    let columns = columns.min((MAX_ATLAS_SIZE / cell_width).max(1));
+    let rows_per_page = (MAX_ATLAS_SIZE / cell_height).max(1);
+    let frames_per_page = (columns * rows_per_page) as usize;
+
+    let mut pages = Vec::new();
+    let mut rects = Vec::new();
+    for (p, page_frames) in frames.chunks(frames_per_page).enumerate() {
+        let rows = (page_frames.len() as u32 + columns - 1) / columns;
+        let page_columns = columns.min(page_frames.len() as u32);
+        let mut page = RgbaImage::new(page_columns * cell_width, rows * cell_height);
+        for (j, (i, name, frame)) in page_frames.iter().enumerate() {
+            let x = (j as u32 % columns) * cell_width;
+            let y = (j as u32 / columns) * cell_height;
+            image::imageops::replace(&mut page, frame, x as i64, y as i64);
+            rects.push(serde_json::json!({
+                "camera_index": i,
+                "img_name": name,
+                "page": p,
+                "x": x,
+                "y": y,
+                "width": frame.width(),
+                "height": frame.height(),
+            }));
+        }
+        let file_name = format!("atlas_{p:0>3}.png");
+        page.save(img_out.join(&file_name))?;
+        pages.push(file_name);
+    }
+
+    let file = File::create(img_out.join("atlas.json"))?;
+    serde_json::to_writer_pretty(
+        file,
+        &serde_json::json!({ "pages": pages, "frames": rects }),
+    )?;
+    return Ok(());
+}
+
+fn parse_resolution(s: &str) -> Result<Vector2<u32>, String> {
+    let (w, h) = s
+        .split_once('x')
+        .ok_or(format!("resolution '{s}' is not in the format WxH"))?;
+    let parse = |v: &str| match v.trim().parse::<u32>() {
+        Ok(v) if v > 0 => Ok(v),
+        _ => Err(format!("invalid resolution '{s}'")),
+    };
+    return Ok(Vector2::new(parse(w)?, parse(h)?));
+}
+
+/// render stages measured by the stopwatch (see --timings)
+const TIMING_STAGES: [&str; 3] = ["preprocess", "sorting", "rasterization"];
+
+/// writes the header of the --timings csv file
+fn timings_header(writer: &mut impl std::io::Write) -> std::io::Result<()> {
+    write!(writer, "split,camera_index,image_name")?;
+    for stage in TIMING_STAGES {
+        write!(writer, ",{stage}_ms")?;
+    }
+    return writeln!(writer, ",total_ms");
+}
+
+/// renders the camera view into a new texture (submitted to the queue)
+fn render_frame(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    renderer: &mut GaussianRenderer,
+    pc: &mut PointCloud,
+    s: &SceneCamera,
+    opt: &Opt,
+    stopwatch: &mut Option<GPUStopwatch>,
+) -> anyhow::Result<(wgpu::Texture, SplattingArgs)> {
+    let mut resolution: Vector2<u32> = Vector2::new(s.width, s.height);
+    let mut camera: PerspectiveCamera = s.clone().into();
+
+    if let Some(forced) = opt.resolution {
+        // --resolution is used as is (no 1600 pixel limit), only the field of view is adapted
+        resolution = forced;
+        camera.projection.resize(forced.x, forced.y);
+    } else if resolution.x > 1600 {
+        let s = resolution.x as f32 / 1600.;
+        resolution.x = 1600;
+        resolution.y = (resolution.y as f32 / s) as u32;
+    }
+
+    return render_camera(
+        device,
+        queue,
+        renderer,
+        pc,
+        camera,
+        resolution,
+        &s.overrides,
+        opt,
+        stopwatch,
+    );
+}
+
+fn render_camera(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    renderer: &mut GaussianRenderer,
+    pc: &mut PointCloud,
+    mut camera: PerspectiveCamera,
+    resolution: Vector2<u32>,
+    overrides: &CameraOverrides,
+    opt: &Opt,
+    stopwatch: &mut Option<GPUStopwatch>,
+) -> anyhow::Result<(wgpu::Texture, SplattingArgs)> {
+    let render_resolution = resolution * opt.ssaa;
+
+    if opt.ortho {
+        let distance = camera.position.distance(pc.bbox().center());
+        camera.projection.kind = Projection::Orthographic {
+            height: 2. * distance * (camera.projection.fovy / 2.).tan(),
+        };
+    }
+    camera.fit_near_far(pc.bbox());
+    let settings = SplattingArgs {
+        camera: camera,
+        viewport: render_resolution,
+        gaussian_scaling: overrides.scale_modifier.unwrap_or(1.),
+        max_sh_deg: opt.render_sh_deg.min(pc.sh_deg()),
+        show_env_map: false,
+        mip_splatting: None,
+        kernel_size: None,
+        clipping_box: None,
+        walltime: Duration::from_secs(100),
+        scene_center: None,
+        scene_extend: None,
+        clip_sphere: opt.clip_sphere(),
+        render_mode: opt.render_mode(&camera),
+        preview_quality: opt.preview_quality,
+        // the limits are given in pixels of the output image
+        max_splat_size: overrides
+            .max_screen_size
+            .or(opt.max_splat_size)
+            .map(|size| size * opt.ssaa as f32),
+        max_layers_per_pixel: opt.max_layers,
+        region: None,
+    };
+    let target = render_to_texture(device, queue, renderer, pc, settings, stopwatch)?;
+    return Ok((target, settings));
+}
+
+/// Remote rendering server.
+///
+/// Wire format (TCP, one client at a time):
+/// - client -> server: one camera per line, encoded as json like the entries of the scene file
+/// - server -> client: for every received camera the rendered frame as
+///   byte length (`u32`, little endian) followed by the JPEG encoded image
+#[cfg(feature = "stream")]
+mod stream {
+    use std::io::{BufRead, BufReader, BufWriter, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    use image::{codecs::jpeg::JpegEncoder, DynamicImage};
+
+    use super::*;
+
+    const JPEG_QUALITY: u8 = 90;
+
+    pub async fn serve(
+        addr: &str,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &mut GaussianRenderer,
+        pc: &mut PointCloud,
+        opt: &Opt,
+    ) -> anyhow::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        println!("waiting for connections on {}", listener.local_addr()?);
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let peer = stream.peer_addr()?;
+            println!("client {peer} connected");
+            match serve_client(stream, device, queue, renderer, pc, opt).await {
+                Ok(()) => println!("client {peer} disconnected"),
+                Err(err) => println!("client {peer} disconnected: {err}"),
+            }
+        }
+        return Ok(());
+    }
+
+    async fn serve_client(
+        stream: TcpStream,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &mut GaussianRenderer,
+        pc: &mut PointCloud,
+        opt: &Opt,
+    ) -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(stream.try_clone()?);
+        for line in BufReader::new(stream).lines() {
+            let camera: SceneCamera = serde_json::from_str(&line?)?;
+            let (target, _) = render_frame(device, queue, renderer, pc, &camera, opt, &mut None)?;
+            let img = opt.tonemap().apply(downsample(
+                composite_background(
+                    download_texture(&target, device, queue, opt.max_download_size()).await?,
+                    renderer,
+                ),
+                opt.ssaa,
+            ));
+            let rgb = DynamicImage::ImageRgba8(to_rgba8(&img)).to_rgb8();
+
+            let mut jpeg = Vec::new();
+            JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY).encode_image(&rgb)?;
+            writer.write_all(&(jpeg.len() as u32).to_le_bytes())?;
+            writer.write_all(&jpeg)?;
+            writer.flush()?;
+
+            renderer.update_exposure(device, queue, &target).await;
         }
-        queue.submit(std::iter::once(encoder.finish()));
-        let img = download_texture(&target, device, queue).await;
-        img.save(img_out.join(format!("{i:0>5}.png"))).unwrap();
+        return Ok(());
     }
 }
 
@@ -132,132 +1304,932 @@ async fn render_views(
 async fn main() {
     #[cfg(not(target_arch = "wasm32"))]
     env_logger::init();
-    let opt = Opt::parse();
+    let mut opt = Opt::parse();
+    if opt.bit_depth == BitDepth::Sixteen && !matches!(opt.format, ImageFormat::Png) {
+        Opt::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--bit-depth 16 is only supported for --format png",
+            )
+            .exit();
+    }
 
-    println!("reading scene file '{}'", opt.scene.to_string_lossy());
+    // fail before loading anything if the frames cannot be encoded
+    let mut video = opt.video.as_ref().map(|file| {
+        VideoEncoder::new(file.clone(), opt.fps).unwrap_or_else(|err| {
+            eprintln!("error: {err:#}");
+            std::process::exit(1);
+        })
+    });
 
-    // TODO this is suboptimal as it is never closed
-    let ply_file = File::open(&opt.input).unwrap();
-    let scene_file = File::open(opt.scene).unwrap();
+    println!("reading scene file '{}'", opt.scene.to_string_lossy());
 
-    let scene = Scene::from_json(scene_file).unwrap();
+    let mut scene = if opt.scene.is_dir() {
+        Scene::from_colmap(&opt.scene).unwrap()
+    } else if opt.scene.file_name() == Some("transforms.json".as_ref()) {
+        Scene::from_nerfstudio_json(File::open(&opt.scene).unwrap()).unwrap()
+    } else {
+        Scene::from_json(File::open(&opt.scene).unwrap()).unwrap()
+    };
+    // move the cameras into the coordinate system of the point cloud
+    if scene.global_transform() != Matrix4::identity() {
+        let inverse = scene
+            .global_transform()
+            .invert()
+            .expect("global transform of the scene is not invertible");
+        scene.transform_cameras(inverse);
+    }
 
-    let wgpu_context = WGPUContext::new_instance().await;
+    let wgpu_context = if opt.gpu.is_some() || opt.backend.is_some() {
+        WGPUContext::new_with_options(
+            opt.backend.map_or(wgpu::Backends::PRIMARY, Into::into),
+            opt.gpu.map_or(wgpu::PowerPreference::default(), Into::into),
+        )
+        .await
+        .unwrap_or_else(|err| panic!("{err:?}"))
+    } else {
+        WGPUContext::new_instance().await
+    };
+    let adapter_info = wgpu_context.adapter.get_info();
+    println!(
+        "using adapter '{}' ({:?})",
+        adapter_info.name, adapter_info.backend
+    );
     let device = &wgpu_context.device;
     let queue = &wgpu_context.queue;
 
-    println!("reading point cloud file '{}'", opt.input.to_string_lossy());
-
-    let pc_raw = GenericGaussianPointCloud::load(ply_file).unwrap();
+    let pb = ProgressBar::new(0);
+    pb.set_style(
+        ProgressStyle::with_template("{msg} [{bar:.cyan/blue}] {pos}/{len} points [{elapsed}]")
+            .unwrap()
+            .progress_chars("#>-"),
+    );
+    let data_type = PCDataType::from_path(&opt.input)
+        .with_context(|| format!("cannot read '{}'", opt.input.to_string_lossy()))
+        .unwrap();
+    pb.set_message(format!(
+        "reading {:?} file '{}'",
+        data_type,
+        opt.input.to_string_lossy()
+    ));
+    let progress = |read, total| {
+        pb.set_length(total);
+        pb.set_position(read);
+    };
+    #[cfg(feature = "mmap")]
+    let mut pc_raw =
+        GenericGaussianPointCloud::load_mmap_with_progress(&opt.input, progress).unwrap();
+    #[cfg(not(feature = "mmap"))]
+    let mut pc_raw =
+        GenericGaussianPointCloud::load_with_progress(File::open(&opt.input).unwrap(), progress)
+            .unwrap();
+    pb.finish();
+    let sh_warnings = pc_raw.validate();
+    for warning in &sh_warnings {
+        println!("warning: {warning}");
+    }
+    if opt.strict_sh && !sh_warnings.is_empty() {
+        panic!(
+            "the spherical harmonics of '{}' look malformed",
+            opt.input.to_string_lossy()
+        );
+    }
+    pc_raw.convert_axes(opt.axis.into()).unwrap();
     let mut pc = PointCloud::new(&device, pc_raw).unwrap();
+    for file in &opt.merge {
+        let mut raw = GenericGaussianPointCloud::load(File::open(file).unwrap()).unwrap();
+        raw.convert_axes(opt.axis.into()).unwrap();
+        let other = PointCloud::new(device, raw).unwrap();
+        pc.merge(device, queue, &other).unwrap();
+    }
+
+    if let Some(max_radius) = opt.remove_outliers {
+        let removed = pc.remove_outliers(device, queue, max_radius).await.unwrap();
+        println!("removed {removed} outliers");
+    }
+    if opt.morton_order {
+        pc.reorder_morton(device, queue).await.unwrap();
+    }
+    for warning in scene.validate_against(&pc) {
+        println!("warning: {warning}");
+    }
+    println!(
+        "{} gaussians ({:.1} MiB GPU memory)",
+        pc.num_points(),
+        pc.gpu_memory_bytes() as f64 / (1 << 20) as f64
+    );
 
     let render_format = wgpu::TextureFormat::Rgba16Float;
 
+    // the subgroup size search of the sorter is cached to speed up subsequent runs
+    let sorter = if opt.stable_sort {
+        GPURSSorter::new_u64(device, queue).await
+    } else {
+        GPURSSorter::new_cached(
+            device,
+            queue,
+            &adapter_info,
+            &std::env::temp_dir().join("web_splats_subgroup_size.json"),
+        )
+        .await
+    };
+    let sorter: Box<dyn DepthSorter> = match sorter {
+        Ok(sorter) => Box::new(sorter),
+        Err(err) => {
+            log::warn!("cannot create the GPU sorter ({err}), sorting on the CPU instead");
+            Box::new(CpuSorter::new(device))
+        }
+    };
     let mut renderer =
-        GaussianRenderer::new(&device, &queue, render_format, pc.sh_deg(), pc.compressed()).await;
+        GaussianRenderer::with_sorter(&device, render_format, pc.sh_deg(), pc.compressed(), sorter);
+    renderer.set_auto_exposure(opt.auto_exposure.map(|target| AutoExposure {
+        target,
+        speed: opt.exposure_speed,
+    }));
+    let msaa_samples = renderer.set_msaa(device, &wgpu_context.adapter, opt.msaa);
+    if msaa_samples < opt.msaa && opt.ssaa == 1 {
+        // supersampling smooths the splat edges as well (at a higher cost)
+        opt.ssaa = ((opt.msaa as f32).sqrt().ceil() as u32).min(4);
+        println!(
+            "warning: {}x MSAA is not supported, using {}x{} supersampling instead",
+            opt.msaa, opt.ssaa, opt.ssaa
+        );
+    }
+    if let Some(bias) = opt.depth_bias {
+        renderer.set_depth_bias(bias);
+    }
+    if let Some(margin) = opt.cull_margin {
+        renderer.set_cull_margin(margin);
+    }
+    if let Some(opacity) = opt.min_opacity {
+        renderer.set_min_opacity(opacity);
+    }
+    if let Some(size) = opt.min_screen_size {
+        renderer.set_min_screen_size(size);
+    }
+    if let Some(c) = &opt.background {
+        renderer.set_background(wgpu::Color {
+            r: c[0],
+            g: c[1],
+            b: c[2],
+            a: c[3],
+        });
+    }
+    if let Some(strength) = opt.ambient_occlusion {
+        renderer.set_ambient_occlusion(strength);
+    }
 
-    render_views(
-        device,
-        queue,
-        &mut renderer,
-        &mut pc,
-        scene.cameras(Some(Split::Test)),
-        &opt.img_out,
-        "test",
-    )
-    .await;
-    render_views(
-        device,
-        queue,
-        &mut renderer,
-        &mut pc,
-        scene.cameras(Some(Split::Train)),
-        &opt.img_out,
-        "train",
-    )
-    .await;
+    #[cfg(feature = "stream")]
+    if let Some(addr) = &opt.stream {
+        stream::serve(addr, device, queue, &mut renderer, &mut pc, &opt)
+            .await
+            .unwrap();
+        return;
+    }
+
+    if let Some(p) = &opt.light_probe {
+        let position = Point3::new(p[0], p[1], p[2]);
+        let probe = render_light_probe(device, queue, &mut renderer, &mut pc, position, &opt).await;
+        std::fs::create_dir_all(&opt.img_out).unwrap();
+        let file = opt.img_out.join("light_probe.exr");
+        println!("saving light probe to '{}'", file.to_string_lossy());
+        image::DynamicImage::ImageRgba32F(probe).save(file).unwrap();
+        return;
+    }
+
+    if opt.axis_views {
+        render_axis_views(device, queue, &mut renderer, &mut pc, &opt).await;
+        println!("done!");
+        return;
+    }
+
+    let mut timings = opt.timings.as_ref().map(|file| {
+        let mut writer = std::io::BufWriter::new(File::create(file).unwrap());
+        timings_header(&mut writer).unwrap();
+        writer
+    });
+
+    if let Some(dir) = &opt.frame_sequence {
+        if scene.num_cameras() == 0 {
+            eprintln!("error: the scene has no cameras to render the frame sequence with");
+            std::process::exit(1);
+        }
+        let frames = FrameSequence::load(dir, pc.num_points() as usize).unwrap();
+        pc.set_frame_sequence(device, queue, frames).unwrap();
+        let cameras: Vec<&SceneCamera> = scene.cameras_iter(None).collect();
+        let num_frames = pc.num_frames();
+        check_start(&opt, num_frames, "the frame sequence");
+        render_views(
+            device,
+            queue,
+            &mut renderer,
+            &mut pc,
+            (0..num_frames).map(|i| cameras[i % cameras.len()]),
+            &opt,
+            "animation",
+            &mut timings,
+            &mut video,
+        )
+        .await;
+        finish_video(video);
+        println!("done!");
+        return;
+    }
 
+    if let Some(num_frames) = opt.interpolate {
+        let keyframes: Vec<SceneCamera> = opt
+            .split
+            .splits()
+            .iter()
+            .flat_map(|split| scene.cameras(Some(*split)))
+            .collect();
+        if keyframes.is_empty() {
+            println!("error: the scene has no cameras to interpolate");
+            return;
+        }
+        check_start(&opt, num_frames as usize, "the camera path");
+        let first = &keyframes[0];
+        let resolution = Vector2::new(first.width, first.height);
+        let path = CameraPath::new(keyframes, opt.loop_path);
+        // the last frame of a looping path would be the first one again
+        let steps = if opt.loop_path || num_frames == 1 {
+            num_frames
+        } else {
+            num_frames - 1
+        };
+        let cameras: Vec<SceneCamera> = (0..num_frames)
+            .map(|i| {
+                SceneCamera::from_perspective(
+                    path.sample(i as f32 / steps as f32),
+                    format!("{i:0>5}"),
+                    i as usize,
+                    resolution,
+                    Split::Test,
+                )
+            })
+            .collect();
+        render_views(
+            device,
+            queue,
+            &mut renderer,
+            &mut pc,
+            cameras.iter(),
+            &opt,
+            "path",
+            &mut timings,
+            &mut video,
+        )
+        .await;
+        finish_video(video);
+        println!("done!");
+        return;
+    }
+
+    // check all splits before rendering the first one
+    for split in opt.split.splits() {
+        let num_cameras = scene.cameras_iter(Some(*split)).count();
+        if num_cameras > 0 {
+            check_start(&opt, num_cameras, &split.to_string());
+        }
+    }
+    for split in opt.split.splits() {
+        let name = match split {
+            Split::Test => "test",
+            Split::Train => "train",
+        };
+        let cameras = scene.cameras_iter(Some(*split));
+        if cameras.clone().next().is_none() {
+            println!("warning: the scene has no {name} cameras");
+            continue;
+        }
+        render_views(
+            device,
+            queue,
+            &mut renderer,
+            &mut pc,
+            cameras,
+            &opt,
+            name,
+            &mut timings,
+            &mut video,
+        )
+        .await;
+    }
+
+    finish_video(video);
     println!("done!");
 }
 #[cfg(target_arch = "wasm32")]
-fn main(){todo!("not implemented")}
+fn main() {
+    todo!("not implemented")
+}
+
+/// resolution of the axis aligned views
+const AXIS_VIEW_SIZE: u32 = 800;
 
-pub async fn download_texture(
-    texture: &wgpu::Texture,
+/// cameras looking at the bounding box center along the six axis directions.
+/// The distance is chosen such that the bounding sphere fits into the view
+fn axis_view_cameras(pc: &PointCloud) -> Vec<(&'static str, PerspectiveCamera)> {
+    let fov = Deg(45.);
+    let center = pc.bbox().center();
+    let distance = pc.bbox().radius() / (fov / 2.).sin();
+    let views = [
+        ("front", Vector3::unit_z(), Vector3::unit_y()),
+        ("back", -Vector3::unit_z(), Vector3::unit_y()),
+        ("left", -Vector3::unit_x(), Vector3::unit_y()),
+        ("right", Vector3::unit_x(), Vector3::unit_y()),
+        ("top", Vector3::unit_y(), -Vector3::unit_z()),
+        ("bottom", -Vector3::unit_y(), Vector3::unit_z()),
+    ];
+    return views
+        .into_iter()
+        .map(|(name, offset, up)| {
+            let camera = PerspectiveCamera::new(
+                center + offset * distance,
+                Quaternion::look_at(-offset, up),
+                PerspectiveProjection::new(
+                    Vector2::new(AXIS_VIEW_SIZE, AXIS_VIEW_SIZE),
+                    Vector2::new(fov, fov),
+                    0.01,
+                    1000.,
+                ),
+            );
+            (name, camera)
+        })
+        .collect();
+}
+
+/// renders the axis views as separate images or packed into an atlas if --atlas-columns is set
+async fn render_axis_views(
     device: &wgpu::Device,
     queue: &wgpu::Queue,
-) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-    let texture_format = texture.format();
+    renderer: &mut GaussianRenderer,
+    pc: &mut PointCloud,
+    opt: &Opt,
+) {
+    let img_out = opt.img_out.join("axis_views");
+    println!("saving axis views to '{}'", img_out.to_string_lossy());
+    std::fs::create_dir_all(&img_out).unwrap();
 
-    let texel_size: u32 = texture_format.block_copy_size(None).unwrap();
-    let fb_size = texture.size();
-    let align: u32 = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT - 1;
-    let bytes_per_row = (texel_size * fb_size.width) + align & !align;
+    let mut frames = Vec::new();
+    for (i, (name, camera)) in axis_view_cameras(pc).into_iter().enumerate() {
+        let resolution = Vector2::new(AXIS_VIEW_SIZE, AXIS_VIEW_SIZE);
+        let (target, _) = render_camera(
+            device,
+            queue,
+            renderer,
+            pc,
+            camera,
+            resolution,
+            &CameraOverrides::default(),
+            opt,
+            &mut None,
+        )
+        .unwrap();
+        let img = opt.tonemap().apply(downsample(
+            composite_background(
+                download_texture(&target, device, queue, opt.max_download_size())
+                    .await
+                    .unwrap(),
+                renderer,
+            ),
+            opt.ssaa,
+        ));
+        if opt.atlas_columns.is_some() {
+            frames.push((
+                i,
+                name.to_string(),
+                to_rgba8(&with_alpha_mode(&img, opt.alpha)),
+            ));
+        } else {
+            let file = img_out.join(format!("{name}.{}", opt.format.extension()));
+            save_image(
+                &img,
+                &file,
+                opt.format,
+                opt.quality,
+                opt.grayscale,
+                opt.alpha,
+                opt.bit_depth,
+            )
+            .unwrap();
+        }
+    }
+    if let Some(columns) = opt.atlas_columns {
+        write_atlas(&img_out, frames, columns).unwrap();
+    }
+}
 
-    let output_buffer_size = (bytes_per_row * fb_size.height) as wgpu::BufferAddress;
+/// Renders the six faces of a cube map around `position` and resamples them
+/// into an equirectangular panorama with linear colors.
+/// Uses the same mapping as the environment map of the viewer.
+async fn render_light_probe(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    renderer: &mut GaussianRenderer,
+    pc: &mut PointCloud,
+    position: Point3<f32>,
+    opt: &Opt,
+) -> Rgba32FImage {
+    let width = opt.light_probe_width.max(2);
+    let height = width / 2;
+    let face_size = (width / 4).max(1);
+    let faces = [
+        (Vector3::unit_x(), Vector3::unit_y()),
+        (-Vector3::unit_x(), Vector3::unit_y()),
+        (Vector3::unit_y(), Vector3::unit_z()),
+        (-Vector3::unit_y(), Vector3::unit_z()),
+        (Vector3::unit_z(), Vector3::unit_y()),
+        (-Vector3::unit_z(), Vector3::unit_y()),
+    ];
+    let mut rendered: Vec<(Matrix4<f32>, Rgba32FImage)> = Vec::with_capacity(faces.len());
+    for (dir, up) in faces {
+        let camera = PerspectiveCamera::new(
+            position,
+            Quaternion::look_at(dir, up),
+            PerspectiveProjection::new(
+                Vector2::new(face_size, face_size),
+                Vector2::new(Deg(90.), Deg(90.)),
+                0.01,
+                1000.,
+            ),
+        );
+        let (target, _) = render_camera(
+            device,
+            queue,
+            renderer,
+            pc,
+            camera,
+            Vector2::new(face_size, face_size),
+            &CameraOverrides::default(),
+            opt,
+            &mut None,
+        )
+        .unwrap();
+        let img = downsample(
+            composite_background(
+                download_texture(&target, device, queue, opt.max_download_size())
+                    .await
+                    .unwrap(),
+                renderer,
+            ),
+            opt.ssaa,
+        );
+        rendered.push((camera.proj_matrix() * camera.view_matrix(), img));
+    }
 
-    let output_buffer_desc = wgpu::BufferDescriptor {
-        size: output_buffer_size,
-        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-        label: Some("texture download buffer"),
-        mapped_at_creation: false,
-    };
-    let staging_buffer = device.create_buffer(&output_buffer_desc);
+    return Rgba32FImage::from_fn(width, height, |x, y| {
+        let phi = ((x as f32 + 0.5) / width as f32 - 0.5) * std::f32::consts::TAU;
+        let lat = (0.5 - (y as f32 + 0.5) / height as f32) * std::f32::consts::PI;
+        let dir = Vector3::new(lat.cos() * phi.cos(), lat.sin(), lat.cos() * phi.sin());
+        for (view_proj, img) in rendered.iter() {
+            // directions are not affected by the camera position
+            let p = view_proj * dir.extend(0.);
+            if p.w <= 0. || p.x.abs() > p.w || p.y.abs() > p.w {
+                continue;
+            }
+            // the renderer flips the y axis of the viewport
+            let u = (p.x / p.w + 1.) / 2. * img.width() as f32 - 0.5;
+            let v = (p.y / p.w + 1.) / 2. * img.height() as f32 - 0.5;
+            let [r, g, b, a] = sample_bilinear(img, u, v);
+            return Rgba([
+                srgb_to_linear_unclamped(r),
+                srgb_to_linear_unclamped(g),
+                srgb_to_linear_unclamped(b),
+                a,
+            ]);
+        }
+        return Rgba([0.; 4]);
+    });
+}
+
+fn sample_bilinear(img: &Rgba32FImage, u: f32, v: f32) -> [f32; 4] {
+    let max_x = img.width() as f32 - 1.;
+    let max_y = img.height() as f32 - 1.;
+    let (u, v) = (u.clamp(0., max_x), v.clamp(0., max_y));
+    let (x0, y0) = (u.floor(), v.floor());
+    let (x1, y1) = ((x0 + 1.).min(max_x), (y0 + 1.).min(max_y));
+    let (fx, fy) = (u - x0, v - y0);
+    let p = |x: f32, y: f32| img.get_pixel(x as u32, y as u32).0;
+    let (p00, p10, p01, p11) = (p(x0, y0), p(x1, y0), p(x0, y1), p(x1, y1));
+    let mut result = [0.; 4];
+    for i in 0..4 {
+        let top = p00[i] * (1. - fx) + p10[i] * fx;
+        let bottom = p01[i] * (1. - fx) + p11[i] * fx;
+        result[i] = top * (1. - fy) + bottom * fy;
+    }
+    return result;
+}
+
+/// like srgb_to_linear but keeps values above one (HDR)
+fn srgb_to_linear_unclamped(v: f32) -> f32 {
+    let v = v.max(0.);
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
 
-    let mut encoder: wgpu::CommandEncoder =
-        device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("download frame buffer encoder"),
+/// blends the renderer background under the image if it could not be used as clear color
+/// (see [`GaussianRenderer::clear_color`])
+fn composite_background(mut img: Rgba32FImage, renderer: &GaussianRenderer) -> Rgba32FImage {
+    if !renderer.composite_background() {
+        return img;
+    }
+    let bg = renderer.background();
+    let bg = [bg.r * bg.a, bg.g * bg.a, bg.b * bg.a, bg.a].map(|v| v as f32);
+    for p in img.pixels_mut() {
+        let a = p.0[3];
+        for c in 0..4 {
+            p.0[c] += (1. - a) * bg[c];
+        }
+    }
+    return img;
+}
+
+/// box filters the image by the given factor.
+/// The rendered colors are sRGB encoded (like the training images),
+/// so they are averaged in linear space to not darken edges.
+fn downsample(img: Rgba32FImage, factor: u32) -> Rgba32FImage {
+    if factor == 1 {
+        return img;
+    }
+    let weight = 1. / (factor * factor) as f32;
+    return Rgba32FImage::from_fn(img.width() / factor, img.height() / factor, |x, y| {
+        let mut sum = [0.; 4];
+        for dy in 0..factor {
+            for dx in 0..factor {
+                let [r, g, b, a] = img.get_pixel(x * factor + dx, y * factor + dy).0;
+                sum[0] += srgb_to_linear(r);
+                sum[1] += srgb_to_linear(g);
+                sum[2] += srgb_to_linear(b);
+                sum[3] += a;
+            }
+        }
+        Rgba([
+            linear_to_srgb(sum[0] * weight),
+            linear_to_srgb(sum[1] * weight),
+            linear_to_srgb(sum[2] * weight),
+            sum[3] * weight,
+        ])
+    });
+}
+
+fn srgb_to_linear(v: f32) -> f32 {
+    let v = v.clamp(0., 1.);
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// maps the high dynamic range of the rendered colors to the displayable range
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tonemap {
+    None,
+    /// encodes the linear colors with the power 1/gamma instead of the sRGB curve
+    Gamma(f32),
+    /// `x / (1 + x)` on the linear colors
+    Reinhard,
+    /// ACES filmic curve (fit by Narkowicz) on the linear colors
+    Aces,
+}
+
+impl Tonemap {
+    /// maps the premultiplied sRGB encoded colors of the image (alpha is kept)
+    fn apply(&self, mut img: Rgba32FImage) -> Rgba32FImage {
+        if *self == Tonemap::None {
+            return img;
+        }
+        for p in img.pixels_mut() {
+            let a = p.0[3];
+            if a <= 0. {
+                continue;
+            }
+            for c in 0..3 {
+                let v = srgb_to_linear_unclamped(p.0[c] / a);
+                let v = match self {
+                    Tonemap::None => unreachable!(),
+                    Tonemap::Gamma(gamma) => v.powf(1. / gamma),
+                    Tonemap::Reinhard => linear_to_srgb(v / (1. + v)),
+                    Tonemap::Aces => {
+                        linear_to_srgb((v * (2.51 * v + 0.03)) / (v * (2.43 * v + 0.59) + 0.14))
+                    }
+                };
+                p.0[c] = v * a;
+            }
+        }
+        return img;
+    }
+}
+
+/// converts the premultiplied sRGB colors to premultiplied linear colors (alpha is kept)
+fn to_linear(img: &Rgba32FImage) -> Rgba32FImage {
+    return Rgba32FImage::from_fn(img.width(), img.height(), |x, y| {
+        let [r, g, b, a] = img.get_pixel(x, y).0;
+        if a <= 0. {
+            return Rgba([0., 0., 0., 0.]);
+        }
+        let linear = |c: f32| srgb_to_linear_unclamped(c / a) * a;
+        Rgba([linear(r), linear(g), linear(b), a])
+    });
+}
+
+fn to_rgba8(img: &Rgba32FImage) -> RgbaImage {
+    return RgbaImage::from_fn(img.width(), img.height(), |x, y| {
+        Rgba(
+            img.get_pixel(x, y)
+                .0
+                .map(|v| (v.clamp(0., 1.) * 255.) as u8),
+        )
+    });
+}
+
+/// clamps the value to [0,1] and scales it to 16 bits (rounded)
+fn to_u16(v: f32) -> u16 {
+    return (v.clamp(0., 1.) * 65535.).round() as u16;
+}
+
+fn to_rgba16(img: &Rgba32FImage) -> ImageBuffer<Rgba<u16>, Vec<u16>> {
+    return ImageBuffer::from_fn(img.width(), img.height(), |x, y| {
+        Rgba(img.get_pixel(x, y).0.map(to_u16))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// device on the default adapter, tests are skipped if there is none
+    fn test_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        return pollster::block_on(async {
+            let instance = wgpu::Instance::default();
+            let adapter = instance.request_adapter(&Default::default()).await?;
+            let descriptor = wgpu::DeviceDescriptor {
+                label: Some("test device"),
+                required_features: wgpu::Features::empty(),
+                required_limits: adapter.limits(),
+            };
+            return adapter.request_device(&descriptor, None).await.ok();
         });
+    }
 
-    encoder.copy_texture_to_buffer(
-        texture.as_image_copy(),
-        wgpu::ImageCopyBufferBase {
-            buffer: &staging_buffer,
-            layout: wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(bytes_per_row),
-                rows_per_image: Some(fb_size.height),
+    /// ply file with one big white gaussian at the origin and two tiny ones
+    /// that extend the bounding box (the near and far plane are fitted to it)
+    fn big_splat_ply() -> Vec<u8> {
+        use byteorder::{LittleEndian, WriteBytesExt};
+        let mut ply = Vec::new();
+        writeln!(ply, "ply").unwrap();
+        writeln!(ply, "format binary_little_endian 1.0").unwrap();
+        writeln!(ply, "element vertex 3").unwrap();
+        let properties = [
+            "x", "y", "z", "nx", "ny", "nz", "f_dc_0", "f_dc_1", "f_dc_2", "opacity", "scale_0",
+            "scale_1", "scale_2", "rot_0", "rot_1", "rot_2", "rot_3",
+        ];
+        for name in properties {
+            writeln!(ply, "property float {name}").unwrap();
+        }
+        writeln!(ply, "end_header").unwrap();
+        // standard deviations of about 0.37 x 0.22 (rotated by 30 degrees) and 0.007.
+        // The big splat is rotated, the preprocess shader skips footprints whose major axis
+        // is exactly the x axis (including circular ones).
+        for (pos, scale) in [(0., [-1., -1.5, -1.]), (-1., [-5.; 3]), (1., [-5.; 3])] {
+            let values = [
+                pos, pos, pos, 0., 0., 0., 2., 2., 2., 3., scale[0], scale[1], scale[2], 0.966, 0.,
+                0., 0.259,
+            ];
+            for v in values {
+                ply.write_f32::<LittleEndian>(v).unwrap();
+            }
+        }
+        return ply;
+    }
+
+    /// 64x64 camera at (0,0,-4) looking at the origin
+    fn front_camera() -> SceneCamera {
+        let position = Point3::new(0., 0., -4.);
+        let camera = PerspectiveCamera::new(
+            position,
+            Quaternion::look_at(Point3::new(0., 0., 0.) - position, Vector3::unit_y()),
+            PerspectiveProjection::new(
+                Vector2::new(64, 64),
+                Vector2::new(Deg(60.), Deg(60.)),
+                0.1,
+                100.,
+            ),
+        );
+        return SceneCamera::from_perspective(
+            camera,
+            "plain".into(),
+            0,
+            Vector2::new(64, 64),
+            Split::Test,
+        );
+    }
+
+    #[test]
+    fn camera_overrides_only_change_their_camera() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let raw = GenericGaussianPointCloud::load_from_bytes(&big_splat_ply()).unwrap();
+        let mut pc = PointCloud::new(&device, raw).unwrap();
+        let mut renderer = GaussianRenderer::with_sorter(
+            &device,
+            wgpu::TextureFormat::Rgba16Float,
+            0,
+            false,
+            Box::new(CpuSorter::new(&device)),
+        );
+        let plain = front_camera();
+        let overridden = SceneCamera {
+            overrides: CameraOverrides {
+                max_screen_size: Some(2.),
+                scale_modifier: None,
             },
-        },
-        fb_size,
-    );
-    let sub_idx = queue.submit(std::iter::once(encoder.finish()));
-
-    let mut image = {
-        let data: wgpu::BufferView<'_> =
-            download_buffer(device, &staging_buffer, Some(sub_idx)).await;
-
-        ImageBuffer::<Rgba<u8>, _>::from_raw(
-            bytes_per_row / texel_size,
-            fb_size.height,
-            data.to_vec()
-                .chunks(2)
-                .map(|c| (f16::from_le_bytes([c[0], c[1]]).to_f32().clamp(0., 1.) * 255.) as u8)
-                .collect::<Vec<u8>>(),
+            ..plain.clone()
+        };
+
+        let mut render = |opt: &Opt, camera: &SceneCamera| {
+            let (target, settings) = render_frame(
+                &device,
+                &queue,
+                &mut renderer,
+                &mut pc,
+                camera,
+                opt,
+                &mut None,
+            )
+            .unwrap();
+            let img = pollster::block_on(download_texture(&target, &device, &queue, None)).unwrap();
+            return (img, settings);
+        };
+        let opt = Opt::parse_from(["render", "in.ply", "scene.json", "out"]);
+        let (plain_img, plain_settings) = render(&opt, &plain);
+        let (img, settings) = render(&opt, &overridden);
+        assert_eq!(plain_settings.max_splat_size, None);
+        assert_eq!(settings.max_splat_size, Some(2.));
+        // the clamped splat covers fewer pixels
+        let coverage = |img: &Rgba32FImage| img.pixels().filter(|p| p.0[3] > 0.1).count();
+        assert!(coverage(&img) < coverage(&plain_img) / 2);
+
+        // the override does not leak into the next camera
+        let (again, _) = render(&opt, &plain);
+        assert_eq!(again, plain_img);
+
+        // the limit is given in output pixels
+        let opt = Opt::parse_from(["render", "in.ply", "scene.json", "out", "--ssaa", "2"]);
+        let (_, settings) = render(&opt, &overridden);
+        assert_eq!(settings.max_splat_size, Some(4.));
+    }
+
+    #[test]
+    fn supersampling_reduces_aliasing() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let raw = GenericGaussianPointCloud::load_from_bytes(&big_splat_ply()).unwrap();
+        let mut pc = PointCloud::new(&device, raw).unwrap();
+        let mut renderer = GaussianRenderer::with_sorter(
+            &device,
+            wgpu::TextureFormat::Rgba16Float,
+            0,
+            false,
+            Box::new(CpuSorter::new(&device)),
+        );
+        let camera = front_camera();
+        let mut render = |ssaa: u32| {
+            let ssaa = ssaa.to_string();
+            let opt = Opt::parse_from(["render", "in.ply", "scene.json", "out", "--ssaa", &ssaa]);
+            let (target, _) = render_frame(
+                &device,
+                &queue,
+                &mut renderer,
+                &mut pc,
+                &camera,
+                &opt,
+                &mut None,
+            )
+            .unwrap();
+            let img = pollster::block_on(download_texture(&target, &device, &queue, None)).unwrap();
+            return downsample(img, opt.ssaa);
+        };
+        // heavily supersampled image as reference
+        let reference = render(4);
+        let error = |img: &Rgba32FImage| -> f32 {
+            assert_eq!(img.dimensions(), reference.dimensions());
+            return img
+                .pixels()
+                .zip(reference.pixels())
+                .flat_map(|(a, b)| a.0.into_iter().zip(b.0).map(|(a, b)| (a - b).powi(2)))
+                .sum();
+        };
+        let plain = render(1);
+        let supersampled = render(2);
+        assert!(plain.pixels().any(|p| p.0[3] > 0.5));
+        assert!(
+            error(&supersampled) < error(&plain),
+            "2x: {} 1x: {}",
+            error(&supersampled),
+            error(&plain)
+        );
+    }
+
+    #[test]
+    fn empty_pixels_get_the_far_plane_as_depth() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let raw = GenericGaussianPointCloud::load_from_bytes(&big_splat_ply()).unwrap();
+        let mut pc = PointCloud::new(&device, raw).unwrap();
+        let mut renderer = GaussianRenderer::with_sorter(
+            &device,
+            wgpu::TextureFormat::Rgba16Float,
+            0,
+            false,
+            Box::new(CpuSorter::new(&device)),
+        );
+        let opt = Opt::parse_from(["render", "in.ply", "scene.json", "out"]);
+        let (_, settings) = render_frame(
+            &device,
+            &queue,
+            &mut renderer,
+            &mut pc,
+            &front_camera(),
+            &opt,
+            &mut None,
         )
-        .unwrap()
-    };
+        .unwrap();
+        let depth = pollster::block_on(render_depth(
+            &device,
+            &queue,
+            &mut renderer,
+            &pc,
+            settings,
+            &opt,
+        ))
+        .unwrap();
+        let far = settings.camera.projection.zfar;
+        assert_eq!(depth.get_pixel(0, 0).0[0], far);
+        // the big splat is at the origin, 4 units in front of the camera
+        let center = depth.get_pixel(32, 32).0[0];
+        assert!((center - 4.).abs() < 0.1, "{center}");
+        assert!(depth.pixels().all(|p| p.0[0].is_finite()));
+    }
 
-    staging_buffer.unmap();
+    #[test]
+    fn to_u16_scales_and_clamps() {
+        assert_eq!(to_u16(1.), 65535);
+        assert_eq!(to_u16(0.5), 32768);
+        assert_eq!(to_u16(0.), 0);
+        assert_eq!(to_u16(-0.5), 0);
+        assert_eq!(to_u16(2.), 65535);
+    }
 
-    return image::imageops::crop(&mut image, 0, 0, fb_size.width, fb_size.height).to_image();
-}
+    #[test]
+    fn tonemap_maps_linear_colors() {
+        let mid_gray = linear_to_srgb(0.18);
+        // opaque, half transparent (premultiplied) and transparent pixel
+        let img = Rgba32FImage::from_fn(3, 1, |x, _| match x {
+            0 => Rgba([mid_gray, mid_gray, mid_gray, 1.]),
+            1 => Rgba([mid_gray * 0.5, mid_gray * 0.5, mid_gray * 0.5, 0.5]),
+            _ => Rgba([0.; 4]),
+        });
+        assert_eq!(Tonemap::None.apply(img.clone()), img);
 
-async fn download_buffer<'a>(
-    device: &wgpu::Device,
-    buffer: &'a wgpu::Buffer,
-    wait_idx: Option<wgpu::SubmissionIndex>,
-) -> wgpu::BufferView<'a> {
-    let slice = buffer.slice(..);
-
-    let (tx, rx) = futures_intrusive::channel::shared::oneshot_channel();
-    slice.map_async(wgpu::MapMode::Read, move |result| tx.send(result).unwrap());
-    device.poll(match wait_idx {
-        Some(idx) => wgpu::Maintain::WaitForSubmissionIndex(idx),
-        None => wgpu::Maintain::Wait,
-    });
-    rx.receive().await.unwrap().unwrap();
+        let expected = 0.18f32.powf(1. / 2.2);
+        let gamma = Tonemap::Gamma(2.2).apply(img.clone());
+        assert!((gamma.get_pixel(0, 0).0[0] - expected).abs() < 1e-4);
+        assert!((gamma.get_pixel(1, 0).0[0] - expected * 0.5).abs() < 1e-4);
+        assert_eq!(gamma.get_pixel(1, 0).0[3], 0.5);
+        assert_eq!(gamma.get_pixel(2, 0).0, [0.; 4]);
+
+        let reinhard = Tonemap::Reinhard.apply(img);
+        let expected = linear_to_srgb(0.18 / 1.18);
+        assert!((reinhard.get_pixel(0, 0).0[0] - expected).abs() < 1e-4);
+    }
 
-    let view = slice.get_mapped_range();
-    return view;
+    #[test]
+    fn straight_alpha_divides_colors() {
+        let img = Rgba32FImage::from_fn(2, 1, |x, _| match x {
+            0 => Rgba([0.2, 0.1, 0.4, 0.5]),
+            _ => Rgba([0.; 4]),
+        });
+        assert_eq!(with_alpha_mode(&img, AlphaMode::Premultiplied), img);
+
+        let straight = with_alpha_mode(&img, AlphaMode::Straight);
+        assert_eq!(straight.get_pixel(0, 0).0, [0.4, 0.2, 0.8, 0.5]);
+        // transparent pixels are not divided by zero
+        assert_eq!(straight.get_pixel(1, 0).0, [0.; 4]);
+    }
 }
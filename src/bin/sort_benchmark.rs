@@ -0,0 +1,81 @@
+use clap::Parser;
+use web_splats::{gpu_rs::GPURSSorter, WGPUContext};
+
+#[derive(Debug, Parser)]
+#[command(author, version)]
+#[command(
+    about = "Compares the throughput of the GPU radix sort for all working subgroup sizes",
+    long_about = None
+)]
+struct Opt {
+    /// smallest number of keys (rounded up to a power of two)
+    #[arg(long, default_value_t = 1 << 10)]
+    min_keys: usize,
+
+    /// largest number of keys (rounded up to a power of two)
+    #[arg(long, default_value_t = 1 << 24)]
+    max_keys: usize,
+
+    /// sorts per measurement, the fastest one is reported
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    runs: u32,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[pollster::main]
+async fn main() {
+    env_logger::init();
+    let opt = Opt::parse();
+
+    let wgpu_context = WGPUContext::new_instance().await;
+    let device = &wgpu_context.device;
+    let queue = &wgpu_context.queue;
+    println!("using adapter '{}'", wgpu_context.adapter.get_info().name);
+    if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+        eprintln!("the adapter does not support timestamp queries");
+        std::process::exit(1);
+    }
+
+    // every power of four between min and max keys
+    let mut key_counts = vec![opt.min_keys.max(1).next_power_of_two()];
+    while *key_counts.last().unwrap() < opt.max_keys {
+        key_counts.push(key_counts.last().unwrap() * 4);
+    }
+
+    let selected = GPURSSorter::new(device, queue)
+        .await
+        .map(|sorter| sorter.subgroup_size())
+        .ok();
+    let timings =
+        GPURSSorter::benchmark_subgroup_sizes(device, queue, &key_counts, opt.runs as usize).await;
+    if timings.is_empty() {
+        eprintln!("the GPU sort does not work with any subgroup size");
+        std::process::exit(1);
+    }
+
+    println!(
+        "{:>10} {:>10} {:>12} {:>14}",
+        "subgroup", "keys", "time [ms]", "Mkeys/s"
+    );
+    for t in timings {
+        let marker = if Some(t.subgroup_size) == selected {
+            "*"
+        } else {
+            ""
+        };
+        println!(
+            "{:>10} {:>10} {:>12.3} {:>14.1}",
+            format!("{}{}", marker, t.subgroup_size),
+            t.keys,
+            t.duration.as_secs_f64() * 1e3,
+            t.keys_per_second() / 1e6
+        );
+    }
+    if let Some(size) = selected {
+        println!("* subgroup size selected by GPURSSorter::new ({size})");
+    }
+}
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    todo!("not implemented")
+}
@@ -9,7 +9,7 @@ use std::{
     time::Duration,
 };
 use web_splats::{
-    io, smoothstep, Animation, GaussianRenderer, PointCloud, Scene, SceneCamera, SplattingArgs, TrackingShot, WGPUContext
+    io, smoothstep, Animation, GaussianRenderer, PointCloud, RenderMode, Scene, SceneCamera, SplattingArgs, TrackingShot, WGPUContext
 };
 
 #[derive(Debug, Parser)]
@@ -127,6 +127,12 @@ async fn render_tracking_shot(
                 walltime: state_time,
                 scene_center: None,
                 scene_extend: None,
+                clip_sphere: None,
+                render_mode: RenderMode::Color,
+                preview_quality: None,
+                max_splat_size: None,
+                max_layers_per_pixel: None,
+                region: None,
             },
             &mut None,
         );
@@ -4,8 +4,18 @@ use std::{fmt::Debug, fs::File, path::PathBuf};
 #[allow(unused_imports)]
 use web_splats::{open_window, RenderConfig};
 
+/// Interactive viewer for gaussian splatting point clouds.
+///
+/// Controls:
+/// - left mouse: orbit around the center of the point cloud (alt: roll)
+/// - right mouse: pan
+/// - scroll: zoom
+/// - W/A/S/D, Space/Shift, Q/E: move and roll the camera
+/// - 0-9, PageUp/PageDown: jump to or step through the scene cameras
+/// - N: nearest scene camera, R: random scene camera
+/// - T: tracking shot along the scene cameras, U: toggle the ui, C: save the current view
 #[derive(Debug, Parser)]
-#[command(author, version, about)]
+#[command(author, version, about, verbatim_doc_comment)]
 struct Opt {
     /// Input file
     input: PathBuf,
@@ -0,0 +1,59 @@
+use clap::Parser;
+use std::{fs::File, path::PathBuf};
+use web_splats::{io::GenericGaussianPointCloud, Scene, VisibilityMatrix};
+
+#[derive(Debug, Parser)]
+#[command(author, version)]
+#[command(
+    about = "Exports a sparse camera x gaussian visibility matrix as npz file",
+    long_about = None
+)]
+struct Opt {
+    /// input file
+    input: PathBuf,
+
+    /// scene json file
+    scene: PathBuf,
+
+    /// output npz file
+    out: PathBuf,
+
+    /// gaussians with a lower opacity are considered invisible
+    #[arg(long, default_value_t = 1. / 255.)]
+    min_opacity: f32,
+
+    /// additional margin in pixels around the image for the frustum culling
+    /// (same as the cull margin of the renderer)
+    #[arg(long, default_value_t = 0.)]
+    cull_margin: f32,
+
+    /// only test every n-th gaussian (reduces memory and run time for large scenes)
+    #[arg(long, default_value_t = 1)]
+    stride: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let opt = Opt::parse();
+
+    println!("reading scene file '{}'", opt.scene.to_string_lossy());
+    let scene = Scene::from_json(File::open(&opt.scene)?)?;
+
+    println!("reading point cloud file '{}'", opt.input.to_string_lossy());
+    let pc = GenericGaussianPointCloud::load(File::open(&opt.input)?)?;
+
+    let cameras = scene.cameras(None);
+    let matrix =
+        VisibilityMatrix::compute(&pc, &cameras, opt.min_opacity, opt.cull_margin, opt.stride)?;
+    let counts = matrix.counts();
+    println!(
+        "{} cameras see on average {:.0} of {} gaussians",
+        matrix.num_cameras(),
+        counts.iter().sum::<usize>() as f32 / counts.len().max(1) as f32,
+        matrix.num_points()
+    );
+
+    matrix.save_npz(&opt.out)?;
+    println!("saved visibility matrix to '{}'", opt.out.to_string_lossy());
+    return Ok(());
+}
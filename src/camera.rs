@@ -67,6 +67,7 @@ impl Default for PerspectiveCamera {
                 znear: 0.1,
                 zfar: 100.,
                 fov2view_ratio: 1.,
+                kind: Projection::Perspective,
             },
         }
     }
@@ -82,6 +83,16 @@ impl Camera for PerspectiveCamera {
     }
 }
 
+/// how the camera space is projected onto the image plane
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    /// parallel projection with the given view height in world units.
+    /// The width follows from the ratio of the field of views, so resizing works as for perspective
+    Orthographic { height: f32 },
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct PerspectiveProjection {
     pub fovx: Rad<f32>,
@@ -91,6 +102,7 @@ pub struct PerspectiveProjection {
     /// fov ratio to viewport ratio
     /// needed for camera viewport resize
     pub(crate) fov2view_ratio: f32,
+    pub kind: Projection,
 }
 
 impl Hash for PerspectiveProjection {
@@ -100,6 +112,9 @@ impl Hash for PerspectiveProjection {
         self.znear.to_bits().hash(state);
         self.zfar.to_bits().hash(state);
         self.fov2view_ratio.to_bits().hash(state);
+        if let Projection::Orthographic { height } = self.kind {
+            height.to_bits().hash(state);
+        }
     }
 }
 
@@ -127,11 +142,22 @@ impl PerspectiveProjection {
             znear,
             zfar,
             fov2view_ratio: vr / fr,
+            kind: Projection::Perspective,
         }
     }
 
     pub fn projection_matrix(&self) -> Matrix4<f32> {
-        build_proj(self.znear, self.zfar, self.fovx, self.fovy)
+        match self.kind {
+            Projection::Perspective => build_proj(self.znear, self.zfar, self.fovx, self.fovy),
+            Projection::Orthographic { height } => {
+                build_ortho(self.znear, self.zfar, self.ortho_width(height), height)
+            }
+        }
+    }
+
+    /// width of the orthographic view with the given height
+    fn ortho_width(&self, height: f32) -> f32 {
+        height * (self.fovx / 2.).tan() / (self.fovy / 2.).tan()
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
@@ -143,8 +169,13 @@ impl PerspectiveProjection {
         }
     }
 
+    /// focal length in pixels.
+    /// For orthographic projections this is the number of pixels per world unit
     pub(crate) fn focal(&self, viewport: Vector2<u32>) -> Vector2<f32> {
         let viewport: Vector2<f32> = viewport.cast().unwrap();
+        if let Projection::Orthographic { height } = self.kind {
+            return Vector2::new(viewport.x / self.ortho_width(height), viewport.y / height);
+        }
         return Vector2::new(
             fov2focal(self.fovx, viewport.x),
             fov2focal(self.fovy, viewport.y),
@@ -158,6 +189,16 @@ impl PerspectiveProjection {
             znear: self.znear * (1. - amount) + other.znear * amount,
             zfar: self.zfar * (1. - amount) + other.zfar * amount,
             fov2view_ratio: self.fov2view_ratio * (1. - amount) + other.fov2view_ratio * amount,
+            kind: match (self.kind, other.kind) {
+                (
+                    Projection::Orthographic { height: a },
+                    Projection::Orthographic { height: b },
+                ) => Projection::Orthographic {
+                    height: a * (1. - amount) + b * amount,
+                },
+                _ if amount < 0.5 => self.kind,
+                _ => other.kind,
+            },
         }
     }
 }
@@ -233,6 +274,17 @@ pub fn build_proj(znear: f32, zfar: f32, fov_x: Rad<f32>, fov_y: Rad<f32>) -> Ma
     return p.transpose();
 }
 
+/// orthographic projection with the same conventions as [`build_proj`] (depth mapped to [0,1])
+pub fn build_ortho(znear: f32, zfar: f32, width: f32, height: f32) -> Matrix4<f32> {
+    let mut p = Matrix4::zero();
+    p[0][0] = 2.0 / width;
+    p[1][1] = 2.0 / height;
+    p[2][2] = 1. / (zfar - znear);
+    p[2][3] = -znear / (zfar - znear);
+    p[3][3] = 1.;
+    return p.transpose();
+}
+
 pub fn focal2fov(focal: f32, pixels: f32) -> Rad<f32> {
     return Rad(2. * (pixels / (2. * focal)).atan());
 }
@@ -0,0 +1,148 @@
+use image::{Rgb, RgbImage};
+
+/// colormaps for visualizing single channel data (depth, counts, ...)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Colormap {
+    /// rainbow like colormap, see https://research.google/blog/turbo-an-improved-rainbow-colormap-for-visualization/
+    #[default]
+    Turbo,
+    /// perceptually uniform colormap from matplotlib
+    Viridis,
+    Gray,
+}
+
+impl Colormap {
+    /// maps a value in [0,1] (clamped) to a color.
+    /// Uses the same polynomial approximations as the preprocess shader.
+    pub fn map(&self, value: f32) -> [f32; 3] {
+        let t = value.clamp(0., 1.);
+        match self {
+            Colormap::Turbo => {
+                let r = [
+                    0.13572138,
+                    4.61539260,
+                    -42.66032258,
+                    132.13108234,
+                    -152.94239396,
+                    59.28637943,
+                ];
+                let g = [
+                    0.09140261,
+                    2.19418839,
+                    4.84296658,
+                    -14.18503333,
+                    4.27729857,
+                    2.82956604,
+                ];
+                let b = [
+                    0.10667330,
+                    12.64194608,
+                    -60.58204836,
+                    110.36276771,
+                    -89.90310912,
+                    27.34824973,
+                ];
+                [polynomial(&r, t), polynomial(&g, t), polynomial(&b, t)]
+            }
+            Colormap::Viridis => {
+                let r = [
+                    0.2777273272234177,
+                    0.1050930431085774,
+                    -0.3308618287255563,
+                    -4.634230498983486,
+                    6.228269936347081,
+                    4.776384997670288,
+                    -5.435455855934631,
+                ];
+                let g = [
+                    0.005407344544966578,
+                    1.404613529898575,
+                    0.214847559468213,
+                    -5.799100973351585,
+                    14.17993336680509,
+                    -13.74514537774601,
+                    4.645852612178535,
+                ];
+                let b = [
+                    0.3340998053353061,
+                    1.384590162594685,
+                    0.09509516302823659,
+                    -19.33244095627987,
+                    56.69055260068105,
+                    -65.35303263337234,
+                    26.3124352495832,
+                ];
+                [polynomial(&r, t), polynomial(&g, t), polynomial(&b, t)]
+            }
+            Colormap::Gray => [t, t, t],
+        }
+        .map(|c| c.clamp(0., 1.))
+    }
+
+    /// index used by the shaders
+    pub(crate) fn shader_index(&self) -> u32 {
+        match self {
+            Colormap::Turbo => 0,
+            Colormap::Viridis => 1,
+            Colormap::Gray => 2,
+        }
+    }
+}
+
+/// evaluates the polynomial with the given coefficients (lowest degree first)
+fn polynomial(coefs: &[f32], t: f32) -> f32 {
+    coefs.iter().rev().fold(0., |acc, c| acc * t + c)
+}
+
+/// colorizes a single channel image with values in [0,1].
+/// NaN values (e.g. pixels without data) are black.
+pub fn apply_colormap(
+    values: &[f32],
+    width: u32,
+    height: u32,
+    colormap: Colormap,
+) -> anyhow::Result<RgbImage> {
+    if values.len() != (width * height) as usize {
+        return Err(anyhow::anyhow!(
+            "expected {} values for a {}x{} image but got {}",
+            width * height,
+            width,
+            height,
+            values.len()
+        ));
+    }
+    return Ok(RgbImage::from_fn(width, height, |x, y| {
+        let v = values[(y * width + x) as usize];
+        if v.is_nan() {
+            return Rgb([0, 0, 0]);
+        }
+        Rgb(colormap.map(v).map(|c| (c * 255.).round() as u8))
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramp_ends_map_to_end_colors() {
+        let ramp = [0., 0.5, 1.];
+        let gray = apply_colormap(&ramp, 3, 1, Colormap::Gray).unwrap();
+        assert_eq!(gray.get_pixel(0, 0).0, [0, 0, 0]);
+        assert_eq!(gray.get_pixel(1, 0).0, [128, 128, 128]);
+        assert_eq!(gray.get_pixel(2, 0).0, [255, 255, 255]);
+
+        // end colors of matplotlib's viridis (the polynomial fit is off by a few values)
+        let viridis = apply_colormap(&ramp, 3, 1, Colormap::Viridis).unwrap();
+        let close = |a: [u8; 3], b: [u8; 3]| a.iter().zip(b).all(|(a, b)| a.abs_diff(b) <= 4);
+        assert!(close(viridis.get_pixel(0, 0).0, [68, 1, 84]));
+        assert!(close(viridis.get_pixel(2, 0).0, [253, 231, 37]));
+    }
+
+    #[test]
+    fn nan_is_black_and_sizes_are_checked() {
+        let img = apply_colormap(&[f32::NAN], 1, 1, Colormap::Turbo).unwrap();
+        assert_eq!(img.get_pixel(0, 0).0, [0, 0, 0]);
+        assert!(apply_colormap(&[0., 1.], 3, 1, Colormap::Turbo).is_err());
+    }
+}
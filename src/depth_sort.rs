@@ -0,0 +1,350 @@
+use crate::gpu_rs::{GPURSSorter, PointCloudSortStuff, SortBuffers};
+
+/// Sorts the depth keys and splat indices (payload) written by the preprocess shader
+/// in ascending key order.
+/// Implemented by the [`GPURSSorter`] and the [`CpuSorter`] fallback.
+pub trait DepthSorter {
+    /// size of a key in bytes
+    fn key_bytes(&self) -> usize;
+
+    /// number of keys per workgroup of the sort dispatch counted by the preprocess shader
+    fn keys_per_workgroup(&self) -> usize;
+
+    /// sizes in bytes of the buffers created for sorting `keysize` keys
+    fn sort_buffer_sizes(&self, keysize: usize) -> Vec<(&'static str, u64)>;
+
+    /// creates buffers for sorting `keysize` keys
+    fn create_sort_buffers(&self, device: &wgpu::Device, keysize: usize) -> SortBuffers;
+
+    /// prepares the buffers for sorting `keysize` keys.
+    /// Returns true if the buffers had to be reallocated (the bind groups changed in this case).
+    fn resize_sort_buffers(
+        &self,
+        buffers: &mut SortBuffers,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        keysize: usize,
+    ) -> bool;
+
+    /// sorts the keys written by the commands recorded in `encoder`
+    fn sort_depths(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        sort_stuff: &PointCloudSortStuff,
+    );
+
+    /// the gpu sorter (used for other compute tasks like the luminance histogram)
+    fn gpu_sorter(&self) -> Option<&GPURSSorter> {
+        None
+    }
+}
+
+impl DepthSorter for GPURSSorter {
+    fn key_bytes(&self) -> usize {
+        GPURSSorter::key_bytes(self)
+    }
+
+    fn keys_per_workgroup(&self) -> usize {
+        GPURSSorter::keys_per_workgroup(self)
+    }
+
+    fn sort_buffer_sizes(&self, keysize: usize) -> Vec<(&'static str, u64)> {
+        GPURSSorter::sort_buffer_sizes(self, keysize)
+    }
+
+    fn create_sort_buffers(&self, device: &wgpu::Device, keysize: usize) -> SortBuffers {
+        SortBuffers::new(self, device, keysize)
+    }
+
+    fn resize_sort_buffers(
+        &self,
+        buffers: &mut SortBuffers,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        keysize: usize,
+    ) -> bool {
+        buffers.resize(self, device, queue, keysize)
+    }
+
+    fn sort_depths(
+        &self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        sort_stuff: &PointCloudSortStuff,
+    ) {
+        self.record_sort_indirect(
+            sort_stuff.sorter_bg.as_ref().unwrap(),
+            &sort_stuff.sorter_dis,
+            encoder,
+        );
+    }
+
+    fn gpu_sorter(&self) -> Option<&GPURSSorter> {
+        Some(self)
+    }
+}
+
+/// Fallback for devices on which the [`GPURSSorter`] cannot be created.
+/// The keys are downloaded, sorted on the cpu and uploaded again every frame.
+/// This stalls until the preprocessing is done and is much slower than the gpu sort.
+/// Only available on native targets as it blocks on the buffer download.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct CpuSorter {
+    render_bind_group_layout: wgpu::BindGroupLayout,
+    preprocess_bind_group_layout: wgpu::BindGroupLayout,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CpuSorter {
+    /// the preprocess shader only uses the workgroup size to count the sort dispatch
+    const KEYS_PER_WORKGROUP: usize = 256;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            render_bind_group_layout: GPURSSorter::bind_group_layout_rendering(device),
+            preprocess_bind_group_layout: GPURSSorter::bind_group_layout_preprocess(device),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DepthSorter for CpuSorter {
+    fn key_bytes(&self) -> usize {
+        4
+    }
+
+    fn keys_per_workgroup(&self) -> usize {
+        Self::KEYS_PER_WORKGROUP
+    }
+
+    fn sort_buffer_sizes(&self, keysize: usize) -> Vec<(&'static str, u64)> {
+        let size = (keysize.max(1) * 4) as u64;
+        return vec![("sort keys buffer", size), ("sort payload buffer", size)];
+    }
+
+    fn create_sort_buffers(&self, device: &wgpu::Device, keysize: usize) -> SortBuffers {
+        use wgpu::util::DeviceExt;
+
+        let usage = wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_DST
+            | wgpu::BufferUsages::COPY_SRC;
+        let size = (keysize.max(1) * 4) as u64;
+        let keyval_a = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("cpu sort keys buffer"),
+            size,
+            usage,
+            mapped_at_creation: false,
+        });
+        let payload_a = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("cpu sort payload buffer"),
+            size,
+            usage,
+            mapped_at_creation: false,
+        });
+        // same layout as the GeneralInfo of the gpu sorter (keys_size, padded_size, passes, even_pass, odd_pass)
+        let sorter_uni = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("cpu sort uniform buffer"),
+            contents: bytemuck::cast_slice(&[keysize as u32, keysize as u32, 0, 0, 0]),
+            usage,
+        });
+        let sorter_dis = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("cpu sort dispatch buffer"),
+            contents: bytemuck::cast_slice(&[0u32, 1, 1]),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::INDIRECT,
+        });
+        let sorter_render_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("cpu sort render bind group"),
+            layout: &self.render_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: sorter_uni.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: keyval_a.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: payload_a.as_entire_binding(),
+                },
+            ],
+        });
+        let sorter_bg_pre = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("cpu sort preprocess bind group"),
+            layout: &self.preprocess_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: sorter_uni.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: keyval_a.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: payload_a.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: sorter_dis.as_entire_binding(),
+                },
+            ],
+        });
+        return SortBuffers {
+            padded_size: keysize,
+            sort_stuff: PointCloudSortStuff {
+                num_points: keysize,
+                sorter_uni,
+                sorter_dis,
+                sorter_bg: None,
+                sorter_render_bg,
+                sorter_bg_pre,
+                keyval_a,
+                payload_a,
+            },
+        };
+    }
+
+    fn resize_sort_buffers(
+        &self,
+        buffers: &mut SortBuffers,
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        keysize: usize,
+    ) -> bool {
+        // the number of keys is counted by the preprocess shader, only the capacity matters.
+        // The buffers are shrunk if most of the memory would be unused.
+        if keysize > buffers.padded_size || keysize * 2 < buffers.padded_size {
+            *buffers = self.create_sort_buffers(device, keysize);
+            return true;
+        }
+        buffers.sort_stuff.num_points = keysize;
+        return false;
+    }
+
+    fn sort_depths(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        sort_stuff: &PointCloudSortStuff,
+    ) {
+        use crate::gpu_rs::download_buffer;
+
+        // the keys have to be written before they can be downloaded
+        let recorded = std::mem::replace(
+            encoder,
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("cpu sort encoder"),
+            }),
+        );
+        queue.submit([recorded.finish()]);
+
+        let download = |buffer| pollster::block_on(download_buffer::<u32>(buffer, device, queue));
+        let (info, keys, payload) = match (
+            download(&sort_stuff.sorter_uni),
+            download(&sort_stuff.keyval_a),
+            download(&sort_stuff.payload_a),
+        ) {
+            (Ok(info), Ok(keys), Ok(payload)) => (info, keys, payload),
+            _ => {
+                log::error!("cannot download the keys for sorting");
+                return;
+            }
+        };
+        let n = (info[0] as usize).min(keys.len());
+        if n == 0 {
+            return;
+        }
+        // stable like the radix sort, so both sorters produce the same order
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by(|a, b| keys[*a].cmp(&keys[*b]));
+        let sorted_keys: Vec<u32> = order.iter().map(|i| keys[*i]).collect();
+        let sorted_payload: Vec<u32> = order.iter().map(|i| payload[*i]).collect();
+        queue.write_buffer(&sort_stuff.keyval_a, 0, bytemuck::cast_slice(&sorted_keys));
+        queue.write_buffer(
+            &sort_stuff.payload_a,
+            0,
+            bytemuck::cast_slice(&sorted_payload),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{gpu_rs::download_buffer, test_utils::test_device};
+
+    #[test]
+    fn cpu_sorter_matches_gpu_sorter() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let n = 10000;
+        let mut rng = StdRng::seed_from_u64(5);
+        let keys: Vec<f32> = (0..n).map(|_| rng.gen_range(0.0..100.0)).collect();
+        let payload: Vec<u32> = (0..n as u32).collect();
+
+        let gpu_sorter = pollster::block_on(GPURSSorter::new(&device, &queue)).unwrap();
+        let (gpu_keys, gpu_payload) =
+            pollster::block_on(gpu_sorter.sort(&device, &queue, &keys, &payload)).unwrap();
+
+        let cpu_sorter = CpuSorter::new(&device);
+        let buffers = cpu_sorter.create_sort_buffers(&device, n);
+        let sort_stuff = buffers.sort_stuff();
+        queue.write_buffer(&sort_stuff.keyval_a, 0, bytemuck::cast_slice(&keys));
+        queue.write_buffer(&sort_stuff.payload_a, 0, bytemuck::cast_slice(&payload));
+        let mut encoder = device.create_command_encoder(&Default::default());
+        cpu_sorter.sort_depths(&device, &queue, &mut encoder, sort_stuff);
+        queue.submit([encoder.finish()]);
+        let cpu_keys = pollster::block_on(download_buffer::<f32>(
+            &sort_stuff.keyval_a,
+            &device,
+            &queue,
+        ))
+        .unwrap();
+        let cpu_payload = pollster::block_on(download_buffer::<u32>(
+            &sort_stuff.payload_a,
+            &device,
+            &queue,
+        ))
+        .unwrap();
+
+        assert!(gpu_keys.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(cpu_keys[..n], gpu_keys[..]);
+        assert_eq!(cpu_payload[..n], gpu_payload[..]);
+    }
+
+    #[test]
+    fn sort_buffer_sizes_match_the_buffers() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let n = 12345;
+        let gpu_sorter = pollster::block_on(GPURSSorter::new(&device, &queue)).unwrap();
+        let sorters: [&dyn DepthSorter; 2] = [&gpu_sorter, &CpuSorter::new(&device)];
+        for sorter in sorters {
+            let sizes = sorter.sort_buffer_sizes(n);
+            let buffers = sorter.create_sort_buffers(&device, n);
+            let sort_stuff = buffers.sort_stuff();
+            assert_eq!(sizes[0].1, sort_stuff.keyval_a.size());
+            assert!(sizes.iter().any(|(_, s)| *s == sort_stuff.payload_a.size()));
+            assert!(sizes[0].1 >= (n * sorter.key_bytes()) as u64);
+        }
+        if gpu_sorter.block_rows() == crate::gpu_rs::RS_HISTOGRAM_BLOCK_ROWS {
+            assert_eq!(
+                GPURSSorter::default_sort_buffer_sizes(n),
+                gpu_sorter.sort_buffer_sizes(n)
+            );
+        }
+    }
+}
@@ -3,20 +3,27 @@
     be found here: http://www.codercorner.com/RadixSortRevisited.htm
 
     The gpu radix sort implemented here is a reimplementation of the vulkan radix sort found in the fuchsia repos: https://fuchsia.googlesource.com/fuchsia/+/refs/heads/main/src/graphics/lib/compute/radix_sort/
-    Currently only the sorting for floating point key-value pairs is implemented, as only this is needed for this project
+    Keys are either 32 bit (floats or unsigned integers) or 64 bit unsigned integers (see GPURSSorter::new_u64), payloads are always 32 bit
 
     All shaders can be found in shaders/radix_sort.wgsl
 */
 
 use wgpu::{util::DeviceExt, ComputePassDescriptor};
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::utils::GPUStopwatch;
+
 // IMPORTANT: the following constants have to be synced with the numbers in radix_sort.wgsl
 pub const HISTOGRAM_WG_SIZE: usize = 256;
 const RS_RADIX_LOG2: usize = 8; // 8 bit radices
 const RS_RADIX_SIZE: usize = 1 << RS_RADIX_LOG2; // 256 entries into the radix table
-const RS_KEYVAL_SIZE: usize = 32 / RS_RADIX_LOG2;
+
+// default number of keys per thread, histogram and scatter block rows are always the same (shader assume this!!!)
 pub const RS_HISTOGRAM_BLOCK_ROWS: usize = 15;
-const RS_SCATTER_BLOCK_ROWS: usize = RS_HISTOGRAM_BLOCK_ROWS; // DO NOT CHANGE, shader assume this!!!
+// block rows tried by GPURSSorter::new_tuned (limited by the workgroup memory of the scatter pass)
+const RS_BLOCK_ROWS_CANDIDATES: [usize; 5] = [7, 11, 15, 19, 23];
+// subgroup sizes probed by GPURSSorter::new and compared by GPURSSorter::benchmark_subgroup_sizes
+const RS_SUBGROUP_SIZES: [i32; 4] = [1, 8, 16, 32];
 const PREFIX_WG_SIZE: usize = 1 << 7; // one thread operates on 2 prefixes at the same time
 const SCATTER_WG_SIZE: usize = 1 << 8;
 
@@ -30,142 +37,650 @@ pub struct GPURSSorter {
     scatter_even_p: wgpu::ComputePipeline,
     scatter_odd_p: wgpu::ComputePipeline,
     subgroup_size: usize,
+    block_rows: usize,
+    /// size of a key in bytes (4 or 8)
+    key_bytes: usize,
+}
+
+/// sort time of a subgroup size measured by [`GPURSSorter::benchmark_subgroup_sizes`]
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, Copy)]
+pub struct SortTiming {
+    pub subgroup_size: usize,
+    /// number of sorted keys
+    pub keys: usize,
+    pub duration: std::time::Duration,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SortTiming {
+    pub fn keys_per_second(&self) -> f64 {
+        self.keys as f64 / self.duration.as_secs_f64()
+    }
+}
+
+/// subgroup size and block rows of a sorter (stored in the cache of [`GPURSSorter::new_cached`])
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+struct SorterConfig {
+    subgroup_size: usize,
+    block_rows: usize,
+}
+
+/// reasons why a [`GPURSSorter`] cannot be created on a device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SortError {
+    /// the test sort failed with all subgroup sizes
+    NoValidSubgroupSize { attempted: Vec<u32> },
+    /// the device does not support compute shaders with the workgroup size of the sorter
+    ComputeNotSupported,
+    /// keys and payload passed to [`GPURSSorter::sort`] differ in length
+    LengthMismatch { keys: usize, payload: usize },
+    /// [`GPURSSorter::sort`] or [`GPURSSorter::sort_u64`] was called on a sorter for keys of another size
+    UnsupportedKeySize { key_bytes: usize },
+    /// the sorted values could not be read back (e.g. because the device was lost)
+    DownloadFailed { reason: String },
+}
+
+impl std::fmt::Display for SortError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SortError::NoValidSubgroupSize { attempted } => write!(
+                f,
+                "the GPU sort does not work with any of the subgroup sizes {:?}",
+                attempted
+            ),
+            SortError::ComputeNotSupported => write!(
+                f,
+                "the device does not support compute shaders with {} invocations per workgroup",
+                HISTOGRAM_WG_SIZE
+            ),
+            SortError::LengthMismatch { keys, payload } => write!(
+                f,
+                "cannot sort {} keys with a payload of {} values",
+                keys, payload
+            ),
+            SortError::UnsupportedKeySize { key_bytes } => write!(
+                f,
+                "the keys do not match the sorter for {} bit keys",
+                key_bytes * 8
+            ),
+            SortError::DownloadFailed { reason } => {
+                write!(f, "cannot download the sorted values: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SortError {}
+
+/// errors of [`download_buffer`]
+impl From<anyhow::Error> for SortError {
+    fn from(err: anyhow::Error) -> Self {
+        SortError::DownloadFailed {
+            reason: format!("{:#}", err),
+        }
+    }
+}
+
+/// number of keys the key buffers hold, padded to whole histogram blocks plus one spare block
+fn padded_keysize(keysize: usize, block_rows: usize) -> usize {
+    let keys_per_workgroup = HISTOGRAM_WG_SIZE * block_rows;
+    return ((keysize + keys_per_workgroup) / keys_per_workgroup + 1) * keys_per_workgroup;
+}
+
+/// size of the internal buffer holding one histogram per pass and the scatter partitions
+fn internal_mem_size(keysize: usize, key_bytes: usize, block_rows: usize) -> usize {
+    let scatter_blocks_ru =
+        (keysize + HISTOGRAM_WG_SIZE * block_rows - 1) / (HISTOGRAM_WG_SIZE * block_rows);
+    let histo_size = RS_RADIX_SIZE * std::mem::size_of::<u32>();
+    let passes = key_bytes * 8 / RS_RADIX_LOG2;
+    return (passes + scatter_blocks_ru - 1 + 1) * histo_size; // +1 safety
+}
+
+fn sort_buffer_sizes(
+    keysize: usize,
+    key_bytes: usize,
+    block_rows: usize,
+) -> Vec<(&'static str, u64)> {
+    let keyval_size = (padded_keysize(keysize, block_rows) * key_bytes) as u64;
+    let payload_size = (keysize * 4).max(1) as u64;
+    return vec![
+        ("sort keys buffer a", keyval_size),
+        ("sort keys buffer b", keyval_size),
+        ("sort payload buffer a", payload_size),
+        ("sort payload buffer b", payload_size),
+        (
+            "sort histogram buffer",
+            internal_mem_size(keysize, key_bytes, block_rows) as u64,
+        ),
+    ];
 }
 
 pub struct PointCloudSortStuff {
     pub num_points: usize,
     pub(crate) sorter_uni: wgpu::Buffer, // uniform buffer information
     pub(crate) sorter_dis: wgpu::Buffer, // dispatch buffer
-    pub(crate) sorter_bg: wgpu::BindGroup, // sorter bind group
-    pub(crate) sorter_render_bg: wgpu::BindGroup, // bind group only with the sorted indices for rendering
+    pub(crate) sorter_bg: Option<wgpu::BindGroup>, // sorter bind group (None for the cpu sorter)
+    pub(crate) sorter_render_bg: wgpu::BindGroup, // bind group only with the sorted keys and indices for rendering
     pub(crate) sorter_bg_pre: wgpu::BindGroup, // bind group for the preprocess (is the sorter_dis and sorter_bg merged as we only have a limited amount of bgs for the preprocessing)
+    pub(crate) keyval_a: wgpu::Buffer,         // sorted keys
+    pub(crate) payload_a: wgpu::Buffer,        // sorted payload
+}
+
+/// Sort buffers that are kept alive across frames.
+/// [`SortBuffers::resize`] only reallocates when the padded key count grows,
+/// otherwise the size information in the uniform buffer is updated in place.
+pub struct SortBuffers {
+    pub(crate) padded_size: usize, // number of keys the keyval buffers can hold
+    pub(crate) sort_stuff: PointCloudSortStuff,
+}
+
+impl SortBuffers {
+    pub fn new(sorter: &GPURSSorter, device: &wgpu::Device, keysize: usize) -> Self {
+        let (sorter_b_a, sorter_b_b, sorter_p_a, sorter_p_b) =
+            sorter.create_keyval_buffers(device, keysize, 4);
+        let sorter_int = sorter.create_internal_mem_buffer(device, keysize);
+        let (sorter_uni, sorter_dis, sorter_bg) = sorter.create_bind_group(
+            device,
+            keysize,
+            &sorter_int,
+            &sorter_b_a,
+            &sorter_b_b,
+            &sorter_p_a,
+            &sorter_p_b,
+        );
+        let sorter_render_bg =
+            sorter.create_bind_group_render(device, &sorter_uni, &sorter_b_a, &sorter_p_a);
+        let sorter_bg_pre = sorter.create_bind_group_preprocess(
+            device,
+            &sorter_uni,
+            &sorter_dis,
+            &sorter_b_a,
+            &sorter_p_a,
+        );
+
+        let (_, _, _, _, _, padded_size) = sorter.get_scatter_histogram_sizes(keysize);
+        Self {
+            padded_size,
+            sort_stuff: PointCloudSortStuff {
+                num_points: keysize,
+                sorter_uni,
+                sorter_dis,
+                sorter_bg: Some(sorter_bg),
+                sorter_render_bg,
+                sorter_bg_pre,
+                keyval_a: sorter_b_a,
+                payload_a: sorter_p_a,
+            },
+        }
+    }
+
+    /// Prepares the buffers for sorting `keysize` keys.
+    /// Returns true if the buffers had to be reallocated (the bind groups changed in this case).
+    pub fn resize(
+        &mut self,
+        sorter: &GPURSSorter,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        keysize: usize,
+    ) -> bool {
+        if keysize == self.sort_stuff.num_points {
+            return false;
+        }
+        let (_, scatter_blocks_ru, _, _, _, count_ru_histo) =
+            sorter.get_scatter_histogram_sizes(keysize);
+        // reallocate if the keys do not fit or more than half of the memory would be unused.
+        // The number of visible splats changes with every camera movement, in between the
+        // buffers are reused so that the bind groups stay valid
+        if count_ru_histo > self.padded_size || count_ru_histo * 2 < self.padded_size {
+            *self = Self::new(sorter, device, keysize);
+            return true;
+        }
+        let uniform_infos = GeneralInfo::new(keysize, count_ru_histo, sorter.passes());
+        let dispatch_infos = IndirectDispatch {
+            dispatch_x: scatter_blocks_ru as u32,
+            dispatch_y: 1,
+            dispatch_z: 1,
+        };
+        queue.write_buffer(
+            &self.sort_stuff.sorter_uni,
+            0,
+            bytemuck::bytes_of(&uniform_infos),
+        );
+        queue.write_buffer(
+            &self.sort_stuff.sorter_dis,
+            0,
+            bytemuck::bytes_of(&dispatch_infos),
+        );
+        self.sort_stuff.num_points = keysize;
+        return false;
+    }
+
+    /// number of keys the buffers are currently set up for
+    pub fn keysize(&self) -> usize {
+        self.sort_stuff.num_points
+    }
+
+    pub fn sort_stuff(&self) -> &PointCloudSortStuff {
+        &self.sort_stuff
+    }
 }
 
 #[allow(dead_code)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct IndirectDispatch {
     dispatch_x: u32,
     dispatch_y: u32,
     dispatch_z: u32,
 }
 
+/// sort infos shared by all passes (`GeneralInfo` in radix_sort.wgsl, same layout)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct GeneralInfo {
+    /// number of keys to sort (written by the preprocess shader for indirect sorts)
     pub keys_size: u32,
+    /// number of keys rounded up to full scatter blocks, the keys after keys_size are
+    /// filled with the maximum key by the zeroing pass
     pub padded_size: u32,
+    /// number of radix passes (informational, the shader uses the constant rs_keyval_size)
     pub passes: u32,
+    /// index of the next even scatter pass, reset to 0 by the zeroing pass
     pub even_pass: u32,
+    /// index of the previous odd scatter pass, reset to passes / 2 - 1 by the zeroing pass
     pub odd_pass: u32,
 }
 
-unsafe fn any_as_u8_slice<T: Sized>(p: &T) -> &[u8] {
-    ::core::slice::from_raw_parts((p as *const T) as *const u8, ::core::mem::size_of::<T>())
+// the wgsl struct consists of five u32 values
+const _: () = assert!(std::mem::size_of::<GeneralInfo>() == 5 * 4);
+
+impl GeneralInfo {
+    /// infos before the first pass (the same values the zeroing pass sets)
+    pub fn new(keys_size: usize, padded_size: usize, passes: usize) -> Self {
+        debug_assert!(
+            keys_size <= padded_size,
+            "{keys_size} keys do not fit into the padded size {padded_size}"
+        );
+        debug_assert!(passes >= 2 && passes % 2 == 0);
+        return Self {
+            keys_size: keys_size as u32,
+            padded_size: padded_size as u32,
+            passes: passes as u32,
+            even_pass: 0,
+            odd_pass: (passes / 2 - 1) as u32,
+        };
+    }
 }
 
-impl GPURSSorter {
-    // The new call also needs the queue to be able to determine the maximum subgroup size (Does so by running test runs)
-    pub async fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
-        let mut cur_sorter: GPURSSorter;
-
-        log::debug!("Searching for the maximum subgroup size (wgpu currently does not allow to query subgroup sizes)");
-        let sizes = vec![1, 8, 16, 32];
-        let mut cur_size = 2;
-        enum State {
-            Init,
-            Increasing,
-            Decreasing,
-        }
-        let mut biggest_that_worked = 0;
-        let mut s = State::Init;
-        loop {
-            if cur_size >= sizes.len() {
-                break;
+/// Searches the biggest of the ascending subgroup `sizes` for which the test sort `works`.
+/// Starts at the third size and tests bigger sizes while the sort works,
+/// or smaller sizes until it works. If no smaller size works either, the sizes above
+/// the start are tested. Every size is tested at most once.
+/// Fails with the attempted sizes if none works.
+async fn search_subgroup_size<F, Fut>(sizes: &[i32], mut works: F) -> Result<i32, SortError>
+where
+    F: FnMut(i32) -> Fut,
+    Fut: std::future::Future<Output = Result<bool, SortError>>,
+{
+    enum State {
+        Init,
+        Increasing,
+        Decreasing,
+        // nothing below the start worked, searching above it
+        SearchingUp,
+    }
+    let start = 2.min(sizes.len() - 1);
+    let mut cur_size = start;
+    let mut biggest_that_worked = None;
+    let mut attempted = Vec::new();
+    let mut s = State::Init;
+    loop {
+        log::debug!("Checking sorting with subgroupsize {}", sizes[cur_size]);
+        attempted.push(sizes[cur_size] as u32);
+        let sort_success = works(sizes[cur_size]).await?;
+        log::debug!("{} worked: {}", sizes[cur_size], sort_success);
+        if sort_success {
+            biggest_that_worked = biggest_that_worked.max(Some(sizes[cur_size]));
+        }
+        // index of the next size to test, None ends the search
+        let next = match (&s, sort_success) {
+            (State::Init | State::SearchingUp, true) => {
+                s = State::Increasing;
+                Some(cur_size + 1)
             }
-            log::debug!("Checking sorting with subgroupsize {}", sizes[cur_size]);
-            cur_sorter = Self::new_with_sg_size(device, sizes[cur_size]);
-            let sort_success = cur_sorter.test_sort(device, queue).await;
-            log::debug!("{} worked: {}", sizes[cur_size], sort_success);
-            match s {
-                State::Init => {
-                    if sort_success {
-                        biggest_that_worked = sizes[cur_size];
-                        s = State::Increasing;
-                        cur_size += 1;
-                    } else {
-                        s = State::Decreasing;
-                        cur_size -= 1;
-                    }
+            (State::Increasing, _) => sort_success.then_some(cur_size + 1),
+            (State::SearchingUp, false) => Some(cur_size + 1),
+            (State::Decreasing, true) => None,
+            (State::Init | State::Decreasing, false) => match cur_size.checked_sub(1) {
+                Some(i) => {
+                    s = State::Decreasing;
+                    Some(i)
                 }
-                State::Increasing => {
-                    if sort_success {
-                        if sizes[cur_size] > biggest_that_worked {
-                            biggest_that_worked = sizes[cur_size];
-                        }
-                        cur_size += 1;
-                    } else {
-                        break;
-                    }
+                None => {
+                    s = State::SearchingUp;
+                    Some(start + 1)
                 }
-                State::Decreasing => {
-                    if sort_success {
-                        if sizes[cur_size] > biggest_that_worked {
-                            biggest_that_worked = sizes[cur_size];
-                        }
-                        break;
-                    } else {
-                        cur_size -= 1;
-                    }
-                }
-            }
+            },
+        };
+        match next {
+            Some(i) if i < sizes.len() => cur_size = i,
+            _ => break,
         }
-        if biggest_that_worked == 0 {
-            panic!(
-                "GPURSSorter::new() No workgroup size that works was found. Unable to use sorter"
-            );
+    }
+    log::debug!(
+        "probed the subgroup size with {} test sorts",
+        attempted.len()
+    );
+    return biggest_that_worked.ok_or(SortError::NoValidSubgroupSize { attempted });
+}
+
+impl GPURSSorter {
+    // The new call also needs the queue to be able to determine the maximum subgroup size (Does so by running test runs)
+    pub async fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self, SortError> {
+        // wgpu 0.19 exposes neither Features::SUBGROUP nor subgroup size limits,
+        // so the size has to be probed with test sorts (use new_cached to skip this on later runs)
+        log::debug!("Searching for the maximum subgroup size with test sorts");
+        let sg_size = search_subgroup_size(&RS_SUBGROUP_SIZES, |size| async move {
+            let sorter = Self::new_with_sg_size(device, size, RS_HISTOGRAM_BLOCK_ROWS, 4)?;
+            return Ok(sorter.test_sort(device, queue).await);
+        })
+        .await?;
+        let sorter = Self::new_with_sg_size(device, sg_size, RS_HISTOGRAM_BLOCK_ROWS, 4)?;
+        log::info!(
+            "Created a sorter with subgroup size {}",
+            sorter.subgroup_size
+        );
+        return Ok(sorter);
+    }
+
+    /// Like [`GPURSSorter::new`] but additionally benchmarks a few block row configurations
+    /// (keys per thread in the histogram and scatter passes) and keeps the fastest one.
+    /// Every configuration needs its own shader variant, so this takes considerably longer.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn new_tuned(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self, SortError> {
+        let sg_size = Self::new(device, queue).await?.subgroup_size;
+        let mut best: Option<(std::time::Duration, Self)> = None;
+        for block_rows in RS_BLOCK_ROWS_CANDIDATES {
+            let sorter = Self::new_with_sg_size(device, sg_size as i32, block_rows, 4)?;
+            if !sorter.test_sort(device, queue).await {
+                log::debug!("block rows {} do not sort correctly", block_rows);
+                continue;
+            }
+            let time = sorter.benchmark(device, queue).await;
+            log::debug!("block rows {}: {:?}", block_rows, time);
+            if best.as_ref().map_or(true, |(t, _)| time < *t) {
+                best = Some((time, sorter));
+            }
         }
-        cur_sorter = Self::new_with_sg_size(device, biggest_that_worked);
+        // the default configuration passed the test in GPURSSorter::new
+        let sorter = match best {
+            Some((_, s)) => s,
+            None => Self::new_with_sg_size(device, sg_size as i32, RS_HISTOGRAM_BLOCK_ROWS, 4)?,
+        };
         log::info!(
-            "Created a sorter with subgroup size {}\n",
-            cur_sorter.subgroup_size
+            "Created a sorter with subgroup size {} and {} block rows",
+            sorter.subgroup_size,
+            sorter.block_rows
         );
-        return cur_sorter;
+        return Ok(sorter);
     }
 
-    pub fn create_sort_stuff(
-        &self,
+    /// Like [`GPURSSorter::new_tuned`] but first tries the configuration stored for the adapter in the cache file.
+    /// The cached configuration is verified with a test sort. If there is no entry for the adapter
+    /// or the verification fails, the configuration is searched again and the cache file is updated.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn new_cached(
         device: &wgpu::Device,
-        num_points: usize,
-    ) -> PointCloudSortStuff {
-        let (sorter_b_a, sorter_b_b, sorter_p_a, sorter_p_b) =
-            GPURSSorter::create_keyval_buffers(device, num_points, 4);
-        let sorter_int = self.create_internal_mem_buffer(device, num_points);
-        let (sorter_uni, sorter_dis, sorter_bg) = self.create_bind_group(
+        queue: &wgpu::Queue,
+        adapter: &wgpu::AdapterInfo,
+        cache_file: &std::path::Path,
+    ) -> Result<Self, SortError> {
+        let key = format!(
+            "{} ({:?}, {} {})",
+            adapter.name, adapter.backend, adapter.driver, adapter.driver_info
+        );
+        let mut cache: std::collections::HashMap<String, SorterConfig> = std::fs::read(cache_file)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+
+        if let Some(config) = cache.get(&key) {
+            let sorter =
+                Self::new_with_sg_size(device, config.subgroup_size as i32, config.block_rows, 4)?;
+            if sorter.test_sort(device, queue).await {
+                log::info!(
+                    "Created a sorter with cached subgroup size {} and {} block rows",
+                    config.subgroup_size,
+                    config.block_rows
+                );
+                return Ok(sorter);
+            }
+            log::warn!(
+                "cached sorter configuration {:?} does not work, searching again",
+                config
+            );
+        }
+
+        let sorter = Self::new_tuned(device, queue).await?;
+        cache.insert(
+            key,
+            SorterConfig {
+                subgroup_size: sorter.subgroup_size,
+                block_rows: sorter.block_rows,
+            },
+        );
+        if let Err(err) = serde_json::to_vec(&cache)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(std::fs::write(cache_file, data)?))
+        {
+            log::warn!("could not write sorter configuration cache: {}", err);
+        }
+        return Ok(sorter);
+    }
+
+    /// Like [`GPURSSorter::new`] but sorts 64 bit unsigned integer keys (8 passes instead of 4).
+    /// The keyval buffers hold two u32 words per key (lower word first, i.e. little endian u64).
+    /// Not usable by the renderer whose preprocessing writes 32 bit keys.
+    pub async fn new_u64(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self, SortError> {
+        let sg_size = Self::new(device, queue).await?.subgroup_size;
+        let sorter = Self::new_with_sg_size(device, sg_size as i32, RS_HISTOGRAM_BLOCK_ROWS, 8)?;
+        if !sorter.test_sort(device, queue).await {
+            return Err(SortError::NoValidSubgroupSize {
+                attempted: vec![sg_size as u32],
+            });
+        }
+        return Ok(sorter);
+    }
+
+    /// subgroup size the shaders were compiled for
+    pub fn subgroup_size(&self) -> usize {
+        self.subgroup_size
+    }
+
+    /// size of the keys in bytes (4 or 8)
+    pub fn key_bytes(&self) -> usize {
+        self.key_bytes
+    }
+
+    /// number of radix sort passes (one per key byte)
+    pub fn passes(&self) -> usize {
+        self.key_bytes * 8 / RS_RADIX_LOG2
+    }
+
+    /// number of keys each thread processes in the histogram and scatter passes
+    pub fn block_rows(&self) -> usize {
+        self.block_rows
+    }
+
+    /// number of keys sorted by one scatter workgroup
+    pub fn keys_per_workgroup(&self) -> usize {
+        HISTOGRAM_WG_SIZE * self.block_rows
+    }
+
+    /// sizes in bytes of the buffers [`SortBuffers::new`] creates for sorting `keysize` keys
+    pub fn sort_buffer_sizes(&self, keysize: usize) -> Vec<(&'static str, u64)> {
+        sort_buffer_sizes(keysize, self.key_bytes, self.block_rows)
+    }
+
+    /// same as [`GPURSSorter::sort_buffer_sizes`] for a sorter created with [`GPURSSorter::new`]
+    pub fn default_sort_buffer_sizes(keysize: usize) -> Vec<(&'static str, u64)> {
+        sort_buffer_sizes(keysize, 4, RS_HISTOGRAM_BLOCK_ROWS)
+    }
+
+    // returns the fastest of a few sorts of 2^20 keys
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn benchmark(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> std::time::Duration {
+        let n = 1 << 20;
+        let keys: Vec<u32> = (0..n as u32).map(|i| i.wrapping_mul(2654435761)).collect();
+
+        let internal_mem_buffer = self.create_internal_mem_buffer(device, n);
+        let (keyval_a, keyval_b, payload_a, payload_b) = self.create_keyval_buffers(device, n, 4);
+        let (_uniform_buffer, _dispatch_buffer, bind_group) = self.create_bind_group(
             device,
-            num_points,
-            &sorter_int,
-            &sorter_b_a,
-            &sorter_b_b,
-            &sorter_p_a,
-            &sorter_p_b,
+            n,
+            &internal_mem_buffer,
+            &keyval_a,
+            &keyval_b,
+            &payload_a,
+            &payload_b,
         );
-        let sorter_render_bg = self.create_bind_group_render(device, &sorter_uni, &sorter_p_a);
-        let sorter_bg_pre = self.create_bind_group_preprocess(
+
+        let mut best = std::time::Duration::MAX;
+        for _ in 0..5 {
+            upload_to_buffer(&keyval_a, device, queue, keys.as_slice());
+            let start = std::time::Instant::now();
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("GPURSSorter benchmark"),
+            });
+            self.record_sort(&bind_group, n, &mut encoder);
+            let idx = queue.submit([encoder.finish()]);
+            device.poll(wgpu::Maintain::WaitForSubmissionIndex(idx));
+            best = best.min(start.elapsed());
+        }
+        return best;
+    }
+
+    /// Measures the sort throughput of every subgroup size that sorts correctly on the device.
+    /// Each key count is sorted `runs` times with GPU timestamp queries, the fastest run is reported.
+    /// The device needs [`wgpu::Features::TIMESTAMP_QUERY`].
+    /// All buffers of a measurement are destroyed before the next one starts.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn benchmark_subgroup_sizes(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        key_counts: &[usize],
+        runs: usize,
+    ) -> Vec<SortTiming> {
+        let mut timings = Vec::new();
+        for sg_size in RS_SUBGROUP_SIZES {
+            let sorter = match Self::new_with_sg_size(device, sg_size, RS_HISTOGRAM_BLOCK_ROWS, 4) {
+                Ok(sorter) => sorter,
+                Err(err) => {
+                    log::debug!(
+                        "cannot create sorter with subgroup size {}: {}",
+                        sg_size,
+                        err
+                    );
+                    continue;
+                }
+            };
+            if !sorter.test_sort(device, queue).await {
+                log::debug!("subgroup size {} does not sort correctly", sg_size);
+                continue;
+            }
+            for &n in key_counts {
+                let duration = sorter.time_sort(device, queue, n, runs).await;
+                timings.push(SortTiming {
+                    subgroup_size: sorter.subgroup_size,
+                    keys: n,
+                    duration,
+                });
+            }
+        }
+        return timings;
+    }
+
+    // fastest of `runs` sorts of n pseudo random keys measured with timestamp queries
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn time_sort(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        n: usize,
+        runs: usize,
+    ) -> std::time::Duration {
+        let keys: Vec<u32> = (0..n as u32).map(|i| i.wrapping_mul(2654435761)).collect();
+
+        let internal_mem_buffer = self.create_internal_mem_buffer(device, n);
+        let (keyval_a, keyval_b, payload_a, payload_b) = self.create_keyval_buffers(device, n, 4);
+        let (uniform_buffer, dispatch_buffer, bind_group) = self.create_bind_group(
             device,
-            &sorter_uni,
-            &sorter_dis,
-            &sorter_b_a,
-            &sorter_p_a,
+            n,
+            &internal_mem_buffer,
+            &keyval_a,
+            &keyval_b,
+            &payload_a,
+            &payload_b,
         );
+        let mut stopwatch = GPUStopwatch::new(device, Some(1));
+
+        let mut best = std::time::Duration::MAX;
+        for _ in 0..runs.max(1) {
+            // sorted keys would skip most of the scatter work
+            upload_to_buffer(&keyval_a, device, queue, keys.as_slice());
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("GPURSSorter benchmark"),
+            });
+            stopwatch.start(&mut encoder, "sort").unwrap();
+            self.record_sort(&bind_group, n, &mut encoder);
+            stopwatch.stop(&mut encoder, "sort").unwrap();
+            stopwatch.end(&mut encoder);
+            queue.submit([encoder.finish()]);
+            let measurements = stopwatch.take_measurements(device, queue).await;
+            best = best.min(measurements["sort"]);
+        }
 
-        PointCloudSortStuff {
-            num_points,
-            sorter_uni,
-            sorter_dis,
-            sorter_bg,
-            sorter_render_bg,
-            sorter_bg_pre,
+        for buffer in [
+            internal_mem_buffer,
+            keyval_a,
+            keyval_b,
+            payload_a,
+            payload_b,
+            uniform_buffer,
+            dispatch_buffer,
+        ] {
+            buffer.destroy();
         }
+        device.poll(wgpu::Maintain::Wait);
+        return best;
+    }
+
+    pub fn create_sort_stuff(
+        &self,
+        device: &wgpu::Device,
+        num_points: usize,
+    ) -> PointCloudSortStuff {
+        return SortBuffers::new(self, device, num_points).sort_stuff;
     }
 
-    fn new_with_sg_size(device: &wgpu::Device, sg_size: i32) -> Self {
+    fn new_with_sg_size(
+        device: &wgpu::Device,
+        sg_size: i32,
+        block_rows: usize,
+        key_bytes: usize,
+    ) -> Result<Self, SortError> {
+        assert!(
+            key_bytes == 4 || key_bytes == 8,
+            "only 32 and 64 bit keys are supported"
+        );
+        let limits = device.limits();
+        if limits.max_compute_invocations_per_workgroup < HISTOGRAM_WG_SIZE as u32
+            || limits.max_compute_workgroup_size_x < HISTOGRAM_WG_SIZE as u32
+        {
+            return Err(SortError::ComputeNotSupported);
+        }
         // special variables for scatter shade
         let histogram_sg_size: usize = sg_size as usize;
         let rs_sweep_0_size: usize = RS_RADIX_SIZE / histogram_sg_size;
@@ -173,7 +688,7 @@ impl GPURSSorter {
         let rs_sweep_2_size: usize = rs_sweep_1_size / histogram_sg_size;
         let rs_sweep_size: usize = rs_sweep_0_size + rs_sweep_1_size + rs_sweep_2_size;
         let _rs_smem_phase_1: usize = RS_RADIX_SIZE + RS_RADIX_SIZE + rs_sweep_size;
-        let rs_smem_phase_2: usize = RS_RADIX_SIZE + RS_SCATTER_BLOCK_ROWS * SCATTER_WG_SIZE;
+        let rs_smem_phase_2: usize = RS_RADIX_SIZE + block_rows * SCATTER_WG_SIZE;
         // rs_smem_phase_2 will always be larger, so always use phase2
         let rs_mem_dwords: usize = rs_smem_phase_2;
         let rs_mem_sweep_0_offset: usize = 0;
@@ -198,6 +713,7 @@ impl GPURSSorter {
             const rs_radix_log2: u32 = {:}u;\n\
             const rs_radix_size: u32 = {:}u;\n\
             const rs_keyval_size: u32 = {:}u;\n\
+            const rs_key_words: u32 = {:}u;\n\
             const rs_histogram_block_rows: u32 = {:}u;\n\
             const rs_scatter_block_rows: u32 = {:}u;\n\
             const rs_mem_dwords: u32 = {:}u;\n\
@@ -208,9 +724,10 @@ impl GPURSSorter {
             HISTOGRAM_WG_SIZE,
             RS_RADIX_LOG2,
             RS_RADIX_SIZE,
-            RS_KEYVAL_SIZE,
-            RS_HISTOGRAM_BLOCK_ROWS,
-            RS_SCATTER_BLOCK_ROWS,
+            key_bytes * 8 / RS_RADIX_LOG2,
+            key_bytes / 4,
+            block_rows,
+            block_rows,
             rs_mem_dwords,
             rs_mem_sweep_0_offset,
             rs_mem_sweep_1_offset,
@@ -260,7 +777,7 @@ impl GPURSSorter {
             entry_point: "scatter_odd",
         });
 
-        return Self {
+        return Ok(Self {
             bind_group_layout,
             render_bind_group_layout,
             preprocess_bind_group_layout,
@@ -270,17 +787,17 @@ impl GPURSSorter {
             scatter_even_p,
             scatter_odd_p,
             subgroup_size: histogram_sg_size,
-        };
+            block_rows,
+            key_bytes,
+        });
     }
 
     async fn test_sort(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
         // smiply runs a small sort and check if the sorting result is correct
         let n = 8192; // means that 2 workgroups are needed for sorting
-        let scrambled_data: Vec<f32> = (0..n).rev().map(|x| x as f32).collect();
-        let sorted_data: Vec<f32> = (0..n).map(|x| x as f32).collect();
 
         let internal_mem_buffer = Self::create_internal_mem_buffer(self, device, n);
-        let (keyval_a, keyval_b, payload_a, payload_b) = Self::create_keyval_buffers(device, n, 4);
+        let (keyval_a, keyval_b, payload_a, payload_b) = self.create_keyval_buffers(device, n, 4);
         let (_uniform_buffer, _dispatch_buffer, bind_group) = self.create_bind_group(
             device,
             n,
@@ -291,7 +808,15 @@ impl GPURSSorter {
             &payload_b,
         );
 
-        upload_to_buffer(&keyval_a, device, queue, scrambled_data.as_slice());
+        // 64 bit keys span both words to test all passes
+        let key = |x: usize| (x as u64) << 29;
+        if self.key_bytes == 8 {
+            let scrambled_data: Vec<u64> = (0..n).rev().map(key).collect();
+            upload_to_buffer(&keyval_a, device, queue, scrambled_data.as_slice());
+        } else {
+            let scrambled_data: Vec<f32> = (0..n).rev().map(|x| x as f32).collect();
+            upload_to_buffer(&keyval_a, device, queue, scrambled_data.as_slice());
+        }
 
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("GPURSSorter test_sort"),
@@ -300,13 +825,161 @@ impl GPURSSorter {
         let idx = queue.submit([encoder.finish()]);
         device.poll(wgpu::Maintain::WaitForSubmissionIndex(idx));
 
-        let sorted = download_buffer::<f32>(&keyval_a, device, queue).await;
-        for i in 0..n {
-            if sorted[i] != sorted_data[i] {
+        if self.key_bytes == 8 {
+            let Ok(sorted) = download_buffer::<u64>(&keyval_a, device, queue).await else {
                 return false;
-            }
+            };
+            return (0..n).all(|i| sorted[i] == key(i));
+        }
+        let Ok(sorted) = download_buffer::<f32>(&keyval_a, device, queue).await else {
+            return false;
+        };
+        return (0..n).all(|i| sorted[i] == i as f32);
+    }
+
+    /// Sorts the keys in ascending order on the gpu and returns them together with the reordered payload.
+    /// The keys are sorted by their bit pattern, so negative floats end up behind the positive ones.
+    /// Only supported by sorters for 32 bit keys.
+    pub async fn sort(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        keys: &[f32],
+        payload: &[u32],
+    ) -> Result<(Vec<f32>, Vec<u32>), SortError> {
+        return self.sort_keys(device, queue, keys, payload).await;
+    }
+
+    /// same as [`GPURSSorter::sort`] for 64 bit keys, only supported by sorters created with
+    /// [`GPURSSorter::new_u64`]
+    pub async fn sort_u64(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        keys: &[u64],
+        payload: &[u32],
+    ) -> Result<(Vec<u64>, Vec<u32>), SortError> {
+        return self.sort_keys(device, queue, keys, payload).await;
+    }
+
+    async fn sort_keys<K: bytemuck::Pod>(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        keys: &[K],
+        payload: &[u32],
+    ) -> Result<(Vec<K>, Vec<u32>), SortError> {
+        if self.key_bytes != std::mem::size_of::<K>() {
+            return Err(SortError::UnsupportedKeySize {
+                key_bytes: self.key_bytes,
+            });
+        }
+        if keys.len() != payload.len() {
+            return Err(SortError::LengthMismatch {
+                keys: keys.len(),
+                payload: payload.len(),
+            });
+        }
+        let n = keys.len();
+        if n == 0 {
+            return Ok((Vec::new(), Vec::new()));
         }
-        return true;
+
+        let internal_mem_buffer = self.create_internal_mem_buffer(device, n);
+        let (keyval_a, keyval_b, payload_a, payload_b) = self.create_keyval_buffers(device, n, 4);
+        let (_uniform_buffer, _dispatch_buffer, bind_group) = self.create_bind_group(
+            device,
+            n,
+            &internal_mem_buffer,
+            &keyval_a,
+            &keyval_b,
+            &payload_a,
+            &payload_b,
+        );
+        upload_to_buffer(&keyval_a, device, queue, keys);
+        upload_to_buffer(&payload_a, device, queue, payload);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("GPURSSorter sort"),
+        });
+        self.record_sort(&bind_group, n, &mut encoder);
+        let idx = queue.submit([encoder.finish()]);
+        device.poll(wgpu::Maintain::WaitForSubmissionIndex(idx));
+
+        // the buffers are padded, only the first n entries belong to the input
+        let mut sorted_keys = download_buffer::<K>(&keyval_a, device, queue).await?;
+        let mut sorted_payload = download_buffer::<u32>(&payload_a, device, queue).await?;
+        sorted_keys.truncate(n);
+        sorted_payload.truncate(n);
+        return Ok((sorted_keys, sorted_payload));
+    }
+
+    /// Computes a 256 bin histogram over the most significant byte of the given 32 bit values
+    /// (for positive floats this bins them by sign and exponent).
+    /// Only the histogram pass of the sort is executed, the values are not scattered.
+    pub async fn compute_histogram<T: bytemuck::Pod>(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        values: &[T],
+    ) -> Result<[u32; RS_RADIX_SIZE], SortError> {
+        assert!(std::mem::size_of::<T>() == 4); // only 32 bit keys are supported
+        if values.is_empty() {
+            return Ok([0; RS_RADIX_SIZE]);
+        }
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("histogram values buffer"),
+            contents: bytemuck::cast_slice(values),
+            usage: wgpu::BufferUsages::COPY_SRC,
+        });
+        return self
+            .compute_histogram_buffer(device, queue, &buffer, values.len())
+            .await;
+    }
+
+    /// same as [`GPURSSorter::compute_histogram`] for the first `n` 32 bit values of a gpu buffer
+    /// (the buffer needs `COPY_SRC` usage)
+    pub async fn compute_histogram_buffer(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        buffer: &wgpu::Buffer,
+        n: usize,
+    ) -> Result<[u32; RS_RADIX_SIZE], SortError> {
+        assert!(self.key_bytes == 4); // only 32 bit keys are supported
+        if n == 0 {
+            return Ok([0; RS_RADIX_SIZE]);
+        }
+        let internal_mem_buffer = self.create_internal_mem_buffer(device, n);
+        let (keyval_a, keyval_b, payload_a, payload_b) = self.create_keyval_buffers(device, n, 4);
+        let (_uniform_buffer, _dispatch_buffer, bind_group) = self.create_bind_group(
+            device,
+            n,
+            &internal_mem_buffer,
+            &keyval_a,
+            &keyval_b,
+            &payload_a,
+            &payload_b,
+        );
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("GPURSSorter compute_histogram"),
+        });
+        encoder.copy_buffer_to_buffer(buffer, 0, &keyval_a, 0, (n * 4) as u64);
+        self.record_calculate_histogram(&bind_group, n, &mut encoder);
+        let idx = queue.submit([encoder.finish()]);
+        device.poll(wgpu::Maintain::WaitForSubmissionIndex(idx));
+
+        let histograms = download_buffer::<u32>(&internal_mem_buffer, device, queue).await?;
+        // histogram of pass 3 contains the most significant byte
+        let offset = (self.passes() - 1) * RS_RADIX_SIZE;
+        let mut histogram = [0; RS_RADIX_SIZE];
+        histogram.copy_from_slice(&histograms[offset..offset + RS_RADIX_SIZE]);
+
+        // the padding keys are filled with 0xFFFFFFFF and end up in the last bin
+        let (_, _, _, _, _, count_ru_histo) = self.get_scatter_histogram_sizes(n);
+        histogram[RS_RADIX_SIZE - 1] -= (count_ru_histo - n) as u32;
+        return Ok(histogram);
     }
 
     // layouts used by the sorting pipeline, as the dispatch buffer has to be in separate bind group
@@ -440,6 +1113,16 @@ impl GPURSSorter {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
                 wgpu::BindGroupLayoutEntry {
                     binding: 4,
                     visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::VERTEX,
@@ -454,14 +1137,17 @@ impl GPURSSorter {
         });
     }
 
-    fn get_scatter_histogram_sizes(keysize: usize) -> (usize, usize, usize, usize, usize, usize) {
+    fn get_scatter_histogram_sizes(
+        &self,
+        keysize: usize,
+    ) -> (usize, usize, usize, usize, usize, usize) {
         // as a general rule of thumb, scater_blocks_ru is equal to histo_blocks_ru, except the amount of elements in these two stages is different
 
-        let scatter_block_kvs = HISTOGRAM_WG_SIZE * RS_SCATTER_BLOCK_ROWS;
+        let scatter_block_kvs = HISTOGRAM_WG_SIZE * self.block_rows;
         let scatter_blocks_ru = (keysize + scatter_block_kvs - 1) / scatter_block_kvs;
         let count_ru_scatter = scatter_blocks_ru * scatter_block_kvs;
 
-        let histo_block_kvs = HISTOGRAM_WG_SIZE * RS_HISTOGRAM_BLOCK_ROWS;
+        let histo_block_kvs = HISTOGRAM_WG_SIZE * self.block_rows;
         let histo_blocks_ru = (count_ru_scatter + histo_block_kvs - 1) / histo_block_kvs;
         let count_ru_histo = histo_blocks_ru * histo_block_kvs;
 
@@ -476,19 +1162,17 @@ impl GPURSSorter {
     }
 
     pub fn create_keyval_buffers(
+        &self,
         device: &wgpu::Device,
         keysize: usize,
         bytes_per_payload_elem: usize,
     ) -> (wgpu::Buffer, wgpu::Buffer, wgpu::Buffer, wgpu::Buffer) {
-        //let (_, _, _, _, _, count_ru_histo) = Self::get_scatter_histogram_sizes(keysize);
-        let keys_per_workgroup = HISTOGRAM_WG_SIZE * RS_HISTOGRAM_BLOCK_ROWS;
-        let count_ru_histo =
-            ((keysize + keys_per_workgroup) / keys_per_workgroup + 1) * keys_per_workgroup;
+        let keyval_size = padded_keysize(keysize, self.block_rows) * self.key_bytes;
 
         // creating the two needed buffers for sorting
         let buffer_a = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Radix data buffer a"),
-            size: (count_ru_histo * std::mem::size_of::<f32>()) as u64,
+            size: keyval_size as u64,
             usage: wgpu::BufferUsages::STORAGE
                 | wgpu::BufferUsages::COPY_DST
                 | wgpu::BufferUsages::COPY_SRC,
@@ -496,7 +1180,7 @@ impl GPURSSorter {
         });
         let buffer_b = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Radix data buffer a"),
-            size: (count_ru_histo * std::mem::size_of::<f32>()) as u64,
+            size: keyval_size as u64,
             usage: wgpu::BufferUsages::STORAGE
                 | wgpu::BufferUsages::COPY_DST
                 | wgpu::BufferUsages::COPY_SRC,
@@ -549,11 +1233,7 @@ impl GPURSSorter {
         //   | workgroup_ids[keyval_size]      |
         //   +---------------------------------+ <-- (keyval_size + scatter_blocks_ru - 1) * histo_size + workgroup_ids_size
 
-        let (_, scatter_blocks_ru, _, _, _, _) = Self::get_scatter_histogram_sizes(keysize);
-
-        let histo_size = RS_RADIX_SIZE * std::mem::size_of::<u32>();
-
-        let internal_size = (RS_KEYVAL_SIZE + scatter_blocks_ru - 1 + 1) * histo_size; // +1 safety
+        let internal_size = internal_mem_size(keysize, self.key_bytes, self.block_rows);
 
         let buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Internal radix sort buffer"),
@@ -577,7 +1257,7 @@ impl GPURSSorter {
         payload_b: &wgpu::Buffer,
     ) -> (wgpu::Buffer, wgpu::Buffer, wgpu::BindGroup) {
         let (_, scatter_blocks_ru, _, _, _, count_ru_histo) =
-            Self::get_scatter_histogram_sizes(keysize);
+            self.get_scatter_histogram_sizes(keysize);
         // if keyval_a.size() as usize != count_ru_histo * std::mem::size_of::<f32>()
         //     || keyval_b.size() as usize != count_ru_histo * std::mem::size_of::<f32>()
         // {
@@ -588,23 +1268,17 @@ impl GPURSSorter {
             dispatch_y: 1,
             dispatch_z: 1,
         };
-        let uniform_infos = GeneralInfo {
-            keys_size: keysize as u32,
-            padded_size: count_ru_histo as u32,
-            passes: 4,
-            even_pass: 0,
-            odd_pass: 0,
-        };
+        let uniform_infos = GeneralInfo::new(keysize, count_ru_histo, self.passes());
         let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Radix uniform buffer"),
-            contents: unsafe { any_as_u8_slice(&uniform_infos) },
+            contents: bytemuck::bytes_of(&uniform_infos),
             usage: wgpu::BufferUsages::STORAGE
                 | wgpu::BufferUsages::COPY_DST
                 | wgpu::BufferUsages::COPY_SRC,
         });
         let dispatch_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Dispatch indirect buffer"),
-            contents: unsafe { any_as_u8_slice(&dispatch_infos) },
+            contents: bytemuck::bytes_of(&dispatch_infos),
             usage: wgpu::BufferUsages::STORAGE
                 | wgpu::BufferUsages::COPY_DST
                 | wgpu::BufferUsages::INDIRECT,
@@ -645,6 +1319,7 @@ impl GPURSSorter {
         &self,
         device: &wgpu::Device,
         general_infos: &wgpu::Buffer,
+        keyval_a: &wgpu::Buffer,
         payload_a: &wgpu::Buffer,
     ) -> wgpu::BindGroup {
         let rendering_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -655,6 +1330,10 @@ impl GPURSSorter {
                     binding: 0,
                     resource: general_infos.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: keyval_a.as_entire_binding(),
+                },
                 wgpu::BindGroupEntry {
                     binding: 4,
                     resource: payload_a.as_entire_binding(),
@@ -711,12 +1390,11 @@ impl GPURSSorter {
         keysize: usize,
         encoder: &mut wgpu::CommandEncoder,
     ) {
-        // histogram has to be zeroed out such that counts that might have been done in the past are erased and do not interfere with the new count
-        // encoder.clear_buffer(histogram_buffer, 0, None);
+        // histogram has to be zeroed out such that counts that might have been done in the past are erased and do not interfere with the new count.
+        // This is not done with clear_buffer, as the zeroing pass also resets the pass infos and fills the padding keys
+        // (in the indirect case the number of keys is only known on the gpu)
 
-        // as we only deal with 32 bit float values always 4 passes are conducted
-        let (_, _, _, _, hist_blocks_ru, _) = Self::get_scatter_histogram_sizes(keysize);
-        const _PASSES: u32 = 4;
+        let (_, _, _, _, hist_blocks_ru, _) = self.get_scatter_histogram_sizes(keysize);
 
         {
             let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
@@ -793,25 +1471,28 @@ impl GPURSSorter {
         keysize: usize,
         encoder: &mut wgpu::CommandEncoder,
     ) {
-        assert!(passes == 4); // currently the amount of passes is hardcoded in the shader
-        let (_, scatter_blocks_ru, _, _, _, _) = Self::get_scatter_histogram_sizes(keysize);
+        assert!(passes == self.passes()); // the amount of passes is compiled into the shader
+
+        // the result only ends up in buffer a after an even number of passes
+        assert!(passes % 2 == 0, "odd number of radix passes ({passes})");
+        let (_, scatter_blocks_ru, _, _, _, _) = self.get_scatter_histogram_sizes(keysize);
         let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
             label: Some("Scatter keyvals"),
             timestamp_writes: None,
         });
 
         pass.set_bind_group(0, bind_group, &[]);
-        pass.set_pipeline(&self.scatter_even_p);
-        pass.dispatch_workgroups(scatter_blocks_ru as u32, 1, 1);
-
-        pass.set_pipeline(&self.scatter_odd_p);
-        pass.dispatch_workgroups(scatter_blocks_ru as u32, 1, 1);
-
-        pass.set_pipeline(&self.scatter_even_p);
-        pass.dispatch_workgroups(scatter_blocks_ru as u32, 1, 1);
-
-        pass.set_pipeline(&self.scatter_odd_p);
-        pass.dispatch_workgroups(scatter_blocks_ru as u32, 1, 1);
+        // even passes read from buffer a and odd passes from buffer b,
+        // so the sorted keys always end up in buffer a.
+        // The shaders advance the pass index in the GeneralInfo themselves, a queue.write_buffer
+        // between the dispatches would be executed before the whole submission.
+        for _ in 0..passes / 2 {
+            pass.set_pipeline(&self.scatter_even_p);
+            pass.dispatch_workgroups(scatter_blocks_ru as u32, 1, 1);
+
+            pass.set_pipeline(&self.scatter_odd_p);
+            pass.dispatch_workgroups(scatter_blocks_ru as u32, 1, 1);
+        }
     }
     pub fn record_scatter_keys_indirect(
         &self,
@@ -820,7 +1501,8 @@ impl GPURSSorter {
         dispatch_buffer: &wgpu::Buffer,
         encoder: &mut wgpu::CommandEncoder,
     ) {
-        assert!(passes == 4);
+        assert!(passes == self.passes());
+        assert!(passes % 2 == 0, "odd number of radix passes ({passes})");
 
         let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
             label: Some("Scatter keyvals"),
@@ -828,17 +1510,13 @@ impl GPURSSorter {
         });
 
         pass.set_bind_group(0, bind_group, &[]);
-        pass.set_pipeline(&self.scatter_even_p);
-        pass.dispatch_workgroups_indirect(dispatch_buffer, 0);
-
-        pass.set_pipeline(&self.scatter_odd_p);
-        pass.dispatch_workgroups_indirect(dispatch_buffer, 0);
-
-        pass.set_pipeline(&self.scatter_even_p);
-        pass.dispatch_workgroups_indirect(dispatch_buffer, 0);
+        for _ in 0..passes / 2 {
+            pass.set_pipeline(&self.scatter_even_p);
+            pass.dispatch_workgroups_indirect(dispatch_buffer, 0);
 
-        pass.set_pipeline(&self.scatter_odd_p);
-        pass.dispatch_workgroups_indirect(dispatch_buffer, 0);
+            pass.set_pipeline(&self.scatter_odd_p);
+            pass.dispatch_workgroups_indirect(dispatch_buffer, 0);
+        }
     }
 
     pub fn record_sort(
@@ -848,8 +1526,8 @@ impl GPURSSorter {
         encoder: &mut wgpu::CommandEncoder,
     ) {
         self.record_calculate_histogram(&bind_group, keysize, encoder);
-        self.record_prefix_histogram(&bind_group, 4, encoder);
-        self.record_scatter_keys(&bind_group, 4, keysize, encoder);
+        self.record_prefix_histogram(&bind_group, self.passes(), encoder);
+        self.record_scatter_keys(&bind_group, self.passes(), keysize, encoder);
     }
     pub fn record_sort_indirect(
         &self,
@@ -858,8 +1536,8 @@ impl GPURSSorter {
         encoder: &mut wgpu::CommandEncoder,
     ) {
         self.record_calculate_histogram_indirect(bind_group, dispatch_buffer, encoder);
-        self.record_prefix_histogram(bind_group, 4, encoder);
-        self.record_scatter_keys_indirect(bind_group, 4, dispatch_buffer, encoder);
+        self.record_prefix_histogram(bind_group, self.passes(), encoder);
+        self.record_scatter_keys_indirect(bind_group, self.passes(), dispatch_buffer, encoder);
     }
 }
 
@@ -884,11 +1562,19 @@ fn upload_to_buffer<T: bytemuck::Pod>(
     staging_buffer.destroy();
 }
 
-async fn download_buffer<T: Clone>(
+/// downloads the whole buffer, its size has to be a multiple of the size of `T`
+pub(crate) async fn download_buffer<T: bytemuck::Pod>(
     buffer: &wgpu::Buffer,
     device: &wgpu::Device,
     queue: &wgpu::Queue,
-) -> Vec<T> {
+) -> anyhow::Result<Vec<T>> {
+    if buffer.size() % std::mem::size_of::<T>() as u64 != 0 {
+        return Err(anyhow::anyhow!(
+            "buffer size {} is not a multiple of the element size {}",
+            buffer.size(),
+            std::mem::size_of::<T>()
+        ));
+    }
     // copy buffer data
     let download_buffer = device.create_buffer(&wgpu::BufferDescriptor {
         label: Some("Download buffer"),
@@ -907,14 +1593,261 @@ async fn download_buffer<T: Clone>(
     let (tx, rx) = futures_intrusive::channel::shared::oneshot_channel();
     buffer_slice.map_async(wgpu::MapMode::Read, move |result| tx.send(result).unwrap());
     device.poll(wgpu::Maintain::Wait);
-    rx.receive().await.unwrap().unwrap();
+    rx.receive()
+        .await
+        .ok_or(anyhow::anyhow!("buffer mapping was cancelled"))??;
     let data = buffer_slice.get_mapped_range();
-    let r;
+    let r = bytemuck::try_cast_slice::<u8, T>(&data)
+        .map_err(|err| anyhow::anyhow!("cannot read downloaded buffer: {:?}", err))?
+        .to_vec();
+    return Ok(r);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{test_device, test_device_gpu_sort};
+
+    fn buffer_with<T: bytemuck::Pod>(device: &wgpu::Device, values: &[T]) -> wgpu::Buffer {
+        return device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("test buffer"),
+            contents: bytemuck::cast_slice(values),
+            usage: wgpu::BufferUsages::COPY_SRC,
+        });
+    }
+
+    #[test]
+    fn download_buffer_roundtrip() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let values: Vec<u32> = (0..1000).map(|i| i * 7).collect();
+        let buffer = buffer_with(&device, &values);
+        let downloaded = pollster::block_on(download_buffer::<u32>(&buffer, &device, &queue));
+        assert_eq!(downloaded.unwrap(), values);
+
+        let values: Vec<[f32; 3]> = (0..100)
+            .map(|i| [i as f32, -0.5 * i as f32, 1e-3])
+            .collect();
+        let buffer = buffer_with(&device, &values);
+        let downloaded = pollster::block_on(download_buffer::<[f32; 3]>(&buffer, &device, &queue));
+        assert_eq!(downloaded.unwrap(), values);
+
+        // 8 bytes are no multiple of the 12 bytes of [f32; 3]
+        let buffer = buffer_with(&device, &[1u32, 2]);
+        let downloaded = pollster::block_on(download_buffer::<[f32; 3]>(&buffer, &device, &queue));
+        assert!(downloaded.is_err());
+    }
 
-    unsafe {
-        let (_, d, _) = data.align_to::<T>();
-        r = d.to_vec();
+    /// searches the subgroup size with a probe that succeeds for the given sizes
+    fn search_with(working: &[i32]) -> (Result<i32, SortError>, Vec<i32>) {
+        let mut attempted = Vec::new();
+        let result = pollster::block_on(search_subgroup_size(&RS_SUBGROUP_SIZES, |size| {
+            attempted.push(size);
+            std::future::ready(Ok(working.contains(&size)))
+        }));
+        return (result, attempted);
     }
 
-    return r;
+    #[test]
+    fn subgroup_search_finds_the_biggest_working_size() {
+        assert_eq!(search_with(&[1, 8, 16, 32]), (Ok(32), vec![16, 32]));
+        assert_eq!(search_with(&[1, 8, 16]), (Ok(16), vec![16, 32]));
+        assert_eq!(search_with(&[1, 8]), (Ok(8), vec![16, 8]));
+        assert_eq!(search_with(&[1]), (Ok(1), vec![16, 8, 1]));
+        assert_eq!(search_with(&[32]), (Ok(32), vec![16, 8, 1, 32]));
+        let (result, attempted) = search_with(&[]);
+        assert!(result.is_err());
+        assert_eq!(attempted, vec![16, 8, 1, 32]);
+    }
+
+    #[test]
+    fn subgroup_search_fails_if_no_size_works() {
+        let result = pollster::block_on(search_subgroup_size(&RS_SUBGROUP_SIZES, |_| {
+            std::future::ready(Ok(false))
+        }));
+        assert_eq!(
+            result,
+            Err(SortError::NoValidSubgroupSize {
+                attempted: vec![16, 8, 1, 32]
+            })
+        );
+        // errors of the probe are passed on
+        let result = pollster::block_on(search_subgroup_size(&RS_SUBGROUP_SIZES, |_| {
+            std::future::ready(Err(SortError::ComputeNotSupported))
+        }));
+        assert_eq!(result, Err(SortError::ComputeNotSupported));
+    }
+
+    #[test]
+    fn u64_keys_are_sorted() {
+        if let Some((device, queue)) = test_device() {
+            let sorter = pollster::block_on(GPURSSorter::new(&device, &queue)).unwrap();
+            let result = pollster::block_on(sorter.sort_u64(&device, &queue, &[1], &[0]));
+            assert_eq!(result, Err(SortError::UnsupportedKeySize { key_bytes: 4 }));
+        }
+        let Some((device, queue)) = test_device_gpu_sort() else {
+            return;
+        };
+        let sorter = pollster::block_on(GPURSSorter::new_u64(&device, &queue)).unwrap();
+        assert_eq!(sorter.passes(), 8);
+        // the keys differ in both words
+        let n = 2048;
+        let keys: Vec<u64> = (0..n as u64).rev().map(|i| i << 27 | i).collect();
+        let payload: Vec<u32> = (0..n as u32).collect();
+        let (sorted, order) =
+            pollster::block_on(sorter.sort_u64(&device, &queue, &keys, &payload)).unwrap();
+        let mut expected = keys.clone();
+        expected.sort();
+        assert_eq!(sorted, expected);
+        assert_eq!(order, payload.iter().rev().copied().collect::<Vec<_>>());
+        assert!(pollster::block_on(sorter.sort(&device, &queue, &[1.], &[0])).is_err());
+    }
+
+    #[test]
+    fn sorts_sizes_around_the_block_size() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let sorter = pollster::block_on(GPURSSorter::new(&device, &queue)).unwrap();
+        // the histograms are zeroed before every sort, so a bigger sort before
+        // a smaller one must not leave counts behind
+        for n in [512, 4096, 1000] {
+            let keys: Vec<f32> = (0..n).map(|i| ((i * 7919) % n) as f32).collect();
+            let payload: Vec<u32> = (0..n as u32).collect();
+            let (sorted, order) =
+                pollster::block_on(sorter.sort(&device, &queue, &keys, &payload)).unwrap();
+            assert!(sorted.iter().enumerate().all(|(i, k)| *k == i as f32));
+            assert!(order
+                .iter()
+                .zip(&sorted)
+                .all(|(i, k)| keys[*i as usize] == *k));
+        }
+    }
+
+    #[test]
+    fn general_info_matches_the_shader_layout() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let sorter = pollster::block_on(GPURSSorter::new(&device, &queue)).unwrap();
+        let n = 1000;
+        let internal_mem_buffer = sorter.create_internal_mem_buffer(&device, n);
+        let (keyval_a, keyval_b, payload_a, payload_b) =
+            sorter.create_keyval_buffers(&device, n, 4);
+        let (uniform_buffer, _dispatch_buffer, bind_group) = sorter.create_bind_group(
+            &device,
+            n,
+            &internal_mem_buffer,
+            &keyval_a,
+            &keyval_b,
+            &payload_a,
+            &payload_b,
+        );
+        let (_, _, _, _, _, padded_size) = sorter.get_scatter_histogram_sizes(n);
+        let expected = GeneralInfo::new(n, padded_size, 4);
+        assert_eq!(expected.odd_pass, 1);
+        let infos = pollster::block_on(download_buffer::<GeneralInfo>(
+            &uniform_buffer,
+            &device,
+            &queue,
+        ))
+        .unwrap();
+        assert_eq!(infos, vec![expected]);
+
+        // the shaders read the sizes and advance the pass indices, which wrap around
+        // to their initial values after all passes
+        upload_to_buffer(
+            &keyval_a,
+            &device,
+            &queue,
+            &(0..n as u32).rev().collect::<Vec<_>>(),
+        );
+        let mut encoder = device.create_command_encoder(&Default::default());
+        sorter.record_sort(&bind_group, n, &mut encoder);
+        queue.submit([encoder.finish()]);
+        let infos = pollster::block_on(download_buffer::<GeneralInfo>(
+            &uniform_buffer,
+            &device,
+            &queue,
+        ))
+        .unwrap();
+        assert_eq!(infos, vec![expected]);
+        let sorted =
+            pollster::block_on(download_buffer::<u32>(&keyval_a, &device, &queue)).unwrap();
+        assert_eq!(sorted[..n], (0..n as u32).collect::<Vec<_>>());
+    }
+
+    /// sorts keys that differ in all bytes with a sorter for the given number of passes
+    fn check_scatter_passes(device: &wgpu::Device, queue: &wgpu::Queue, passes: usize) {
+        let sg_size = pollster::block_on(GPURSSorter::new(device, queue))
+            .unwrap()
+            .subgroup_size;
+        let sorter =
+            GPURSSorter::new_with_sg_size(device, sg_size as i32, RS_HISTOGRAM_BLOCK_ROWS, passes)
+                .unwrap();
+        assert_eq!(sorter.passes(), passes);
+        let n = 3000u64;
+        let bits = passes as u64 * 8;
+        let keys: Vec<u64> = (0..n)
+            .map(|i| ((i * 7919 % n) << (bits - 12)) | (i * 31 % 256))
+            .collect();
+        let payload: Vec<u32> = (0..n as u32).collect();
+        let (sorted, order) = if passes == 8 {
+            pollster::block_on(sorter.sort_u64(device, queue, &keys, &payload)).unwrap()
+        } else {
+            let keys: Vec<f32> = keys.iter().map(|k| f32::from_bits(*k as u32)).collect();
+            let (sorted, order) =
+                pollster::block_on(sorter.sort(device, queue, &keys, &payload)).unwrap();
+            (sorted.iter().map(|k| k.to_bits() as u64).collect(), order)
+        };
+        let mut expected = keys.clone();
+        expected.sort();
+        assert_eq!(sorted, expected, "{passes} passes");
+        assert!(order
+            .iter()
+            .zip(&sorted)
+            .all(|(i, k)| keys[*i as usize] == *k));
+    }
+
+    #[test]
+    fn scatter_passes_leave_the_keys_in_buffer_a() {
+        if let Some((device, queue)) = test_device() {
+            check_scatter_passes(&device, &queue, 4);
+        }
+        // the 8 pass sort does not terminate on software adapters
+        if let Some((device, queue)) = test_device_gpu_sort() {
+            check_scatter_passes(&device, &queue, 8);
+        }
+    }
+
+    #[test]
+    fn sort_buffers_are_reallocated_on_grow_and_large_shrink() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let sorter = pollster::block_on(GPURSSorter::new(&device, &queue)).unwrap();
+        let mut buffers = SortBuffers::new(&sorter, &device, 20_000);
+        let keyval_id = |b: &SortBuffers| b.sort_stuff().keyval_a.global_id();
+        let id = keyval_id(&buffers);
+
+        // the same size twice and a shrink to more than half keep the buffers
+        for keysize in [20_000, 20_000, 15_000, 15_000] {
+            assert!(!buffers.resize(&sorter, &device, &queue, keysize));
+            assert_eq!(buffers.keysize(), keysize);
+            assert_eq!(keyval_id(&buffers), id);
+        }
+
+        assert!(buffers.resize(&sorter, &device, &queue, 30_000));
+        assert_eq!(buffers.keysize(), 30_000);
+        assert!(keyval_id(&buffers) != id);
+        assert!(buffers.padded_size >= 30_000);
+        let id = keyval_id(&buffers);
+
+        // less than half of the memory would be used
+        assert!(buffers.resize(&sorter, &device, &queue, 10_000));
+        assert_eq!(buffers.keysize(), 10_000);
+        assert!(keyval_id(&buffers) != id);
+        assert!(buffers.padded_size < 20_000);
+    }
 }
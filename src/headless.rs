@@ -0,0 +1,379 @@
+use std::time::Duration;
+
+use cgmath::Vector2;
+use image::{Rgba, Rgba32FImage, RgbaImage};
+
+use crate::{
+    utils::download_texture, GPUStopwatch, GaussianRenderer, PerspectiveCamera, PointCloud,
+    RenderMode, SplattingArgs,
+};
+
+/// format of the depth stencil attachment needed to render with the given settings
+/// and the settings of the renderer (if any)
+pub fn depth_stencil_format(
+    renderer: &GaussianRenderer,
+    settings: &SplattingArgs,
+) -> Option<wgpu::TextureFormat> {
+    #[cfg(debug_assertions)]
+    if settings.render_mode == RenderMode::SortViolations {
+        return Some(GaussianRenderer::SORT_CHECK_FORMAT);
+    }
+    if settings.render_mode == RenderMode::DominantColor {
+        return Some(GaussianRenderer::DOMINANT_COLOR_FORMAT);
+    }
+    return renderer
+        .with_renderer_settings(*settings)
+        .max_layers_per_pixel
+        .map(|_| GaussianRenderer::LAYER_STENCIL_FORMAT);
+}
+
+/// Renders the point cloud into a new texture with the color format of the renderer
+/// (size of the viewport or the region, `COPY_SRC` and `TEXTURE_BINDING` usage).
+/// Creates the MSAA and depth stencil attachments the renderer needs for the settings.
+/// [`RenderMode::DominantColor`] is rendered with one coverage and one color pass per layer.
+/// The commands are submitted, the texture can be downloaded with [`download_texture`].
+/// Fails if the size is empty or exceeds the texture size limit of the device.
+pub fn render_to_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    renderer: &mut GaussianRenderer,
+    pc: &PointCloud,
+    settings: SplattingArgs,
+    stopwatch: &mut Option<GPUStopwatch>,
+) -> anyhow::Result<wgpu::Texture> {
+    let size = settings.region.map_or(settings.viewport, |r| r.size());
+    let max_size = device.limits().max_texture_dimension_2d;
+    if size.x == 0 || size.y == 0 {
+        return Err(anyhow::anyhow!(
+            "cannot render an empty image ({}x{})",
+            size.x,
+            size.y
+        ));
+    }
+    if size.x > max_size || size.y > max_size {
+        return Err(anyhow::anyhow!(
+            "image size {}x{} exceeds the texture size limit of the device ({})",
+            size.x,
+            size.y,
+            max_size
+        ));
+    }
+    let target = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("render texture"),
+        size: wgpu::Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: renderer.color_format(),
+        usage: wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let sample_count = renderer.msaa_samples();
+    let msaa_view = (sample_count > 1).then(|| {
+        device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("msaa render texture"),
+                size: target.size(),
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format: renderer.color_format(),
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+            .create_view(&Default::default())
+    });
+
+    let stencil_format = depth_stencil_format(renderer, &settings);
+    let stencil_view = stencil_format.map(|format| {
+        device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("layer stencil texture"),
+                size: target.size(),
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+            .create_view(&Default::default())
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("render encoder"),
+    });
+    renderer.prepare(&mut encoder, device, queue, pc, settings, stopwatch);
+    if let Some(stopwatch) = stopwatch {
+        stopwatch.start(&mut encoder, "rasterization").unwrap();
+    }
+    if settings.render_mode == RenderMode::DominantColor {
+        render_dominant_color(
+            device,
+            &mut encoder,
+            renderer,
+            pc,
+            &target,
+            msaa_view.as_ref(),
+            stencil_view.as_ref().unwrap(),
+        );
+    } else {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("render pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: msaa_view.as_ref().unwrap_or(&target_view),
+                resolve_target: msaa_view.as_ref().map(|_| &target_view),
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(renderer.clear_color()),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: stencil_view.as_ref().map(|view| {
+                wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: stencil_format
+                        .is_some_and(|f| f.has_depth_aspect())
+                        .then_some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.),
+                            store: wgpu::StoreOp::Discard,
+                        }),
+                    stencil_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                }
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        renderer.render(&mut render_pass, pc);
+    }
+    if let Some(stopwatch) = stopwatch {
+        stopwatch.stop(&mut encoder, "rasterization").unwrap();
+        stopwatch.end(&mut encoder);
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+    return Ok(target);
+}
+
+/// Renders [`RenderMode::DominantColor`] by peeling the splats front to back.
+/// For every layer the coverage of the layers in front of it is rendered first, then the
+/// splats of the layer are drawn with their blending weight as depth.
+/// The depth buffer is kept between the layers, so the color with the highest weight remains.
+fn render_dominant_color(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    renderer: &GaussianRenderer,
+    pc: &PointCloud,
+    target: &wgpu::Texture,
+    msaa_view: Option<&wgpu::TextureView>,
+    depth_view: &wgpu::TextureView,
+) {
+    let sample_count = renderer.msaa_samples();
+    let attachment = |label, format, sample_count, usage| {
+        device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: target.size(),
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+                view_formats: &[],
+            })
+            .create_view(&Default::default())
+    };
+    let target_view = target.create_view(&Default::default());
+    let coverage = attachment(
+        "coverage texture",
+        renderer.color_format(),
+        1,
+        wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    );
+    let coverage_msaa = (sample_count > 1).then(|| {
+        attachment(
+            "msaa coverage texture",
+            renderer.color_format(),
+            sample_count,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+        )
+    });
+    let coverage_stencil = attachment(
+        "coverage stencil texture",
+        GaussianRenderer::LAYER_STENCIL_FORMAT,
+        sample_count,
+        wgpu::TextureUsages::RENDER_ATTACHMENT,
+    );
+    let coverage_bg = GaussianRenderer::coverage_bind_group(device, &coverage);
+
+    for layer in 0..renderer.dominant_color_layers() {
+        {
+            // clears the coverage for the first layer
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("coverage pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: coverage_msaa.as_ref().unwrap_or(&coverage),
+                    resolve_target: coverage_msaa.as_ref().map(|_| &coverage),
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &coverage_stencil,
+                    depth_ops: None,
+                    stencil_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            if layer > 0 {
+                renderer.render_coverage(&mut render_pass, pc, layer);
+            }
+        }
+        let first = layer == 0;
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("dominant color pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: msaa_view.unwrap_or(&target_view),
+                resolve_target: msaa_view.map(|_| &target_view),
+                ops: wgpu::Operations {
+                    load: if first {
+                        wgpu::LoadOp::Clear(renderer.clear_color())
+                    } else {
+                        wgpu::LoadOp::Load
+                    },
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: if first {
+                        wgpu::LoadOp::Clear(1.)
+                    } else {
+                        wgpu::LoadOp::Load
+                    },
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        renderer.render_dominant_layer(&mut render_pass, pc, layer, &coverage_bg);
+    }
+}
+
+/// Renders the point cloud from the camera with default settings and downloads the image.
+/// The near and far plane of the camera are fitted to the bounding box of the point cloud.
+/// The renderer needs a float color format (`Rgba16Float` or `Rgba32Float`).
+/// Returns the sRGB encoded colors with premultiplied alpha (not clamped).
+pub async fn render_to_image_f32(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    renderer: &mut GaussianRenderer,
+    pc: &PointCloud,
+    mut camera: PerspectiveCamera,
+    resolution: Vector2<u32>,
+) -> anyhow::Result<Rgba32FImage> {
+    camera.fit_near_far(pc.bbox());
+    let settings = SplattingArgs {
+        camera,
+        viewport: resolution,
+        gaussian_scaling: 1.,
+        max_sh_deg: pc.sh_deg(),
+        show_env_map: false,
+        mip_splatting: None,
+        kernel_size: None,
+        clipping_box: None,
+        // past the fade in of the gaussians
+        walltime: Duration::from_secs(100),
+        scene_center: None,
+        scene_extend: None,
+        clip_sphere: None,
+        render_mode: RenderMode::Color,
+        preview_quality: None,
+        max_splat_size: None,
+        max_layers_per_pixel: None,
+        region: None,
+    };
+    let target = render_to_texture(device, queue, renderer, pc, settings, &mut None)?;
+    return download_texture(&target, device, queue, None).await;
+}
+
+/// same as [`render_to_image_f32`] but with 8 bit colors (clamped to [0,1])
+pub async fn render_to_image(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    renderer: &mut GaussianRenderer,
+    pc: &PointCloud,
+    camera: PerspectiveCamera,
+    resolution: Vector2<u32>,
+) -> anyhow::Result<RgbaImage> {
+    let img = render_to_image_f32(device, queue, renderer, pc, camera, resolution).await?;
+    return Ok(RgbaImage::from_fn(img.width(), img.height(), |x, y| {
+        Rgba(
+            img.get_pixel(x, y)
+                .0
+                .map(|v| (v.clamp(0., 1.) * 255.) as u8),
+        )
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point3;
+
+    use super::*;
+    use crate::test_utils::{cpu_renderer, orbit_camera, splat_cloud, test_device};
+
+    #[test]
+    fn render_to_image_shows_the_splat_at_the_center() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        // the outer splats are culled by the fitted near and far plane
+        let pc = PointCloud::new(
+            &device,
+            splat_cloud(&[
+                (Point3::new(0., 0., -1.), 1., [0., 0., 1.]),
+                (Point3::new(0., 0., 0.), 1., [1., 0., 0.]),
+                (Point3::new(0., 0., 1.), 1., [0., 0., 1.]),
+            ]),
+        )
+        .unwrap();
+        let mut renderer = cpu_renderer(&device);
+        let img = pollster::block_on(render_to_image(
+            &device,
+            &queue,
+            &mut renderer,
+            &pc,
+            orbit_camera(0.),
+            Vector2::new(48, 32),
+        ))
+        .unwrap();
+        assert_eq!(img.dimensions(), (48, 32));
+        let center = img.get_pixel(24, 16).0;
+        assert!(
+            center[0] > 200 && center[3] > 200,
+            "center pixel is {center:?}"
+        );
+        assert_eq!(img.get_pixel(0, 0).0, [0; 4]);
+    }
+}
@@ -0,0 +1,67 @@
+use std::path::Path;
+
+/// Per-frame deltas of an animated point cloud.
+///
+/// The sequence is stored as a directory with one `.bin` file per frame (ordered by file name).
+/// Each file contains `num_points` little endian `[f32; 4]` entries: the position offset
+/// `(dx, dy, dz)` and the opacity offset relative to the base point cloud.
+/// Frames replace each other, i.e. the deltas are not accumulated.
+#[derive(Debug, Clone)]
+pub struct FrameSequence {
+    num_points: usize,
+    frames: Vec<Vec<[f32; 4]>>,
+}
+
+impl FrameSequence {
+    pub fn load<P: AsRef<Path>>(dir: P, num_points: usize) -> anyhow::Result<Self> {
+        let mut files = std::fs::read_dir(dir.as_ref())?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        files.retain(|f| f.extension().map_or(false, |e| e == "bin"));
+        files.sort();
+        if files.is_empty() {
+            return Err(anyhow::anyhow!(
+                "no .bin frame files found in '{}'",
+                dir.as_ref().to_string_lossy()
+            ));
+        }
+
+        let frames = files
+            .iter()
+            .map(|f| {
+                let data = std::fs::read(f)?;
+                if data.len() != num_points * 16 {
+                    return Err(anyhow::anyhow!(
+                        "frame '{}' has {} bytes but {} are expected for {} points",
+                        f.to_string_lossy(),
+                        data.len(),
+                        num_points * 16,
+                        num_points
+                    ));
+                }
+                return Ok(data
+                    .chunks_exact(16)
+                    .map(|c| {
+                        let v =
+                            |i: usize| f32::from_le_bytes(c[i * 4..i * 4 + 4].try_into().unwrap());
+                        [v(0), v(1), v(2), v(3)]
+                    })
+                    .collect());
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        return Ok(Self { num_points, frames });
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn num_points(&self) -> usize {
+        self.num_points
+    }
+
+    /// position and opacity deltas of the given frame
+    pub fn frame(&self, index: usize) -> Option<&[[f32; 4]]> {
+        self.frames.get(index).map(|f| f.as_slice())
+    }
+}
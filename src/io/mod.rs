@@ -1,10 +1,10 @@
 
 #[cfg(feature = "npz")]
 use std::io::BufReader;
-use std::io::{Read, Seek};
+use std::io::{Read, Seek, Write};
 
 use bytemuck::Zeroable;
-use cgmath::{Array, EuclideanSpace, InnerSpace, Point3, Vector3};
+use cgmath::{Array, EuclideanSpace, InnerSpace, Matrix, Matrix3, Point3, SquareMatrix, Vector3};
 use half::f16;
 
 use crate::pointcloud::{Aabb, Covariance3D, Gaussian, GaussianCompressed, GaussianQuantization};
@@ -12,11 +12,18 @@ use crate::pointcloud::{Aabb, Covariance3D, Gaussian, GaussianCompressed, Gaussi
 #[cfg(feature = "npz")]
 use self::npz::NpzReader;
 
-use self::ply::PlyReader;
+use self::ply::{PlyChunks, PlyReader};
+#[cfg(feature = "spz")]
+use self::spz::SpzReader;
 
+mod frames;
 #[cfg(feature = "npz")]
 pub mod npz;
 pub mod ply;
+#[cfg(feature = "spz")]
+pub mod spz;
+
+pub use frames::FrameSequence;
 
 pub trait PointCloudReader {
     fn read(&mut self) -> Result<GenericGaussianPointCloud, anyhow::Error>;
@@ -25,6 +32,66 @@ pub trait PointCloudReader {
     fn file_ending() -> &'static str;
 }
 
+/// point cloud file formats, identified by their file ending
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PCDataType {
+    PLY,
+    #[cfg(feature = "npz")]
+    NPZ,
+    #[cfg(feature = "spz")]
+    SPZ,
+}
+
+impl PCDataType {
+    /// format of the file at `path`, fails for unknown endings and formats of disabled features
+    pub fn from_path<P: AsRef<std::path::Path>>(path: P) -> Result<Self, anyhow::Error> {
+        let ending = path
+            .as_ref()
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        return match ending.as_str() {
+            "ply" => Ok(PCDataType::PLY),
+            #[cfg(feature = "npz")]
+            "npz" => Ok(PCDataType::NPZ),
+            #[cfg(feature = "spz")]
+            "spz" => Ok(PCDataType::SPZ),
+            #[cfg(not(feature = "npz"))]
+            "npz" => Err(anyhow::anyhow!("npz files need the 'npz' feature")),
+            #[cfg(not(feature = "spz"))]
+            "spz" => Err(anyhow::anyhow!("spz files need the 'spz' feature")),
+            _ => Err(anyhow::anyhow!(
+                "unknown point cloud file ending '{}'",
+                ending
+            )),
+        };
+    }
+}
+
+/// coordinate system convention of a point cloud file
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AxisConvention {
+    /// y axis points up (no conversion)
+    #[default]
+    YUp,
+    /// z axis points up
+    ZUp,
+    /// custom orthonormal basis that maps file coordinates to renderer coordinates
+    Custom(Matrix3<f32>),
+}
+
+impl AxisConvention {
+    pub fn basis(&self) -> Matrix3<f32> {
+        match self {
+            AxisConvention::YUp => Matrix3::identity(),
+            // rotate -90 degrees around the x axis: (x,y,z) -> (x,z,-y)
+            AxisConvention::ZUp => Matrix3::new(1., 0., 0., 0., 0., -1., 0., 1., 0.),
+            AxisConvention::Custom(m) => *m,
+        }
+    }
+}
+
 pub struct GenericGaussianPointCloud {
     gaussians: Vec<u8>,
     sh_coefs: Vec<u8>,
@@ -40,28 +107,101 @@ pub struct GenericGaussianPointCloud {
     pub up: Option<Vector3<f32>>,
     pub center: Point3<f32>,
     pub aabb: Aabb<f32>,
+
+    /// problems found in the file header while loading (see [`GenericGaussianPointCloud::validate`])
+    header_warnings: Vec<LoadWarning>,
 }
 
 impl GenericGaussianPointCloud {
     pub fn load<'a, R: Read + Seek>(f: R) -> Result<Self, anyhow::Error> {
+        return Self::load_with_progress(f, |_, _| {});
+    }
+
+    /// Same as [`GenericGaussianPointCloud::load`] but fails if [`GenericGaussianPointCloud::validate`]
+    /// reports any problem with the spherical harmonics.
+    pub fn load_strict<R: Read + Seek>(f: R) -> Result<Self, anyhow::Error> {
+        let pc = Self::load(f)?;
+        let warnings = pc.validate();
+        if !warnings.is_empty() {
+            let messages: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+            return Err(anyhow::anyhow!(
+                "invalid sh coefficients: {}",
+                messages.join("; ")
+            ));
+        }
+        return Ok(pc);
+    }
+
+    /// loads a point cloud from memory (e.g. a downloaded file or an embedded asset)
+    pub fn load_from_bytes(bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        return Self::load(std::io::Cursor::new(bytes));
+    }
+
+    /// same as [`GenericGaussianPointCloud::load`] but reports the loading progress
+    /// as `(points_read, total_points)` (at most a few hundred times)
+    pub fn load_with_progress<R: Read + Seek, F: FnMut(u64, u64)>(
+        f: R,
+        progress: F,
+    ) -> Result<Self, anyhow::Error> {
         let mut signature: [u8; 4] = [0; 4];
         let mut f = f;
         f.read_exact(&mut signature)?;
         f.rewind()?;
         if signature.starts_with(PlyReader::<R>::magic_bytes()) {
             let mut ply_reader = PlyReader::new(f)?;
-            return ply_reader.read();
+            return ply_reader.read_with_progress(progress);
         }
         #[cfg(feature = "npz")]
         if signature.starts_with(NpzReader::<R>::magic_bytes()) {
             let mut reader = BufReader::new(f);
             let mut npz_reader = NpzReader::new(&mut reader)?;
-            return npz_reader.read();
+            let pc = npz_reader.read()?;
+            let mut progress = progress;
+            progress(pc.num_points as u64, pc.num_points as u64);
+            return Ok(pc);
+        }
+        #[cfg(feature = "spz")]
+        if signature.starts_with(SpzReader::<R>::magic_bytes()) {
+            let mut spz_reader = SpzReader::new(f)?;
+            let pc = spz_reader.read()?;
+            let mut progress = progress;
+            progress(pc.num_points as u64, pc.num_points as u64);
+            return Ok(pc);
         }
         return Err(anyhow::anyhow!("Unknown file format"));
     }
 
-    fn new(
+    /// Reads a ply file in chunks of `chunk_size` points.
+    /// Each chunk is a separate point cloud that can be appended with [`crate::PointCloud::append`].
+    pub fn load_ply_streaming<R: Read + Seek>(
+        f: R,
+        chunk_size: usize,
+    ) -> Result<PlyChunks<R>, anyhow::Error> {
+        return Ok(PlyChunks::new(PlyReader::new(f)?, chunk_size));
+    }
+
+    /// memory maps the file and parses it directly from the mapped pages
+    /// instead of reading it into memory first
+    #[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+    pub fn load_mmap<P: AsRef<std::path::Path>>(path: P) -> Result<Self, anyhow::Error> {
+        return Self::load_mmap_with_progress(path, |_, _| {});
+    }
+
+    /// memory mapped version of [`GenericGaussianPointCloud::load_with_progress`]
+    #[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+    pub fn load_mmap_with_progress<P: AsRef<std::path::Path>, F: FnMut(u64, u64)>(
+        path: P,
+        progress: F,
+    ) -> Result<Self, anyhow::Error> {
+        let file = std::fs::File::open(path)?;
+        // safety: the file must not be modified while it is mapped
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        #[cfg(unix)]
+        mmap.advise(memmap2::Advice::Sequential)?;
+        return Self::load_with_progress(std::io::Cursor::new(&mmap[..]), progress);
+    }
+
+    pub(crate) fn new(
         gaussians: Vec<Gaussian>,
         sh_coefs: Vec<[[f16; 3]; 16]>,
         sh_deg: u32,
@@ -102,6 +242,7 @@ impl GenericGaussianPointCloud {
             center,
             aabb: bbox,
             compressed: false,
+            header_warnings: Vec::new(),
         }
     }
 
@@ -147,6 +288,7 @@ impl GenericGaussianPointCloud {
             center,
             aabb: bbox,
             compressed: true,
+            header_warnings: Vec::new(),
         }
     }
 
@@ -159,17 +301,186 @@ impl GenericGaussianPointCloud {
     }
 
     pub fn gaussians_compressed(&self) -> anyhow::Result<&[GaussianCompressed]> {
-        if self.compressed {
-            Err(anyhow::anyhow!("Gaussians are compressed"))
+        if !self.compressed {
+            Err(anyhow::anyhow!("Gaussians are not compressed"))
         } else {
             Ok(bytemuck::cast_slice(&self.gaussians))
         }
     }
 
+    /// bounding box of the gaussians including `k` standard deviations of their extent along
+    /// each axis (`k = 0` gives the bounding box of the means)
+    pub fn aabb_with_extent(&self, k: f32) -> Aabb<f32> {
+        let mut bbox = Aabb::new(
+            Point3::from_value(f32::INFINITY),
+            Point3::from_value(f32::NEG_INFINITY),
+        );
+        let mut grow = |xyz: Point3<f16>, cov: &[f16; 6], scale: f32| {
+            let center = xyz.map(|v| v.to_f32());
+            // the standard deviation along an axis is the square root of the diagonal entry
+            let sigma = Vector3::new(cov[0], cov[3], cov[5]).map(|v| v.to_f32().max(0.).sqrt());
+            let extent = sigma * scale * k;
+            bbox.grow(&(center - extent));
+            bbox.grow(&(center + extent));
+        };
+        if self.compressed {
+            let covars = self.covars.as_ref().map_or(&[][..], |c| c.as_slice());
+            let q = self.quantization.unwrap_or_default().scaling_factor;
+            for g in self.gaussians_compressed().unwrap() {
+                let cov = covars.get(g.geometry_idx as usize).map_or([f16::ZERO; 6], |c| c.0);
+                let scale = ((g.scale_factor as i32 - q.zero_point) as f32 * q.scale).exp();
+                grow(g.xyz, &cov, scale);
+            }
+        } else {
+            for g in self.gaussians().unwrap() {
+                grow(g.xyz, &g.cov, 1.);
+            }
+        }
+        if self.num_points == 0 {
+            return Aabb::zeroed();
+        }
+        return bbox;
+    }
+
+    /// converts the point cloud into the renderers coordinate system.
+    /// transforms positions, covariances and degree 1 spherical harmonics
+    /// (higher degrees are left unchanged)
+    pub fn convert_axes(&mut self, convention: AxisConvention) -> anyhow::Result<()> {
+        if convention == AxisConvention::YUp {
+            return Ok(());
+        }
+        if self.compressed {
+            return Err(anyhow::anyhow!(
+                "axis conversion is not supported for compressed point clouds"
+            ));
+        }
+        let r = convention.basis();
+        let mut gaussians: Vec<Gaussian> = bytemuck::pod_collect_to_vec(&self.gaussians);
+        for g in gaussians.iter_mut() {
+            let xyz = r * g.xyz.map(|v| v.to_f32()).to_vec();
+            g.xyz = Point3::from_vec(xyz.map(f16::from_f32));
+
+            let c = g.cov.map(|v| v.to_f32());
+            let cov = Matrix3::new(c[0], c[1], c[2], c[1], c[3], c[4], c[2], c[4], c[5]);
+            let m = r * cov * r.transpose();
+            g.cov = [m[0][0], m[0][1], m[0][2], m[1][1], m[1][2], m[2][2]].map(f16::from_f32);
+        }
+
+        if self.sh_deg > 0 {
+            let mut sh_coefs: Vec<[[f16; 3]; 16]> = bytemuck::pod_collect_to_vec(&self.sh_coefs);
+            for sh in sh_coefs.iter_mut() {
+                for c in 0..3 {
+                    // degree 1 basis functions are proportional to (-y, z, -x)
+                    let v = Vector3::new(-sh[3][c], -sh[1][c], sh[2][c]).map(|v| v.to_f32());
+                    let v = (r * v).map(f16::from_f32);
+                    sh[1][c] = -v.y;
+                    sh[2][c] = v.z;
+                    sh[3][c] = -v.x;
+                }
+            }
+            self.sh_coefs = bytemuck::cast_slice(&sh_coefs).to_vec();
+        }
+
+        let mut bbox: Aabb<f32> = Aabb::zeroed();
+        for g in &gaussians {
+            bbox.grow(&g.xyz.map(|x| x.to_f32()));
+        }
+        let (center, mut up) = plane_from_points(
+            gaussians
+                .iter()
+                .map(|g| g.xyz.cast().unwrap())
+                .collect::<Vec<Point3<f32>>>()
+                .as_slice(),
+        );
+        if bbox.radius() < 10. {
+            up = None;
+        }
+        self.aabb = bbox;
+        self.center = center;
+        self.up = up;
+        self.gaussians = bytemuck::cast_slice(&gaussians).to_vec();
+        return Ok(());
+    }
+
+    /// number of gaussians with a NaN or infinite position or covariance
+    /// (positions beyond the half precision range become infinite)
+    pub fn count_non_finite(&self) -> usize {
+        let finite = |v: &f16| v.is_finite();
+        if self.compressed {
+            return self
+                .gaussians_compressed()
+                .unwrap()
+                .iter()
+                .filter(|g| !(g.xyz.x.is_finite() && g.xyz.y.is_finite() && g.xyz.z.is_finite()))
+                .count();
+        }
+        return self
+            .gaussians()
+            .unwrap()
+            .iter()
+            .filter(|g| {
+                !(g.xyz.x.is_finite()
+                    && g.xyz.y.is_finite()
+                    && g.xyz.z.is_finite()
+                    && g.cov.iter().all(finite))
+            })
+            .count();
+    }
+
+    /// Checks the spherical harmonics for signs of a different channel order or normalization
+    /// than the renderer expects (e.g. files written by other trainers) and the gaussians for
+    /// invalid values. Returns the problems found in the file header and in the data.
+    /// The sh coefficients of compressed point clouds are not checked.
+    pub fn validate(&self) -> Vec<LoadWarning> {
+        let mut warnings = self.header_warnings.clone();
+        let non_finite = self.count_non_finite();
+        if non_finite > 0 {
+            warnings.push(LoadWarning::NonFiniteGaussians { points: non_finite });
+        }
+        if self.compressed || self.num_points == 0 {
+            return warnings;
+        }
+        let sh_coefs: &[[[f16; 3]; 16]] = bytemuck::cast_slice(&self.sh_coefs);
+        let num_coefs = ((self.sh_deg + 1) * (self.sh_deg + 1)) as usize;
+        let non_finite = sh_coefs
+            .iter()
+            .filter(|sh| sh[..num_coefs].iter().flatten().any(|v| !v.is_finite()))
+            .count();
+        if non_finite > 0 {
+            warnings.push(LoadWarning::NonFiniteSh { points: non_finite });
+        }
+        let dc_out_of_range = sh_coefs
+            .iter()
+            .filter(|sh| sh[0].iter().any(|v| v.to_f32().abs() > MAX_SH_DC))
+            .count();
+        if dc_out_of_range as f32 > MAX_DC_OUT_OF_RANGE_FRACTION * self.num_points as f32 {
+            warnings.push(LoadWarning::DcOutOfRange {
+                points: dc_out_of_range,
+                total: self.num_points,
+            });
+        }
+        return warnings;
+    }
+
+    /// sh coefficients in half precision, 16 per point (`[[f16; 3]; 16]`) independent of the degree
     pub fn sh_coefs_buffer(&self) -> &[u8] {
         &self.sh_coefs
     }
 
+    /// Saves the point cloud as ply file that can be loaded again (see [`ply::write_ply`]).
+    /// Compressed point clouds are not supported.
+    pub fn save_ply<W: Write>(&self, writer: W) -> anyhow::Result<()> {
+        return ply::write_ply(
+            writer,
+            self.gaussians()?,
+            bytemuck::cast_slice(&self.sh_coefs),
+            self.sh_deg,
+            self.mip_splatting,
+            self.kernel_size,
+            self.background_color,
+        );
+    }
+
     pub fn gaussian_buffer(&self) -> &[u8] {
         &self.gaussians
     }
@@ -179,6 +490,61 @@ impl GenericGaussianPointCloud {
     }
 }
 
+/// largest plausible absolute value of a degree 0 sh coefficient (a color channel of about 3.3)
+const MAX_SH_DC: f32 = 10.;
+/// fraction of points with an implausible degree 0 coefficient above which the file is reported
+const MAX_DC_OUT_OF_RANGE_FRACTION: f32 = 0.01;
+
+/// potential problem with the spherical harmonics of a loaded point cloud
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadWarning {
+    /// the number of higher order coefficients (`f_rest_*`) does not match the sh degree
+    ShCoefficientCount {
+        sh_deg: u32,
+        expected: usize,
+        found: usize,
+    },
+    /// the sh properties are not stored in index order, the reader expects
+    /// `f_dc_0..2` followed by `f_rest_0..n`
+    ShPropertyOrder,
+    /// degree 0 coefficients (colors) far outside of the usual range
+    DcOutOfRange { points: usize, total: usize },
+    /// coefficients that are NaN or infinite (also after conversion to half precision)
+    NonFiniteSh { points: usize },
+    /// gaussians with NaN or infinite positions or covariances (see [`GenericGaussianPointCloud::count_non_finite`])
+    NonFiniteGaussians { points: usize },
+}
+
+impl std::fmt::Display for LoadWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadWarning::ShCoefficientCount {
+                sh_deg,
+                expected,
+                found,
+            } => write!(
+                f,
+                "found {found} higher order sh coefficients but sh degree {sh_deg} needs {expected}"
+            ),
+            LoadWarning::ShPropertyOrder => write!(
+                f,
+                "sh coefficients are not stored in the expected order (f_dc_0..2, f_rest_0..n)"
+            ),
+            LoadWarning::DcOutOfRange { points, total } => write!(
+                f,
+                "{points} of {total} points have degree 0 sh coefficients above {MAX_SH_DC} (colors stored as rgb or with a different normalization?)"
+            ),
+            LoadWarning::NonFiniteSh { points } => {
+                write!(f, "{points} points have NaN or infinite sh coefficients")
+            }
+            LoadWarning::NonFiniteGaussians { points } => write!(
+                f,
+                "{points} points have NaN or infinite positions or covariances"
+            ),
+        }
+    }
+}
+
 // Fit a plane to a collection of points.
 // Fast, and accurate to within a few degrees.
 // Returns None if the points do not span a plane.
@@ -283,3 +649,86 @@ fn plane_from_points(points: &[Point3<f32>]) -> (Point3<f32>, Option<Vector3<f32
         (centroid, None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::random_point_cloud;
+
+    #[test]
+    fn ply_from_file_and_bytes_match() {
+        let path = std::env::temp_dir().join("web_splat_bytes_test.ply");
+        random_point_cloud(700, 12)
+            .save_ply(std::fs::File::create(&path).unwrap())
+            .unwrap();
+        let from_file =
+            GenericGaussianPointCloud::load(std::fs::File::open(&path).unwrap()).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let from_bytes = GenericGaussianPointCloud::load_from_bytes(&bytes).unwrap();
+        assert_eq!(from_bytes.num_points, 700);
+        assert_eq!(from_bytes.num_points, from_file.num_points);
+        assert_eq!(from_bytes.gaussians, from_file.gaussians);
+        assert_eq!(from_bytes.sh_coefs, from_file.sh_coefs);
+    }
+
+    #[test]
+    #[cfg(feature = "npz")]
+    fn npz_from_file_and_bytes_match() {
+        use npyz::WriterBuilder;
+
+        let n = 50;
+        let path = std::env::temp_dir().join("web_splat_bytes_test.npz");
+        {
+            let options = npyz::zip::write::FileOptions::default();
+            let mut npz = npyz::npz::NpzWriter::create(&path).unwrap();
+            let xyz = (0..n * 3).map(|i| f16::from_f32(i as f32 * 0.01));
+            let mut writer = npz
+                .array::<f16>("xyz", options)
+                .unwrap()
+                .default_dtype()
+                .shape(&[n, 3])
+                .begin_nd()
+                .unwrap();
+            writer.extend(xyz).unwrap();
+            writer.finish().unwrap();
+            // int8 arrays: scaling, rotation, opacity and degree 1 sh coefficients
+            for (name, shape, value) in [
+                ("scaling", vec![n, 3], -40),
+                ("rotation", vec![n, 4], 1),
+                ("opacity", vec![n], 100),
+                ("features_dc", vec![n, 1, 3], 10),
+                ("features_rest", vec![n, 3, 3], 0),
+            ] {
+                let len = shape.iter().product::<u64>() as usize;
+                let mut writer = npz
+                    .array::<i8>(name, options)
+                    .unwrap()
+                    .default_dtype()
+                    .shape(&shape)
+                    .begin_nd()
+                    .unwrap();
+                writer.extend(std::iter::repeat(value).take(len)).unwrap();
+                writer.finish().unwrap();
+            }
+        }
+
+        let from_file =
+            GenericGaussianPointCloud::load(std::fs::File::open(&path).unwrap()).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // the zip central directory is read by seeking in the cursor
+        let from_cursor =
+            GenericGaussianPointCloud::load(std::io::Cursor::new(bytes.clone())).unwrap();
+        let from_bytes = GenericGaussianPointCloud::load_from_bytes(&bytes).unwrap();
+        assert!(from_file.compressed);
+        assert_eq!(from_file.num_points, n as usize);
+        assert_eq!(from_file.sh_deg, 1);
+        for pc in [from_cursor, from_bytes] {
+            assert_eq!(pc.num_points, from_file.num_points);
+            assert_eq!(pc.sh_deg, from_file.sh_deg);
+            assert_eq!(pc.gaussians, from_file.gaussians);
+            assert_eq!(pc.sh_coefs, from_file.sh_coefs);
+        }
+    }
+}
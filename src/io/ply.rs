@@ -2,17 +2,17 @@ use anyhow::Ok;
 use half::f16;
 use ply_rs::ply;
 
-use std::io::{self, BufReader, Read, Seek};
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
 
-use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
 use cgmath::{InnerSpace, Point3, Quaternion, Vector3};
 
 use crate::{
     pointcloud::Gaussian,
-    utils::{build_cov, sh_deg_from_num_coefs, sigmoid},
+    utils::{build_cov, decompose_cov, sh_deg_from_num_coefs, sh_num_coefficients, sigmoid},
 };
 
-use super::{GenericGaussianPointCloud, PointCloudReader};
+use super::{GenericGaussianPointCloud, LoadWarning, PointCloudReader};
 
 pub struct PlyReader<R: Read + Seek> {
     header: ply_rs::ply::Header,
@@ -22,13 +22,19 @@ pub struct PlyReader<R: Read + Seek> {
     mip_splatting: Option<bool>,
     kernel_size: Option<f32>,
     background_color: Option<[f32; 3]>,
+    /// file stores the 3d covariance (cov_0..cov_5) instead of scale and rotation
+    covariance: bool,
+    /// number of points that were already read
+    points_read: usize,
+    /// problems with the sh properties in the header
+    warnings: Vec<LoadWarning>,
 }
 
 impl<R: io::Read + io::Seek> PlyReader<R> {
     pub fn new(reader: R) -> Result<Self, anyhow::Error> {
         let mut reader = BufReader::new(reader);
         let parser = ply_rs::parser::Parser::<ply_rs::ply::DefaultElement>::new();
-        let header = parser.read_header(&mut reader).unwrap();
+        let header = parser.read_header(&mut reader)?;
         let sh_deg = Self::file_sh_deg(&header)?;
         let num_points = Self::num_points(&header)?;
         let mip_splatting = Self::mip_splatting(&header)?;
@@ -36,6 +42,11 @@ impl<R: io::Read + io::Seek> PlyReader<R> {
         let background_color = Self::background_color(&header)
             .map_err(|e| log::warn!("could not parse background_color: {}", e))
             .unwrap_or_default();
+        let covariance = Self::covariance(&header)?;
+        let warnings = Self::sh_warnings(&header, sh_deg);
+        for warning in &warnings {
+            log::warn!("{}", warning);
+        }
         Ok(Self {
             header,
             reader,
@@ -44,6 +55,9 @@ impl<R: io::Read + io::Seek> PlyReader<R> {
             mip_splatting,
             kernel_size,
             background_color,
+            covariance,
+            points_read: 0,
+            warnings,
         })
     }
 
@@ -76,6 +90,26 @@ impl<R: io::Read + io::Seek> PlyReader<R> {
 
         let opacity = sigmoid(self.reader.read_f32::<B>()?);
 
+        let cov = if self.covariance {
+            let mut cov = [0.; 6];
+            self.reader.read_f32_into::<B>(&mut cov)?;
+            cov
+        } else {
+            self.read_scale_rot::<B>()?
+        };
+
+        return Ok((
+            Gaussian {
+                xyz: Point3::from(pos).cast().unwrap(),
+                opacity: f16::from_f32(opacity),
+                cov: cov.map(|x| f16::from_f32(x)),
+            },
+            sh.map(|x| x.map(|y| f16::from_f32(y))),
+        ));
+    }
+
+    /// reads scale and rotation and computes the covariance from them
+    fn read_scale_rot<B: ByteOrder>(&mut self) -> anyhow::Result<[f32; 6]> {
         let scale_1 = self.reader.read_f32::<B>()?.exp();
         let scale_2 = self.reader.read_f32::<B>()?.exp();
         let scale_3 = self.reader.read_f32::<B>()?.exp();
@@ -87,16 +121,20 @@ impl<R: io::Read + io::Seek> PlyReader<R> {
         let rot_3 = self.reader.read_f32::<B>()?;
         let rot = Quaternion::new(rot_0, rot_1, rot_2, rot_3).normalize();
 
-        let cov = build_cov(rot, scale);
+        return Ok(build_cov(rot, scale));
+    }
 
-        return Ok((
-            Gaussian {
-                xyz: Point3::from(pos).cast().unwrap(),
-                opacity: f16::from_f32(opacity),
-                cov: cov.map(|x| f16::from_f32(x)),
-            },
-            sh.map(|x| x.map(|y| f16::from_f32(y))),
-        ));
+    /// checks whether the file contains a precomputed covariance (upper triangle as cov_0..cov_5)
+    fn covariance(header: &ply::Header) -> anyhow::Result<bool> {
+        let properties = &header.elements["vertex"].properties;
+        let has_cov = (0..6).all(|i| properties.contains_key(&format!("cov_{i}")));
+        let has_scale_rot = properties.contains_key("scale_0") && properties.contains_key("rot_0");
+        if !has_cov && !has_scale_rot {
+            return Err(anyhow::anyhow!(
+                "ply file contains neither scale and rotation nor covariance"
+            ));
+        }
+        Ok(has_cov && !has_scale_rot)
     }
 
     fn file_sh_deg(header: &ply::Header) -> Result<u32, anyhow::Error> {
@@ -112,6 +150,35 @@ impl<R: io::Read + io::Seek> PlyReader<R> {
         Ok(file_sh_deg)
     }
 
+    /// Checks that the sh properties can be read in the fixed order of [`PlyReader::read_line`].
+    /// The degree is derived from the total number of `f_*` properties, so additional or
+    /// missing coefficients can go unnoticed otherwise.
+    fn sh_warnings(header: &ply::Header, sh_deg: u32) -> Vec<LoadWarning> {
+        let mut warnings = Vec::new();
+        let names: Vec<&String> = header.elements["vertex"]
+            .properties
+            .keys()
+            .filter(|k| k.starts_with("f_"))
+            .collect();
+        let found = names.iter().filter(|k| k.starts_with("f_rest_")).count();
+        let expected = ((sh_deg + 1) * (sh_deg + 1) - 1) as usize * 3;
+        if found != expected {
+            warnings.push(LoadWarning::ShCoefficientCount {
+                sh_deg,
+                expected,
+                found,
+            });
+        }
+        let in_order = names.iter().enumerate().all(|(i, name)| match i {
+            0..=2 => **name == format!("f_dc_{i}"),
+            _ => **name == format!("f_rest_{}", i - 3),
+        });
+        if !in_order {
+            warnings.push(LoadWarning::ShPropertyOrder);
+        }
+        return warnings;
+    }
+
     fn num_points(header: &ply::Header) -> Result<usize, anyhow::Error> {
         Ok(header
             .elements
@@ -161,38 +228,112 @@ impl<R: io::Read + io::Seek> PlyReader<R> {
     }
 }
 
-impl<R: io::Read + io::Seek> PointCloudReader for PlyReader<R> {
-    fn read(&mut self) -> Result<GenericGaussianPointCloud, anyhow::Error> {
-        let mut gaussians = Vec::with_capacity(self.num_points);
-        let mut sh_coefs = Vec::with_capacity(self.num_points);
-        match self.header.encoding {
-            ply_rs::ply::Encoding::Ascii => todo!("acsii ply format not supported"),
-            ply_rs::ply::Encoding::BinaryBigEndian => {
-                for _ in 0..self.num_points {
-                    let (g, s) = self.read_line::<BigEndian>(self.sh_deg as usize)?;
-                    gaussians.push(g);
-                    sh_coefs.push(s);
-                }
+impl<R: io::Read + io::Seek> PlyReader<R> {
+    /// same as [`PointCloudReader::read`] but calls `progress(points_read, total_points)`
+    /// every 1/256th of the file (and once at the end)
+    pub fn read_with_progress<F: FnMut(u64, u64)>(
+        &mut self,
+        mut progress: F,
+    ) -> Result<GenericGaussianPointCloud, anyhow::Error> {
+        let total = self.num_points;
+        let offset = self.points_read;
+        let interval = (total / 256).max(1);
+        return self.read_points(total - offset, |i| {
+            let read = offset + i + 1;
+            if read % interval == 0 || read == total {
+                progress(read as u64, total as u64);
             }
-            ply_rs::ply::Encoding::BinaryLittleEndian => {
-                for _ in 0..self.num_points {
-                    let (g, s) = self.read_line::<LittleEndian>(self.sh_deg as usize)?;
-                    gaussians.push(g);
-                    sh_coefs.push(s);
+        });
+    }
+
+    /// Reads the next `max_points` points (or less at the end of the file) as a separate point cloud.
+    /// Returns None once all points were read.
+    pub fn read_chunk(
+        &mut self,
+        max_points: usize,
+    ) -> Result<Option<GenericGaussianPointCloud>, anyhow::Error> {
+        let n = max_points.min(self.num_points - self.points_read);
+        if n == 0 {
+            return Ok(None);
+        }
+        return self.read_points(n, |_| {}).map(Some);
+    }
+
+    fn read_points<F: FnMut(usize)>(
+        &mut self,
+        n: usize,
+        mut on_point: F,
+    ) -> Result<GenericGaussianPointCloud, anyhow::Error> {
+        let mut gaussians = Vec::with_capacity(n);
+        let mut sh_coefs = Vec::with_capacity(n);
+        for i in 0..n {
+            let (g, s) = match self.header.encoding {
+                ply_rs::ply::Encoding::Ascii => {
+                    return Err(anyhow::anyhow!(
+                        "ascii ply format not supported (only binary little and big endian)"
+                    ))
                 }
-            }
-        };
-        return Ok(GenericGaussianPointCloud::new(
+                ply_rs::ply::Encoding::BinaryBigEndian => {
+                    self.read_line::<BigEndian>(self.sh_deg as usize)?
+                }
+                ply_rs::ply::Encoding::BinaryLittleEndian => {
+                    self.read_line::<LittleEndian>(self.sh_deg as usize)?
+                }
+            };
+            gaussians.push(g);
+            sh_coefs.push(s);
+            self.points_read += 1;
+            on_point(i);
+        }
+        let mut pc = GenericGaussianPointCloud::new(
             gaussians,
             sh_coefs,
             self.sh_deg,
-            self.num_points,
+            n,
             self.kernel_size,
             self.mip_splatting,
             self.background_color,
             None,
             None,
-        ));
+        );
+        pc.header_warnings = self.warnings.clone();
+        return Ok(pc);
+    }
+}
+
+/// iterator over the point chunks of a ply file (see [`GenericGaussianPointCloud::load_ply_streaming`])
+pub struct PlyChunks<R: Read + Seek> {
+    reader: PlyReader<R>,
+    chunk_size: usize,
+}
+
+impl<R: Read + Seek> PlyChunks<R> {
+    pub fn new(reader: PlyReader<R>, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must not be zero");
+        Self { reader, chunk_size }
+    }
+
+    /// total number of points in the file
+    pub fn num_points(&self) -> usize {
+        self.reader.num_points
+    }
+
+    pub fn sh_deg(&self) -> u32 {
+        self.reader.sh_deg
+    }
+}
+
+impl<R: Read + Seek> Iterator for PlyChunks<R> {
+    type Item = Result<GenericGaussianPointCloud, anyhow::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.reader.read_chunk(self.chunk_size).transpose()
+    }
+}
+
+impl<R: io::Read + io::Seek> PointCloudReader for PlyReader<R> {
+    fn read(&mut self) -> Result<GenericGaussianPointCloud, anyhow::Error> {
+        return self.read_with_progress(|_, _| {});
     }
 
     fn magic_bytes() -> &'static [u8] {
@@ -203,3 +344,275 @@ impl<R: io::Read + io::Seek> PointCloudReader for PlyReader<R> {
         "ply"
     }
 }
+
+/// Writes the gaussians as binary little endian ply file in the layout of the 3DGS reference
+/// implementation (position, normals, sh coefficients, opacity before the sigmoid,
+/// log scales and rotation as w,x,y,z), which [`PlyReader`] reads again.
+/// The covariances are decomposed into scale and rotation.
+pub fn write_ply<W: Write>(
+    writer: W,
+    gaussians: &[Gaussian],
+    sh_coefs: &[[[f16; 3]; 16]],
+    sh_deg: u32,
+    mip_splatting: Option<bool>,
+    kernel_size: Option<f32>,
+    background_color: Option<[f32; 3]>,
+) -> anyhow::Result<()> {
+    if gaussians.len() != sh_coefs.len() {
+        return Err(anyhow::anyhow!(
+            "{} gaussians but {} sh coefficients",
+            gaussians.len(),
+            sh_coefs.len()
+        ));
+    }
+    let mut writer = BufWriter::new(writer);
+    let num_rest = sh_num_coefficients(sh_deg) as usize - 1;
+
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format binary_little_endian 1.0")?;
+    // same comments as parsed by the reader
+    if let Some(mip) = mip_splatting {
+        writeln!(writer, "comment mip={mip}")?;
+    }
+    if let Some(kernel_size) = kernel_size {
+        writeln!(writer, "comment kernel_size={kernel_size}")?;
+    }
+    if let Some([r, g, b]) = background_color {
+        writeln!(writer, "comment background_color={r},{g},{b}")?;
+    }
+    writeln!(writer, "element vertex {}", gaussians.len())?;
+    let mut properties: Vec<String> = ["x", "y", "z", "nx", "ny", "nz"].map(String::from).to_vec();
+    properties.extend((0..3).map(|i| format!("f_dc_{i}")));
+    properties.extend((0..num_rest * 3).map(|i| format!("f_rest_{i}")));
+    properties.push("opacity".into());
+    properties.extend((0..3).map(|i| format!("scale_{i}")));
+    properties.extend((0..4).map(|i| format!("rot_{i}")));
+    for name in properties {
+        writeln!(writer, "property float {name}")?;
+    }
+    writeln!(writer, "end_header")?;
+
+    for (g, sh) in gaussians.iter().zip(sh_coefs) {
+        let xyz = g.xyz.map(f16::to_f32);
+        let mut line = vec![xyz.x, xyz.y, xyz.z, 0., 0., 0.];
+        line.extend(sh[0].map(f16::to_f32));
+        // higher order coefficients are stored with channel first (shape:[N,3,C])
+        for c in 0..3 {
+            line.extend(sh[1..=num_rest].iter().map(|coef| coef[c].to_f32()));
+        }
+        // inverse of the sigmoid, fully opaque or transparent gaussians stay finite
+        let opacity = g.opacity.to_f32().clamp(1e-6, 1. - 1e-6);
+        line.push((opacity / (1. - opacity)).ln());
+        let (rot, scale) = decompose_cov(g.cov.map(f16::to_f32));
+        // zero extents would be written as -inf
+        let log_scale = scale.map(|s| s.max(1e-12).ln());
+        line.extend([log_scale.x, log_scale.y, log_scale.z]);
+        line.extend([rot.s, rot.v.x, rot.v.y, rot.v.z]);
+        for v in line {
+            writer.write_f32::<LittleEndian>(v)?;
+        }
+    }
+    writer.flush()?;
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::test_utils::random_point_cloud;
+
+    /// saves the point cloud and loads it again
+    fn save_and_load(pc: &GenericGaussianPointCloud) -> GenericGaussianPointCloud {
+        let mut file = Vec::new();
+        pc.save_ply(&mut file).unwrap();
+        return GenericGaussianPointCloud::load_from_bytes(&file).unwrap();
+    }
+
+    #[test]
+    fn save_and_load_roundtrip() {
+        // random cloud with sh degree 3
+        let pc = random_point_cloud(500, 8);
+        let mut rng = StdRng::seed_from_u64(9);
+        let mut sh_coefs: Vec<[[f16; 3]; 16]> = bytemuck::cast_slice(pc.sh_coefs_buffer()).to_vec();
+        for sh in sh_coefs.iter_mut() {
+            for coef in sh[1..].iter_mut() {
+                *coef = [0; 3].map(|_| f16::from_f32(rng.gen_range(-0.5..0.5)));
+            }
+        }
+        let mut gaussians = pc.gaussians().unwrap().to_vec();
+        // fully opaque gaussians stay opaque
+        gaussians[0].opacity = f16::ONE;
+        let pc = GenericGaussianPointCloud::new(
+            gaussians,
+            sh_coefs,
+            3,
+            pc.num_points,
+            Some(0.3),
+            Some(true),
+            Some([0.1, 0.2, 0.3]),
+            None,
+            None,
+        );
+
+        // load -> save -> load
+        let loaded = save_and_load(&pc);
+        let reloaded = save_and_load(&loaded);
+        assert_eq!(reloaded.num_points, 500);
+        assert_eq!(reloaded.sh_deg, 3);
+        assert_eq!(reloaded.kernel_size, Some(0.3));
+        assert_eq!(reloaded.mip_splatting, Some(true));
+        assert_eq!(reloaded.background_color, Some([0.1, 0.2, 0.3]));
+        assert_eq!(reloaded.sh_coefs_buffer(), pc.sh_coefs_buffer());
+        assert_eq!(loaded.sh_coefs_buffer(), pc.sh_coefs_buffer());
+
+        let original = pc.gaussians().unwrap();
+        let gaussians = loaded.gaussians().unwrap();
+        for (i, (a, b)) in gaussians
+            .iter()
+            .zip(reloaded.gaussians().unwrap())
+            .enumerate()
+        {
+            assert_eq!(a.xyz, original[i].xyz);
+            assert_eq!(a.opacity, original[i].opacity);
+            assert_eq!(a.xyz, b.xyz);
+            assert_eq!(a.opacity, b.opacity);
+            // the covariance is decomposed into scale and rotation
+            let max = a.cov.iter().map(|c| c.to_f32().abs()).fold(0., f32::max);
+            for (ca, cb) in a.cov.iter().zip(b.cov) {
+                let diff = (ca.to_f32() - cb.to_f32()).abs();
+                assert!(diff <= max * 2e-3, "{:?} != {:?}", a.cov, b.cov);
+            }
+        }
+    }
+
+    /// converts a binary little endian ply file with float properties to big endian
+    fn to_big_endian(ply: &[u8]) -> Vec<u8> {
+        let end = b"end_header\n";
+        let body = ply.windows(end.len()).position(|w| w == end).unwrap() + end.len();
+        let header = String::from_utf8(ply[..body].to_vec()).unwrap().replace(
+            "format binary_little_endian 1.0",
+            "format binary_big_endian 1.0",
+        );
+        let mut data = header.into_bytes();
+        for v in ply[body..].chunks_exact(4) {
+            data.extend(v.iter().rev());
+        }
+        return data;
+    }
+
+    #[test]
+    fn big_endian_matches_little_endian() {
+        let mut little = Vec::new();
+        random_point_cloud(300, 4).save_ply(&mut little).unwrap();
+        let big = to_big_endian(&little);
+        assert_ne!(big, little);
+
+        let expected = GenericGaussianPointCloud::load_from_bytes(&little).unwrap();
+        let loaded = GenericGaussianPointCloud::load_from_bytes(&big).unwrap();
+        assert_eq!(loaded.num_points, expected.num_points);
+        assert_eq!(loaded.sh_deg, expected.sh_deg);
+        assert_eq!(loaded.sh_coefs_buffer(), expected.sh_coefs_buffer());
+        assert_eq!(
+            bytemuck::cast_slice::<_, u8>(loaded.gaussians().unwrap()),
+            bytemuck::cast_slice::<_, u8>(expected.gaussians().unwrap())
+        );
+    }
+
+    #[test]
+    fn covariance_matches_scale_and_rotation() {
+        let pc = random_point_cloud(300, 5);
+        let gaussians = pc.gaussians().unwrap();
+        let sh_coefs: &[[[f16; 3]; 16]] = bytemuck::cast_slice(pc.sh_coefs_buffer());
+        let mut scale_rot = Vec::new();
+        write_ply(&mut scale_rot, gaussians, sh_coefs, 0, None, None, None).unwrap();
+
+        // same gaussians with the covariance stored directly
+        let mut cov = Vec::new();
+        writeln!(cov, "ply\nformat binary_little_endian 1.0").unwrap();
+        writeln!(cov, "element vertex {}", gaussians.len()).unwrap();
+        let mut properties = ["x", "y", "z", "nx", "ny", "nz"].map(String::from).to_vec();
+        properties.extend((0..3).map(|i| format!("f_dc_{i}")));
+        properties.push("opacity".into());
+        properties.extend((0..6).map(|i| format!("cov_{i}")));
+        for name in properties {
+            writeln!(cov, "property float {name}").unwrap();
+        }
+        writeln!(cov, "end_header").unwrap();
+        for (g, sh) in gaussians.iter().zip(sh_coefs) {
+            let xyz = g.xyz.map(f16::to_f32);
+            let opacity = g.opacity.to_f32().clamp(1e-6, 1. - 1e-6);
+            let mut line = vec![xyz.x, xyz.y, xyz.z, 0., 0., 0.];
+            line.extend(sh[0].map(f16::to_f32));
+            line.push((opacity / (1. - opacity)).ln());
+            line.extend(g.cov.map(f16::to_f32));
+            for v in line {
+                cov.write_f32::<LittleEndian>(v).unwrap();
+            }
+        }
+
+        let from_scale_rot = GenericGaussianPointCloud::load_from_bytes(&scale_rot).unwrap();
+        let from_cov = GenericGaussianPointCloud::load_from_bytes(&cov).unwrap();
+        assert_eq!(from_cov.num_points, from_scale_rot.num_points);
+        assert_eq!(from_cov.sh_coefs_buffer(), from_scale_rot.sh_coefs_buffer());
+        for (a, b) in from_cov
+            .gaussians()
+            .unwrap()
+            .iter()
+            .zip(from_scale_rot.gaussians().unwrap())
+        {
+            assert_eq!(a.xyz, b.xyz);
+            assert_eq!(a.opacity, b.opacity);
+            let max = a.cov.iter().map(|c| c.to_f32().abs()).fold(0., f32::max);
+            for (ca, cb) in a.cov.iter().zip(b.cov) {
+                let diff = (ca.to_f32() - cb.to_f32()).abs();
+                assert!(diff <= max * 2e-3, "{:?} != {:?}", a.cov, b.cov);
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_sh_coefficient_count_is_reported() {
+        // degree 1 needs 9 higher order coefficients, the file has 10
+        let num_rest = 10;
+        let mut ply = Vec::new();
+        writeln!(
+            ply,
+            "ply\nformat binary_little_endian 1.0\nelement vertex 20"
+        )
+        .unwrap();
+        let mut properties = ["x", "y", "z", "nx", "ny", "nz"].map(String::from).to_vec();
+        properties.extend((0..3).map(|i| format!("f_dc_{i}")));
+        properties.extend((0..num_rest).map(|i| format!("f_rest_{i}")));
+        properties.push("opacity".into());
+        properties.extend((0..3).map(|i| format!("scale_{i}")));
+        properties.extend((0..4).map(|i| format!("rot_{i}")));
+        for name in &properties {
+            writeln!(ply, "property float {name}").unwrap();
+        }
+        writeln!(ply, "end_header").unwrap();
+        for _ in 0..20 * properties.len() {
+            ply.write_f32::<LittleEndian>(0.).unwrap();
+        }
+
+        let pc = GenericGaussianPointCloud::load_from_bytes(&ply).unwrap();
+        assert_eq!(pc.sh_deg, 1);
+        assert!(pc.validate().contains(&LoadWarning::ShCoefficientCount {
+            sh_deg: 1,
+            expected: 9,
+            found: 10,
+        }));
+        let Err(err) = GenericGaussianPointCloud::load_strict(std::io::Cursor::new(&ply)) else {
+            panic!("malformed sh coefficients are accepted in strict mode");
+        };
+        assert!(err
+            .to_string()
+            .contains("found 10 higher order sh coefficients"));
+
+        // a well formed file passes the strict mode
+        let mut ply = Vec::new();
+        random_point_cloud(20, 3).save_ply(&mut ply).unwrap();
+        assert!(GenericGaussianPointCloud::load_strict(std::io::Cursor::new(&ply)).is_ok());
+    }
+}
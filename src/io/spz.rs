@@ -0,0 +1,176 @@
+use std::io::{Read, Seek};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use cgmath::{InnerSpace, Point3, Quaternion, Vector3};
+use flate2::read::GzDecoder;
+use half::f16;
+
+use crate::{
+    pointcloud::Gaussian,
+    utils::{build_cov, sh_num_coefficients},
+};
+
+use super::{GenericGaussianPointCloud, PointCloudReader};
+
+const SPZ_MAGIC: u32 = 0x5053474e; // "NGSP"
+
+/// color scale used by spz to quantize the zero order sh coefficient
+const COLOR_SCALE: f32 = 0.15;
+
+/// sign flips of the sh coefficients (degree 1 to 3) when flipping the y and z axis
+const SH_FLIP_YZ: [f32; 15] = [
+    -1., -1., 1., -1., 1., 1., -1., 1., -1., 1., -1., -1., 1., -1., 1.,
+];
+
+/// Reader for gzip compressed spz files (version 2 and 3).
+/// spz stores the points in a right-up-back coordinate system, they are converted to the
+/// right-down-front system of the 3DGS ply files.
+pub struct SpzReader<R: Read + Seek> {
+    reader: GzDecoder<R>,
+    num_points: usize,
+    sh_deg: u32,
+    version: u32,
+    fractional_bits: u8,
+    antialiased: bool,
+}
+
+impl<R: Read + Seek> SpzReader<R> {
+    pub fn new(reader: R) -> Result<Self, anyhow::Error> {
+        let mut reader = GzDecoder::new(reader);
+        let magic = reader.read_u32::<LittleEndian>()?;
+        if magic != SPZ_MAGIC {
+            return Err(anyhow::anyhow!("not a spz file (magic {:#x})", magic));
+        }
+        let version = reader.read_u32::<LittleEndian>()?;
+        if version != 2 && version != 3 {
+            return Err(anyhow::anyhow!(
+                "spz version {} is not supported (only 2 and 3)",
+                version
+            ));
+        }
+        let num_points = reader.read_u32::<LittleEndian>()? as usize;
+        let sh_deg = reader.read_u8()? as u32;
+        if sh_deg > 3 {
+            return Err(anyhow::anyhow!("invalid sh degree {} in spz file", sh_deg));
+        }
+        let fractional_bits = reader.read_u8()?;
+        let flags = reader.read_u8()?;
+        let _reserved = reader.read_u8()?;
+        Ok(Self {
+            reader,
+            num_points,
+            sh_deg,
+            version,
+            fractional_bits,
+            antialiased: flags & 0x1 != 0,
+        })
+    }
+
+    fn read_bytes(&mut self, n: usize) -> anyhow::Result<Vec<u8>> {
+        let mut data = vec![0; n];
+        self.reader.read_exact(&mut data)?;
+        return Ok(data);
+    }
+
+    fn position(&self, data: &[u8]) -> Point3<f32> {
+        let scale = 1. / (1 << self.fractional_bits) as f32;
+        let p = [0, 1, 2].map(|i| {
+            let b = &data[i * 3..i * 3 + 3];
+            // sign extend the 24 bit fixed point value
+            let v = ((b[0] as i32) | (b[1] as i32) << 8 | (b[2] as i32) << 16) << 8 >> 8;
+            v as f32 * scale
+        });
+        return Point3::new(p[0], -p[1], -p[2]);
+    }
+
+    /// returns the rotation as (w, x, y, z)
+    fn rotation(&self, data: &[u8]) -> Quaternion<f32> {
+        let mut r = [0f32; 4]; // x, y, z, w
+        if self.version >= 3 {
+            // smallest three encoding: index of the largest component in the upper two bits
+            // followed by the other three components with 9 bit magnitude and a sign bit each
+            let mut comp = u32::from_le_bytes(data[..4].try_into().unwrap());
+            let largest = (comp >> 30) as usize;
+            let mask = (1 << 9) - 1;
+            let mut sum_squares = 0.;
+            for i in (0..4).rev() {
+                if i != largest {
+                    let mag = comp & mask;
+                    let negative = (comp >> 9) & 1 == 1;
+                    comp >>= 10;
+                    r[i] = std::f32::consts::FRAC_1_SQRT_2 * mag as f32 / mask as f32;
+                    if negative {
+                        r[i] = -r[i];
+                    }
+                    sum_squares += r[i] * r[i];
+                }
+            }
+            r[largest] = (1. - sum_squares).max(0.).sqrt();
+        } else {
+            let xyz = Vector3::new(data[0], data[1], data[2]).map(|v| v as f32 / 127.5 - 1.);
+            r = [xyz.x, xyz.y, xyz.z, (1. - xyz.magnitude2()).max(0.).sqrt()];
+        }
+        return Quaternion::new(r[3], r[0], -r[1], -r[2]).normalize();
+    }
+}
+
+impl<R: Read + Seek> PointCloudReader for SpzReader<R> {
+    fn read(&mut self) -> Result<GenericGaussianPointCloud, anyhow::Error> {
+        let n = self.num_points;
+        let num_coefs = sh_num_coefficients(self.sh_deg) as usize;
+        let rotation_bytes = if self.version >= 3 { 4 } else { 3 };
+
+        let positions = self.read_bytes(n * 9)?;
+        let alphas = self.read_bytes(n)?;
+        let colors = self.read_bytes(n * 3)?;
+        let scales = self.read_bytes(n * 3)?;
+        let rotations = self.read_bytes(n * rotation_bytes)?;
+        let sh_rest = self.read_bytes(n * (num_coefs - 1) * 3)?;
+
+        let mut gaussians = Vec::with_capacity(n);
+        let mut sh_coefs = Vec::with_capacity(n);
+        for i in 0..n {
+            let scale = Vector3::new(scales[i * 3], scales[i * 3 + 1], scales[i * 3 + 2])
+                .map(|v| (v as f32 / 16. - 10.).exp());
+            let rot = self.rotation(&rotations[i * rotation_bytes..]);
+            gaussians.push(Gaussian {
+                xyz: self.position(&positions[i * 9..]).cast().unwrap(),
+                opacity: f16::from_f32(alphas[i] as f32 / 255.),
+                cov: build_cov(rot, scale).map(f16::from_f32),
+            });
+
+            let mut sh = [[f16::ZERO; 3]; 16];
+            for c in 0..3 {
+                sh[0][c] = f16::from_f32((colors[i * 3 + c] as f32 / 255. - 0.5) / COLOR_SCALE);
+            }
+            // coefficients are stored with the color channel last (shape: [N,C,3])
+            for j in 0..num_coefs - 1 {
+                for c in 0..3 {
+                    let v = sh_rest[(i * (num_coefs - 1) + j) * 3 + c] as f32;
+                    sh[j + 1][c] = f16::from_f32((v - 128.) / 128. * SH_FLIP_YZ[j]);
+                }
+            }
+            sh_coefs.push(sh);
+        }
+        return Ok(GenericGaussianPointCloud::new(
+            gaussians,
+            sh_coefs,
+            self.sh_deg,
+            n,
+            None,
+            self.antialiased.then_some(true),
+            None,
+            None,
+            None,
+        ));
+    }
+
+    fn magic_bytes() -> &'static [u8] {
+        // spz files are gzip streams
+        &[0x1f, 0x8b]
+    }
+
+    fn file_ending() -> &'static str {
+        "spz"
+    }
+}
@@ -34,28 +34,42 @@ use winit::{
 
 mod animation;
 mod ui;
-pub use animation::{Animation, Sampler, TrackingShot, Transition};
+pub use animation::{Animation, CameraPath, Sampler, TrackingShot, Transition};
 mod camera;
-pub use camera::{Camera, PerspectiveCamera, PerspectiveProjection};
+pub use camera::{Camera, PerspectiveCamera, PerspectiveProjection, Projection};
+mod colormap;
+pub use colormap::{apply_colormap, Colormap};
 mod controller;
 pub use controller::CameraController;
+mod headless;
+pub use headless::{
+    depth_stencil_format, render_to_image, render_to_image_f32, render_to_texture,
+};
 mod pointcloud;
-pub use pointcloud::PointCloud;
+pub use pointcloud::{LoadError, PointCloud};
 
 pub mod io;
 
 mod renderer;
-pub use renderer::{GaussianRenderer, SplattingArgs};
+pub use renderer::{
+    AutoExposure, ClipSide, GaussianRenderer, RenderMode, RenderRegion, ResortPolicy,
+    SplattingArgs,
+};
 
 mod scene;
-use crate::utils::GPUStopwatch;
+pub use crate::utils::{download_texture, GPUStopwatch};
 
-pub use self::scene::{Scene, SceneCamera, Split};
+pub use self::scene::{CameraOverrides, Scene, SceneCamera, SceneWarning, Split};
 
+pub mod depth_sort;
 pub mod gpu_rs;
 mod ui_renderer;
 mod uniform;
 mod utils;
+mod visibility;
+#[cfg(test)]
+mod test_utils;
+pub use visibility::VisibilityMatrix;
 
 pub struct RenderConfig {
     pub no_vsync: bool,
@@ -79,10 +93,35 @@ impl WGPUContext {
         return WGPUContext::new(&instance, None).await;
     }
 
+    /// creates a headless context on an adapter of the given backends.
+    /// Fails if no adapter of these backends is available.
+    pub async fn new_with_options(
+        backends: wgpu::Backends,
+        power_preference: wgpu::PowerPreference,
+    ) -> anyhow::Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends,
+            ..Default::default()
+        });
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference,
+                force_fallback_adapter: false,
+                compatible_surface: None,
+            })
+            .await
+            .ok_or(anyhow::anyhow!("no adapter found for the backends {:?}", backends))?;
+        return Ok(Self::with_adapter(adapter).await?);
+    }
+
     pub async fn new(instance: &wgpu::Instance, surface: Option<&wgpu::Surface<'static>>) -> Self {
         let adapter = wgpu::util::initialize_adapter_from_env_or_default(instance, surface)
             .await
             .unwrap();
+        return Self::with_adapter(adapter).await.unwrap();
+    }
+
+    async fn with_adapter(adapter: wgpu::Adapter) -> Result<Self, wgpu::RequestDeviceError> {
         log::info!("using {}",adapter.get_info().name);
 
         #[cfg(target_arch = "wasm32")]
@@ -119,14 +158,24 @@ impl WGPUContext {
                 },
                 None,
             )
-            .await
-            .unwrap();
+            .await?;
 
-        Self {
+        Ok(Self {
             device,
             queue,
             adapter,
-        }
+        })
+    }
+
+    /// requests a new adapter and device, e.g. after the device was lost.
+    /// All resources created with the old device have to be recreated
+    /// (see [`GaussianRenderer::on_device_lost`]).
+    pub async fn recreate(
+        &mut self,
+        instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface<'static>>,
+    ) {
+        *self = Self::new(instance, surface).await;
     }
 }
 
@@ -279,6 +328,12 @@ impl WindowContext {
                 walltime: Duration::ZERO,
                 scene_center: None,
                 scene_extend: None,
+                clip_sphere: None,
+                render_mode: RenderMode::Color,
+                preview_quality: None,
+                max_splat_size: None,
+                max_layers_per_pixel: None,
+                region: None,
             },
             pc,
             // camera: view_camera,
@@ -650,6 +705,28 @@ pub async fn open_window<R: Read + Seek + Send + Sync + 'static>(
     config: RenderConfig,
     pointcloud_file_path: Option<PathBuf>,
     scene_file_path: Option<PathBuf>,
+) {
+    open_window_in(
+        file,
+        scene_file,
+        config,
+        pointcloud_file_path,
+        scene_file_path,
+        None,
+    )
+    .await
+}
+
+/// opens the viewer window.
+/// On wasm the viewer renders into the canvas with the given id if set,
+/// otherwise a new canvas is appended to the document body
+async fn open_window_in<R: Read + Seek + Send + Sync + 'static>(
+    file: R,
+    scene_file: Option<R>,
+    config: RenderConfig,
+    pointcloud_file_path: Option<PathBuf>,
+    scene_file_path: Option<PathBuf>,
+    canvas_id: Option<String>,
 ) {
     #[cfg(not(target_arch = "wasm32"))]
     env_logger::init();
@@ -674,14 +751,31 @@ pub async fn open_window<R: Read + Seek + Send + Sync + 'static>(
         PhysicalSize::new(800, 600)
     };
 
-    let window = WindowBuilder::new()
-        .with_title("web-splats")
-        .with_inner_size(window_size)
-        .build(&event_loop)
-        .unwrap();
+    let window_builder = WindowBuilder::new().with_title("web-splats");
+    #[cfg(target_arch = "wasm32")]
+    let window_builder = match &canvas_id {
+        Some(id) => {
+            use wasm_bindgen::JsCast;
+            use winit::platform::web::WindowBuilderExtWebSys;
+            // the canvas keeps the size given by the page
+            let canvas = web_sys::window()
+                .and_then(|win| win.document())
+                .and_then(|doc| doc.get_element_by_id(id))
+                .and_then(|elm| elm.dyn_into::<web_sys::HtmlCanvasElement>().ok())
+                .expect("canvas not found");
+            window_builder.with_canvas(Some(canvas))
+        }
+        None => window_builder.with_inner_size(window_size),
+    };
+    #[cfg(not(target_arch = "wasm32"))]
+    let window_builder = {
+        debug_assert!(canvas_id.is_none(), "canvas is only supported on wasm");
+        window_builder.with_inner_size(window_size)
+    };
+    let window = window_builder.build(&event_loop).unwrap();
 
     #[cfg(target_arch = "wasm32")]
-    {
+    if canvas_id.is_none() {
         use winit::platform::web::WindowExtWebSys;
         // On wasm, append the canvas to the document body
         web_sys::window()
@@ -722,15 +816,12 @@ pub async fn open_window<R: Read + Seek + Send + Sync + 'static>(
     }
 
     #[cfg(target_arch = "wasm32")]
-    web_sys::window()
+    if let Some(spinner) = web_sys::window()
         .and_then(|win| win.document())
-        .and_then(|doc| {
-            doc.get_element_by_id("spinner")
-                .unwrap()
-                .set_attribute("style", "display:none;")
-                .unwrap();
-            doc.body()
-        });
+        .and_then(|doc| doc.get_element_by_id("spinner"))
+    {
+        spinner.set_attribute("style", "display:none;").unwrap();
+    }
 
     let mut last = Instant::now();
 
@@ -789,7 +880,8 @@ pub async fn open_window<R: Read + Seek + Send + Sync + 'static>(
                             Some(state.current_view.map_or(0, |v|v+1) % scene.num_cameras())
                         }
                         else if key == KeyCode::PageDown{
-                            Some(state.current_view.map_or(0, |v|v-1) % scene.num_cameras())
+                            // wrap around to the last camera
+                            Some(state.current_view.map_or(0, |v|v + scene.num_cameras() - 1) % scene.num_cameras())
                         }else{None};
 
                         if let Some(new_camera) = new_camera{
@@ -869,3 +961,27 @@ pub async fn run_wasm(pc: Vec<u8>, scene: Option<Vec<u8>>,pc_file:Option<String>
         scene_file.and_then(|s|PathBuf::from_str(s.as_str()).ok()),
     ));
 }
+
+/// Renders a point cloud (ply file content) into the canvas with the given id.
+/// `scene_json` contains the cameras of the scene, use an empty string if there are none.
+/// The camera is controlled with mouse and keyboard like in the viewer.
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+#[wasm_bindgen]
+pub fn run_web(canvas_id: &str, ply_bytes: &[u8], scene_json: &str) {
+    use std::io::Cursor;
+
+    std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+    // the logger may already be set up by an earlier viewer on the same page
+    let _ = console_log::init();
+    let pc_reader = Cursor::new(ply_bytes.to_vec());
+    let scene_reader = (!scene_json.is_empty()).then(|| Cursor::new(scene_json.as_bytes().to_vec()));
+
+    wasm_bindgen_futures::spawn_local(open_window_in(
+        pc_reader,
+        scene_reader,
+        RenderConfig { no_vsync: false, skybox: None, hdr: false },
+        None,
+        None,
+        Some(canvas_id.to_string()),
+    ));
+}
@@ -1,15 +1,22 @@
 use bytemuck::Zeroable;
 use cgmath::{
-    BaseNum, ElementWise, EuclideanSpace, MetricSpace, Point3, Vector2, Vector3, Vector4,
+    Angle, BaseNum, Deg, ElementWise, EuclideanSpace, InnerSpace, MetricSpace, Point3, Quaternion,
+    Rotation, Vector2, Vector3, Vector4, Zero,
 };
 use half::f16;
 use num_traits::Float;
 use std::fmt::Debug;
 use std::mem;
+use std::time::Duration;
 use wgpu::util::DeviceExt;
 
-use crate::io::GenericGaussianPointCloud;
+use crate::camera::{PerspectiveCamera, PerspectiveProjection};
+use crate::depth_sort::DepthSorter;
+use crate::gpu_rs::{download_buffer, GPURSSorter};
+use crate::io::{FrameSequence, GenericGaussianPointCloud};
+use crate::renderer::{GaussianRenderer, RenderMode, SplattingArgs};
 use crate::uniform::UniformBuffer;
+use crate::utils::download_texture_rgba8;
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
@@ -51,9 +58,48 @@ impl Default for Covariance3D {
     }
 }
 
+/// reasons why a point cloud cannot be uploaded to a device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// a buffer of the point cloud is larger than the device allows
+    InsufficientMemory {
+        buffer: &'static str,
+        needed: u64,
+        available: u64,
+    },
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::InsufficientMemory {
+                buffer,
+                needed,
+                available,
+            } => write!(
+                f,
+                "the scene is too large for the device: the {} needs {} MiB but buffers are limited to {} MiB",
+                buffer,
+                needed >> 20,
+                available >> 20
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
 #[allow(dead_code)]
 pub struct PointCloud {
     splat_2d_buffer: wgpu::Buffer,
+    gaussian_buffer: wgpu::Buffer,
+    sh_buffer: wgpu::Buffer,
+    density_grid_buffer: wgpu::Buffer,
+    /// covariances and quantization of compressed point clouds
+    compressed_buffers: Option<(wgpu::Buffer, UniformBuffer<GaussianQuantization>)>,
+    animation: Option<FrameAnimation>,
+    /// pipelines of the edit shader, created on first use
+    edit_pipelines: Option<EditPipelines>,
 
     bind_group: wgpu::BindGroup,
     render_bind_group: wgpu::BindGroup,
@@ -79,14 +125,77 @@ impl Debug for PointCloud {
 }
 
 impl PointCloud {
+    /// estimated sizes in bytes of the largest buffers needed to render the point cloud
+    /// (including the sort buffers created by the renderer with `sorter`,
+    /// the buffers of [`GPURSSorter::new`] are assumed if it is None)
+    pub fn estimate_buffer_sizes(
+        pc: &GenericGaussianPointCloud,
+        sorter: Option<&dyn DepthSorter>,
+    ) -> Vec<(&'static str, u64)> {
+        let n = pc.num_points as u64;
+        let mut sizes = vec![
+            ("gaussians buffer", pc.gaussian_buffer().len() as u64),
+            ("sh coefs buffer", pc.sh_coefs_buffer().len() as u64),
+            ("2d gaussians buffer", n * mem::size_of::<Splat>() as u64),
+        ];
+        sizes.extend(match sorter {
+            Some(sorter) => sorter.sort_buffer_sizes(pc.num_points),
+            None => GPURSSorter::default_sort_buffer_sizes(pc.num_points),
+        });
+        if let Some(covars) = &pc.covars {
+            sizes.push((
+                "covariances buffer",
+                (covars.len() * mem::size_of::<Covariance3D>()) as u64,
+            ));
+        }
+        return sizes;
+    }
+
+    /// checks the estimated buffer sizes against the limits of a device before allocating them
+    pub fn check_buffer_sizes(
+        limits: &wgpu::Limits,
+        pc: &GenericGaussianPointCloud,
+    ) -> Result<(), LoadError> {
+        let available = limits
+            .max_buffer_size
+            .min(limits.max_storage_buffer_binding_size as u64);
+        let sizes = Self::estimate_buffer_sizes(pc, None);
+        log::info!(
+            "uploading {} gaussians (about {} MiB)",
+            pc.num_points,
+            sizes.iter().map(|(_, size)| size).sum::<u64>() >> 20
+        );
+        for (buffer, needed) in sizes {
+            if needed > available {
+                return Err(LoadError::InsufficientMemory {
+                    buffer,
+                    needed,
+                    available,
+                });
+            }
+        }
+        return Ok(());
+    }
+
     pub fn new(
         device: &wgpu::Device,
         pc: GenericGaussianPointCloud,
     ) -> Result<Self, anyhow::Error> {
+        Self::check_buffer_sizes(&device.limits(), &pc)?;
+        let non_finite = pc.count_non_finite();
+        if non_finite > 0 {
+            log::warn!(
+                "{} gaussians have NaN or infinite positions or covariances, they are drawn at the far plane",
+                non_finite
+            );
+        }
+
         let splat_2d_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("2d gaussians buffer"),
             size: (pc.num_points * mem::size_of::<Splat>()) as u64,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+            usage: wgpu::BufferUsages::VERTEX
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC,
             mapped_at_creation: false,
         });
 
@@ -102,12 +211,22 @@ impl PointCloud {
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("3d gaussians buffer"),
             contents: pc.gaussian_buffer(),
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
         });
 
         let sh_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("sh coefs buffer"),
             contents: pc.sh_coefs_buffer(),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        });
+
+        let density_grid_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("density grid buffer"),
+            contents: &density_grid(&pc)?,
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         });
 
@@ -124,8 +243,13 @@ impl PointCloud {
                 binding: 2,
                 resource: splat_2d_buffer.as_entire_binding(),
             },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: density_grid_buffer.as_entire_binding(),
+            },
         ];
 
+        let mut compressed_buffers = None;
         let bind_group = if pc.compressed() {
             let covars_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Covariances buffer"),
@@ -146,11 +270,13 @@ impl PointCloud {
                 resource: quantization_uniform.buffer().as_entire_binding(),
             });
 
-            device.create_bind_group(&wgpu::BindGroupDescriptor {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
                 label: Some("point cloud bind group (compressed)"),
                 layout: &Self::bind_group_layout_compressed(device),
                 entries: &bind_group_entries,
-            })
+            });
+            compressed_buffers = Some((covars_buffer, quantization_uniform));
+            bind_group
         } else {
             device.create_bind_group(&wgpu::BindGroupDescriptor {
                 label: Some("point cloud bind group"),
@@ -161,6 +287,12 @@ impl PointCloud {
 
         Ok(Self {
             splat_2d_buffer,
+            gaussian_buffer: vertex_buffer,
+            sh_buffer,
+            density_grid_buffer,
+            compressed_buffers,
+            animation: None,
+            edit_pipelines: None,
 
             bind_group,
             render_bind_group,
@@ -185,6 +317,810 @@ impl PointCloud {
         self.compressed
     }
 
+    /// Memory maps a ply file and uploads its gaussians in chunks (see [`PointCloud::append`]),
+    /// so that neither the file nor all parsed gaussians are held in memory at once.
+    /// Spherical harmonics above `max_sh_deg` are not evaluated.
+    /// The center, up direction and density grid are derived from the first chunk.
+    #[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+    pub fn load_ply_mmap<P: AsRef<std::path::Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: P,
+        max_sh_deg: u32,
+    ) -> anyhow::Result<Self> {
+        return Self::load_ply_mmap_chunked(device, queue, path, max_sh_deg, 1 << 20);
+    }
+
+    #[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+    fn load_ply_mmap_chunked<P: AsRef<std::path::Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: P,
+        max_sh_deg: u32,
+        chunk_size: usize,
+    ) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        // safety: the file must not be modified while it is mapped
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        #[cfg(unix)]
+        mmap.advise(memmap2::Advice::Sequential)?;
+        let mut chunks = GenericGaussianPointCloud::load_ply_streaming(
+            std::io::Cursor::new(&mmap[..]),
+            chunk_size,
+        )?
+        .map(|chunk| {
+            return chunk.map(|mut chunk| {
+                chunk.sh_deg = chunk.sh_deg.min(max_sh_deg);
+                chunk
+            });
+        });
+        let Some(first) = chunks.next() else {
+            return Err(anyhow::anyhow!("the ply file contains no gaussians"));
+        };
+        let mut pc = Self::new(device, first?)?;
+        for chunk in chunks {
+            pc.append(device, queue, &chunk?)?;
+        }
+        return Ok(pc);
+    }
+
+    /// Reads a gzip compressed spz file and uploads its gaussians.
+    /// Spherical harmonics above `max_sh_deg` are not evaluated.
+    #[cfg(feature = "spz")]
+    pub fn load_spz<R: std::io::Read + std::io::Seek>(
+        device: &wgpu::Device,
+        reader: R,
+        max_sh_deg: u32,
+    ) -> anyhow::Result<Self> {
+        use crate::io::{spz::SpzReader, PointCloudReader};
+        let mut pc = SpzReader::new(reader)?.read()?;
+        pc.sh_deg = pc.sh_deg.min(max_sh_deg);
+        return Self::new(device, pc);
+    }
+
+    /// sets the per-frame deltas used by [`PointCloud::set_frame`] (only for uncompressed point clouds).
+    /// The current gaussians are used as the base for all frames.
+    pub fn set_frame_sequence(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frames: FrameSequence,
+    ) -> anyhow::Result<()> {
+        if self.compressed {
+            return Err(anyhow::anyhow!(
+                "animations are not supported for compressed point clouds"
+            ));
+        }
+        if frames.num_points() != self.num_points as usize {
+            return Err(anyhow::anyhow!(
+                "frame sequence has {} points but the point cloud has {}",
+                frames.num_points(),
+                self.num_points
+            ));
+        }
+        self.animation = Some(FrameAnimation::new(
+            device,
+            queue,
+            &self.gaussian_buffer,
+            frames,
+        ));
+        return Ok(());
+    }
+
+    /// Appends the gaussians of `chunk` (e.g. from [`GenericGaussianPointCloud::load_ply_streaming`]).
+    /// The GPU buffers are reallocated and the existing gaussians are copied on the GPU,
+    /// the buffers cannot be allocated with spare capacity as the shaders derive the number of points from their size.
+    /// The bounding box grows with the chunk, the density grid of the first chunk is kept.
+    /// Only supported for uncompressed point clouds without animation.
+    pub fn append(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        chunk: &GenericGaussianPointCloud,
+    ) -> anyhow::Result<()> {
+        if self.compressed || chunk.compressed() {
+            return Err(anyhow::anyhow!(
+                "appending is not supported for compressed point clouds"
+            ));
+        }
+        if self.animation.is_some() {
+            return Err(anyhow::anyhow!(
+                "appending is not supported for animated point clouds"
+            ));
+        }
+        if chunk.sh_deg != self.sh_deg {
+            return Err(anyhow::anyhow!(
+                "chunk has sh degree {} but the point cloud has {}",
+                chunk.sh_deg,
+                self.sh_deg
+            ));
+        }
+        if chunk.num_points == 0 {
+            return Ok(());
+        }
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("append encoder"),
+        });
+        let (gaussian_buffer, sh_buffer) =
+            self.grow_buffers(device, &mut encoder, chunk.num_points);
+        let gaussian_bytes = self.num_points as u64 * mem::size_of::<Gaussian>() as u64;
+        let sh_bytes = self.num_points as u64 * mem::size_of::<[[f16; 3]; 16]>() as u64;
+        queue.write_buffer(&gaussian_buffer, gaussian_bytes, chunk.gaussian_buffer());
+        queue.write_buffer(&sh_buffer, sh_bytes, chunk.sh_coefs_buffer());
+        queue.submit([encoder.finish()]);
+        self.finish_grow(device, gaussian_buffer, sh_buffer, chunk.num_points);
+        self.bbox.grow_union(&chunk.aabb);
+        return Ok(());
+    }
+
+    /// Merges the gaussians of `other` into this point cloud, so both are sorted and blended
+    /// together (e.g. an object placed in an environment). The gaussians are copied on the GPU.
+    /// The point clouds can have different sh degrees, the merged one has the higher degree.
+    /// The missing coefficients of the lower degree are zero, so its colors do not change.
+    /// Renderers have to be created for the merged sh degree.
+    /// The bounding box grows with `other`, all other properties (center, mip splatting,
+    /// density grid, ...) are kept. Only supported for uncompressed point clouds without animation.
+    pub fn merge(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        other: &PointCloud,
+    ) -> anyhow::Result<()> {
+        if self.compressed || other.compressed {
+            return Err(anyhow::anyhow!(
+                "merging is not supported for compressed point clouds"
+            ));
+        }
+        if self.animation.is_some() || other.animation.is_some() {
+            return Err(anyhow::anyhow!(
+                "merging is not supported for animated point clouds"
+            ));
+        }
+        if other.num_points == 0 {
+            return Ok(());
+        }
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("merge encoder"),
+        });
+        let (gaussian_buffer, sh_buffer) =
+            self.grow_buffers(device, &mut encoder, other.num_points as usize);
+        let gaussian_size = mem::size_of::<Gaussian>() as u64;
+        let sh_size = mem::size_of::<[[f16; 3]; 16]>() as u64;
+        encoder.copy_buffer_to_buffer(
+            &other.gaussian_buffer,
+            0,
+            &gaussian_buffer,
+            self.num_points as u64 * gaussian_size,
+            other.num_points as u64 * gaussian_size,
+        );
+        encoder.copy_buffer_to_buffer(
+            &other.sh_buffer,
+            0,
+            &sh_buffer,
+            self.num_points as u64 * sh_size,
+            other.num_points as u64 * sh_size,
+        );
+        queue.submit([encoder.finish()]);
+        self.finish_grow(
+            device,
+            gaussian_buffer,
+            sh_buffer,
+            other.num_points as usize,
+        );
+        self.sh_deg = self.sh_deg.max(other.sh_deg);
+        self.bbox.grow_union(&other.bbox);
+        return Ok(());
+    }
+
+    // creates gaussian and sh buffers with room for `extra_points` more gaussians
+    // and records copying the existing gaussians to their beginning
+    fn grow_buffers(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        extra_points: usize,
+    ) -> (wgpu::Buffer, wgpu::Buffer) {
+        let gaussian_bytes = self.num_points as u64 * mem::size_of::<Gaussian>() as u64;
+        let sh_bytes = self.num_points as u64 * mem::size_of::<[[f16; 3]; 16]>() as u64;
+        let usage = wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_DST
+            | wgpu::BufferUsages::COPY_SRC;
+        let gaussian_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("3d gaussians buffer"),
+            size: gaussian_bytes + (extra_points * mem::size_of::<Gaussian>()) as u64,
+            usage,
+            mapped_at_creation: false,
+        });
+        let sh_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sh coefs buffer"),
+            size: sh_bytes + (extra_points * mem::size_of::<[[f16; 3]; 16]>()) as u64,
+            usage,
+            mapped_at_creation: false,
+        });
+        encoder.copy_buffer_to_buffer(
+            &self.gaussian_buffer,
+            0,
+            &gaussian_buffer,
+            0,
+            gaussian_bytes,
+        );
+        encoder.copy_buffer_to_buffer(&self.sh_buffer, 0, &sh_buffer, 0, sh_bytes);
+        return (gaussian_buffer, sh_buffer);
+    }
+
+    // switches to the buffers created by grow_buffers (the 2d splat buffer is reallocated)
+    fn finish_grow(
+        &mut self,
+        device: &wgpu::Device,
+        gaussian_buffer: wgpu::Buffer,
+        sh_buffer: wgpu::Buffer,
+        extra_points: usize,
+    ) {
+        let num_points = self.num_points as usize + extra_points;
+        self.splat_2d_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("2d gaussians buffer"),
+            size: (num_points * mem::size_of::<Splat>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        self.render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("point cloud rendering bind group"),
+            layout: &Self::bind_group_layout_render(device),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 2,
+                resource: self.splat_2d_buffer.as_entire_binding(),
+            }],
+        });
+        self.replace_gaussians(device, gaussian_buffer, sh_buffer, num_points as u32);
+    }
+
+    /// Downloads the gaussians and saves them as ply file that can be loaded again
+    /// (see [`crate::io::ply::write_ply`]). Edits made on the GPU (e.g. removed outliers)
+    /// are included, animated point clouds are saved with the current frame.
+    /// Only supported for uncompressed point clouds.
+    pub async fn save_ply<W: std::io::Write>(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        writer: W,
+    ) -> anyhow::Result<()> {
+        if self.compressed {
+            return Err(anyhow::anyhow!(
+                "saving compressed point clouds is not supported"
+            ));
+        }
+        let (gaussians, sh_coefs) = self.download_gaussians(device, queue).await?;
+        return crate::io::ply::write_ply(
+            writer,
+            &gaussians,
+            &sh_coefs,
+            self.sh_deg,
+            self.mip_splatting,
+            self.kernel_size,
+            self.background_color
+                .map(|c| [c.r as f32, c.g as f32, c.b as f32]),
+        );
+    }
+
+    /// Removes all gaussians whose center is further than `max_radius` away from the center
+    /// of the point cloud (e.g. floaters far away from the object).
+    /// The remaining gaussians are compacted on the GPU, their order is not preserved.
+    /// The bounding box is not updated. Only supported for uncompressed point clouds without animation.
+    /// Returns the number of removed gaussians.
+    pub async fn remove_outliers(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        max_radius: f32,
+    ) -> anyhow::Result<u32> {
+        if self.compressed {
+            return Err(anyhow::anyhow!(
+                "removing outliers is not supported for compressed point clouds"
+            ));
+        }
+        if self.animation.is_some() {
+            return Err(anyhow::anyhow!(
+                "cannot remove gaussians from an animated point cloud"
+            ));
+        }
+        let gaussian_size = self.gaussian_buffer.size() / self.num_points as u64;
+        let sh_size = self.sh_buffer.size() / self.num_points as u64;
+
+        let kept_gaussians = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("kept gaussians buffer"),
+            size: self.gaussian_buffer.size(),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let kept_sh = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("kept sh coefs buffer"),
+            size: self.sh_buffer.size(),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let num_kept = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("kept gaussians counter"),
+            contents: bytemuck::bytes_of(&0u32),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        });
+        let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("outlier params buffer"),
+            contents: bytemuck::cast_slice(&[
+                self.center.x,
+                self.center.y,
+                self.center.z,
+                max_radius,
+            ]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let num_kept_staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("kept gaussians counter staging buffer"),
+            size: 4,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/outliers.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("remove outliers pipeline"),
+            layout: None,
+            module: &shader,
+            entry_point: "remove_outliers",
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("remove outliers bind group"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                self.gaussian_buffer.as_entire_binding(),
+                self.sh_buffer.as_entire_binding(),
+                kept_gaussians.as_entire_binding(),
+                kept_sh.as_entire_binding(),
+                num_kept.as_entire_binding(),
+                params.as_entire_binding(),
+            ]
+            .into_iter()
+            .enumerate()
+            .map(|(i, resource)| wgpu::BindGroupEntry {
+                binding: i as u32,
+                resource,
+            })
+            .collect::<Vec<_>>(),
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("remove outliers encoder"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("remove outliers compute pass"),
+                ..Default::default()
+            });
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            let wgs_x = (self.num_points as f32 / 256.0).ceil() as u32;
+            pass.dispatch_workgroups(wgs_x, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&num_kept, 0, &num_kept_staging, 0, 4);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = num_kept_staging.slice(..);
+        let (tx, rx) = futures_intrusive::channel::shared::oneshot_channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| tx.send(result).unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        rx.receive().await.unwrap()?;
+        let kept: u32 = bytemuck::pod_read_unaligned(&slice.get_mapped_range());
+        num_kept_staging.unmap();
+
+        if kept == 0 {
+            return Err(anyhow::anyhow!(
+                "all gaussians are further than {} away from the center",
+                max_radius
+            ));
+        }
+        let removed = self.num_points - kept;
+        if removed == 0 {
+            return Ok(0);
+        }
+
+        // copy into buffers of the exact size as the shaders use the array length
+        let gaussian_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("3d gaussians buffer"),
+            size: gaussian_size * kept as u64,
+            usage: self.gaussian_buffer.usage(),
+            mapped_at_creation: false,
+        });
+        let sh_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sh coefs buffer"),
+            size: sh_size * kept as u64,
+            usage: self.sh_buffer.usage(),
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("compact gaussians encoder"),
+        });
+        encoder.copy_buffer_to_buffer(
+            &kept_gaussians,
+            0,
+            &gaussian_buffer,
+            0,
+            gaussian_buffer.size(),
+        );
+        encoder.copy_buffer_to_buffer(&kept_sh, 0, &sh_buffer, 0, sh_buffer.size());
+        queue.submit(std::iter::once(encoder.finish()));
+
+        self.replace_gaussians(device, gaussian_buffer, sh_buffer, kept);
+        return Ok(removed);
+    }
+
+    /// sorts the gaussians along a Morton (Z-order) curve through the bounding box.
+    /// Spatially close gaussians end up next to each other in memory which improves
+    /// the cache hit rate during preprocessing. The rendered image does not change.
+    /// Only supported for uncompressed point clouds without animation.
+    pub async fn reorder_morton(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> anyhow::Result<()> {
+        if self.compressed {
+            return Err(anyhow::anyhow!(
+                "reordering is not supported for compressed point clouds"
+            ));
+        }
+        if self.animation.is_some() {
+            return Err(anyhow::anyhow!(
+                "cannot reorder the gaussians of an animated point cloud"
+            ));
+        }
+        let sorter = GPURSSorter::new(device, queue).await?;
+        let sort_stuff = sorter.create_sort_stuff(device, self.num_points as usize);
+        GPURSSorter::record_reset_indirect_buffer(
+            &sort_stuff.sorter_dis,
+            &sort_stuff.sorter_uni,
+            queue,
+        );
+
+        let gaussian_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("3d gaussians buffer"),
+            size: self.gaussian_buffer.size(),
+            usage: self.gaussian_buffer.usage(),
+            mapped_at_creation: false,
+        });
+        let sh_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sh coefs buffer"),
+            size: self.sh_buffer.size(),
+            usage: self.sh_buffer.usage(),
+            mapped_at_creation: false,
+        });
+        let bounds = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("morton bounds buffer"),
+            contents: bytemuck::cast_slice(&[
+                self.bbox.min.to_vec().extend(0.),
+                self.bbox.max.to_vec().extend(0.),
+            ]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("morton bind group layout"),
+            entries: &[(0, true), (1, true), (2, false), (3, false)]
+                .map(|(binding, read_only)| wgpu::BindGroupLayoutEntry {
+                    binding,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                })
+                .into_iter()
+                .chain(std::iter::once(wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }))
+                .collect::<Vec<_>>(),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("morton bind group"),
+            layout: &bind_group_layout,
+            entries: &[
+                &self.gaussian_buffer,
+                &self.sh_buffer,
+                &gaussian_buffer,
+                &sh_buffer,
+                &bounds,
+            ]
+            .iter()
+            .enumerate()
+            .map(|(i, b)| wgpu::BindGroupEntry {
+                binding: i as u32,
+                resource: b.as_entire_binding(),
+            })
+            .collect::<Vec<_>>(),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("morton pipeline layout"),
+            bind_group_layouts: &[
+                &bind_group_layout,
+                &GPURSSorter::bind_group_layout_preprocess(device),
+            ],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("morton shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                format!(
+                    "const KEYS_PER_WG:u32 = {:}u;\n{:}",
+                    sorter.keys_per_workgroup(),
+                    include_str!("shaders/morton.wgsl")
+                )
+                .into(),
+            ),
+        });
+        let [codes_pipeline, reorder_pipeline] = ["morton_codes", "reorder"].map(|entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+            })
+        });
+
+        let wgs_x = (self.num_points as f32 / 256.0).ceil() as u32;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("morton reorder encoder"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("morton codes compute pass"),
+                ..Default::default()
+            });
+            pass.set_pipeline(&codes_pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.set_bind_group(1, &sort_stuff.sorter_bg_pre, &[]);
+            pass.dispatch_workgroups(wgs_x, 1, 1);
+        }
+        sorter.record_sort_indirect(
+            sort_stuff.sorter_bg.as_ref().unwrap(),
+            &sort_stuff.sorter_dis,
+            &mut encoder,
+        );
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("morton reorder compute pass"),
+                ..Default::default()
+            });
+            pass.set_pipeline(&reorder_pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.set_bind_group(1, &sort_stuff.sorter_bg_pre, &[]);
+            pass.dispatch_workgroups(wgs_x, 1, 1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        self.replace_gaussians(device, gaussian_buffer, sh_buffer, self.num_points);
+        return Ok(());
+    }
+
+    /// Returns a new point cloud with all gaussians whose opacity is at least `min_opacity`.
+    /// The bounding box, center and density grid are computed for the remaining gaussians.
+    /// Animated point clouds are filtered with the current frame, the frame sequence is not copied.
+    /// Only supported for uncompressed point clouds.
+    pub async fn filter_by_opacity(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        min_opacity: f32,
+    ) -> anyhow::Result<PointCloud> {
+        if self.compressed {
+            return Err(anyhow::anyhow!(
+                "filtering is not supported for compressed point clouds"
+            ));
+        }
+        let (gaussians, sh_coefs) = self.download_gaussians(device, queue).await?;
+        let (gaussians, sh_coefs): (Vec<Gaussian>, Vec<[[f16; 3]; 16]>) = gaussians
+            .into_iter()
+            .zip(sh_coefs)
+            .filter(|(g, _)| g.opacity.to_f32() >= min_opacity)
+            .unzip();
+        if gaussians.is_empty() {
+            return Err(anyhow::anyhow!(
+                "all gaussians have an opacity below {}",
+                min_opacity
+            ));
+        }
+        let num_points = gaussians.len();
+        let pc = GenericGaussianPointCloud::new(
+            gaussians,
+            sh_coefs,
+            self.sh_deg,
+            num_points,
+            self.kernel_size,
+            self.mip_splatting,
+            self.background_color
+                .map(|c| [c.r as f32, c.g as f32, c.b as f32]),
+            None,
+            None,
+        );
+        return Self::new(device, pc);
+    }
+
+    /// multiplies the opacity of all gaussians with `factor` (clamped to [0,1]).
+    /// Only supported for uncompressed point clouds without animation.
+    pub fn scale_opacities(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        factor: f32,
+    ) -> anyhow::Result<()> {
+        self.edit_gaussians(device, queue, Edit::ScaleOpacities(factor))
+    }
+
+    /// Moves all gaussians by `offset`, the bounding box, center and density grid are moved along.
+    /// Positions are stored with half precision, so far away point clouds lose detail.
+    /// Only supported for uncompressed point clouds without animation.
+    pub fn translate(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        offset: Vector3<f32>,
+    ) -> anyhow::Result<()> {
+        self.edit_gaussians(device, queue, Edit::Translate(offset))?;
+        self.bbox = Aabb::new(self.bbox.min + offset, self.bbox.max + offset);
+        self.center += offset;
+        // the grid starts at the minimum of the bounding box (first field of the header)
+        let grid_min: [f32; 3] = self.bbox.min.into();
+        queue.write_buffer(
+            &self.density_grid_buffer,
+            0,
+            bytemuck::cast_slice(&grid_min),
+        );
+        return Ok(());
+    }
+
+    /// runs the edit shader on the gaussian buffer
+    fn edit_gaussians(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        edit: Edit,
+    ) -> anyhow::Result<()> {
+        if self.compressed {
+            return Err(anyhow::anyhow!(
+                "editing is not supported for compressed point clouds"
+            ));
+        }
+        if self.animation.is_some() {
+            // the next frame would overwrite the edit
+            return Err(anyhow::anyhow!(
+                "cannot edit the gaussians of an animated point cloud"
+            ));
+        }
+        let pipelines = self
+            .edit_pipelines
+            .get_or_insert_with(|| EditPipelines::new(device));
+        let (pipeline, offset, opacity_factor) = match edit {
+            Edit::Translate(offset) => (&pipelines.translate, offset, 1.),
+            Edit::ScaleOpacities(factor) => (&pipelines.scale_opacities, Vector3::zero(), factor),
+        };
+        queue.write_buffer(
+            &pipelines.params,
+            0,
+            bytemuck::cast_slice(&[offset.x, offset.y, offset.z, opacity_factor]),
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("edit gaussians bind group"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.gaussian_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: pipelines.params.as_entire_binding(),
+                },
+            ],
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("edit gaussians encoder"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("edit gaussians compute pass"),
+                ..Default::default()
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            let wgs_x = (self.num_points as f32 / 256.0).ceil() as u32;
+            pass.dispatch_workgroups(wgs_x, 1, 1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+        return Ok(());
+    }
+
+    /// downloads the gaussians and sh coefficients of an uncompressed point cloud
+    async fn download_gaussians(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> anyhow::Result<(Vec<Gaussian>, Vec<[[f16; 3]; 16]>)> {
+        let n = self.num_points as usize;
+        let mut gaussians =
+            download_buffer::<Gaussian>(&self.gaussian_buffer, device, queue).await?;
+        let mut sh_coefs =
+            download_buffer::<[[f16; 3]; 16]>(&self.sh_buffer, device, queue).await?;
+        if gaussians.len() < n || sh_coefs.len() < n {
+            return Err(anyhow::anyhow!(
+                "the gaussian buffers are smaller than expected"
+            ));
+        }
+        gaussians.truncate(n);
+        sh_coefs.truncate(n);
+        return Ok((gaussians, sh_coefs));
+    }
+
+    /// swaps the gaussian and sh buffers of an uncompressed point cloud
+    fn replace_gaussians(
+        &mut self,
+        device: &wgpu::Device,
+        gaussian_buffer: wgpu::Buffer,
+        sh_buffer: wgpu::Buffer,
+        num_points: u32,
+    ) {
+        self.bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("point cloud bind group"),
+            layout: &Self::bind_group_layout(device),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: gaussian_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: sh_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.splat_2d_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: self.density_grid_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        self.gaussian_buffer = gaussian_buffer;
+        self.sh_buffer = sh_buffer;
+        self.num_points = num_points;
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.animation.as_ref().map_or(0, |a| a.frames.num_frames())
+    }
+
+    /// applies the deltas of the given frame to the base point cloud on the GPU.
+    /// The bounding box and density grid are not updated.
+    pub fn set_frame(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frame_index: usize,
+    ) -> anyhow::Result<()> {
+        let animation = self
+            .animation
+            .as_ref()
+            .ok_or(anyhow::anyhow!("point cloud has no frame sequence"))?;
+        animation.apply(device, queue, &self.gaussian_buffer, frame_index)
+    }
+
     pub fn num_points(&self) -> u32 {
         self.num_points
     }
@@ -193,6 +1129,22 @@ impl PointCloud {
         self.sh_deg
     }
 
+    /// sum of the sizes of all gpu buffers owned by the point cloud.
+    /// The sort buffers are owned by the renderer and not included.
+    pub fn gpu_memory_bytes(&self) -> u64 {
+        let mut size = self.splat_2d_buffer.size()
+            + self.gaussian_buffer.size()
+            + self.sh_buffer.size()
+            + self.density_grid_buffer.size();
+        if let Some((covars, quantization)) = &self.compressed_buffers {
+            size += covars.size() + quantization.buffer().size();
+        }
+        if let Some(animation) = &self.animation {
+            size += animation.base.size() + animation.deltas.size();
+        }
+        return size;
+    }
+
     pub fn bbox(&self) -> &Aabb<f32> {
         &self.bbox
     }
@@ -203,6 +1155,9 @@ impl PointCloud {
     pub(crate) fn render_bind_group(&self) -> &wgpu::BindGroup {
         &self.render_bind_group
     }
+    pub(crate) fn splat_2d_buffer(&self) -> &wgpu::Buffer {
+        &self.splat_2d_buffer
+    }
 
     pub fn bind_group_layout_compressed(device: &wgpu::Device) -> wgpu::BindGroupLayout {
         device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -258,6 +1213,16 @@ impl PointCloud {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         })
     }
@@ -296,6 +1261,16 @@ impl PointCloud {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         })
     }
@@ -330,6 +1305,193 @@ impl PointCloud {
     pub fn up(&self) -> Option<Vector3<f32>> {
         self.up
     }
+
+    /// renders a square preview image of the point cloud from an elevated three-quarter view
+    /// that fits the whole bounding box.
+    /// Creates its own renderer, so use [`GaussianRenderer`] directly for repeated rendering.
+    pub async fn thumbnail(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: u32,
+    ) -> anyhow::Result<image::RgbaImage> {
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let mut renderer =
+            GaussianRenderer::new(device, queue, format, self.sh_deg, self.compressed).await;
+        return self
+            .render_thumbnail(device, queue, &mut renderer, size)
+            .await;
+    }
+
+    /// renders the thumbnail with the given renderer (for an `Rgba8Unorm` target)
+    async fn render_thumbnail(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &mut GaussianRenderer,
+        size: u32,
+    ) -> anyhow::Result<image::RgbaImage> {
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+
+        let up = self.up.unwrap_or(Vector3::unit_y()).normalize();
+        let side = if up.cross(Vector3::unit_x()).magnitude2() > 1e-6 {
+            up.cross(Vector3::unit_x()).normalize()
+        } else {
+            up.cross(Vector3::unit_z()).normalize()
+        };
+        let dir = ((side + up.cross(side)).normalize() * Deg(30.).cos() + up * Deg(30.).sin())
+            .normalize();
+        let fov = Deg(45.);
+        let distance = self.bbox.radius() / (fov / 2.).sin();
+        let center = self.bbox.center();
+        let position = center + dir * distance;
+        let mut camera = PerspectiveCamera::new(
+            position,
+            Quaternion::look_at(center - position, up),
+            PerspectiveProjection::new(
+                Vector2::new(size, size),
+                Vector2::new(fov, fov),
+                0.01,
+                1000.,
+            ),
+        );
+        camera.fit_near_far(&self.bbox);
+
+        let target = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("thumbnail texture"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let target_view = target.create_view(&Default::default());
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("thumbnail encoder"),
+        });
+        let settings = SplattingArgs {
+            camera,
+            viewport: Vector2::new(size, size),
+            gaussian_scaling: 1.,
+            max_sh_deg: self.sh_deg,
+            show_env_map: false,
+            mip_splatting: None,
+            kernel_size: None,
+            clipping_box: None,
+            // past the fade-in of the viewer, at zero all gaussians are scaled to nothing
+            walltime: Duration::from_secs(100),
+            scene_center: None,
+            scene_extend: None,
+            clip_sphere: None,
+            render_mode: RenderMode::Color,
+            preview_quality: None,
+            max_splat_size: None,
+            max_layers_per_pixel: None,
+            region: None,
+        };
+        renderer.prepare(&mut encoder, device, queue, self, settings, &mut None);
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("thumbnail render pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            renderer.render(&mut render_pass, self);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        return download_texture_rgba8(&target, device, queue).await;
+    }
+}
+
+/// number of density grid cells along the longest side of the bounding box
+const DENSITY_GRID_RESOLUTION: u32 = 64;
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct DensityGridHeader {
+    min: [f32; 3],
+    cell_size: f32,
+    resolution: [u32; 3],
+}
+
+/// Builds a regular grid over the point cloud's bounding box that stores an
+/// approximated ambient occlusion value in [0,1) per cell (used by the preprocess shader).
+/// The value is derived from the number of gaussians in the cell and its 26 neighbors
+/// relative to the mean of all occupied cells. This is not physically based.
+fn density_grid(pc: &GenericGaussianPointCloud) -> anyhow::Result<Vec<u8>> {
+    let positions: Vec<Point3<f32>> = if pc.compressed() {
+        pc.gaussians_compressed()?
+            .iter()
+            .map(|g| g.xyz.map(|v| v.to_f32()))
+            .collect()
+    } else {
+        pc.gaussians()?
+            .iter()
+            .map(|g| g.xyz.map(|v| v.to_f32()))
+            .collect()
+    };
+
+    let size = pc.aabb.size();
+    let cell_size =
+        size.x.max(size.y).max(size.z).max(f32::EPSILON) / DENSITY_GRID_RESOLUTION as f32;
+    let res = size.map(|v| ((v / cell_size).ceil() as u32).clamp(1, DENSITY_GRID_RESOLUTION));
+    let cell_idx = |x: u32, y: u32, z: u32| (x + res.x * (y + res.y * z)) as usize;
+
+    let mut counts = vec![0u32; (res.x * res.y * res.z) as usize];
+    for p in positions.iter() {
+        let c = ((p - pc.aabb.min) / cell_size).map(|v| v.max(0.) as u32);
+        counts[cell_idx(c.x.min(res.x - 1), c.y.min(res.y - 1), c.z.min(res.z - 1))] += 1;
+    }
+
+    let mut neighbors = vec![0f32; counts.len()];
+    for z in 0..res.z {
+        for y in 0..res.y {
+            for x in 0..res.x {
+                let mut n = 0;
+                for nz in z.saturating_sub(1)..(z + 2).min(res.z) {
+                    for ny in y.saturating_sub(1)..(y + 2).min(res.y) {
+                        for nx in x.saturating_sub(1)..(x + 2).min(res.x) {
+                            n += counts[cell_idx(nx, ny, nz)];
+                        }
+                    }
+                }
+                neighbors[cell_idx(x, y, z)] = n as f32;
+            }
+        }
+    }
+
+    let occupied = neighbors.iter().filter(|n| **n > 0.).count().max(1);
+    let mean = neighbors.iter().sum::<f32>() / occupied as f32;
+    let occlusion: Vec<f32> = neighbors
+        .iter()
+        .map(|n| 1. - (-n / mean.max(1.)).exp())
+        .collect();
+
+    let header = DensityGridHeader {
+        min: pc.aabb.min.into(),
+        cell_size,
+        resolution: res.into(),
+    };
+    let mut data = bytemuck::bytes_of(&header).to_vec();
+    data.extend_from_slice(bytemuck::cast_slice(&occlusion));
+    return Ok(data);
 }
 
 #[repr(C)]
@@ -378,6 +1540,142 @@ pub struct GaussianQuantization {
     pub scaling_factor: Quantization,
 }
 
+/// in place edits of the gaussians, see shaders/edit.wgsl
+enum Edit {
+    Translate(Vector3<f32>),
+    ScaleOpacities(f32),
+}
+
+/// pipelines of the edit shader and the buffer with their parameters
+struct EditPipelines {
+    translate: wgpu::ComputePipeline,
+    scale_opacities: wgpu::ComputePipeline,
+    /// offset and opacity factor
+    params: wgpu::Buffer,
+}
+
+impl EditPipelines {
+    fn new(device: &wgpu::Device) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/edit.wgsl"));
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: None,
+                module: &shader,
+                entry_point,
+            })
+        };
+        Self {
+            translate: pipeline("translate"),
+            scale_opacities: pipeline("scale_opacities"),
+            params: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("edit params buffer"),
+                size: mem::size_of::<[f32; 4]>() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+        }
+    }
+}
+
+/// base gaussians and deltas of an animated point cloud
+struct FrameAnimation {
+    frames: FrameSequence,
+    base: wgpu::Buffer,
+    deltas: wgpu::Buffer,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl FrameAnimation {
+    fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        gaussians: &wgpu::Buffer,
+        frames: FrameSequence,
+    ) -> Self {
+        let base = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("base gaussians buffer"),
+            size: gaussians.size(),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let deltas = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("frame deltas buffer"),
+            size: (frames.num_points() * mem::size_of::<[f32; 4]>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("copy base gaussians encoder"),
+        });
+        encoder.copy_buffer_to_buffer(gaussians, 0, &base, 0, gaussians.size());
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/animation.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("animation pipeline"),
+            layout: None,
+            module: &shader,
+            entry_point: "apply_frame",
+        });
+        Self {
+            frames,
+            base,
+            deltas,
+            pipeline,
+        }
+    }
+
+    fn apply(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        gaussians: &wgpu::Buffer,
+        frame_index: usize,
+    ) -> anyhow::Result<()> {
+        let deltas = self.frames.frame(frame_index).ok_or(anyhow::anyhow!(
+            "frame {} out of range (sequence has {} frames)",
+            frame_index,
+            self.frames.num_frames()
+        ))?;
+        queue.write_buffer(&self.deltas, 0, bytemuck::cast_slice(deltas));
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("animation bind group"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.base.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.deltas.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: gaussians.as_entire_binding(),
+                },
+            ],
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("animation encoder"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("animation compute pass"),
+                ..Default::default()
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            let wgs_x = (deltas.len() as f32 / 256.0).ceil() as u32;
+            pass.dispatch_workgroups(wgs_x, 1, 1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+        return Ok(());
+    }
+}
+
 #[repr(C)]
 #[derive(Zeroable, Clone, Copy, Debug)]
 pub struct Aabb<F: Float + BaseNum> {
@@ -453,3 +1751,305 @@ impl Into<Aabb<f32>> for Aabb<f16> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{random_point_cloud, test_device, test_device_gpu_sort};
+
+    #[test]
+    fn frames_replace_the_base_deltas() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let n = 100;
+        let mut pc = PointCloud::new(&device, random_point_cloud(n, 4)).unwrap();
+        let (base, _) = pollster::block_on(pc.download_gaussians(&device, &queue)).unwrap();
+
+        let dir = std::env::temp_dir().join("web_splat_frames_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let delta = [0.25f32, -0.5, 0.125, -0.05];
+        for (name, d) in [("0000.bin", [0.; 4]), ("0001.bin", delta)] {
+            let data: Vec<u8> = (0..n)
+                .flat_map(|_| bytemuck::cast::<_, [u8; 16]>(d))
+                .collect();
+            std::fs::write(dir.join(name), data).unwrap();
+        }
+        let frames = FrameSequence::load(&dir, n).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        pc.set_frame_sequence(&device, &queue, frames).unwrap();
+        assert_eq!(pc.num_frames(), 2);
+
+        pc.set_frame(&device, &queue, 1).unwrap();
+        let (moved, _) = pollster::block_on(pc.download_gaussians(&device, &queue)).unwrap();
+        for (a, b) in base.iter().zip(&moved) {
+            let expected = a.xyz.map(f16::to_f32) + Vector3::new(delta[0], delta[1], delta[2]);
+            assert!(b.xyz.map(f16::to_f32).distance(expected) < 2e-3);
+            let opacity = (a.opacity.to_f32() + delta[3]).clamp(0., 1.);
+            assert!((b.opacity.to_f32() - opacity).abs() < 1e-3);
+        }
+
+        // the deltas are not accumulated
+        pc.set_frame(&device, &queue, 0).unwrap();
+        let (reset, _) = pollster::block_on(pc.download_gaussians(&device, &queue)).unwrap();
+        assert!(base
+            .iter()
+            .zip(&reset)
+            .all(|(a, b)| a.xyz == b.xyz && a.opacity == b.opacity));
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn mmap_loader_matches_the_standard_loader() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let path = std::env::temp_dir().join("web_splat_mmap_test.ply");
+        let file = std::fs::File::create(&path).unwrap();
+        random_point_cloud(2500, 8)
+            .save_ply(std::io::BufWriter::new(file))
+            .unwrap();
+
+        let file = std::fs::File::open(&path).unwrap();
+        let expected = PointCloud::new(
+            &device,
+            GenericGaussianPointCloud::load(std::io::BufReader::new(file)).unwrap(),
+        )
+        .unwrap();
+        let expected_data =
+            pollster::block_on(expected.download_gaussians(&device, &queue)).unwrap();
+        // one chunk and several chunks
+        let loaded = [
+            PointCloud::load_ply_mmap(&device, &queue, &path, 3).unwrap(),
+            PointCloud::load_ply_mmap_chunked(&device, &queue, &path, 3, 1000).unwrap(),
+        ];
+        std::fs::remove_file(&path).unwrap();
+        for pc in loaded {
+            assert_eq!(pc.num_points(), expected.num_points());
+            assert_eq!(pc.sh_deg(), expected.sh_deg());
+            assert_eq!(pc.bbox().min, expected.bbox().min);
+            assert_eq!(pc.bbox().max, expected.bbox().max);
+            let data = pollster::block_on(pc.download_gaussians(&device, &queue)).unwrap();
+            assert_eq!(
+                bytemuck::cast_slice::<_, u8>(&data.0),
+                bytemuck::cast_slice::<_, u8>(&expected_data.0)
+            );
+            assert_eq!(data.1, expected_data.1);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "spz")]
+    fn spz_round_trip_keeps_count_and_bbox() {
+        use std::io::Write;
+        let Some((device, _queue)) = test_device() else {
+            return;
+        };
+        let positions: [[f32; 3]; 4] = [
+            [0., 0., 0.],
+            [1.5, -2., 0.25],
+            [-3., 1., 2.],
+            [0.5, 4., -1.],
+        ];
+        let n = positions.len();
+        let mut data = Vec::new();
+        data.extend_from_slice(&0x5053474eu32.to_le_bytes());
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&(n as u32).to_le_bytes());
+        // sh degree, fractional bits, flags, reserved
+        data.extend_from_slice(&[0, 12, 0, 0]);
+        for p in positions {
+            for v in p {
+                data.extend_from_slice(&(((v * 4096.) as i32).to_le_bytes()[..3]));
+            }
+        }
+        data.extend(std::iter::repeat(255).take(n)); // alpha
+        data.extend(std::iter::repeat(128).take(n * 3)); // color
+        data.extend(std::iter::repeat(144).take(n * 3)); // scale
+        data.extend(std::iter::repeat(128).take(n * 3)); // rotation
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&data).unwrap();
+        let spz = encoder.finish().unwrap();
+
+        assert_eq!(
+            crate::io::PCDataType::from_path("scene.spz").unwrap(),
+            crate::io::PCDataType::SPZ
+        );
+        let pc = PointCloud::load_spz(&device, std::io::Cursor::new(spz), 3).unwrap();
+        assert_eq!(pc.num_points(), n as u32);
+        assert_eq!(pc.sh_deg(), 0);
+        // spz is right-up-back, the loader flips y and z
+        let expected = positions.map(|p| Point3::new(p[0], -p[1], -p[2]));
+        let mut bbox = Aabb::new(expected[0], expected[0]);
+        for p in &expected {
+            bbox.grow(p);
+        }
+        assert!((pc.bbox().min - bbox.min).magnitude() < 1e-3);
+        assert!((pc.bbox().max - bbox.max).magnitude() < 1e-3);
+    }
+
+    /// checks the size of a thumbnail and that it shows the whole point cloud
+    fn check_thumbnail(img: &image::RgbaImage, size: u32) {
+        assert_eq!(img.dimensions(), (size, size));
+        // the background is transparent
+        let covered = img.pixels().filter(|p| p.0[3] > 0).count() as u32;
+        assert!(
+            covered > size * size / 10,
+            "only {covered} pixels are covered"
+        );
+        // the cloud is framed, so the corners stay empty
+        for (x, y) in [(0, 0), (size - 1, 0), (0, size - 1), (size - 1, size - 1)] {
+            assert_eq!(img.get_pixel(x, y).0[3], 0);
+        }
+    }
+
+    #[test]
+    fn thumbnail_shows_the_point_cloud() {
+        if let Some((device, queue)) = test_device() {
+            let pc = PointCloud::new(&device, random_point_cloud(2000, 15)).unwrap();
+            // the gpu sort is not reliable on software adapters
+            let mut renderer = GaussianRenderer::with_sorter(
+                &device,
+                wgpu::TextureFormat::Rgba8Unorm,
+                pc.sh_deg(),
+                false,
+                Box::new(crate::depth_sort::CpuSorter::new(&device)),
+            );
+            let thumbnail = pc.render_thumbnail(&device, &queue, &mut renderer, 48);
+            check_thumbnail(&pollster::block_on(thumbnail).unwrap(), 48);
+        }
+        if let Some((device, queue)) = test_device_gpu_sort() {
+            let pc = PointCloud::new(&device, random_point_cloud(2000, 15)).unwrap();
+            let thumbnail = pc.thumbnail(&device, &queue, 40);
+            check_thumbnail(&pollster::block_on(thumbnail).unwrap(), 40);
+        }
+    }
+
+    #[test]
+    fn remove_outliers_drops_a_distant_floater() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let raw = random_point_cloud(1000, 16);
+        let mut gaussians = raw.gaussians().unwrap().to_vec();
+        let mut sh_coefs: Vec<[[f16; 3]; 16]> =
+            bytemuck::cast_slice(raw.sh_coefs_buffer()).to_vec();
+        let floater = Point3::new(50., 50., 50.).map(f16::from_f32);
+        gaussians.insert(
+            500,
+            Gaussian {
+                xyz: floater,
+                ..gaussians[0]
+            },
+        );
+        sh_coefs.insert(500, sh_coefs[0]);
+        let raw = GenericGaussianPointCloud::new(
+            gaussians, sh_coefs, 0, 1001, None, None, None, None, None,
+        );
+        let mut pc = PointCloud::new(&device, raw).unwrap();
+
+        let removed = pollster::block_on(pc.remove_outliers(&device, &queue, 5.)).unwrap();
+        assert_eq!(removed, 1);
+        assert_eq!(pc.num_points(), 1000);
+        let (kept, _) = pollster::block_on(pc.download_gaussians(&device, &queue)).unwrap();
+        assert_eq!(kept.len(), 1000);
+        assert!(kept.iter().all(|g| g.xyz != floater));
+        // the body is kept (in any order)
+        let mut expected: Vec<[u16; 3]> = random_point_cloud(1000, 16)
+            .gaussians()
+            .unwrap()
+            .iter()
+            .map(|g| g.xyz.map(f16::to_bits).into())
+            .collect();
+        let mut kept: Vec<[u16; 3]> = kept
+            .iter()
+            .map(|g| g.xyz.map(f16::to_bits).into())
+            .collect();
+        expected.sort();
+        kept.sort();
+        assert_eq!(kept, expected);
+    }
+
+    #[test]
+    fn reorder_morton_keeps_close_gaussians_together() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let mut pc = PointCloud::new(&device, random_point_cloud(2000, 17)).unwrap();
+        let mut renderer = GaussianRenderer::with_sorter(
+            &device,
+            wgpu::TextureFormat::Rgba8Unorm,
+            0,
+            false,
+            Box::new(crate::depth_sort::CpuSorter::new(&device)),
+        );
+        let thumbnail = |pc: &PointCloud, renderer: &mut GaussianRenderer| {
+            let img = pc.render_thumbnail(&device, &queue, renderer, 48);
+            return pollster::block_on(img).unwrap();
+        };
+        // mean distance between gaussians that are next to each other in the buffer
+        let neighbor_distance = |pc: &PointCloud| {
+            let (gaussians, _) =
+                pollster::block_on(pc.download_gaussians(&device, &queue)).unwrap();
+            let positions: Vec<Point3<f32>> =
+                gaussians.iter().map(|g| g.xyz.map(f16::to_f32)).collect();
+            let sum: f32 = positions.windows(2).map(|w| w[0].distance(w[1])).sum();
+            return sum / (positions.len() - 1) as f32;
+        };
+        let before = thumbnail(&pc, &mut renderer);
+        let distance_before = neighbor_distance(&pc);
+
+        pollster::block_on(pc.reorder_morton(&device, &queue)).unwrap();
+        assert_eq!(pc.num_points(), 2000);
+        let distance_after = neighbor_distance(&pc);
+        assert!(
+            distance_after < distance_before / 4.,
+            "{distance_after} vs {distance_before}"
+        );
+        assert_eq!(thumbnail(&pc, &mut renderer), before);
+    }
+
+    #[test]
+    fn filter_by_opacity_keeps_opaque_gaussians() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let raw = random_point_cloud(1000, 6);
+        let expected = raw
+            .gaussians()
+            .unwrap()
+            .iter()
+            .filter(|g| g.opacity.to_f32() >= 0.5)
+            .count();
+        let pc = PointCloud::new(&device, raw).unwrap();
+        let filtered = pollster::block_on(pc.filter_by_opacity(&device, &queue, 0.5)).unwrap();
+        assert!(expected > 0 && expected < 1000);
+        assert_eq!(filtered.num_points() as usize, expected);
+        assert!(pollster::block_on(pc.filter_by_opacity(&device, &queue, 2.)).is_err());
+    }
+
+    #[test]
+    fn translate_moves_gaussians_and_bbox() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let mut pc = PointCloud::new(&device, random_point_cloud(1000, 7)).unwrap();
+        let bbox = *pc.bbox();
+        let offset = Vector3::new(1., -2., 0.5);
+        pc.translate(&device, &queue, offset).unwrap();
+        assert_eq!(pc.bbox().min, bbox.min + offset);
+        assert_eq!(pc.bbox().max, bbox.max + offset);
+
+        // the pipelines are reused for further edits
+        pc.scale_opacities(&device, &queue, 0.5).unwrap();
+        let (gaussians, _) = pollster::block_on(pc.download_gaussians(&device, &queue)).unwrap();
+        let first = gaussians[0].xyz.map(f16::to_f32);
+        let mut moved = Aabb::new(first, first);
+        for g in &gaussians {
+            moved.grow(&g.xyz.map(f16::to_f32));
+        }
+        assert!(moved.min.distance(bbox.min + offset) < 1e-2);
+        assert!(moved.max.distance(bbox.max + offset) < 1e-2);
+        assert!(gaussians.iter().all(|g| g.opacity.to_f32() <= 0.5 + 1e-3));
+    }
+}
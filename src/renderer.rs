@@ -1,9 +1,13 @@
-use crate::gpu_rs::{GPURSSorter, PointCloudSortStuff};
+use crate::colormap::Colormap;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::depth_sort::CpuSorter;
+use crate::depth_sort::DepthSorter;
+use crate::gpu_rs::{download_buffer, GPURSSorter, SortBuffers};
 use crate::pointcloud::Aabb;
 use crate::utils::GPUStopwatch;
 use crate::{
     camera::{Camera, PerspectiveCamera, VIEWPORT_Y_FLIP},
-    pointcloud::PointCloud,
+    pointcloud::{PointCloud, Splat},
     uniform::UniformBuffer,
 };
 
@@ -13,10 +17,24 @@ use std::time::Duration;
 
 use wgpu::{include_wgsl, Extent3d, MultisampleState};
 
-use cgmath::{EuclideanSpace, Matrix4, Point3, SquareMatrix, Vector2, Vector4};
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix2, Matrix4, MetricSpace, Point3, SquareMatrix, Vector2,
+    Vector4,
+};
+use half::f16;
 
 pub struct GaussianRenderer {
     pipeline: wgpu::RenderPipeline,
+    pipeline_front_opaque: wgpu::RenderPipeline,
+    pipeline_layered: wgpu::RenderPipeline,
+    /// splat and heatmap pipelines for [`RenderMode::SortViolations`], created on first use
+    #[cfg(debug_assertions)]
+    pipelines_sort_violations: Option<(wgpu::RenderPipeline, wgpu::RenderPipeline)>,
+    /// pipeline for the color passes of [`RenderMode::DominantColor`], created on first use
+    pipeline_dominant: Option<wgpu::RenderPipeline>,
+    render_mode: RenderMode,
+    /// layers per pixel of the prepared frame (from the settings or the renderer)
+    layers_per_pixel: Option<u32>,
     camera: UniformBuffer<CameraUniform>,
 
     render_settings: UniformBuffer<SplattingArgsUniform>,
@@ -26,11 +44,123 @@ pub struct GaussianRenderer {
     #[allow(dead_code)]
     draw_indirect: wgpu::BindGroup,
     color_format: wgpu::TextureFormat,
-    sorter: GPURSSorter,
-    sorter_suff: Option<PointCloudSortStuff>,
+    sh_deg: u32,
+    compressed: bool,
+    sorter: Box<dyn DepthSorter>,
+    sort_buffers: Option<SortBuffers>,
+
+    auto_exposure: Option<AutoExposure>,
+    exposure: f32,
+    /// measures the frame luminance for [`GaussianRenderer::update_exposure`]
+    #[cfg(not(target_arch = "wasm32"))]
+    luminance: LuminancePipeline,
+
+    ambient_occlusion: f32,
+    msaa_samples: u32,
+    cull_margin: f32,
+    depth_bias: f32,
+    background: wgpu::Color,
+    min_opacity: f32,
+    min_screen_size: f32,
+    /// used if [`SplattingArgs::clip_sphere`] is not set
+    clip_sphere: Option<(Point3<f32>, f32, ClipSide)>,
+    /// used if [`SplattingArgs::max_splat_size`] is not set
+    max_screen_size: Option<f32>,
+    /// used if [`SplattingArgs::max_layers_per_pixel`] is not set
+    max_layers_per_pixel: Option<u32>,
+    /// writes the gaussian indices instead of the colors into the splats (see [`GaussianRenderer::pick`])
+    store_index: bool,
+    resort_policy: ResortPolicy,
+    /// inputs of the last frame that was sorted
+    last_sort: Option<SortState>,
+}
+
+/// automatic exposure adaption based on the luminance of the previous frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutoExposure {
+    /// target average luminance
+    pub target: f32,
+    /// adaption speed per frame in [0,1] (1 means immediate adaption)
+    pub speed: f32,
+}
+
+impl Default for AutoExposure {
+    fn default() -> Self {
+        Self {
+            target: 0.18,
+            speed: 0.1,
+        }
+    }
+}
+
+/// when the gaussians are sorted by depth again (see [`GaussianRenderer::set_resort_policy`])
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ResortPolicy {
+    /// sort every frame
+    #[default]
+    Always,
+    /// Reuses the order of the last sorted frame while the camera moved less than `threshold`
+    /// world units and rotated less than `threshold` radians since then.
+    /// Every other change of the settings or the point cloud (except exposure and time) sorts again.
+    OnMotion { threshold: f32 },
+}
+
+/// what the order of a sorted frame depends on
+struct SortState {
+    /// with the near and far plane set to zero (they are fitted to the camera position by the viewer
+    /// and do not change the order)
+    camera: PerspectiveCamera,
+    viewport: Vector2<u32>,
+    /// with exposure and walltime set to zero
+    settings: SplattingArgsUniform,
+    /// changes when the point cloud is replaced or resized
+    splat_buffer: wgpu::Id<wgpu::Buffer>,
+}
+
+impl SortState {
+    fn can_reuse_order(&self, other: &SortState, policy: ResortPolicy) -> bool {
+        let threshold = match policy {
+            ResortPolicy::Always => return false,
+            ResortPolicy::OnMotion { threshold } => threshold,
+        };
+        let translation = self.camera.position.distance(other.camera.position);
+        let rotation = 2.
+            * self
+                .camera
+                .rotation
+                .normalize()
+                .dot(other.camera.rotation.normalize())
+                .abs()
+                .min(1.)
+                .acos();
+        return translation < threshold
+            && rotation < threshold
+            && self.camera.projection == other.camera.projection
+            && self.viewport == other.viewport
+            && bytemuck::bytes_of(&self.settings) == bytemuck::bytes_of(&other.settings)
+            && self.splat_buffer == other.splat_buffer;
+    }
 }
 
 impl GaussianRenderer {
+    /// format of the stencil attachment needed when rendering with `max_layers_per_pixel`
+    pub const LAYER_STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Stencil8;
+
+    /// format of the depth stencil attachment needed for [`RenderMode::SortViolations`]
+    #[cfg(debug_assertions)]
+    pub const SORT_CHECK_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+
+    /// number of out of order splats per pixel up to which the heatmap gets redder
+    #[cfg(debug_assertions)]
+    const SORT_VIOLATION_LEVELS: u32 = 8;
+
+    /// format of the depth stencil attachment of the color passes of [`RenderMode::DominantColor`]
+    pub const DOMINANT_COLOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+
+    /// number of front-most gaussians per pixel searched for [`RenderMode::DominantColor`]
+    /// if `max_layers_per_pixel` is not set
+    pub const DOMINANT_COLOR_LAYERS: u32 = 32;
+
     pub async fn new(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
@@ -38,47 +168,55 @@ impl GaussianRenderer {
         sh_deg: u32,
         compressed: bool,
     ) -> Self {
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("render pipeline layout"),
-            bind_group_layouts: &[
-                &PointCloud::bind_group_layout_render(device), // Needed for points_2d (on binding 2)
-                &GPURSSorter::bind_group_layout_rendering(device), // Needed for indices   (on binding 4)
-            ],
-            push_constant_ranges: &[],
-        });
+        let sorter = Self::create_sorter(device, queue, 4).await;
+        Self::with_sorter(device, color_format, sh_deg, compressed, sorter)
+    }
 
-        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/gaussian.wgsl"));
+    /// creates the gpu sorter for 32 or 64 bit keys or falls back to sorting on the cpu
+    /// if it is not supported by the device
+    async fn create_sorter(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        key_bytes: usize,
+    ) -> Box<dyn DepthSorter> {
+        let sorter = if key_bytes == 8 {
+            GPURSSorter::new_u64(device, queue).await
+        } else {
+            GPURSSorter::new(device, queue).await
+        };
+        match sorter {
+            Ok(sorter) => Box::new(sorter),
+            #[cfg(not(target_arch = "wasm32"))]
+            Err(err) => {
+                log::warn!("cannot create the GPU sorter ({err}), sorting on the CPU instead");
+                Box::new(CpuSorter::new(device))
+            }
+            #[cfg(target_arch = "wasm32")]
+            Err(err) => panic!("cannot create the GPU sorter: {err}"),
+        }
+    }
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("render pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: color_format,
-                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleStrip,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: None,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
-        });
+    /// creates the renderer with an existing sorter (e.g. from [`GPURSSorter::new_cached`]).
+    /// With a 64 bit sorter ([`GPURSSorter::new_u64`]) gaussians at exactly the same depth
+    /// are ordered by their index, so their order does not change between frames (sorting takes twice as long).
+    pub fn with_sorter(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        sh_deg: u32,
+        compressed: bool,
+        sorter: Box<dyn DepthSorter>,
+    ) -> Self {
+        assert!(
+            sorter.key_bytes() == 4 || sorter.key_bytes() == 8,
+            "the renderer needs a sorter for 32 or 64 bit keys"
+        );
+        assert!(
+            color_format.has_color_aspect() && !color_format.is_depth_stencil_format(),
+            "{:?} is not a color format",
+            color_format
+        );
+        let (pipeline, pipeline_front_opaque, pipeline_layered) =
+            Self::create_render_pipelines(device, color_format, 1);
 
         let draw_indirect_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("indirect draw buffer"),
@@ -100,19 +238,49 @@ impl GaussianRenderer {
             }],
         });
 
-        let sorter = GPURSSorter::new(device, queue).await;
-
         let camera = UniformBuffer::new_default(device, Some("camera uniform buffer"));
-        let preprocess = PreprocessPipeline::new(device, sh_deg, compressed);
+        let preprocess = PreprocessPipeline::new(
+            device,
+            sh_deg,
+            compressed,
+            sorter.keys_per_workgroup(),
+            sorter.key_bytes(),
+        );
         GaussianRenderer {
             pipeline,
+            pipeline_front_opaque,
+            pipeline_layered,
+            #[cfg(debug_assertions)]
+            pipelines_sort_violations: None,
+            pipeline_dominant: None,
+            render_mode: RenderMode::default(),
+            layers_per_pixel: None,
             camera,
             preprocess,
             draw_indirect_buffer,
             draw_indirect,
             color_format,
+            sh_deg,
+            compressed,
             sorter,
-            sorter_suff: None,
+            sort_buffers: None,
+            auto_exposure: None,
+            exposure: 1.,
+            #[cfg(not(target_arch = "wasm32"))]
+            luminance: LuminancePipeline::new(device),
+            ambient_occlusion: 0.,
+            msaa_samples: 1,
+            cull_margin: 0.,
+            depth_bias: 0.,
+            background: wgpu::Color::TRANSPARENT,
+            min_opacity: 0.,
+            min_screen_size: 0.,
+            clip_sphere: None,
+            max_screen_size: None,
+            max_layers_per_pixel: None,
+            store_index: false,
+            resort_policy: ResortPolicy::Always,
+            last_sort: None,
             render_settings: UniformBuffer::new_default(
                 device,
                 Some("render settings uniform buffer"),
@@ -120,26 +288,381 @@ impl GaussianRenderer {
         }
     }
 
+    /// recreates all pipelines and buffers on a new device after the old one was lost
+    /// (see [`wgpu::Device::set_device_lost_callback`] and [`crate::WGPUContext::recreate`]).
+    /// Render settings are kept.
+    /// Point clouds are not owned by the renderer and have to be created again with [`PointCloud::new`].
+    pub async fn on_device_lost(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        // a 64 bit sorter keeps breaking depth ties with the gaussian index
+        let sorter = Self::create_sorter(device, queue, self.sorter.key_bytes()).await;
+        let mut renderer = Self::with_sorter(
+            device,
+            self.color_format,
+            self.sh_deg,
+            self.compressed,
+            sorter,
+        );
+        if self.msaa_samples > 1 {
+            (
+                renderer.pipeline,
+                renderer.pipeline_front_opaque,
+                renderer.pipeline_layered,
+            ) = Self::create_render_pipelines(device, self.color_format, self.msaa_samples);
+            renderer.msaa_samples = self.msaa_samples;
+        }
+        renderer.render_mode = self.render_mode;
+        renderer.layers_per_pixel = self.layers_per_pixel;
+        renderer.max_layers_per_pixel = self.max_layers_per_pixel;
+        renderer.auto_exposure = self.auto_exposure;
+        renderer.exposure = self.exposure;
+        renderer.ambient_occlusion = self.ambient_occlusion;
+        renderer.cull_margin = self.cull_margin;
+        renderer.depth_bias = self.depth_bias;
+        renderer.background = self.background;
+        renderer.min_opacity = self.min_opacity;
+        renderer.min_screen_size = self.min_screen_size;
+        renderer.clip_sphere = self.clip_sphere;
+        renderer.max_screen_size = self.max_screen_size;
+        renderer.resort_policy = self.resort_policy;
+        *self = renderer;
+    }
+
+    /// creates the pipelines for color, front opaque color and layered rendering.
+    /// For sRGB formats the colors are decoded in the fragment shader since the hardware
+    /// encodes them on write. Blending then happens in linear space.
+    fn create_render_pipelines(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> (
+        wgpu::RenderPipeline,
+        wgpu::RenderPipeline,
+        wgpu::RenderPipeline,
+    ) {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("render pipeline layout"),
+            bind_group_layouts: &[
+                &PointCloud::bind_group_layout_render(device), // Needed for points_2d (on binding 2)
+                &GPURSSorter::bind_group_layout_rendering(device), // Needed for indices   (on binding 4)
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/gaussian.wgsl"));
+        let (fs_main, fs_front_opaque) = if color_format.is_srgb() {
+            ("fs_main_srgb", "fs_front_opaque_srgb")
+        } else {
+            ("fs_main", "fs_front_opaque")
+        };
+
+        let pipeline = Self::create_pipeline(
+            device,
+            &pipeline_layout,
+            &shader,
+            color_format,
+            "vs_main",
+            fs_main,
+            Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+            None,
+            sample_count,
+        );
+        let pipeline_front_opaque = Self::create_pipeline(
+            device,
+            &pipeline_layout,
+            &shader,
+            color_format,
+            "vs_main",
+            fs_front_opaque,
+            None,
+            None,
+            sample_count,
+        );
+        // draws front to back with the "under" operator and counts the blended
+        // splats per pixel in the stencil buffer
+        let under_blending = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::OneMinusDstAlpha,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let layer_stencil = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::Greater,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op: wgpu::StencilOperation::IncrementClamp,
+        };
+        let pipeline_layered = Self::create_pipeline(
+            device,
+            &pipeline_layout,
+            &shader,
+            color_format,
+            "vs_main_reversed",
+            fs_main,
+            Some(wgpu::BlendState {
+                color: under_blending,
+                alpha: under_blending,
+            }),
+            Some(wgpu::DepthStencilState {
+                format: Self::LAYER_STENCIL_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState {
+                    front: layer_stencil,
+                    back: layer_stencil,
+                    read_mask: 0xff,
+                    write_mask: 0xff,
+                },
+                bias: Default::default(),
+            }),
+            sample_count,
+        );
+        return (pipeline, pipeline_front_opaque, pipeline_layered);
+    }
+
+    /// pipelines for the sort check.
+    /// The splats are drawn with their sort depth and a depth test, fragments failing it
+    /// (blended behind a splat that was drawn before) increment the stencil value.
+    /// The heatmap is drawn afterwards with one fullscreen pass per violation count.
+    #[cfg(debug_assertions)]
+    fn create_sort_violation_pipelines(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> (wgpu::RenderPipeline, wgpu::RenderPipeline) {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("sort check pipeline layout"),
+            bind_group_layouts: &[
+                &PointCloud::bind_group_layout_render(device),
+                &GPURSSorter::bind_group_layout_rendering(device),
+            ],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/gaussian.wgsl"));
+        let fs_main = if color_format.is_srgb() {
+            "fs_main_srgb"
+        } else {
+            "fs_main"
+        };
+        let count_violations = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::Always,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::IncrementClamp,
+            pass_op: wgpu::StencilOperation::Keep,
+        };
+        let splats = Self::create_pipeline(
+            device,
+            &pipeline_layout,
+            &shader,
+            color_format,
+            "vs_main_sort_check",
+            fs_main,
+            Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+            Some(wgpu::DepthStencilState {
+                format: Self::SORT_CHECK_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState {
+                    front: count_violations,
+                    back: count_violations,
+                    read_mask: 0xff,
+                    write_mask: 0xff,
+                },
+                bias: Default::default(),
+            }),
+            sample_count,
+        );
+        // passes where the stencil value is at least the reference
+        let heatmap_stencil = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::LessEqual,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op: wgpu::StencilOperation::Keep,
+        };
+        let heatmap = Self::create_pipeline(
+            device,
+            &pipeline_layout,
+            &shader,
+            color_format,
+            "vs_fullscreen",
+            "fs_sort_violation",
+            Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+            Some(wgpu::DepthStencilState {
+                format: Self::SORT_CHECK_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState {
+                    front: heatmap_stencil,
+                    back: heatmap_stencil,
+                    read_mask: 0xff,
+                    write_mask: 0,
+                },
+                bias: Default::default(),
+            }),
+            sample_count,
+        );
+        return (splats, heatmap);
+    }
+
+    /// pipeline for the color passes of [`RenderMode::DominantColor`].
+    /// Only the fragments of one layer pass the stencil test (every fragment increments the stencil
+    /// value), their depth is `1 - weight` so the depth test keeps the one with the highest weight.
+    fn create_dominant_color_pipeline(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("dominant color pipeline layout"),
+            bind_group_layouts: &[
+                &PointCloud::bind_group_layout_render(device),
+                &GPURSSorter::bind_group_layout_rendering(device),
+                &Self::bind_group_layout_coverage(device),
+            ],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/gaussian.wgsl"));
+        let fs_dominant = if color_format.is_srgb() {
+            "fs_dominant_srgb"
+        } else {
+            "fs_dominant"
+        };
+        let layer_stencil = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::Equal,
+            fail_op: wgpu::StencilOperation::IncrementClamp,
+            depth_fail_op: wgpu::StencilOperation::IncrementClamp,
+            pass_op: wgpu::StencilOperation::IncrementClamp,
+        };
+        return Self::create_pipeline(
+            device,
+            &pipeline_layout,
+            &shader,
+            color_format,
+            "vs_main_reversed",
+            fs_dominant,
+            None,
+            Some(wgpu::DepthStencilState {
+                format: Self::DOMINANT_COLOR_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState {
+                    front: layer_stencil,
+                    back: layer_stencil,
+                    read_mask: 0xff,
+                    write_mask: 0xff,
+                },
+                bias: Default::default(),
+            }),
+            sample_count,
+        );
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        color_format: wgpu::TextureFormat,
+        vs_entry_point: &str,
+        fs_entry_point: &str,
+        blend: Option<wgpu::BlendState>,
+        depth_stencil: Option<wgpu::DepthStencilState>,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("render pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: vs_entry_point,
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: fs_entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: color_format,
+                    blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil,
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        })
+    }
+
     pub(crate) fn camera(&self) -> &UniformBuffer<CameraUniform> {
         &self.camera
     }
 
+    /// returns true if the order of the last sorted frame is reused (see [`ResortPolicy`])
     fn preprocess<'a>(
         &'a mut self,
         encoder: &'a mut wgpu::CommandEncoder,
         queue: &wgpu::Queue,
         pc: &'a PointCloud,
         render_settings: SplattingArgs,
-    ) {
+    ) -> bool {
         let camera = render_settings.camera;
-        let uniform = self.camera.as_mut();
+        let mut uniform = *self.camera.data();
         uniform.set_focal(camera.projection.focal(render_settings.viewport));
-        uniform.set_viewport(render_settings.viewport.cast().unwrap());
         uniform.set_camera(camera);
-        self.camera.sync(queue);
+        match render_settings.region {
+            Some(region) => {
+                uniform.set_viewport(region.size().cast().unwrap());
+                uniform.crop(region.ndc_transform(render_settings.viewport));
+            }
+            None => uniform.set_viewport(render_settings.viewport.cast().unwrap()),
+        }
+        // while the camera only moves the projection part does not have to be uploaded again
+        if uniform.projection_bytes() == self.camera.data().projection_bytes() {
+            self.camera.update_camera(queue, camera);
+        } else {
+            *self.camera.as_mut() = uniform;
+            self.camera.sync(queue);
+        }
 
         let settings_uniform = self.render_settings.as_mut();
         *settings_uniform = SplattingArgsUniform::from_args_and_pc(render_settings, pc);
+        settings_uniform.exposure = self.exposure;
+        settings_uniform.ambient_occlusion = self.ambient_occlusion;
+        settings_uniform.cull_margin = self.cull_margin;
+        settings_uniform.depth_bias = self.depth_bias;
+        settings_uniform.min_opacity = settings_uniform.min_opacity.max(self.min_opacity);
+        settings_uniform.min_splat_size = settings_uniform.min_splat_size.max(self.min_screen_size);
+        settings_uniform.store_index = self.store_index as u32;
+
+        let mut sort_camera = camera;
+        sort_camera.projection.znear = 0.;
+        sort_camera.projection.zfar = 0.;
+        let sort_state = SortState {
+            camera: sort_camera,
+            viewport: render_settings.viewport,
+            settings: SplattingArgsUniform {
+                exposure: 0.,
+                walltime: 0.,
+                ..*settings_uniform
+            },
+            splat_buffer: pc.splat_2d_buffer().global_id(),
+        };
+        let reuse_order = self
+            .last_sort
+            .as_ref()
+            .is_some_and(|last| last.can_reuse_order(&sort_state, self.resort_policy));
+        if !reuse_order {
+            self.last_sort = Some(sort_state);
+        }
+        settings_uniform.reuse_order = reuse_order as u32;
         self.render_settings.sync(queue);
 
         // TODO perform this in vertex buffer after draw call
@@ -154,7 +677,12 @@ impl GaussianRenderer {
             }
             .as_bytes(),
         );
-        let depth_buffer = &self.sorter_suff.as_ref().unwrap().sorter_bg_pre;
+        let depth_buffer = &self
+            .sort_buffers
+            .as_ref()
+            .unwrap()
+            .sort_stuff()
+            .sorter_bg_pre;
         self.preprocess.run(
             encoder,
             pc,
@@ -162,6 +690,7 @@ impl GaussianRenderer {
             &self.render_settings,
             depth_buffer,
         );
+        return reuse_order;
     }
 
     #[cfg(not(target_arch = "wasm32"))]
@@ -186,6 +715,79 @@ impl GaussianRenderer {
         return n;
     }
 
+    /// Index of the gaussian with the highest blending weight (alpha times transmittance)
+    /// at the pixel (origin in the upper left corner), `None` if no gaussian covers it.
+    /// Only the pixel is preprocessed (see [`SplattingArgs::region`]), the splats covering it
+    /// are downloaded and blended front to back on the cpu like in the color pass.
+    /// The render mode and layer limit of the settings are ignored.
+    pub async fn pick(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pc: &PointCloud,
+        settings: SplattingArgs,
+        pixel: Vector2<u32>,
+    ) -> anyhow::Result<Option<usize>> {
+        if pixel.x >= settings.viewport.x || pixel.y >= settings.viewport.y {
+            return Ok(None);
+        }
+        let settings = SplattingArgs {
+            render_mode: RenderMode::Color,
+            max_layers_per_pixel: None,
+            region: Some(RenderRegion {
+                x: pixel.x,
+                y: pixel.y,
+                width: 1,
+                height: 1,
+            }),
+            ..settings
+        };
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("pick encoder"),
+        });
+        self.store_index = true;
+        self.prepare(&mut encoder, device, queue, pc, settings, &mut None);
+        self.store_index = false;
+        queue.submit([encoder.finish()]);
+
+        let sort_stuff = self.sort_buffers.as_ref().unwrap().sort_stuff();
+        let info = download_buffer::<u32>(&sort_stuff.sorter_uni, device, queue).await?;
+        let order = download_buffer::<u32>(&sort_stuff.payload_a, device, queue).await?;
+        let splats = download_buffer::<Splat>(pc.splat_2d_buffer(), device, queue).await?;
+
+        // same cutoff as the fragment shader
+        const CUTOFF: f32 = 2.3539888583335364;
+        let n = (info[0] as usize).min(order.len());
+        let mut transmittance = 1.;
+        let mut picked = None;
+        let mut max_weight = 0.;
+        // the splats are sorted back to front
+        for s in order[..n].iter().rev() {
+            let splat = &splats[*s as usize];
+            let v = splat.v.map(f16::to_f32);
+            let axes = Matrix2::new(v.x, v.y, v.z, v.w) * 2.;
+            // the pixel center is the origin of the 1x1 region
+            let p = match axes.invert() {
+                Some(inv) => inv * -splat.pos.map(f16::to_f32),
+                None => continue,
+            };
+            let a = p.dot(p);
+            // negated to skip degenerate splats with NaN axes as well, they are not rasterized
+            if !(p.x.abs() <= CUTOFF && p.y.abs() <= CUTOFF && a <= 2. * CUTOFF) {
+                continue;
+            }
+            let alpha = (f32::exp(-a) * splat.color.w.to_f32()).min(0.99);
+            let weight = alpha * transmittance;
+            if weight > max_weight {
+                max_weight = weight;
+                let index = splat.color.x.to_bits() as u32 | (splat.color.y.to_bits() as u32) << 16;
+                picked = Some(index as usize);
+            }
+            transmittance *= 1. - alpha;
+        }
+        return Ok(picked);
+    }
+
     pub fn prepare(
         &mut self,
         encoder: &mut wgpu::CommandEncoder,
@@ -195,31 +797,52 @@ impl GaussianRenderer {
         render_settings: SplattingArgs,
         stopwatch: &mut Option<GPUStopwatch>,
     ) {
-        if self.sorter_suff.is_none()
-            || self
-                .sorter_suff
-                .as_ref()
-                .is_some_and(|s| s.num_points != pc.num_points() as usize)
+        let render_settings = self.with_renderer_settings(render_settings);
+        self.render_mode = render_settings.render_mode;
+        self.layers_per_pixel = render_settings.max_layers_per_pixel;
+        #[cfg(debug_assertions)]
+        if self.render_mode == RenderMode::SortViolations
+            && self.pipelines_sort_violations.is_none()
         {
-            log::debug!("created sort buffers for {:} points", pc.num_points());
-            self.sorter_suff = Some(
-                self.sorter
-                    .create_sort_stuff(device, pc.num_points() as usize),
-            );
+            self.pipelines_sort_violations = Some(Self::create_sort_violation_pipelines(
+                device,
+                self.color_format,
+                self.msaa_samples,
+            ));
+        }
+        if self.render_mode == RenderMode::DominantColor && self.pipeline_dominant.is_none() {
+            self.pipeline_dominant = Some(Self::create_dominant_color_pipeline(
+                device,
+                self.color_format,
+                self.msaa_samples,
+            ));
+        }
+        match &mut self.sort_buffers {
+            Some(buffers) => {
+                if self
+                    .sorter
+                    .resize_sort_buffers(buffers, device, queue, pc.num_points() as usize)
+                {
+                    log::debug!("resized sort buffers for {:} points", pc.num_points());
+                    // the sorted order is lost
+                    self.last_sort = None;
+                }
+            }
+            None => {
+                log::debug!("created sort buffers for {:} points", pc.num_points());
+                self.sort_buffers = Some(
+                    self.sorter
+                        .create_sort_buffers(device, pc.num_points() as usize),
+                );
+            }
         }
-
-        GPURSSorter::record_reset_indirect_buffer(
-            &self.sorter_suff.as_ref().unwrap().sorter_dis,
-            &self.sorter_suff.as_ref().unwrap().sorter_uni,
-            &queue,
-        );
 
         // convert 3D gaussian splats to 2D gaussian splats
         if let Some(stopwatch) = stopwatch {
             stopwatch.start(encoder, "preprocess").unwrap();
         }
 
-        self.preprocess(encoder, queue, &pc, render_settings);
+        let reuse_order = self.preprocess(encoder, queue, &pc, render_settings);
         if let Some(stopwatch) = stopwatch {
             stopwatch.stop(encoder, "preprocess").unwrap();
         }
@@ -227,17 +850,26 @@ impl GaussianRenderer {
         if let Some(stopwatch) = stopwatch {
             stopwatch.start(encoder, "sorting").unwrap();
         }
-        self.sorter.record_sort_indirect(
-            &self.sorter_suff.as_ref().unwrap().sorter_bg,
-            &self.sorter_suff.as_ref().unwrap().sorter_dis,
-            encoder,
-        );
+        if !reuse_order {
+            // written before the recorded commands are executed
+            GPURSSorter::record_reset_indirect_buffer(
+                &self.sort_buffers.as_ref().unwrap().sort_stuff().sorter_dis,
+                &self.sort_buffers.as_ref().unwrap().sort_stuff().sorter_uni,
+                &queue,
+            );
+            self.sorter.sort_depths(
+                device,
+                queue,
+                encoder,
+                self.sort_buffers.as_ref().unwrap().sort_stuff(),
+            );
+        }
         if let Some(stopwatch) = stopwatch {
             stopwatch.stop(encoder, "sorting").unwrap();
         }
 
         encoder.copy_buffer_to_buffer(
-            &self.sorter_suff.as_ref().unwrap().sorter_uni,
+            &self.sort_buffers.as_ref().unwrap().sort_stuff().sorter_uni,
             0,
             &self.draw_indirect_buffer,
             std::mem::size_of::<u32>() as u64,
@@ -245,18 +877,173 @@ impl GaussianRenderer {
         );
     }
 
+    /// Renders a coarse preview into a new texture in one call (see [`crate::render_to_texture`]),
+    /// e.g. while the point cloud is loading or the camera moves.
+    /// `quality` ranges from 0 (fastest) to 3 (full quality), see [`SplattingArgs::preview_quality`].
+    pub fn render_preview(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pc: &PointCloud,
+        settings: SplattingArgs,
+        quality: u32,
+    ) -> anyhow::Result<wgpu::Texture> {
+        let settings = SplattingArgs {
+            preview_quality: Some(quality.min(3)),
+            ..settings
+        };
+        return crate::render_to_texture(device, queue, self, pc, settings, &mut None);
+    }
+
     pub fn render<'rpass>(
         &'rpass self,
         render_pass: &mut wgpu::RenderPass<'rpass>,
         pc: &'rpass PointCloud,
     ) {
-        render_pass.set_bind_group(0, pc.render_bind_group(), &[]);
-        render_pass.set_bind_group(1, &self.sorter_suff.as_ref().unwrap().sorter_render_bg, &[]);
-        render_pass.set_pipeline(&self.pipeline);
+        self.bind_splats(render_pass, pc);
+        #[cfg(debug_assertions)]
+        if self.render_mode == RenderMode::SortViolations {
+            let (splats, heatmap) = self.pipelines_sort_violations.as_ref().unwrap();
+            render_pass.set_pipeline(splats);
+            render_pass.draw_indirect(&self.draw_indirect_buffer, 0);
+            render_pass.set_pipeline(heatmap);
+            for level in 1..=Self::SORT_VIOLATION_LEVELS {
+                render_pass.set_stencil_reference(level);
+                render_pass.draw(0..3, 0..1);
+            }
+            return;
+        }
+        match (self.render_mode, self.layers_per_pixel) {
+            (
+                RenderMode::Color
+                | RenderMode::DominantColor
+                | RenderMode::DepthColor { .. }
+                | RenderMode::Depth
+                | RenderMode::Contribution { .. },
+                None,
+            ) => render_pass.set_pipeline(&self.pipeline),
+            (
+                RenderMode::Color
+                | RenderMode::DominantColor
+                | RenderMode::DepthColor { .. }
+                | RenderMode::Depth
+                | RenderMode::Contribution { .. },
+                Some(k),
+            ) => {
+                render_pass.set_pipeline(&self.pipeline_layered);
+                render_pass.set_stencil_reference(k.min(u8::MAX as u32));
+            }
+            (RenderMode::FrontOpaqueColor, _) => {
+                render_pass.set_pipeline(&self.pipeline_front_opaque)
+            }
+            #[cfg(debug_assertions)]
+            (RenderMode::SortViolations, _) => unreachable!(),
+        }
+
+        render_pass.draw_indirect(&self.draw_indirect_buffer, 0);
+    }
+
+    /// number of layers (front-most gaussians per pixel) searched for [`RenderMode::DominantColor`]
+    pub fn dominant_color_layers(&self) -> u32 {
+        return self
+            .layers_per_pixel
+            .unwrap_or(Self::DOMINANT_COLOR_LAYERS)
+            .min(u8::MAX as u32);
+    }
+
+    /// draws the coverage (alpha) of the front-most `layers` gaussians per pixel for
+    /// [`RenderMode::DominantColor`]. The splats are blended front to back like with
+    /// `max_layers_per_pixel`, the pass needs a transparent target and a stencil attachment
+    /// with [`GaussianRenderer::LAYER_STENCIL_FORMAT`] that is cleared to 0.
+    pub fn render_coverage<'rpass>(
+        &'rpass self,
+        render_pass: &mut wgpu::RenderPass<'rpass>,
+        pc: &'rpass PointCloud,
+        layers: u32,
+    ) {
+        self.bind_splats(render_pass, pc);
+        render_pass.set_pipeline(&self.pipeline_layered);
+        render_pass.set_stencil_reference(layers.min(u8::MAX as u32));
+        render_pass.draw_indirect(&self.draw_indirect_buffer, 0);
+    }
 
+    /// draws the colors of the gaussians in the given layer (counted from the front, starting at 0)
+    /// for [`RenderMode::DominantColor`].
+    /// `coverage` is the coverage of the layers in front of it (see [`GaussianRenderer::render_coverage`]
+    /// and [`GaussianRenderer::coverage_bind_group`]).
+    /// The blending weight of the fragments is written as depth (`1 - weight`), so the depth test
+    /// keeps the color with the highest weight over all layers.
+    /// The depth stencil attachment ([`GaussianRenderer::DOMINANT_COLOR_FORMAT`]) is cleared to depth 1
+    /// before the first layer and the stencil is cleared to 0 for every layer.
+    pub fn render_dominant_layer<'rpass>(
+        &'rpass self,
+        render_pass: &mut wgpu::RenderPass<'rpass>,
+        pc: &'rpass PointCloud,
+        layer: u32,
+        coverage: &'rpass wgpu::BindGroup,
+    ) {
+        self.bind_splats(render_pass, pc);
+        render_pass.set_bind_group(2, coverage, &[]);
+        render_pass.set_pipeline(
+            self.pipeline_dominant
+                .as_ref()
+                .expect("the renderer was not prepared for the dominant color"),
+        );
+        render_pass.set_stencil_reference(layer.min(u8::MAX as u32));
         render_pass.draw_indirect(&self.draw_indirect_buffer, 0);
     }
 
+    fn bind_splats<'rpass>(
+        &'rpass self,
+        render_pass: &mut wgpu::RenderPass<'rpass>,
+        pc: &'rpass PointCloud,
+    ) {
+        render_pass.set_bind_group(0, pc.render_bind_group(), &[]);
+        render_pass.set_bind_group(
+            1,
+            &self
+                .sort_buffers
+                .as_ref()
+                .unwrap()
+                .sort_stuff()
+                .sorter_render_bg,
+            &[],
+        );
+    }
+
+    /// layout of the coverage texture read by the color passes of [`RenderMode::DominantColor`]
+    pub fn bind_group_layout_coverage(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("coverage"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        })
+    }
+
+    /// binds the coverage texture (single sample, size of the render target)
+    /// for [`GaussianRenderer::render_dominant_layer`]
+    pub fn coverage_bind_group(
+        device: &wgpu::Device,
+        coverage: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("coverage"),
+            layout: &Self::bind_group_layout_coverage(device),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(coverage),
+            }],
+        })
+    }
+
     pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
         device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("draw indirect"),
@@ -280,11 +1067,278 @@ impl GaussianRenderer {
         self.color_format
     }
 
+    /// enables automatic exposure adaption.
+    /// the exposure is derived from the previous frame, see [`GaussianRenderer::update_exposure`].
+    /// The first frame is rendered with the default exposure of 1.
+    pub fn set_auto_exposure(&mut self, auto_exposure: Option<AutoExposure>) {
+        self.auto_exposure = auto_exposure;
+        if auto_exposure.is_none() {
+            self.exposure = 1.;
+        }
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    /// darkens gaussians in densely populated regions of the point cloud (0 disables it).
+    /// This is a crude approximation of ambient occlusion based on the number of
+    /// neighboring gaussians and not physically based.
+    pub fn set_ambient_occlusion(&mut self, strength: f32) {
+        self.ambient_occlusion = strength.clamp(0., 1.);
+    }
+
+    pub fn ambient_occlusion(&self) -> f32 {
+        self.ambient_occlusion
+    }
+
+    /// keeps gaussians whose center is up to `px` pixels outside of the screen
+    /// (in addition to the default margin of 10% of the screen size).
+    /// Avoids popping of large splats at the screen edges during camera motion.
+    pub fn set_cull_margin(&mut self, px: f32) {
+        self.cull_margin = px.max(0.);
+    }
+
+    pub fn cull_margin(&self) -> f32 {
+        self.cull_margin
+    }
+
+    /// offsets the sort depth of every gaussian by `bias` times its size (largest standard deviation)
+    /// so that coplanar gaussians of different size are always blended in the same order
+    /// (larger ones behind smaller ones for positive values).
+    /// Gaussians with the same depth and size still tie, their order is only deterministic
+    /// with a 64 bit sorter (see [`GaussianRenderer::with_sorter`]).
+    pub fn set_depth_bias(&mut self, bias: f32) {
+        self.depth_bias = bias;
+    }
+
+    pub fn depth_bias(&self) -> f32 {
+        self.depth_bias
+    }
+
+    /// Skips the preprocessing of the sort keys and the sort while the camera barely moves.
+    /// The splats are still projected every frame but drawn in the order of the last sort,
+    /// gaussians that became visible since then are missing until the next sort.
+    /// The splats of animated point clouds move without sorting again.
+    pub fn set_resort_policy(&mut self, policy: ResortPolicy) {
+        self.resort_policy = policy;
+        self.last_sort = None;
+    }
+
+    pub fn resort_policy(&self) -> ResortPolicy {
+        self.resort_policy
+    }
+
+    /// gaussians with a lower opacity are skipped before sorting (faster but less accurate).
+    /// The larger value of this and the threshold of [`SplattingArgs::preview_quality`] is used.
+    pub fn set_min_opacity(&mut self, opacity: f32) {
+        self.min_opacity = opacity.max(0.);
+    }
+
+    pub fn min_opacity(&self) -> f32 {
+        self.min_opacity
+    }
+
+    /// gaussians with a smaller screen space size in pixels (along the major axis) are skipped before sorting.
+    /// The larger value of this and the threshold of [`SplattingArgs::preview_quality`] is used.
+    pub fn set_min_screen_size(&mut self, px: f32) {
+        self.min_screen_size = px.max(0.);
+    }
+
+    pub fn min_screen_size(&self) -> f32 {
+        self.min_screen_size
+    }
+
+    /// only renders the gaussians inside or outside of the sphere (center, radius).
+    /// [`SplattingArgs::clip_sphere`] takes precedence if it is set
+    pub fn set_clip_sphere(&mut self, sphere: Option<(Point3<f32>, f32, ClipSide)>) {
+        self.clip_sphere = sphere;
+    }
+
+    pub fn clip_sphere(&self) -> Option<(Point3<f32>, f32, ClipSide)> {
+        self.clip_sphere
+    }
+
+    /// clamps the screen space extent of each splat to `px` pixels, so a gaussian close to
+    /// the camera does not fill the whole screen (None disables it).
+    /// [`SplattingArgs::max_splat_size`] takes precedence if it is set
+    pub fn set_max_screen_size(&mut self, px: Option<f32>) {
+        self.max_screen_size = px.map(|px| px.max(0.));
+    }
+
+    pub fn max_screen_size(&self) -> Option<f32> {
+        self.max_screen_size
+    }
+
+    /// only blends the `k` front-most splats per pixel (None blends all of them).
+    /// Thick semi-transparent regions look too transparent if k is small.
+    /// The render pass needs a stencil attachment (see [`crate::depth_stencil_format`]).
+    /// [`SplattingArgs::max_layers_per_pixel`] takes precedence if it is set
+    pub fn set_max_layers_per_pixel(&mut self, k: Option<u32>) {
+        self.max_layers_per_pixel = k;
+    }
+
+    pub fn max_layers_per_pixel(&self) -> Option<u32> {
+        self.max_layers_per_pixel
+    }
+
+    /// fills the settings that are not set with the ones of the renderer
+    pub(crate) fn with_renderer_settings(&self, settings: SplattingArgs) -> SplattingArgs {
+        return SplattingArgs {
+            clip_sphere: settings.clip_sphere.or(self.clip_sphere),
+            max_splat_size: settings.max_splat_size.or(self.max_screen_size),
+            max_layers_per_pixel: settings.max_layers_per_pixel.or(self.max_layers_per_pixel),
+            ..settings
+        };
+    }
+
+    /// sets the background color (straight alpha, sRGB encoded like the framebuffer).
+    /// Default is transparent.
+    pub fn set_background(&mut self, color: wgpu::Color) {
+        self.background = color;
+    }
+
+    pub fn background(&self) -> wgpu::Color {
+        self.background
+    }
+
+    /// color to clear the render target with before calling [`GaussianRenderer::render`].
+    /// The framebuffer stores premultiplied colors so the background is premultiplied here.
+    /// With `max_layers_per_pixel` the splats are blended front to back and need a transparent
+    /// target. In this case the background has to be composited after rendering
+    /// (`color + (1 - alpha) * background`).
+    pub fn clear_color(&self) -> wgpu::Color {
+        let layered =
+            self.layers_per_pixel.is_some() && self.render_mode != RenderMode::DominantColor;
+        if layered || self.render_mode == RenderMode::Depth {
+            return wgpu::Color::TRANSPARENT;
+        }
+        let c = self.background;
+        return wgpu::Color {
+            r: c.r * c.a,
+            g: c.g * c.a,
+            b: c.b * c.a,
+            a: c.a,
+        };
+    }
+
+    /// true if the background set with [`GaussianRenderer::set_background`] is not part of
+    /// [`GaussianRenderer::clear_color`] and has to be composited after rendering
+    pub fn composite_background(&self) -> bool {
+        self.layers_per_pixel.is_some() && self.background.a > 0.
+    }
+
+    /// renders with multisample anti-aliasing (only smooths the edges of the splat quads).
+    /// The color (and stencil) attachments of the render pass need the returned sample count
+    /// and the color attachment must be resolved into the output.
+    /// Splats are blended per sample with premultiplied alpha, so the resolved (averaged) image
+    /// is premultiplied as well.
+    /// Falls back to a single sample with a warning if the count is not supported.
+    pub fn set_msaa(
+        &mut self,
+        device: &wgpu::Device,
+        adapter: &wgpu::Adapter,
+        samples: u32,
+    ) -> u32 {
+        let color_flags = adapter.get_texture_format_features(self.color_format).flags;
+        let stencil_flags = adapter
+            .get_texture_format_features(Self::LAYER_STENCIL_FORMAT)
+            .flags;
+        let supported = color_flags.sample_count_supported(samples)
+            && color_flags.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE)
+            && stencil_flags.sample_count_supported(samples);
+        let samples = if samples <= 1 || supported {
+            samples.max(1)
+        } else {
+            log::warn!(
+                "{samples}x MSAA is not supported for {:?}, rendering without MSAA",
+                self.color_format
+            );
+            1
+        };
+        if samples != self.msaa_samples {
+            (
+                self.pipeline,
+                self.pipeline_front_opaque,
+                self.pipeline_layered,
+            ) = Self::create_render_pipelines(device, self.color_format, samples);
+            #[cfg(debug_assertions)]
+            {
+                self.pipelines_sort_violations = None;
+            }
+            self.pipeline_dominant = None;
+            self.msaa_samples = samples;
+        }
+        return samples;
+    }
+
+    pub fn msaa_samples(&self) -> u32 {
+        self.msaa_samples
+    }
+
+    /// measures the average luminance of a rendered frame and adapts the exposure
+    /// used for the next frame. The frame needs `TEXTURE_BINDING` usage.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn update_exposure(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frame: &wgpu::Texture,
+    ) {
+        let Some(auto_exposure) = self.auto_exposure else {
+            return;
+        };
+        let num_pixels = (frame.width() * frame.height()) as usize;
+        let luminance = self.luminance.run(device, queue, frame);
+        // the histogram pass of 64 bit sorters expects two words per value
+        let histogram = match self.sorter.gpu_sorter().filter(|s| s.key_bytes() == 4) {
+            Some(sorter) => {
+                let Ok(histogram) = sorter
+                    .compute_histogram_buffer(device, queue, &luminance, num_pixels)
+                    .await
+                else {
+                    return;
+                };
+                histogram
+            }
+            None => {
+                let Ok(values) = download_buffer::<u32>(&luminance, device, queue).await else {
+                    return;
+                };
+                let mut histogram = [0; 256];
+                for v in values.iter().take(num_pixels) {
+                    histogram[(v >> 24) as usize] += 1;
+                }
+                histogram
+            }
+        };
+
+        // the most significant byte of a positive float contains the upper 7 bits of the exponent.
+        // bin 0 contains black pixels and bins >= 128 negative values, both are ignored
+        let mut log_sum = 0.;
+        let mut count = 0;
+        for (bin, n) in histogram.iter().enumerate().take(128).skip(1) {
+            log_sum += *n as f32 * ((bin * 2) as f32 - 126.);
+            count += n;
+        }
+        if count == 0 {
+            return;
+        }
+        let avg_luminance = (log_sum / count as f32).exp2();
+        let target_exposure = self.exposure * auto_exposure.target / avg_luminance;
+        let log_exposure = self.exposure.log2()
+            + (target_exposure.log2() - self.exposure.log2()) * auto_exposure.speed.clamp(0., 1.);
+        self.exposure = log_exposure.exp2().clamp(1e-3, 1e3);
+    }
+
     pub(crate) fn render_settings(&self) -> &UniformBuffer<SplattingArgsUniform> {
         &self.render_settings
     }
 }
 
+/// Camera uniform (`CameraUniforms` in the shaders).
+/// Byte layout: view matrix 0..64, inverse view matrix 64..128, projection matrix 128..192,
+/// inverse projection matrix 192..256, viewport 256..264, focal length 264..272.
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
@@ -316,7 +1370,20 @@ impl Default for CameraUniform {
     }
 }
 
+// the wgsl struct consists of four 4x4 matrices and two vec2 (no padding)
+const _: () = assert!(std::mem::size_of::<CameraUniform>() == 272);
+
 impl CameraUniform {
+    /// bytes of the view and inverse view matrix
+    pub const VIEW_BYTES: std::ops::Range<usize> = 0..2 * std::mem::size_of::<Matrix4<f32>>();
+    /// bytes of the projection matrices, viewport and focal length
+    pub const PROJECTION_BYTES: std::ops::Range<usize> =
+        Self::VIEW_BYTES.end..std::mem::size_of::<Self>();
+
+    fn projection_bytes(&self) -> &[u8] {
+        &bytemuck::bytes_of(self)[Self::PROJECTION_BYTES]
+    }
+
     pub(crate) fn set_view_mat(&mut self, view_matrix: Matrix4<f32>) {
         self.view_matrix = view_matrix;
         self.view_inv_matrix = view_matrix.invert().unwrap();
@@ -335,15 +1402,47 @@ impl CameraUniform {
     pub fn set_viewport(&mut self, viewport: Vector2<f32>) {
         self.viewport = viewport;
     }
+
+    /// maps the normalized device coordinates with the given scale and center
+    /// (see [`RenderRegion::ndc_transform`])
+    pub(crate) fn crop(&mut self, (scale, center): (Vector2<f32>, Vector2<f32>)) {
+        #[rustfmt::skip]
+        let crop = Matrix4::new(
+            scale.x, 0., 0., 0.,
+            0., scale.y, 0., 0.,
+            0., 0., 1., 0.,
+            -scale.x * center.x, -scale.y * center.y, 0., 1.,
+        );
+        self.proj_matrix = crop * self.proj_matrix;
+        // the inverse is stored without the y flip
+        self.proj_inv_matrix =
+            self.proj_inv_matrix * VIEWPORT_Y_FLIP * crop.invert().unwrap() * VIEWPORT_Y_FLIP;
+    }
     pub fn set_focal(&mut self, focal: Vector2<f32>) {
         self.focal = focal
     }
 }
 
+impl UniformBuffer<CameraUniform> {
+    /// Sets the view matrix of the camera and uploads only the view part of the uniform
+    /// ([`CameraUniform::VIEW_BYTES`]). The projection, viewport and focal length are kept,
+    /// use [`UniformBuffer::sync`] after changing them.
+    pub fn update_camera(&mut self, queue: &wgpu::Queue, camera: impl Camera) {
+        self.as_mut().set_view_mat(camera.view_matrix());
+        self.sync_range(queue, CameraUniform::VIEW_BYTES);
+    }
+}
+
 struct PreprocessPipeline(wgpu::ComputePipeline);
 
 impl PreprocessPipeline {
-    fn new(device: &wgpu::Device, sh_deg: u32, compressed: bool) -> Self {
+    fn new(
+        device: &wgpu::Device,
+        sh_deg: u32,
+        compressed: bool,
+        keys_per_wg: usize,
+        key_bytes: usize,
+    ) -> Self {
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("preprocess pipeline layout"),
             bind_group_layouts: &[
@@ -361,7 +1460,9 @@ impl PreprocessPipeline {
 
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("preprocess shader"),
-            source: wgpu::ShaderSource::Wgsl(Self::build_shader(sh_deg, compressed).into()),
+            source: wgpu::ShaderSource::Wgsl(
+                Self::build_shader(sh_deg, compressed, keys_per_wg, key_bytes).into(),
+            ),
         });
         let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
             label: Some("preprocess pipeline"),
@@ -372,7 +1473,7 @@ impl PreprocessPipeline {
         Self(pipeline)
     }
 
-    fn build_shader(sh_deg: u32, compressed: bool) -> String {
+    fn build_shader(sh_deg: u32, compressed: bool, keys_per_wg: usize, key_bytes: usize) -> String {
         let shader_src: &str = if !compressed {
             include_str!("shaders/preprocess.wgsl")
         } else {
@@ -381,8 +1482,13 @@ impl PreprocessPipeline {
         let shader = format!(
             "
         const MAX_SH_DEG:u32 = {:}u;
+        const KEYS_PER_WG:u32 = {:}u;
+        const KEY_WORDS:u32 = {:}u;
         {:}",
-            sh_deg, shader_src
+            sh_deg,
+            keys_per_wg,
+            key_bytes / 4,
+            shader_src
         );
         return shader;
     }
@@ -405,8 +1511,105 @@ impl PreprocessPipeline {
         pass.set_bind_group(2, &sort_bg, &[]);
         pass.set_bind_group(3, render_settings.bind_group(), &[]);
 
-        let wgs_x = (pc.num_points() as f32 / 256.0).ceil() as u32;
-        pass.dispatch_workgroups(wgs_x, 1, 1);
+        let wgs_x = (pc.num_points() as f32 / 256.0).ceil() as u32;
+        pass.dispatch_workgroups(wgs_x, 1, 1);
+    }
+}
+
+/// writes the luminance of each pixel of a texture into a buffer
+#[cfg(not(target_arch = "wasm32"))]
+struct LuminancePipeline {
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl LuminancePipeline {
+    fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("luminance bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("luminance pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(include_wgsl!("shaders/luminance.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("luminance pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "luminance_main",
+        });
+        Self {
+            pipeline,
+            bind_group_layout,
+        }
+    }
+
+    fn run(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frame: &wgpu::Texture,
+    ) -> wgpu::Buffer {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("luminance buffer"),
+            size: (frame.width() * frame.height()) as u64 * std::mem::size_of::<f32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let view = frame.create_view(&Default::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("luminance bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("luminance encoder"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("luminance compute pass"),
+                ..Default::default()
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups((frame.width() + 15) / 16, (frame.height() + 15) / 16, 1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+        return buffer;
     }
 }
 
@@ -664,6 +1867,7 @@ pub struct SplattingArgs {
     pub camera: PerspectiveCamera,
     pub viewport: Vector2<u32>,
     pub gaussian_scaling: f32,
+    /// spherical harmonics degree used for rendering (clamped to the degree of the point cloud)
     pub max_sh_deg: u32,
     pub show_env_map: bool,
     pub mip_splatting: Option<bool>,
@@ -672,6 +1876,120 @@ pub struct SplattingArgs {
     pub walltime: Duration,
     pub scene_center: Option<Point3<f32>>,
     pub scene_extend: Option<f32>,
+    /// spherical clipping region (center, radius, side to keep)
+    pub clip_sphere: Option<(Point3<f32>, f32, ClipSide)>,
+    pub render_mode: RenderMode,
+    /// coarse quality level for fast previews (0 is fastest, 3 is full quality)
+    pub preview_quality: Option<u32>,
+    /// clamps the screen space extent of each splat to this many pixels
+    pub max_splat_size: Option<f32>,
+    /// only blend the k front-most splats per pixel (at most 255).
+    /// Thick semi-transparent regions look too transparent if k is small.
+    /// Requires a stencil attachment with [`GaussianRenderer::LAYER_STENCIL_FORMAT`]
+    /// that is cleared to 0.
+    pub max_layers_per_pixel: Option<u32>,
+    /// only render this region of the image (with `viewport` being the size of the full image).
+    /// The render target has the size of the region. Gaussians are culled against the region
+    /// using their screen space extent, so tiles can be stitched without seams.
+    pub region: Option<RenderRegion>,
+}
+
+/// rectangle in pixels (origin in the upper left corner) of the rendered image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RenderRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RenderRegion {
+    pub fn size(&self) -> Vector2<u32> {
+        Vector2::new(self.width, self.height)
+    }
+
+    /// scale and center (in normalized device coordinates of the full image) of the transformation
+    /// that maps the region to [-1,1]: `ndc_region = (ndc - center) * scale`
+    pub fn ndc_transform(&self, viewport: Vector2<u32>) -> (Vector2<f32>, Vector2<f32>) {
+        let viewport: Vector2<f32> = viewport.cast().unwrap();
+        let scale = Vector2::new(
+            viewport.x / self.width as f32,
+            viewport.y / self.height as f32,
+        );
+        let center = Vector2::new(
+            2. * (self.x as f32 + self.width as f32 / 2.) / viewport.x - 1.,
+            1. - 2. * (self.y as f32 + self.height as f32 / 2.) / viewport.y,
+        );
+        return (scale, center);
+    }
+}
+
+/// what is written to the render target
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RenderMode {
+    /// alpha blended gaussian splatting
+    #[default]
+    Color,
+    /// color of the front-most gaussian with an alpha of at least 0.5 at the pixel
+    /// (albedo proxy, no blending). Fainter gaussians are ignored, so this is not
+    /// necessarily the gaussian with the highest blending weight.
+    FrontOpaqueColor,
+    /// color of the gaussian with the highest blending weight (alpha times transmittance)
+    /// at the pixel, without blending. This is the gaussian [`GaussianRenderer::pick`] returns.
+    /// Needs one pass per layer (see [`crate::render_to_texture`]), only the front-most
+    /// `max_layers_per_pixel` gaussians per pixel are considered
+    /// ([`GaussianRenderer::DOMINANT_COLOR_LAYERS`] by default).
+    /// [`GaussianRenderer::render`] draws the blended colors in this mode.
+    DominantColor,
+    /// gaussians colored by their view space depth mapped from [near,far] to the colormap.
+    /// Spherical harmonics are ignored.
+    DepthColor {
+        near: f32,
+        far: f32,
+        colormap: Colormap,
+    },
+    /// view space depth of the gaussians in the color channels. After blending the color is the
+    /// alpha weighted depth, dividing it by alpha gives the expected depth of the pixel.
+    /// Rendered without background (transparent pixels have no depth).
+    /// The depth is stored with half precision per splat.
+    Depth,
+    /// per pixel blending weight (transmittance times alpha) of a single gaussian, stored in the color channels.
+    /// All other gaussians are black but still occlude it.
+    Contribution { index: u32 },
+    /// debugging aid for the sorting (only in debug builds as it is slow):
+    /// pixels where a splat was blended behind a splat drawn before it are colored red
+    /// (the more out of order splats the redder).
+    /// Requires a depth stencil attachment with [`GaussianRenderer::SORT_CHECK_FORMAT`]
+    /// that is cleared to depth 1 and stencil 0.
+    #[cfg(debug_assertions)]
+    SortViolations,
+}
+
+impl Hash for RenderMode {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        if let RenderMode::DepthColor {
+            near,
+            far,
+            colormap,
+        } = self
+        {
+            near.to_bits().hash(state);
+            far.to_bits().hash(state);
+            colormap.hash(state);
+        }
+        if let RenderMode::Contribution { index } = self {
+            index.hash(state);
+        }
+    }
+}
+
+/// which side of a clipping volume is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ClipSide {
+    #[default]
+    Inside,
+    Outside,
 }
 
 impl Hash for SplattingArgs {
@@ -692,10 +2010,21 @@ impl Hash for SplattingArgs {
             .as_ref()
             .map(|b| bytemuck::bytes_of(&b.max))
             .hash(state);
+        self.clip_sphere
+            .as_ref()
+            .map(|(c, r, side)| (bytemuck::bytes_of(c), r.to_bits(), side))
+            .hash(state);
+        self.render_mode.hash(state);
+        self.preview_quality.hash(state);
+        self.max_splat_size.map(f32::to_bits).hash(state);
+        self.max_layers_per_pixel.hash(state);
+        self.region.hash(state);
     }
 }
 
 pub const DEFAULT_KERNEL_SIZE: f32 = 0.3;
+/// colormap index of the shader that outputs the unmapped view space depth
+const COLORMAP_RAW_DEPTH: u32 = 3;
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct SplattingArgsUniform {
@@ -709,17 +2038,53 @@ pub struct SplattingArgsUniform {
     kernel_size: f32,
     walltime: f32,
     scene_extend: f32,
-    _pad: u32,
+    /// 1: the red and green color channels of the splats hold the gaussian index (used for picking)
+    store_index: u32,
 
     scene_center: Vector4<f32>,
+
+    /// xyz: center, w: radius
+    clip_sphere: Vector4<f32>,
+    /// 0: disabled, 1: keep inside, 2: keep outside
+    clip_sphere_mode: u32,
+    min_opacity: f32,
+    /// minimum splat size in pixels
+    min_splat_size: f32,
+    /// maximum splat size in pixels (0 means unlimited)
+    max_splat_size: f32,
+
+    exposure: f32,
+    ambient_occlusion: f32,
+    /// view space depth range for depth coloring (disabled if y <= x)
+    depth_range: Vector2<f32>,
+    colormap: u32,
+    /// margin in pixels around the screen in which gaussians are not culled
+    cull_margin: f32,
+    depth_bias: f32,
+    /// gaussian whose contribution is rendered (u32::MAX if disabled)
+    contribution_index: u32,
+
+    /// xy: scale, zw: center of the rendered region in normalized device coordinates
+    region: Vector4<f32>,
+
+    /// 1: only the splats are updated, the sort buffers keep the order of the last sorted frame
+    reuse_order: u32,
+    _pad: [u32; 3],
 }
 
 impl SplattingArgsUniform {
     /// replaces values with default values for point cloud
     pub fn from_args_and_pc(args: SplattingArgs, pc: &PointCloud) -> Self {
+        let (preview_sh_deg, min_opacity, min_splat_size) = match args.preview_quality {
+            Some(0) => (0, 0.2, 4.),
+            Some(1) => (1, 0.1, 2.),
+            Some(2) => (2, 0.05, 1.),
+            _ => (3, 0., 0.),
+        };
         Self {
             gaussian_scaling: args.gaussian_scaling,
-            max_sh_deg: args.max_sh_deg,
+            // higher degrees than loaded would read the coefficients of other gaussians
+            max_sh_deg: args.max_sh_deg.min(pc.sh_deg()).min(preview_sh_deg),
             show_env_map: args.show_env_map as u32,
             mip_splatting: args
                 .mip_splatting
@@ -744,6 +2109,38 @@ impl SplattingArgsUniform {
                 .scene_extend
                 .unwrap_or(pc.bbox().radius())
                 .max(pc.bbox().radius()),
+            clip_sphere: args
+                .clip_sphere
+                .map_or(Vector4::new(0., 0., 0., 0.), |(c, r, _)| {
+                    c.to_vec().extend(r)
+                }),
+            clip_sphere_mode: match args.clip_sphere {
+                None => 0,
+                Some((_, _, ClipSide::Inside)) => 1,
+                Some((_, _, ClipSide::Outside)) => 2,
+            },
+            min_opacity,
+            min_splat_size,
+            max_splat_size: args.max_splat_size.unwrap_or(0.),
+            depth_range: match args.render_mode {
+                RenderMode::DepthColor { near, far, .. } => Vector2::new(near, far),
+                // only enables the depth output, the depth is not mapped
+                RenderMode::Depth => Vector2::new(0., 1.),
+                _ => Vector2::new(0., 0.),
+            },
+            colormap: match args.render_mode {
+                RenderMode::DepthColor { colormap, .. } => colormap.shader_index(),
+                RenderMode::Depth => COLORMAP_RAW_DEPTH,
+                _ => 0,
+            },
+            contribution_index: match args.render_mode {
+                RenderMode::Contribution { index } => index,
+                _ => u32::MAX,
+            },
+            region: args.region.map_or(Vector4::new(1., 1., 0., 0.), |r| {
+                let (scale, center) = r.ndc_transform(args.viewport);
+                Vector4::new(scale.x, scale.y, center.x, center.y)
+            }),
             ..Default::default()
         }
     }
@@ -767,7 +2164,896 @@ impl Default for SplattingArgsUniform {
             walltime: 0.,
             scene_center: Vector4::new(0., 0., 0., 0.),
             scene_extend: 1.,
-            _pad: 0,
+            store_index: 0,
+            clip_sphere: Vector4::new(0., 0., 0., 0.),
+            clip_sphere_mode: 0,
+            min_opacity: 0.,
+            min_splat_size: 0.,
+            max_splat_size: 0.,
+            exposure: 1.,
+            ambient_occlusion: 0.,
+            depth_range: Vector2::new(0., 0.),
+            colormap: 0,
+            cull_margin: 0.,
+            depth_bias: 0.,
+            contribution_index: u32::MAX,
+            region: Vector4::new(1., 1., 0., 0.),
+            reuse_order: 0,
+            _pad: [0; 3],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Point3, Quaternion, Vector2, Vector3};
+
+    use super::*;
+    use crate::{
+        depth_sort::CpuSorter,
+        gpu_rs::PointCloudSortStuff,
+        headless::{render_to_image_f32, render_to_texture},
+        io::GenericGaussianPointCloud,
+        test_utils::{
+            cpu_renderer, orbit_camera, random_point_cloud, scaled_splat_cloud, splat_cloud,
+            test_device, test_device_gpu_sort,
+        },
+        utils::download_texture,
+    };
+    use wgpu::util::DeviceExt;
+
+    /// sorted splat indices of the last frame
+    fn sorted_payload(
+        renderer: &GaussianRenderer,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Vec<u32> {
+        let n = pollster::block_on(renderer.num_visible_points(device, queue)) as usize;
+        let payload = &renderer
+            .sort_buffers
+            .as_ref()
+            .unwrap()
+            .sort_stuff()
+            .payload_a;
+        let mut payload =
+            pollster::block_on(download_buffer::<u32>(payload, device, queue)).unwrap();
+        payload.truncate(n);
+        return payload;
+    }
+
+    /// renders a 64x64 image with the given mode
+    fn render_mode(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &mut GaussianRenderer,
+        pc: &PointCloud,
+        camera: PerspectiveCamera,
+        render_mode: RenderMode,
+    ) -> image::Rgba32FImage {
+        let settings = SplattingArgs {
+            camera,
+            viewport: Vector2::new(64, 64),
+            gaussian_scaling: 1.,
+            max_sh_deg: pc.sh_deg(),
+            show_env_map: false,
+            mip_splatting: None,
+            kernel_size: None,
+            clipping_box: None,
+            walltime: Duration::from_secs(100),
+            scene_center: None,
+            scene_extend: None,
+            clip_sphere: None,
+            render_mode,
+            preview_quality: None,
+            max_splat_size: None,
+            max_layers_per_pixel: None,
+            region: None,
+        };
+        let target = render_to_texture(device, queue, renderer, pc, settings, &mut None).unwrap();
+        return pollster::block_on(download_texture(&target, device, queue, None)).unwrap();
+    }
+
+    #[test]
+    fn resort_on_motion_matches_full_sort() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let pc = PointCloud::new(&device, random_point_cloud(2000, 1)).unwrap();
+        let mut renderer = cpu_renderer(&device);
+        renderer.set_resort_policy(ResortPolicy::OnMotion { threshold: 0.07 });
+        let mut reference = cpu_renderer(&device);
+        let resolution = Vector2::new(64, 64);
+
+        // each step moves the camera by 0.02, so the order is only updated every fourth step
+        let camera = |step: usize| orbit_camera(step as f32 * 0.005);
+        let mut img = None;
+        for step in 0..=201 {
+            img = Some(
+                pollster::block_on(render_to_image_f32(
+                    &device,
+                    &queue,
+                    &mut renderer,
+                    &pc,
+                    camera(step),
+                    resolution,
+                ))
+                .unwrap(),
+            );
+        }
+        let img = img.unwrap();
+        let last_sorted = renderer.last_sort.as_ref().unwrap().camera.position;
+        assert_ne!(
+            last_sorted,
+            camera(201).position,
+            "the last frame should reuse an older order"
+        );
+
+        let expected = pollster::block_on(render_to_image_f32(
+            &device,
+            &queue,
+            &mut reference,
+            &pc,
+            camera(201),
+            resolution,
+        ))
+        .unwrap();
+        let covered = expected.pixels().filter(|p| p.0[3] > 0.1).count();
+        assert!(covered > 100, "only {covered} pixels are covered");
+        let diff = img
+            .as_raw()
+            .iter()
+            .zip(expected.as_raw())
+            .map(|(a, b)| (a - b).abs())
+            .sum::<f32>()
+            / img.as_raw().len() as f32;
+        assert!(diff < 1e-3, "mean difference {diff} to the sorted frame");
+    }
+
+    #[test]
+    fn equal_depths_keep_their_order() {
+        let Some((device, queue)) = test_device_gpu_sort() else {
+            return;
+        };
+        let sorter = pollster::block_on(GPURSSorter::new_u64(&device, &queue)).unwrap();
+        let mut renderer = GaussianRenderer::with_sorter(
+            &device,
+            wgpu::TextureFormat::Rgba16Float,
+            0,
+            false,
+            Box::new(sorter),
+        );
+        // all gaussians in the plane z=0, which the camera looks at head-on
+        let mut pc = random_point_cloud(5000, 2);
+        let mut gaussians = pc.gaussians().unwrap().to_vec();
+        for g in gaussians.iter_mut() {
+            g.xyz.z = half::f16::ZERO;
+        }
+        pc = GenericGaussianPointCloud::new(
+            gaussians,
+            bytemuck::cast_slice(pc.sh_coefs_buffer()).to_vec(),
+            0,
+            pc.num_points,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        let pc = PointCloud::new(&device, pc).unwrap();
+        let camera = PerspectiveCamera::new(
+            Point3::new(0., 0., -4.),
+            Quaternion::new(1., 0., 0., 0.),
+            orbit_camera(0.).projection,
+        );
+
+        let mut orders = Vec::new();
+        for _ in 0..5 {
+            pollster::block_on(render_to_image_f32(
+                &device,
+                &queue,
+                &mut renderer,
+                &pc,
+                camera,
+                Vector2::new(64, 64),
+            ))
+            .unwrap();
+            orders.push(sorted_payload(&renderer, &device, &queue));
+        }
+        assert!(
+            orders[0].len() > 1000,
+            "only {} splats are visible",
+            orders[0].len()
+        );
+        // equal keys are ordered by the gaussian index
+        assert!(orders[0].windows(2).all(|w| w[0] < w[1]));
+        assert!(orders.iter().all(|o| *o == orders[0]));
+    }
+
+    #[test]
+    fn front_opaque_color_ignores_faint_splats() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        // opaque red splat behind faint green and blue ones (the camera looks along +z)
+        let pc = PointCloud::new(
+            &device,
+            splat_cloud(&[
+                (Point3::new(0., 0., 0.5), 1., [1., 0., 0.]),
+                (Point3::new(0., 0., 0.), 0.3, [0., 1., 0.]),
+                (Point3::new(0., 0., -0.5), 0.3, [0., 0., 1.]),
+                (Point3::new(0., 0., -1.), 0.3, [0., 1., 0.]),
+            ]),
+        )
+        .unwrap();
+        let mut renderer = cpu_renderer(&device);
+        let camera = orbit_camera(0.);
+
+        let img = render_mode(
+            &device,
+            &queue,
+            &mut renderer,
+            &pc,
+            camera,
+            RenderMode::FrontOpaqueColor,
+        );
+        let center = img.get_pixel(32, 32).0;
+        for (c, expected) in center.iter().zip([1., 0., 0., 1.]) {
+            assert!((c - expected).abs() < 1e-2, "center pixel is {center:?}");
+        }
+        // blending mixes in the faint splats
+        let blended = render_mode(
+            &device,
+            &queue,
+            &mut renderer,
+            &pc,
+            camera,
+            RenderMode::Color,
+        );
+        assert!(blended.get_pixel(32, 32).0[1] > 0.1);
+    }
+
+    #[test]
+    fn dominant_color_is_the_highest_weight_splat() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        // weights at the center: green 0.45, red 0.6 * 0.55, blue 0.99 * 0.22
+        let pc = PointCloud::new(
+            &device,
+            splat_cloud(&[
+                (Point3::new(0., 0., -0.5), 0.45, [0., 1., 0.]),
+                (Point3::new(0., 0., 0.), 0.6, [1., 0., 0.]),
+                (Point3::new(0., 0., 0.5), 1., [0., 0., 1.]),
+            ]),
+        )
+        .unwrap();
+        let mut renderer = cpu_renderer(&device);
+        let camera = orbit_camera(0.);
+
+        let img = render_mode(
+            &device,
+            &queue,
+            &mut renderer,
+            &pc,
+            camera,
+            RenderMode::DominantColor,
+        );
+        let center = img.get_pixel(32, 32).0;
+        for (c, expected) in center.iter().zip([0., 1., 0., 1.]) {
+            assert!((c - expected).abs() < 1e-2, "center pixel is {center:?}");
+        }
+        assert_eq!(img.get_pixel(0, 0).0, [0.; 4]);
+
+        // the front opaque color skips the faint green splat
+        let front_opaque = render_mode(
+            &device,
+            &queue,
+            &mut renderer,
+            &pc,
+            camera,
+            RenderMode::FrontOpaqueColor,
+        );
+        assert!(front_opaque.get_pixel(32, 32).0[0] > 0.99);
+    }
+
+    #[test]
+    fn merged_point_clouds_render_together() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        // red splat with sh degree 0 and a blue splat with a view dependent degree 1 color
+        let mut merged = PointCloud::new(
+            &device,
+            splat_cloud(&[(Point3::new(1.5, 0., 0.), 1., [1., 0., 0.])]),
+        )
+        .unwrap();
+        let raw = splat_cloud(&[(Point3::new(-1.5, 0., 0.), 1., [0., 0., 1.])]);
+        let mut sh_coefs: Vec<[[f16; 3]; 16]> = bytemuck::pod_collect_to_vec(raw.sh_coefs_buffer());
+        sh_coefs[0][2] = [f16::from_f32(0.5); 3];
+        let raw = GenericGaussianPointCloud::new(
+            raw.gaussians().unwrap().to_vec(),
+            sh_coefs,
+            1,
+            1,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        let other = PointCloud::new(&device, raw).unwrap();
+        let camera = orbit_camera(0.);
+
+        let mut renderer = cpu_renderer(&device);
+        let first = render_mode(
+            &device,
+            &queue,
+            &mut renderer,
+            &merged,
+            camera,
+            RenderMode::Color,
+        );
+        let mut renderer = GaussianRenderer::with_sorter(
+            &device,
+            wgpu::TextureFormat::Rgba16Float,
+            1,
+            false,
+            Box::new(CpuSorter::new(&device)),
+        );
+        let second = render_mode(
+            &device,
+            &queue,
+            &mut renderer,
+            &other,
+            camera,
+            RenderMode::Color,
+        );
+
+        merged.merge(&device, &queue, &other).unwrap();
+        assert_eq!(merged.num_points(), 2);
+        assert_eq!(merged.sh_deg(), 1);
+        let img = render_mode(
+            &device,
+            &queue,
+            &mut renderer,
+            &merged,
+            camera,
+            RenderMode::Color,
+        );
+        for (x, y) in [(11, 32), (53, 32)] {
+            assert!(img.get_pixel(x, y).0[3] > 0.5, "nothing at {x},{y}");
+        }
+        // the splats do not overlap, so the merged image is the sum of the separate ones
+        // (with the view dependent color of the second splat)
+        for ((a, b), c) in first.pixels().zip(second.pixels()).zip(img.pixels()) {
+            for i in 0..4 {
+                assert!(
+                    (a.0[i] + b.0[i] - c.0[i]).abs() < 1e-2,
+                    "{a:?} + {b:?} != {c:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn coarse_previews_rasterize_fewer_gaussians() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let pc = PointCloud::new(&device, random_point_cloud(2000, 9)).unwrap();
+        let mut renderer = cpu_renderer(&device);
+        let settings = SplattingArgs {
+            camera: orbit_camera(0.),
+            viewport: Vector2::new(64, 64),
+            gaussian_scaling: 1.,
+            max_sh_deg: pc.sh_deg(),
+            show_env_map: false,
+            mip_splatting: None,
+            kernel_size: None,
+            clipping_box: None,
+            walltime: Duration::from_secs(100),
+            scene_center: None,
+            scene_extend: None,
+            clip_sphere: None,
+            render_mode: RenderMode::Color,
+            preview_quality: None,
+            max_splat_size: None,
+            max_layers_per_pixel: None,
+            region: None,
+        };
+        let visible: Vec<u32> = (0..=3)
+            .map(|quality| {
+                renderer
+                    .render_preview(&device, &queue, &pc, settings, quality)
+                    .unwrap();
+                return pollster::block_on(renderer.num_visible_points(&device, &queue));
+            })
+            .collect();
+        assert!(
+            visible[0] < visible[3],
+            "visible gaussians per quality: {visible:?}"
+        );
+        assert!(visible.windows(2).all(|w| w[0] <= w[1]), "{visible:?}");
+        assert_eq!(visible[3], 2000);
+    }
+
+    /// cpu sorter that shuffles the splats instead of sorting them
+    #[cfg(debug_assertions)]
+    struct ShuffleSorter(CpuSorter);
+
+    #[cfg(debug_assertions)]
+    impl DepthSorter for ShuffleSorter {
+        fn key_bytes(&self) -> usize {
+            self.0.key_bytes()
+        }
+
+        fn keys_per_workgroup(&self) -> usize {
+            self.0.keys_per_workgroup()
+        }
+
+        fn sort_buffer_sizes(&self, keysize: usize) -> Vec<(&'static str, u64)> {
+            self.0.sort_buffer_sizes(keysize)
+        }
+
+        fn create_sort_buffers(&self, device: &wgpu::Device, keysize: usize) -> SortBuffers {
+            self.0.create_sort_buffers(device, keysize)
+        }
+
+        fn resize_sort_buffers(
+            &self,
+            buffers: &mut SortBuffers,
+            device: &wgpu::Device,
+            queue: &wgpu::Queue,
+            keysize: usize,
+        ) -> bool {
+            self.0.resize_sort_buffers(buffers, device, queue, keysize)
+        }
+
+        fn sort_depths(
+            &self,
+            device: &wgpu::Device,
+            queue: &wgpu::Queue,
+            encoder: &mut wgpu::CommandEncoder,
+            sort_stuff: &PointCloudSortStuff,
+        ) {
+            use rand::{seq::SliceRandom, SeedableRng};
+
+            self.0.sort_depths(device, queue, encoder, sort_stuff);
+            let download =
+                |buffer| pollster::block_on(download_buffer::<u32>(buffer, device, queue)).unwrap();
+            let n = download(&sort_stuff.sorter_uni)[0] as usize;
+            let keys = download(&sort_stuff.keyval_a);
+            let payload = download(&sort_stuff.payload_a);
+            let mut order: Vec<usize> = (0..n).collect();
+            order.shuffle(&mut rand::rngs::StdRng::seed_from_u64(0));
+            let keys: Vec<u32> = order.iter().map(|i| keys[*i]).collect();
+            let payload: Vec<u32> = order.iter().map(|i| payload[*i]).collect();
+            queue.write_buffer(&sort_stuff.keyval_a, 0, bytemuck::cast_slice(&keys));
+            queue.write_buffer(&sort_stuff.payload_a, 0, bytemuck::cast_slice(&payload));
+        }
+    }
+
+    /// renders the sort violation heatmap and returns the number of pixels with violations
+    #[cfg(debug_assertions)]
+    fn count_sort_violations(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sorter: Box<dyn DepthSorter>,
+    ) -> usize {
+        // the splats have no red, so red pixels are violations
+        let mut pc = random_point_cloud(2000, 3);
+        let mut sh_coefs: Vec<[[half::f16; 3]; 16]> =
+            bytemuck::cast_slice(pc.sh_coefs_buffer()).to_vec();
+        for sh in sh_coefs.iter_mut() {
+            sh[0][0] = half::f16::from_f32(-2.);
+        }
+        pc = GenericGaussianPointCloud::new(
+            pc.gaussians().unwrap().to_vec(),
+            sh_coefs,
+            0,
+            pc.num_points,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        let pc = PointCloud::new(device, pc).unwrap();
+        let mut renderer = GaussianRenderer::with_sorter(
+            device,
+            wgpu::TextureFormat::Rgba16Float,
+            0,
+            false,
+            sorter,
+        );
+        let img = render_mode(
+            device,
+            queue,
+            &mut renderer,
+            &pc,
+            orbit_camera(0.3),
+            RenderMode::SortViolations,
+        );
+        return img.pixels().filter(|p| p.0[0] > 0.1).count();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn sort_check_flags_shuffled_splats() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let sorted = count_sort_violations(&device, &queue, Box::new(CpuSorter::new(&device)));
+        assert_eq!(sorted, 0, "violations in the sorted order");
+        let shuffled = count_sort_violations(
+            &device,
+            &queue,
+            Box::new(ShuffleSorter(CpuSorter::new(&device))),
+        );
+        assert!(shuffled > 100, "only {shuffled} pixels with violations");
+    }
+
+    #[test]
+    fn auto_exposure_reaches_target_luminance() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let mut renderer = cpu_renderer(&device);
+        renderer.set_auto_exposure(Some(AutoExposure {
+            target: 0.5,
+            speed: 1.,
+        }));
+        // uniform gray frame with a luminance of 0.25
+        let pixels = vec![f16::from_f32(0.25); 32 * 32 * 4];
+        let frame = device.create_texture_with_data(
+            &queue,
+            &wgpu::TextureDescriptor {
+                label: Some("gray frame"),
+                size: wgpu::Extent3d {
+                    width: 32,
+                    height: 32,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba16Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            bytemuck::cast_slice(&pixels),
+        );
+        pollster::block_on(renderer.update_exposure(&device, &queue, &frame));
+        assert!((renderer.exposure() - 2.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn clip_sphere_keeps_the_selected_side() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        // red splat at the center and green ones to the left and right of it
+        let pc = PointCloud::new(
+            &device,
+            splat_cloud(&[
+                (Point3::new(0., 0., 0.), 1., [1., 0., 0.]),
+                (Point3::new(-1.5, 0., 0.), 1., [0., 1., 0.]),
+                (Point3::new(1.5, 0., 0.), 1., [0., 1., 0.]),
+            ]),
+        )
+        .unwrap();
+        let mut renderer = cpu_renderer(&device);
+        let camera = orbit_camera(0.);
+        // the outer splats are about 21 pixels from the center
+        let alpha = |img: &image::Rgba32FImage, x: u32| img.get_pixel(x, 32).0[3];
+
+        renderer.set_clip_sphere(Some((Point3::new(0., 0., 0.), 0.5, ClipSide::Inside)));
+        let img = render_mode(
+            &device,
+            &queue,
+            &mut renderer,
+            &pc,
+            camera,
+            RenderMode::Color,
+        );
+        assert!(alpha(&img, 32) > 0.5);
+        assert_eq!(alpha(&img, 11), 0.);
+        assert_eq!(alpha(&img, 53), 0.);
+
+        renderer.set_clip_sphere(Some((Point3::new(0., 0., 0.), 0.5, ClipSide::Outside)));
+        let img = render_mode(
+            &device,
+            &queue,
+            &mut renderer,
+            &pc,
+            camera,
+            RenderMode::Color,
+        );
+        assert_eq!(alpha(&img, 32), 0.);
+        assert!(alpha(&img, 11) > 0.5);
+        assert!(alpha(&img, 53) > 0.5);
+    }
+
+    #[test]
+    fn max_screen_size_bounds_close_splats() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        // large gaussian right in front of the camera at z = -4
+        let pc = PointCloud::new(
+            &device,
+            scaled_splat_cloud(
+                &[(Point3::new(0., 0., -3.8), 1., [1., 0., 0.])],
+                Vector3::new(3., 2., 1.),
+            ),
+        )
+        .unwrap();
+        let mut renderer = cpu_renderer(&device);
+        let camera = orbit_camera(0.);
+        let covered = |img: &image::Rgba32FImage| img.pixels().filter(|p| p.0[3] > 0.01).count();
+
+        let img = render_mode(
+            &device,
+            &queue,
+            &mut renderer,
+            &pc,
+            camera,
+            RenderMode::Color,
+        );
+        assert_eq!(covered(&img), 64 * 64);
+
+        renderer.set_max_screen_size(Some(4.));
+        let img = render_mode(
+            &device,
+            &queue,
+            &mut renderer,
+            &pc,
+            camera,
+            RenderMode::Color,
+        );
+        let n = covered(&img);
+        // the quad is cut off at about 2.4 standard deviations (4 pixels each)
+        assert!(n > 0 && n < 20 * 20, "{n} pixels are covered");
+        assert!(img.get_pixel(32, 32).0[3] > 0.5);
+    }
+
+    #[test]
+    fn max_layers_per_pixel_blends_the_front_most_splats() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        // red in front of green in front of blue (the camera looks along +z)
+        let pc = PointCloud::new(
+            &device,
+            splat_cloud(&[
+                (Point3::new(0., 0., -1.), 0.6, [1., 0., 0.]),
+                (Point3::new(0., 0., 0.), 0.6, [0., 1., 0.]),
+                (Point3::new(0., 0., 1.), 0.6, [0., 0., 1.]),
+            ]),
+        )
+        .unwrap();
+        let mut renderer = cpu_renderer(&device);
+        let camera = orbit_camera(0.);
+        let full = render_mode(
+            &device,
+            &queue,
+            &mut renderer,
+            &pc,
+            camera,
+            RenderMode::Color,
+        );
+
+        renderer.set_max_layers_per_pixel(Some(255));
+        let layered = render_mode(
+            &device,
+            &queue,
+            &mut renderer,
+            &pc,
+            camera,
+            RenderMode::Color,
+        );
+        for (a, b) in full.pixels().zip(layered.pixels()) {
+            for c in 0..4 {
+                assert!((a.0[c] - b.0[c]).abs() < 1e-2, "{a:?} != {b:?}");
+            }
+        }
+
+        renderer.set_max_layers_per_pixel(Some(1));
+        let front = render_mode(
+            &device,
+            &queue,
+            &mut renderer,
+            &pc,
+            camera,
+            RenderMode::Color,
+        );
+        let [r, g, b, a] = front.get_pixel(32, 32).0;
+        assert!(
+            r > 0.5 && (r - a).abs() < 1e-2,
+            "center pixel is {:?}",
+            [r, g, b, a]
+        );
+        assert!(g < 1e-2 && b < 1e-2, "center pixel is {:?}", [r, g, b, a]);
+        // all three splats are visible with full blending
+        let [_, g, b, _] = full.get_pixel(32, 32).0;
+        assert!(g > 0.05 && b > 0.01);
+    }
+
+    #[test]
+    fn cameras_looking_away_see_no_splats() {
+        use cgmath::Rotation;
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let pc = PointCloud::new(&device, random_point_cloud(2000, 18)).unwrap();
+        let mut renderer = cpu_renderer(&device);
+        let towards = orbit_camera(0.);
+        let away = PerspectiveCamera::new(
+            towards.position,
+            Quaternion::look_at(-Vector3::unit_z(), Vector3::unit_y()),
+            towards.projection,
+        );
+        let visible = |renderer: &mut GaussianRenderer, camera| {
+            render_mode(&device, &queue, renderer, &pc, camera, RenderMode::Color);
+            return pollster::block_on(renderer.num_visible_points(&device, &queue));
+        };
+        assert_eq!(visible(&mut renderer, towards), 2000);
+        assert_eq!(visible(&mut renderer, away), 0);
+    }
+
+    #[test]
+    fn thresholds_skip_negligible_splats() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        // all opacities are below 1
+        let pc = PointCloud::new(&device, random_point_cloud(2000, 19)).unwrap();
+        let mut renderer = cpu_renderer(&device);
+        let render = |renderer: &mut GaussianRenderer| {
+            let img = render_mode(
+                &device,
+                &queue,
+                renderer,
+                &pc,
+                orbit_camera(0.),
+                RenderMode::Color,
+            );
+            let visible = pollster::block_on(renderer.num_visible_points(&device, &queue));
+            return (img, visible);
+        };
+        let (img, visible) = render(&mut renderer);
+        assert_eq!(visible, 2000);
+        assert!(img.pixels().any(|p| p.0[3] > 0.));
+
+        renderer.set_min_opacity(0.5);
+        let (_, half) = render(&mut renderer);
+        assert!(half > 0 && half < 2000, "{half} splats are visible");
+
+        renderer.set_min_opacity(1.);
+        let (img, visible) = render(&mut renderer);
+        assert_eq!(visible, 0);
+        assert!(img.pixels().all(|p| p.0 == [0.; 4]));
+
+        // all splats are much smaller than 100 pixels
+        renderer.set_min_opacity(0.);
+        renderer.set_min_screen_size(100.);
+        let (_, visible) = render(&mut renderer);
+        assert_eq!(visible, 0);
+    }
+
+    #[test]
+    fn depth_of_the_nearer_splat_dominates() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        // the camera is at z=-4, so the splats are 3 and 5 units away
+        let near = (Point3::new(0., 0., -1.), 0.9, [1., 1., 1.]);
+        let far = (Point3::new(0., 0., 1.), 0.9, [1., 1., 1.]);
+        let mut renderer = cpu_renderer(&device);
+        let mut depth = |splats: &[_]| {
+            let pc = PointCloud::new(&device, splat_cloud(splats)).unwrap();
+            let img = render_mode(
+                &device,
+                &queue,
+                &mut renderer,
+                &pc,
+                orbit_camera(0.),
+                RenderMode::Depth,
+            );
+            // pixels without splats have no depth
+            assert_eq!(img.get_pixel(0, 0).0, [0.; 4]);
+            let [d, _, _, alpha] = img.get_pixel(32, 32).0;
+            assert!(alpha > 0.5);
+            return d / alpha;
+        };
+        let near_only = depth(&[near]);
+        let far_only = depth(&[far]);
+        assert!((near_only - 3.).abs() < 0.05, "{near_only}");
+        assert!((far_only - 5.).abs() < 0.05, "{far_only}");
+        let both = depth(&[far, near]);
+        assert!(both > near_only && both < 3.5, "{both}");
+    }
+
+    #[test]
+    fn camera_uniform_layout_matches_the_shader() {
+        use std::mem::offset_of;
+        assert_eq!(offset_of!(CameraUniform, view_matrix), 0);
+        assert_eq!(offset_of!(CameraUniform, view_inv_matrix), 64);
+        assert_eq!(offset_of!(CameraUniform, proj_matrix), 128);
+        assert_eq!(offset_of!(CameraUniform, proj_inv_matrix), 192);
+        assert_eq!(offset_of!(CameraUniform, viewport), 256);
+        assert_eq!(offset_of!(CameraUniform, focal), 264);
+        assert_eq!(CameraUniform::VIEW_BYTES, 0..128);
+        assert_eq!(CameraUniform::PROJECTION_BYTES, 128..272);
+    }
+
+    #[test]
+    fn moving_camera_matches_a_fresh_renderer() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let pc = PointCloud::new(&device, random_point_cloud(2000, 20)).unwrap();
+        let mut moving = cpu_renderer(&device);
+        let first = render_mode(
+            &device,
+            &queue,
+            &mut moving,
+            &pc,
+            orbit_camera(0.),
+            RenderMode::Color,
+        );
+        let projection = moving.camera.data().proj_matrix;
+        // only the view matrices are uploaded for the second frame
+        let camera = orbit_camera(0.3);
+        let img = render_mode(&device, &queue, &mut moving, &pc, camera, RenderMode::Color);
+        assert_eq!(moving.camera.data().view_matrix, camera.view_matrix());
+        assert_eq!(moving.camera.data().proj_matrix, projection);
+        assert_ne!(img, first);
+
+        let mut fresh = cpu_renderer(&device);
+        let expected = render_mode(&device, &queue, &mut fresh, &pc, camera, RenderMode::Color);
+        assert_eq!(img, expected);
+    }
+
+    #[test]
+    fn non_finite_depths_are_sorted_to_the_far_end() {
+        use half::f16;
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let pc = random_point_cloud(100, 21);
+        let mut gaussians = pc.gaussians().unwrap().to_vec();
+        let sh_coefs: Vec<[[f16; 3]; 16]> = bytemuck::cast_slice(pc.sh_coefs_buffer()).to_vec();
+        let non_finite = [f16::NAN, f16::INFINITY, f16::NEG_INFINITY];
+        for (g, v) in gaussians.iter_mut().zip(non_finite) {
+            g.xyz.z = v;
         }
+        let pc_z: Vec<f16> = gaussians.iter().map(|g| g.xyz.z).collect();
+        let pc = GenericGaussianPointCloud::new(
+            gaussians, sh_coefs, 0, 100, None, None, None, None, None,
+        );
+        let pc = PointCloud::new(&device, pc).unwrap();
+        let mut renderer = cpu_renderer(&device);
+        render_mode(
+            &device,
+            &queue,
+            &mut renderer,
+            &pc,
+            orbit_camera(0.),
+            RenderMode::Color,
+        );
+        // the splats are drawn back to front, so the far plane comes first
+        let payload = sorted_payload(&renderer, &device, &queue);
+        assert_eq!(payload.len(), 100);
+        let mut first = payload[..3].to_vec();
+        first.sort();
+        assert_eq!(first, [0, 1, 2]);
+        // the camera looks along +z, so the depth grows with z
+        let depths: Vec<f32> = payload[3..]
+            .iter()
+            .map(|i| pc_z[*i as usize].to_f32())
+            .collect();
+        assert!(depths.windows(2).all(|w| w[0] >= w[1]), "{depths:?}");
     }
 }
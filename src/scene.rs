@@ -1,13 +1,22 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
+    fs::File,
     hash::Hash,
-    io::{self, BufReader},
+    io::{self, BufRead, BufReader},
+    path::Path,
 };
 
-use cgmath::{Matrix3, MetricSpace, Point3, SquareMatrix, Vector2};
+use byteorder::{LittleEndian, ReadBytesExt};
+use cgmath::{
+    Matrix, Matrix3, Matrix4, MetricSpace, Point3, Quaternion, Rad, SquareMatrix, Transform,
+    Vector2, Vector3,
+};
 use serde::{Deserialize, Serialize};
 
-use crate::camera::{focal2fov, fov2focal, PerspectiveCamera, PerspectiveProjection};
+use crate::{
+    camera::{focal2fov, fov2focal, Camera, PerspectiveCamera, PerspectiveProjection},
+    pointcloud::PointCloud,
+};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SceneCamera {
@@ -21,6 +30,21 @@ pub struct SceneCamera {
     pub fy: f32,
     #[serde(skip_deserializing, skip_serializing)]
     pub split: Split,
+    /// render settings for this camera only
+    #[serde(flatten)]
+    pub overrides: CameraOverrides,
+}
+
+/// optional per camera render settings that replace the global ones
+/// (e.g. for close-up views with huge splats)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct CameraOverrides {
+    /// maximum screen space extent of a splat in pixels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_screen_size: Option<f32>,
+    /// scaling factor for the size of all gaussians
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale_modifier: Option<f32>,
 }
 
 impl std::hash::Hash for SceneCamera {
@@ -32,6 +56,8 @@ impl std::hash::Hash for SceneCamera {
         bytemuck::cast_slice::<_, u8>(&self.rotation).hash(state);
         bytemuck::cast_slice::<_, u8>(&[self.fx, self.fy]).hash(state);
         self.split.hash(state);
+        self.overrides.max_screen_size.map(f32::to_bits).hash(state);
+        self.overrides.scale_modifier.map(f32::to_bits).hash(state);
     }
 }
 
@@ -56,6 +82,27 @@ impl SceneCamera {
             fx,
             fy,
             split,
+            overrides: CameraOverrides::default(),
+        }
+    }
+
+    /// applies a similarity transform (rotation, translation and uniform scale) to the camera pose.
+    /// The scale only changes the position
+    pub fn transformed(&self, transform: Matrix4<f32>) -> Self {
+        let linear = Matrix3::from_cols(
+            transform.x.truncate(),
+            transform.y.truncate(),
+            transform.z.truncate(),
+        );
+        let scale = linear.determinant().abs().cbrt();
+        // the rows of the camera to world rotation are stored, so the rotation is applied from the right
+        let rotation = Matrix3::from(self.rotation) * (linear / scale).transpose();
+        Self {
+            position: transform
+                .transform_point(Point3::from(self.position))
+                .into(),
+            rotation: rotation.into(),
+            ..self.clone()
         }
     }
 }
@@ -109,15 +156,77 @@ impl Into<PerspectiveCamera> for SceneCamera {
 
 #[derive(Debug)]
 pub struct Scene {
-    cameras: HashMap<usize, SceneCamera>,
+    /// sorted by id
+    cameras: BTreeMap<usize, SceneCamera>,
     /// maximum distance between two cameras
     extend: f32,
+    global_transform: Matrix4<f32>,
+}
+
+/// scene files are either a list of cameras or an object with the cameras
+/// and an optional transform (3x4 or 4x4, row major) like nerfstudio's `applied_transform`
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum SceneFile {
+    Cameras(Vec<SceneCamera>),
+    WithTransform {
+        cameras: Vec<SceneCamera>,
+        applied_transform: Option<Vec<[f32; 4]>>,
+    },
+}
+
+/// intrinsics of a Nerfstudio `transforms.json` file (global or per frame)
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+struct NerfstudioIntrinsics {
+    fl_x: Option<f32>,
+    fl_y: Option<f32>,
+    cx: Option<f32>,
+    cy: Option<f32>,
+    /// image size (written as floats by some tools)
+    w: Option<f32>,
+    h: Option<f32>,
+    /// horizontal field of view in radians (Instant-NGP)
+    camera_angle_x: Option<f32>,
+    camera_angle_y: Option<f32>,
+}
+
+impl NerfstudioIntrinsics {
+    /// values of self with the missing ones taken from `other`
+    fn or(&self, other: &Self) -> Self {
+        Self {
+            fl_x: self.fl_x.or(other.fl_x),
+            fl_y: self.fl_y.or(other.fl_y),
+            cx: self.cx.or(other.cx),
+            cy: self.cy.or(other.cy),
+            w: self.w.or(other.w),
+            h: self.h.or(other.h),
+            camera_angle_x: self.camera_angle_x.or(other.camera_angle_x),
+            camera_angle_y: self.camera_angle_y.or(other.camera_angle_y),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct NerfstudioFrame {
+    file_path: String,
+    /// camera to world matrix (OpenGL convention, row major)
+    transform_matrix: Vec<[f32; 4]>,
+    #[serde(flatten)]
+    intrinsics: NerfstudioIntrinsics,
+}
+
+#[derive(Deserialize)]
+struct NerfstudioFile {
+    #[serde(flatten)]
+    intrinsics: NerfstudioIntrinsics,
+    frames: Vec<NerfstudioFrame>,
+    applied_transform: Option<Vec<[f32; 4]>>,
 }
 
 impl Scene {
     pub fn from_cameras(cameras: Vec<SceneCamera>) -> Self {
         let extend = max_distance(cameras.iter().map(|c| Point3::from(c.position)).collect());
-        let mut map = HashMap::with_capacity(cameras.len());
+        let mut map = BTreeMap::new();
         for c in cameras {
             let id = c.id;
             if map.insert(c.id, c).is_some() {
@@ -130,12 +239,19 @@ impl Scene {
         Self {
             cameras: map,
             extend,
+            global_transform: Matrix4::identity(),
         }
     }
 
     pub fn from_json<R: io::Read>(file: R) -> Result<Self, anyhow::Error> {
         let mut reader = BufReader::new(file);
-        let mut cameras: Vec<SceneCamera> = serde_json::from_reader(&mut reader)?;
+        let (mut cameras, transform) = match serde_json::from_reader(&mut reader)? {
+            SceneFile::Cameras(cameras) => (cameras, None),
+            SceneFile::WithTransform {
+                cameras,
+                applied_transform,
+            } => (cameras, applied_transform),
+        };
         for (i, c) in cameras.iter_mut().enumerate() {
             // according to Kerbl et al "3D Gaussian Splatting for Real-Time Radiance Field Rendering"
             // 7 out of 8 cameras are taken as training images
@@ -146,9 +262,175 @@ impl Scene {
             }
         }
         log::info!("loaded scene file with {} views", cameras.len());
+        let mut scene = Self::from_cameras(cameras);
+        if let Some(rows) = transform {
+            scene.global_transform = matrix_from_rows(&rows, "applied_transform")?;
+        }
+        Ok(scene)
+    }
+
+    /// Loads the cameras of a Nerfstudio or Instant-NGP `transforms.json` file.
+    /// The OpenGL camera to world matrices (y up, looking along -z) are converted to the
+    /// convention of this crate (y down, looking along +z). Intrinsics of a frame replace the
+    /// global ones, the focal length can also be given as `camera_angle_x`/`camera_angle_y`.
+    /// The cameras are sorted by `file_path`, which is kept as image name.
+    /// Distortion parameters are ignored and the principal point is assumed to be centered.
+    pub fn from_nerfstudio_json<R: io::Read>(file: R) -> Result<Self, anyhow::Error> {
+        let mut transforms: NerfstudioFile = serde_json::from_reader(BufReader::new(file))?;
+        // same order as the Nerfstudio data parser
+        transforms
+            .frames
+            .sort_by(|a, b| a.file_path.cmp(&b.file_path));
+
+        let mut off_center = 0;
+        let cameras = transforms
+            .frames
+            .into_iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                let intrinsics = frame.intrinsics.or(&transforms.intrinsics);
+                let (width, height) = intrinsics.w.zip(intrinsics.h).ok_or(anyhow::anyhow!(
+                    "frame '{}' has no image size (w and h)",
+                    frame.file_path
+                ))?;
+                let fx = intrinsics
+                    .fl_x
+                    .or(intrinsics.camera_angle_x.map(|a| fov2focal(Rad(a), width)))
+                    .ok_or(anyhow::anyhow!(
+                        "frame '{}' has no focal length (fl_x or camera_angle_x)",
+                        frame.file_path
+                    ))?;
+                let fy = intrinsics
+                    .fl_y
+                    .or(intrinsics.camera_angle_y.map(|a| fov2focal(Rad(a), height)))
+                    .unwrap_or(fx);
+                let cx = intrinsics.cx.unwrap_or(width / 2.);
+                let cy = intrinsics.cy.unwrap_or(height / 2.);
+                if (cx - width / 2.).abs() > 1. || (cy - height / 2.).abs() > 1. {
+                    off_center += 1;
+                }
+
+                let c2w = matrix_from_rows(&frame.transform_matrix, "transform_matrix")?;
+                // flip the y and z axis of the camera
+                let rotation =
+                    Matrix3::from_cols(c2w.x.truncate(), -c2w.y.truncate(), -c2w.z.truncate());
+                Ok(SceneCamera {
+                    id: i,
+                    img_name: frame.file_path,
+                    width: width.round() as u32,
+                    height: height.round() as u32,
+                    position: c2w.w.truncate().into(),
+                    // rows of the camera to world rotation like the 3DGS scene files
+                    rotation: rotation.transpose().into(),
+                    fx,
+                    fy,
+                    overrides: CameraOverrides::default(),
+                    split: if i % 8 == 0 {
+                        Split::Test
+                    } else {
+                        Split::Train
+                    },
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if off_center > 0 {
+            log::warn!(
+                "{} cameras have a principal point that is not centered, it is ignored",
+                off_center
+            );
+        }
+        log::info!("loaded transforms file with {} views", cameras.len());
+        let mut scene = Self::from_cameras(cameras);
+        if let Some(rows) = transforms.applied_transform {
+            scene.global_transform = matrix_from_rows(&rows, "applied_transform")?;
+        }
+        Ok(scene)
+    }
+
+    /// Writes the cameras (sorted by id) in the format read by [`Scene::from_json`].
+    /// The global transform is written as `applied_transform` if it is not the identity.
+    /// Splits are not stored, `from_json` assigns them again from the camera order.
+    pub fn to_json<W: io::Write>(&self, writer: W) -> Result<(), anyhow::Error> {
+        let cameras = self.cameras(None);
+        let file = if self.global_transform == Matrix4::identity() {
+            SceneFile::Cameras(cameras)
+        } else {
+            // cgmath matrices are column major
+            let rows: [[f32; 4]; 4] = self.global_transform.transpose().into();
+            SceneFile::WithTransform {
+                cameras,
+                applied_transform: Some(rows.to_vec()),
+            }
+        };
+        serde_json::to_writer_pretty(writer, &file)?;
+        Ok(())
+    }
+
+    /// loads the cameras of a COLMAP reconstruction from the binary `cameras.bin` and `images.bin`
+    /// files in the given directory (e.g. `sparse/0`).
+    /// Only the PINHOLE and SIMPLE_PINHOLE camera models are supported.
+    pub fn from_colmap<P: AsRef<Path>>(path: P) -> Result<Self, anyhow::Error> {
+        let path = path.as_ref();
+        let intrinsics =
+            read_colmap_cameras(&mut BufReader::new(File::open(path.join("cameras.bin"))?))?;
+        let mut images =
+            read_colmap_images(&mut BufReader::new(File::open(path.join("images.bin"))?))?;
+        // same order as the scene files written by the training code
+        images.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let cameras = images
+            .into_iter()
+            .enumerate()
+            .map(|(i, img)| {
+                let cam = intrinsics.get(&img.camera_id).ok_or(anyhow::anyhow!(
+                    "image '{}' references unknown camera {}",
+                    img.name,
+                    img.camera_id
+                ))?;
+                // COLMAP stores the world to camera transform
+                let rotation = Matrix3::from(img.rotation);
+                let position = -(rotation.transpose() * img.translation);
+                Ok(SceneCamera {
+                    id: i,
+                    img_name: img.name,
+                    width: cam.width,
+                    height: cam.height,
+                    position: position.into(),
+                    rotation: rotation.into(),
+                    fx: cam.fx,
+                    fy: cam.fy,
+                    overrides: CameraOverrides::default(),
+                    split: if i % 8 == 0 {
+                        Split::Test
+                    } else {
+                        Split::Train
+                    },
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        log::info!("loaded COLMAP reconstruction with {} views", cameras.len());
         Ok(Self::from_cameras(cameras))
     }
 
+    /// transform from the point cloud to the camera coordinate system
+    /// (identity if the scene file does not contain one)
+    pub fn global_transform(&self) -> Matrix4<f32> {
+        self.global_transform
+    }
+
+    /// applies the transform to all cameras (see [`SceneCamera::transformed`])
+    pub fn transform_cameras(&mut self, transform: Matrix4<f32>) {
+        for c in self.cameras.values_mut() {
+            *c = c.transformed(transform);
+        }
+        self.extend = max_distance(
+            self.cameras
+                .values()
+                .map(|c| Point3::from(c.position))
+                .collect(),
+        );
+    }
+
     pub fn camera(&self, i: usize) -> Option<SceneCamera> {
         self.cameras.get(&i).cloned()
     }
@@ -158,16 +440,15 @@ impl Scene {
     }
 
     pub fn cameras(&self, split: Option<Split>) -> Vec<SceneCamera> {
-        let mut c: Vec<SceneCamera> = if let Some(split) = split {
-            self.cameras
-                .iter()
-                .filter_map(|(_, c)| (c.split == split).then_some(c.clone()))
-                .collect()
-        } else {
-            self.cameras.iter().map(|(_, c)| c.clone()).collect()
-        };
-        c.sort_by_key(|c| c.id);
-        return c;
+        self.cameras_iter(split).cloned().collect()
+    }
+
+    /// iterates over the cameras (sorted by id) without cloning them
+    pub fn cameras_iter(&self, split: Option<Split>) -> impl Iterator<Item = &SceneCamera> + Clone {
+        return self
+            .cameras
+            .values()
+            .filter(move |c| split.map_or(true, |s| c.split == s));
     }
 
     pub fn extend(&self) -> f32 {
@@ -185,6 +466,175 @@ impl Scene {
             .min_by_key(|c| (Point3::from(c.position).distance2(pos) * 1e6) as u32)
             .map(|c| c.id)
     }
+
+    /// checks all cameras against the bounding sphere of the point cloud
+    /// to detect mismatches before rendering (e.g. different units or coordinate systems)
+    pub fn validate_against(&self, pc: &PointCloud) -> Vec<SceneWarning> {
+        let center = pc.bbox().center();
+        let radius = pc.bbox().radius();
+        let mut warnings = Vec::new();
+        for c in self.cameras_iter(None) {
+            let camera: PerspectiveCamera = c.clone().into();
+            let distance = camera.position.distance(center);
+            if distance > MAX_DISTANCE_RATIO * radius.max(f32::EPSILON) {
+                warnings.push(SceneWarning::TooFarAway {
+                    camera: c.id,
+                    distance,
+                    radius,
+                });
+            }
+            // sphere frustum test in view space (camera looks along +z)
+            let p = camera.view_matrix() * center.to_homogeneous();
+            let half_x = camera.projection.fovx.0 / 2.;
+            let half_y = camera.projection.fovy.0 / 2.;
+            let outside = p.z < -radius
+                || p.x.abs() * half_x.cos() - p.z * half_x.sin() > radius
+                || p.y.abs() * half_y.cos() - p.z * half_y.sin() > radius;
+            if outside {
+                warnings.push(SceneWarning::NotFacingPointCloud { camera: c.id });
+            }
+        }
+        return warnings;
+    }
+}
+
+/// ratio between camera distance and point cloud radius above which a camera is considered misplaced
+const MAX_DISTANCE_RATIO: f32 = 100.;
+
+/// potential mismatch between a scene and a point cloud
+#[derive(Debug, Clone, PartialEq)]
+pub enum SceneWarning {
+    /// the bounding sphere of the point cloud does not intersect the camera's view frustum
+    NotFacingPointCloud { camera: usize },
+    /// the camera is orders of magnitude further away than the point cloud is large
+    TooFarAway {
+        camera: usize,
+        distance: f32,
+        radius: f32,
+    },
+}
+
+impl std::fmt::Display for SceneWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneWarning::NotFacingPointCloud { camera } => {
+                write!(f, "camera {camera} does not look at the point cloud")
+            }
+            SceneWarning::TooFarAway {
+                camera,
+                distance,
+                radius,
+            } => write!(
+                f,
+                "camera {camera} is {distance} units away from a point cloud with radius {radius} (unit or coordinate mismatch?)"
+            ),
+        }
+    }
+}
+
+struct ColmapCamera {
+    width: u32,
+    height: u32,
+    fx: f32,
+    fy: f32,
+}
+
+struct ColmapImage {
+    name: String,
+    camera_id: u32,
+    rotation: Quaternion<f32>,
+    translation: Vector3<f32>,
+}
+
+/// model ids from COLMAP's `src/colmap/sensor/models.h`
+const COLMAP_SIMPLE_PINHOLE: i32 = 0;
+const COLMAP_PINHOLE: i32 = 1;
+
+fn read_colmap_cameras<R: io::Read>(reader: &mut R) -> anyhow::Result<HashMap<u32, ColmapCamera>> {
+    let num_cameras = reader.read_u64::<LittleEndian>()?;
+    let mut cameras = HashMap::with_capacity(num_cameras as usize);
+    for _ in 0..num_cameras {
+        let id = reader.read_u32::<LittleEndian>()?;
+        let model = reader.read_i32::<LittleEndian>()?;
+        let width = reader.read_u64::<LittleEndian>()? as u32;
+        let height = reader.read_u64::<LittleEndian>()? as u32;
+        let (fx, fy) = match model {
+            COLMAP_SIMPLE_PINHOLE => {
+                let mut params = [0f64; 3];
+                reader.read_f64_into::<LittleEndian>(&mut params)?;
+                (params[0], params[0])
+            }
+            COLMAP_PINHOLE => {
+                let mut params = [0f64; 4];
+                reader.read_f64_into::<LittleEndian>(&mut params)?;
+                (params[0], params[1])
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "camera {} uses unsupported COLMAP camera model {} (only PINHOLE and SIMPLE_PINHOLE are supported)",
+                    id,
+                    model
+                ))
+            }
+        };
+        cameras.insert(
+            id,
+            ColmapCamera {
+                width,
+                height,
+                fx: fx as f32,
+                fy: fy as f32,
+            },
+        );
+    }
+    return Ok(cameras);
+}
+
+fn read_colmap_images<R: BufRead>(reader: &mut R) -> anyhow::Result<Vec<ColmapImage>> {
+    let num_images = reader.read_u64::<LittleEndian>()?;
+    let mut images = Vec::with_capacity(num_images as usize);
+    for _ in 0..num_images {
+        let _image_id = reader.read_u32::<LittleEndian>()?;
+        let mut q = [0f64; 4];
+        reader.read_f64_into::<LittleEndian>(&mut q)?;
+        let mut t = [0f64; 3];
+        reader.read_f64_into::<LittleEndian>(&mut t)?;
+        let camera_id = reader.read_u32::<LittleEndian>()?;
+        let mut name = Vec::new();
+        reader.read_until(0, &mut name)?;
+        if name.pop() != Some(0) {
+            return Err(anyhow::anyhow!("unexpected end of COLMAP images file"));
+        }
+        // skip 2d points (x: f64, y: f64, point3d_id: i64)
+        let num_points = reader.read_u64::<LittleEndian>()?;
+        io::copy(
+            &mut io::Read::take(&mut *reader, num_points * 24),
+            &mut io::sink(),
+        )?;
+        images.push(ColmapImage {
+            name: String::from_utf8(name)?,
+            camera_id,
+            // COLMAP quaternions are stored as (w, x, y, z)
+            rotation: Quaternion::new(q[0], q[1], q[2], q[3]).cast().unwrap(),
+            translation: Vector3::new(t[0], t[1], t[2]).cast().unwrap(),
+        });
+    }
+    return Ok(images);
+}
+
+/// builds a matrix from 3 or 4 rows (the last row defaults to 0,0,0,1)
+fn matrix_from_rows(rows: &[[f32; 4]], name: &str) -> anyhow::Result<Matrix4<f32>> {
+    if rows.len() != 3 && rows.len() != 4 {
+        return Err(anyhow::anyhow!(
+            "{} must have 3 or 4 rows but has {}",
+            name,
+            rows.len()
+        ));
+    }
+    let mut m = [[0., 0., 0., 1.]; 4];
+    m[..rows.len()].copy_from_slice(rows);
+    // cgmath matrices are column major
+    return Ok(Matrix4::from(m).transpose());
 }
 
 /// calculate the maximum distance between any two points
@@ -199,3 +649,258 @@ fn max_distance(points: Vec<Point3<f32>>) -> f32 {
 
     max_distance.sqrt()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{random_point_cloud, test_device};
+    use std::f32::consts::PI;
+
+    fn test_camera(id: usize) -> SceneCamera {
+        let rotation = Matrix3::from_angle_y(Rad(0.3 * id as f32));
+        SceneCamera {
+            id,
+            img_name: format!("{:04}.png", id),
+            width: 640 + id as u32,
+            height: 480,
+            position: [id as f32, -0.5 * id as f32, 2.],
+            rotation: rotation.into(),
+            fx: 500. + id as f32,
+            fy: 510.,
+            split: Split::default(),
+            overrides: CameraOverrides {
+                max_screen_size: (id == 3).then_some(64.),
+                scale_modifier: (id == 5).then_some(0.5),
+            },
+        }
+    }
+
+    fn assert_same_cameras(a: &Scene, b: &Scene) {
+        let a = a.cameras(None);
+        let b = b.cameras(None);
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert_eq!(a.id, b.id);
+            assert_eq!(a.img_name, b.img_name);
+            assert_eq!((a.width, a.height), (b.width, b.height));
+            assert_eq!(a.position, b.position);
+            assert_eq!(a.rotation, b.rotation);
+            assert_eq!((a.fx, a.fy), (b.fx, b.fy));
+            assert_eq!(a.split, b.split);
+            assert_eq!(a.overrides, b.overrides);
+        }
+    }
+
+    #[test]
+    fn cameras_iter_borrows_the_cameras() {
+        // inserted out of order, every third camera is a test camera
+        let cameras = [7, 2, 9, 0, 4, 3]
+            .map(|id| SceneCamera {
+                split: if id % 3 == 0 {
+                    Split::Test
+                } else {
+                    Split::Train
+                },
+                ..test_camera(id)
+            })
+            .to_vec();
+        let scene = Scene::from_cameras(cameras);
+        for split in [None, Some(Split::Test), Some(Split::Train)] {
+            let cloned = scene.cameras(split);
+            let borrowed: Vec<&SceneCamera> = scene.cameras_iter(split).collect();
+            assert_eq!(borrowed.len(), cloned.len());
+            for (a, b) in borrowed.iter().zip(&cloned) {
+                assert_eq!(a.id, b.id);
+                assert_eq!(a.img_name, b.img_name);
+                assert_eq!(a.split, b.split);
+                // references into the scene, not copies
+                assert!(std::ptr::eq(*a, &scene.cameras[&a.id]));
+            }
+        }
+        let ids: Vec<usize> = scene.cameras_iter(None).map(|c| c.id).collect();
+        assert_eq!(ids, [0, 2, 3, 4, 7, 9]);
+        let ids: Vec<usize> = scene
+            .cameras_iter(Some(Split::Test))
+            .map(|c| c.id)
+            .collect();
+        assert_eq!(ids, [0, 3, 9]);
+    }
+
+    #[test]
+    fn cameras_looking_away_are_flagged() {
+        let Some((device, _)) = test_device() else {
+            return;
+        };
+        let pc = PointCloud::new(&device, random_point_cloud(100, 10)).unwrap();
+        let camera = |id, position, rotation: Matrix3<f32>| SceneCamera {
+            position,
+            rotation: rotation.into(),
+            ..test_camera(id)
+        };
+        let scene = Scene::from_cameras(vec![
+            // looks at the point cloud along +z
+            camera(0, [0., 0., -4.], Matrix3::identity()),
+            camera(1, [0., 0., -4.], Matrix3::from_angle_y(Rad(PI))),
+            camera(2, [0., 0., -1e4], Matrix3::identity()),
+        ]);
+        let warnings = scene.validate_against(&pc);
+        assert_eq!(warnings.len(), 2, "{warnings:?}");
+        assert_eq!(warnings[0], SceneWarning::NotFacingPointCloud { camera: 1 });
+        assert!(matches!(
+            warnings[1],
+            SceneWarning::TooFarAway { camera: 2, .. }
+        ));
+    }
+
+    #[test]
+    fn to_json_roundtrip() {
+        let cameras = (0..10).map(test_camera).collect::<Vec<_>>();
+        let mut scene = Scene::from_json(serde_json::to_vec(&cameras).unwrap().as_slice()).unwrap();
+
+        // without a transform only the cameras are written
+        let mut json = Vec::new();
+        scene.to_json(&mut json).unwrap();
+        let loaded = Scene::from_json(json.as_slice()).unwrap();
+        assert_same_cameras(&scene, &loaded);
+        assert_eq!(loaded.global_transform(), Matrix4::identity());
+
+        scene.global_transform =
+            Matrix4::from_translation(Vector3::new(1., 2., 3.)) * Matrix4::from_angle_x(Rad(0.5));
+        let mut json = Vec::new();
+        scene.to_json(&mut json).unwrap();
+        let loaded = Scene::from_json(json.as_slice()).unwrap();
+        assert_same_cameras(&scene, &loaded);
+        assert_eq!(loaded.global_transform(), scene.global_transform());
+    }
+
+    #[test]
+    fn nerfstudio_cameras() {
+        // frames are out of order, the second one is rotated by 90 degrees around z
+        let json = r#"{
+            "fl_x": 400, "w": 800, "h": 600,
+            "frames": [
+                {
+                    "file_path": "images/b.png",
+                    "transform_matrix": [[0, -1, 0, 0], [1, 0, 0, 0], [0, 0, 1, 0], [0, 0, 0, 1]]
+                },
+                {
+                    "file_path": "images/a.png",
+                    "transform_matrix": [[1, 0, 0, 1], [0, 1, 0, 2], [0, 0, 1, 3], [0, 0, 0, 1]]
+                }
+            ]
+        }"#;
+        let scene = Scene::from_nerfstudio_json(json.as_bytes()).unwrap();
+        assert_eq!(scene.num_cameras(), 2);
+
+        let a = scene.camera(0).unwrap();
+        assert_eq!(a.img_name, "images/a.png");
+        assert_eq!((a.width, a.height), (800, 600));
+        assert_eq!((a.fx, a.fy), (400., 400.));
+        assert_eq!(a.position, [1., 2., 3.]);
+        // the y and z axis of the OpenGL camera are flipped
+        assert_eq!(a.rotation, [[1., 0., 0.], [0., -1., 0.], [0., 0., -1.]]);
+
+        let b = scene.camera(1).unwrap();
+        assert_eq!(b.img_name, "images/b.png");
+        assert_eq!(b.position, [0., 0., 0.]);
+        assert_eq!(b.rotation, [[0., 1., 0.], [1., 0., 0.], [0., 0., -1.]]);
+    }
+
+    #[test]
+    fn transformed_cameras_see_the_transformed_scene() {
+        let json = r#"{
+            "cameras": [],
+            "applied_transform": [[0, 0, 1, 1], [0, 1, 0, -2], [-1, 0, 0, 0.5]]
+        }"#;
+        let transform = Scene::from_json(json.as_bytes())
+            .unwrap()
+            .global_transform();
+        let cameras = (0..5).map(test_camera).collect::<Vec<_>>();
+        let mut scene = Scene::from_cameras(cameras.clone());
+        scene.transform_cameras(transform);
+
+        for (c, t) in cameras.into_iter().zip(scene.cameras(None)) {
+            // the transformed camera sees the transformed points like the original one the original points
+            let view = Into::<PerspectiveCamera>::into(c).view_matrix();
+            let view_transformed = Into::<PerspectiveCamera>::into(t).view_matrix() * transform;
+            let view: [[f32; 4]; 4] = view.into();
+            let view_transformed: [[f32; 4]; 4] = view_transformed.into();
+            for (a, b) in view.iter().flatten().zip(view_transformed.iter().flatten()) {
+                assert!((a - b).abs() < 1e-5, "{view:?} != {view_transformed:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn colmap_fixture_is_loaded() {
+        use byteorder::WriteBytesExt;
+        use cgmath::InnerSpace;
+        use std::io::Write;
+        let dir = std::env::temp_dir().join("web_splat_colmap_test");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut cameras = Vec::new();
+        cameras.write_u64::<LittleEndian>(2).unwrap();
+        // PINHOLE: fx, fy, cx, cy
+        cameras.write_u32::<LittleEndian>(1).unwrap();
+        cameras.write_i32::<LittleEndian>(COLMAP_PINHOLE).unwrap();
+        cameras.write_u64::<LittleEndian>(640).unwrap();
+        cameras.write_u64::<LittleEndian>(480).unwrap();
+        for p in [500., 510., 320., 240.] {
+            cameras.write_f64::<LittleEndian>(p).unwrap();
+        }
+        // SIMPLE_PINHOLE: f, cx, cy
+        cameras.write_u32::<LittleEndian>(2).unwrap();
+        cameras
+            .write_i32::<LittleEndian>(COLMAP_SIMPLE_PINHOLE)
+            .unwrap();
+        cameras.write_u64::<LittleEndian>(100).unwrap();
+        cameras.write_u64::<LittleEndian>(50).unwrap();
+        for p in [80., 50., 25.] {
+            cameras.write_f64::<LittleEndian>(p).unwrap();
+        }
+        std::fs::write(dir.join("cameras.bin"), cameras).unwrap();
+
+        // 90 degrees around z
+        let half = std::f64::consts::FRAC_1_SQRT_2;
+        let views = [
+            ("b.png", [half, 0., 0., half], [1., 2., 3.], 1, 2),
+            ("a.png", [1., 0., 0., 0.], [0., 0., 4.], 2, 0),
+        ];
+        let mut images = Vec::new();
+        images
+            .write_u64::<LittleEndian>(views.len() as u64)
+            .unwrap();
+        for (i, (name, q, t, camera_id, num_points)) in views.iter().enumerate() {
+            images.write_u32::<LittleEndian>(i as u32 + 1).unwrap();
+            for v in q.iter().chain(t.iter()) {
+                images.write_f64::<LittleEndian>(*v).unwrap();
+            }
+            images.write_u32::<LittleEndian>(*camera_id).unwrap();
+            images.write_all(name.as_bytes()).unwrap();
+            images.write_u8(0).unwrap();
+            images.write_u64::<LittleEndian>(*num_points).unwrap();
+            images
+                .write_all(&vec![0; *num_points as usize * 24])
+                .unwrap();
+        }
+        std::fs::write(dir.join("images.bin"), images).unwrap();
+
+        let scene = Scene::from_colmap(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(scene.num_cameras(), 2);
+
+        // sorted by name
+        let a = scene.camera(0).unwrap();
+        assert_eq!(a.img_name, "a.png");
+        assert_eq!((a.width, a.height, a.fx, a.fy), (100, 50, 80., 80.));
+        assert_eq!(a.position, [0., 0., -4.]);
+
+        let b = scene.camera(1).unwrap();
+        assert_eq!(b.img_name, "b.png");
+        assert_eq!((b.width, b.height, b.fx, b.fy), (640, 480, 500., 510.));
+        // position = -R^T t, R^T maps (1,2,3) to (2,-1,3)
+        let expected = Vector3::new(-2., 1., -3.);
+        assert!((Vector3::from(b.position) - expected).magnitude() < 1e-5);
+    }
+}
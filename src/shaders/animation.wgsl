@@ -0,0 +1,29 @@
+// applies the position and opacity deltas of an animation frame to the base point cloud
+
+struct Gaussian {
+    pos_opacity: array<u32,2>,
+    cov: array<u32,3>
+}
+
+@group(0) @binding(0)
+var<storage, read> base: array<Gaussian>;
+@group(0) @binding(1)
+var<storage, read> deltas: array<vec4<f32>>;
+@group(0) @binding(2)
+var<storage, read_write> gaussians: array<Gaussian>;
+
+@compute @workgroup_size(256,1,1)
+fn apply_frame(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let idx = gid.x;
+    if idx >= arrayLength(&deltas) {
+        return;
+    }
+    var g = base[idx];
+    let d = deltas[idx];
+
+    let xy = unpack2x16float(g.pos_opacity[0]) + d.xy;
+    let za = unpack2x16float(g.pos_opacity[1]);
+    g.pos_opacity[0] = pack2x16float(xy);
+    g.pos_opacity[1] = pack2x16float(vec2<f32>(za.x + d.z, clamp(za.y + d.w, 0., 1.)));
+    gaussians[idx] = g;
+}
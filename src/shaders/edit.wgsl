@@ -0,0 +1,39 @@
+// edits the gaussians of an uncompressed point cloud in place
+
+struct Gaussian {
+    pos_opacity: array<u32,2>,
+    cov: array<u32,3>
+}
+
+struct Params {
+    offset: vec3<f32>,
+    opacity_factor: f32,
+}
+
+@group(0) @binding(0)
+var<storage, read_write> gaussians: array<Gaussian>;
+@group(0) @binding(1)
+var<uniform> params: Params;
+
+@compute @workgroup_size(256,1,1)
+fn translate(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let idx = gid.x;
+    if idx >= arrayLength(&gaussians) {
+        return;
+    }
+    let xy = unpack2x16float(gaussians[idx].pos_opacity[0]) + params.offset.xy;
+    let z_opacity = unpack2x16float(gaussians[idx].pos_opacity[1]);
+    gaussians[idx].pos_opacity[0] = pack2x16float(xy);
+    gaussians[idx].pos_opacity[1] = pack2x16float(vec2<f32>(z_opacity.x + params.offset.z, z_opacity.y));
+}
+
+@compute @workgroup_size(256,1,1)
+fn scale_opacities(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let idx = gid.x;
+    if idx >= arrayLength(&gaussians) {
+        return;
+    }
+    let z_opacity = unpack2x16float(gaussians[idx].pos_opacity[1]);
+    let opacity = clamp(z_opacity.y * params.opacity_factor, 0., 1.);
+    gaussians[idx].pos_opacity[1] = pack2x16float(vec2<f32>(z_opacity.x, opacity));
+}
@@ -1,6 +1,12 @@
 // we cutoff at 1/255 alpha value 
 const CUTOFF:f32 = 2.3539888583335364; // = sqrt(log(255))
 
+// minimum alpha (at the pixel) for a splat to count as opaque in the front opaque color mode
+const OPAQUE_ALPHA:f32 = 0.5;
+
+// opacity of the heatmap overlay per out of order splat
+const SORT_VIOLATION_ALPHA:f32 = 0.25;
+
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) screen_pos: vec2<f32>,
@@ -22,19 +28,71 @@ struct Splat {
     color_0: u32,color_1: u32,
 };
 
+struct SortInfos {
+    keys_size: u32,
+    padded_size: u32,
+    passes: u32,
+    even_pass: u32,
+    odd_pass: u32,
+}
+
 @group(0) @binding(2)
 var<storage, read> points_2d : array<Splat>;
+@group(1) @binding(0)
+var<storage, read> sort_infos: SortInfos;
+@group(1) @binding(1)
+var<storage, read> sorted_keys : array<u32>;
 @group(1) @binding(4)
 var<storage, read> indices : array<u32>;
+// coverage (alpha) of the splats in front of the drawn layer in the dominant color mode
+@group(2) @binding(0)
+var coverage : texture_2d<f32>;
+
+struct DominantOutput {
+    @location(0) color: vec4<f32>,
+    // 1 - blending weight, the depth test keeps the splat with the highest weight
+    @builtin(frag_depth) depth: f32,
+};
 
 @vertex
 fn vs_main(
     @builtin(vertex_index) in_vertex_index: u32,
     @builtin(instance_index) in_instance_index: u32
 ) -> VertexOutput {
+    return splat_vertex(in_vertex_index, indices[in_instance_index]);
+}
+
+// draws the splats front to back
+@vertex
+fn vs_main_reversed(
+    @builtin(vertex_index) in_vertex_index: u32,
+    @builtin(instance_index) in_instance_index: u32
+) -> VertexOutput {
+    return splat_vertex(in_vertex_index, indices[sort_infos.keys_size - 1u - in_instance_index]);
+}
+
+// draws the splats back to front with their sort depth as fragment depth.
+// Fragments failing the depth test were blended out of order
+@vertex
+fn vs_main_sort_check(
+    @builtin(vertex_index) in_vertex_index: u32,
+    @builtin(instance_index) in_instance_index: u32
+) -> VertexOutput {
+    var out = splat_vertex(in_vertex_index, indices[in_instance_index]);
+    // the keys are float bits of (zfar - depth) or integers for compressed clouds,
+    // both grow monotonically as u32, so map the raw key monotonically to [0,1]
+    // (the depth is the upper word of 64 bit keys, the cpu sorter reports 0 passes)
+    let key_words = max(sort_infos.passes / 4u, 1u);
+    let key_idx = in_instance_index * key_words + key_words - 1u;
+    let key = sorted_keys[key_idx];
+    out.position.z = 1. - f32(key) / 4294967296.;
+    return out;
+}
+
+fn splat_vertex(in_vertex_index: u32, splat_idx: u32) -> VertexOutput {
     var out: VertexOutput;
 
-    let vertex = points_2d[indices[in_instance_index] + 0u];
+    let vertex = points_2d[splat_idx];
 
     // scaled eigenvectors in screen space 
     let v1 = unpack2x16float(vertex.v_0);
@@ -64,4 +122,90 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     }
     let b = min(0.99, exp(-a) * in.color.a);
     return vec4<f32>(in.color.rgb, 1.) * b;
-}
\ No newline at end of file
+}
+// outputs the color of the front-most splat with an alpha of at least OPAQUE_ALPHA without blending.
+// splats are drawn back to front so the last written fragment wins
+@fragment
+fn fs_front_opaque(in: VertexOutput) -> @location(0) vec4<f32> {
+    let a = dot(in.screen_pos, in.screen_pos);
+    if a > 2. * CUTOFF {
+        discard;
+    }
+    let b = exp(-a) * in.color.a;
+    if b < OPAQUE_ALPHA {
+        discard;
+    }
+    return vec4<f32>(in.color.rgb, 1.);
+}
+
+// variants for sRGB render targets.
+// The splat colors are sRGB encoded, so they are decoded here and the hardware encodes them again on write
+@fragment
+fn fs_main_srgb(in: VertexOutput) -> @location(0) vec4<f32> {
+    let a = dot(in.screen_pos, in.screen_pos);
+    if a > 2. * CUTOFF {
+        discard;
+    }
+    let b = min(0.99, exp(-a) * in.color.a);
+    return vec4<f32>(srgb_to_linear(in.color.rgb), 1.) * b;
+}
+
+@fragment
+fn fs_front_opaque_srgb(in: VertexOutput) -> @location(0) vec4<f32> {
+    let a = dot(in.screen_pos, in.screen_pos);
+    if a > 2. * CUTOFF {
+        discard;
+    }
+    let b = exp(-a) * in.color.a;
+    if b < OPAQUE_ALPHA {
+        discard;
+    }
+    return vec4<f32>(srgb_to_linear(in.color.rgb), 1.);
+}
+
+// color of a splat in the dominant color mode with its blending weight as depth.
+// Only the fragments of one layer pass the stencil test
+@fragment
+fn fs_dominant(in: VertexOutput) -> DominantOutput {
+    let a = dot(in.screen_pos, in.screen_pos);
+    if a > 2. * CUTOFF {
+        discard;
+    }
+    let b = min(0.99, exp(-a) * in.color.a);
+    let covered = textureLoad(coverage, vec2<i32>(in.position.xy), 0).a;
+    var out: DominantOutput;
+    out.color = vec4<f32>(in.color.rgb, 1.);
+    out.depth = 1. - b * (1. - covered);
+    return out;
+}
+
+@fragment
+fn fs_dominant_srgb(in: VertexOutput) -> DominantOutput {
+    let a = dot(in.screen_pos, in.screen_pos);
+    if a > 2. * CUTOFF {
+        discard;
+    }
+    let b = min(0.99, exp(-a) * in.color.a);
+    let covered = textureLoad(coverage, vec2<i32>(in.position.xy), 0).a;
+    var out: DominantOutput;
+    out.color = vec4<f32>(srgb_to_linear(in.color.rgb), 1.);
+    out.depth = 1. - b * (1. - covered);
+    return out;
+}
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) in_vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((in_vertex_index << 1u) & 2u), f32(in_vertex_index & 2u));
+    return vec4<f32>(uv * 2. - 1., 0., 1.);
+}
+
+// red overlay, drawn once per violation level so that pixels get redder with more violations
+@fragment
+fn fs_sort_violation() -> @location(0) vec4<f32> {
+    return vec4<f32>(1., 0., 0., 1.) * SORT_VIOLATION_ALPHA;
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    let cutoff = c <= vec3<f32>(0.04045);
+    return select(pow((max(c, vec3<f32>(0.)) + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, cutoff);
+}
@@ -0,0 +1,16 @@
+// writes the luminance of every pixel into a buffer (used for auto exposure)
+
+@group(0) @binding(0)
+var frame: texture_2d<f32>;
+@group(0) @binding(1)
+var<storage, read_write> luminance: array<f32>;
+
+@compute @workgroup_size(16, 16, 1)
+fn luminance_main(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let size = textureDimensions(frame);
+    if gid.x >= size.x || gid.y >= size.y {
+        return;
+    }
+    let color = textureLoad(frame, vec2<i32>(gid.xy), 0);
+    luminance[gid.y * size.x + gid.x] = dot(color.rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
@@ -0,0 +1,89 @@
+// reorders the gaussians along a Morton (Z-order) curve for better memory locality
+//const KEYS_PER_WG:u32 = <injected>u;
+
+struct Gaussian {
+    pos_opacity: array<u32,2>,
+    cov: array<u32,3>
+}
+
+struct SortInfos {
+    keys_size: atomic<u32>,
+    padded_size: u32,
+    passes: u32,
+    even_pass: u32,
+    odd_pass: u32,
+}
+
+struct DispatchIndirect {
+    dispatch_x: atomic<u32>,
+    dispatch_y: u32,
+    dispatch_z: u32,
+}
+
+struct Bounds {
+    min: vec4<f32>,
+    max: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<storage, read> gaussians: array<Gaussian>;
+@group(0) @binding(1)
+var<storage, read> sh_coefs: array<array<u32,24>>;
+@group(0) @binding(2)
+var<storage, read_write> reordered_gaussians: array<Gaussian>;
+@group(0) @binding(3)
+var<storage, read_write> reordered_sh_coefs: array<array<u32,24>>;
+@group(0) @binding(4)
+var<uniform> bounds: Bounds;
+
+@group(1) @binding(0)
+var<storage, read_write> sort_infos: SortInfos;
+@group(1) @binding(1)
+var<storage, read_write> sort_keys : array<u32>;
+@group(1) @binding(2)
+var<storage, read_write> sort_indices : array<u32>;
+@group(1) @binding(3)
+var<storage, read_write> sort_dispatch: DispatchIndirect;
+
+// inserts two zero bits between each of the lower 10 bits
+fn expand_bits(v: u32) -> u32 {
+    var x = v & 0x3ffu;
+    x = (x | (x << 16u)) & 0x030000ffu;
+    x = (x | (x << 8u)) & 0x0300f00fu;
+    x = (x | (x << 4u)) & 0x030c30c3u;
+    x = (x | (x << 2u)) & 0x09249249u;
+    return x;
+}
+
+@compute @workgroup_size(256,1,1)
+fn morton_codes(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let idx = gid.x;
+    if idx >= arrayLength(&gaussians) {
+        return;
+    }
+    let xy = unpack2x16float(gaussians[idx].pos_opacity[0]);
+    let za = unpack2x16float(gaussians[idx].pos_opacity[1]);
+    let size = max(bounds.max.xyz - bounds.min.xyz, vec3<f32>(1e-6));
+    let p = clamp((vec3<f32>(xy, za.x) - bounds.min.xyz) / size, vec3<f32>(0.), vec3<f32>(1.));
+    let q = vec3<u32>(p * 1023.);
+
+    let store_idx = atomicAdd(&sort_infos.keys_size, 1u);
+    sort_keys[store_idx] = (expand_bits(q.x) << 2u) | (expand_bits(q.y) << 1u) | expand_bits(q.z);
+    sort_indices[store_idx] = idx;
+
+    if (store_idx % KEYS_PER_WG) == 0u {
+        atomicAdd(&sort_dispatch.dispatch_x, 1u);
+    }
+}
+
+// gathers the gaussians in the sorted order
+@compute @workgroup_size(256,1,1)
+fn reorder(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let idx = gid.x;
+    if idx >= arrayLength(&gaussians) {
+        return;
+    }
+    let src = sort_indices[idx];
+    reordered_gaussians[idx] = gaussians[src];
+    reordered_sh_coefs[idx] = sh_coefs[src];
+}
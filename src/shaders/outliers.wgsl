@@ -0,0 +1,40 @@
+// copies all gaussians within the sphere to the output buffers (order is not preserved)
+
+struct Gaussian {
+    pos_opacity: array<u32,2>,
+    cov: array<u32,3>
+}
+
+struct Params {
+    center: vec3<f32>,
+    max_radius: f32,
+}
+
+@group(0) @binding(0)
+var<storage, read> gaussians: array<Gaussian>;
+@group(0) @binding(1)
+var<storage, read> sh_coefs: array<array<u32,24>>;
+@group(0) @binding(2)
+var<storage, read_write> kept_gaussians: array<Gaussian>;
+@group(0) @binding(3)
+var<storage, read_write> kept_sh_coefs: array<array<u32,24>>;
+@group(0) @binding(4)
+var<storage, read_write> num_kept: atomic<u32>;
+@group(0) @binding(5)
+var<uniform> params: Params;
+
+@compute @workgroup_size(256,1,1)
+fn remove_outliers(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let idx = gid.x;
+    if idx >= arrayLength(&gaussians) {
+        return;
+    }
+    let g = gaussians[idx];
+    let xyz = vec3<f32>(unpack2x16float(g.pos_opacity[0]), unpack2x16float(g.pos_opacity[1]).x);
+    if distance(xyz, params.center) > params.max_radius {
+        return;
+    }
+    let store_idx = atomicAdd(&num_kept, 1u);
+    kept_gaussians[store_idx] = g;
+    kept_sh_coefs[store_idx] = sh_coefs[idx];
+}
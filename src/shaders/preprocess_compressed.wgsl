@@ -102,7 +102,37 @@ struct RenderSettings {
     kernel_size: f32,
     walltime: f32,
     scene_extend: f32,
+    // 1: the red and green color channels of the splats hold the gaussian index (used for picking)
+    store_index: u32,
     center: vec3<f32>,
+    clip_sphere: vec4<f32>,
+    clip_sphere_mode: u32,
+    min_opacity: f32,
+    min_splat_size: f32,
+    max_splat_size: f32,
+    exposure: f32,
+    ambient_occlusion: f32,
+    // view space depth range for depth coloring (disabled if y <= x)
+    depth_range: vec2<f32>,
+    // 0: turbo, 1: viridis, 2: gray, 3: unmapped depth
+    colormap: u32,
+    // keep gaussians up to this many pixels outside of the screen
+    cull_margin: f32,
+    // moves larger gaussians back by this factor times their size when sorting
+    depth_bias: f32,
+    contribution_index: u32,
+    // xy: scale, zw: center of the rendered region in normalized device coordinates
+    region: vec4<f32>,
+    // 1: only the splats are written, the sort buffers keep the order of the last sorted frame
+    reuse_order: u32,
+}
+
+struct DensityGrid {
+    min: vec3<f32>,
+    cell_size: f32,
+    resolution: vec3<u32>,
+    // approximated ambient occlusion per cell
+    occlusion: array<f32>,
 }
 
 
@@ -132,9 +162,56 @@ var<storage, read_write> sort_indices : array<u32>;
 @group(2) @binding(3)
 var<storage, read_write> sort_dispatch: DispatchIndirect;
 
+@group(1) @binding(5)
+var<storage,read> density_grid : DensityGrid;
+
 @group(3) @binding(0)
 var<uniform> render_settings: RenderSettings;
 
+/// polynomial approximations of colormaps (same as in colormap.rs)
+fn colormap(x: f32, map: u32) -> vec3<f32> {
+    let t = clamp(x, 0., 1.);
+    if map == 1u {
+        // viridis
+        let c0 = vec3<f32>(0.2777273272234177, 0.005407344544966578, 0.3340998053353061);
+        let c1 = vec3<f32>(0.1050930431085774, 1.404613529898575, 1.384590162594685);
+        let c2 = vec3<f32>(-0.3308618287255563, 0.214847559468213, 0.09509516302823659);
+        let c3 = vec3<f32>(-4.634230498983486, -5.799100973351585, -19.33244095627987);
+        let c4 = vec3<f32>(6.228269936347081, 14.17993336680509, 56.69055260068105);
+        let c5 = vec3<f32>(4.776384997670288, -13.74514537774601, -65.35303263337234);
+        let c6 = vec3<f32>(-5.435455855934631, 4.645852612178535, 26.3124352495832);
+        return saturate(c0 + t * (c1 + t * (c2 + t * (c3 + t * (c4 + t * (c5 + t * c6))))));
+    } else if map == 2u {
+        return vec3<f32>(t);
+    }
+    // turbo (https://research.google/blog/turbo-an-improved-rainbow-colormap-for-visualization/)
+    let r4 = vec4<f32>(0.13572138, 4.61539260, -42.66032258, 132.13108234);
+    let g4 = vec4<f32>(0.09140261, 2.19418839, 4.84296658, -14.18503333);
+    let b4 = vec4<f32>(0.10667330, 12.64194608, -60.58204836, 110.36276771);
+    let r2 = vec2<f32>(-152.94239396, 59.28637943);
+    let g2 = vec2<f32>(4.27729857, 2.82956604);
+    let b2 = vec2<f32>(-89.90310912, 27.34824973);
+    let v4 = vec4<f32>(1., t, t * t, t * t * t);
+    let v2 = v4.zw * v4.z;
+    return saturate(vec3<f32>(
+        dot(v4, r4) + dot(v2, r2),
+        dot(v4, g4) + dot(v2, g2),
+        dot(v4, b4) + dot(v2, b2)
+    ));
+}
+
+/// ambient occlusion of the density grid cell containing the position
+fn ambient_occlusion(pos: vec3<f32>) -> f32 {
+    let res = density_grid.resolution;
+    let cell = clamp(
+        vec3<i32>(floor((pos - density_grid.min) / density_grid.cell_size)),
+        vec3<i32>(0),
+        vec3<i32>(res) - 1
+    );
+    let c = vec3<u32>(cell);
+    return density_grid.occlusion[c.x + res.x * (c.y + res.y * c.z)];
+}
+
 fn dequantize(value: i32, quantization: Quantization) -> f32 {
     return (f32(value) - f32(quantization.zero_point)) * quantization.scaling;
 }
@@ -204,12 +281,21 @@ fn evaluate_sh(dir: vec3<f32>, v_idx: u32, sh_deg: u32) -> vec3<f32> {
     return result;
 }
 
+// false for NaN and infinite values (comparisons with NaN can be optimized away)
+fn is_finite(x: f32) -> bool {
+    return (bitcast<u32>(x) & 0x7f800000u) != 0x7f800000u;
+}
+
 @compute @workgroup_size(256,1,1)
 fn preprocess(@builtin(global_invocation_id) gid: vec3<u32>, @builtin(num_workgroups) wgs: vec3<u32>) {
     let idx = gid.x;
     if idx >= arrayLength(&vertices) {
         return;
     }
+    if render_settings.reuse_order != 0u {
+        // culled gaussians are still part of the reused order
+        points_2d[idx] = Splat(0u, 0u, 0u, 0u, 0u);
+    }
 
     let focal = camera.focal;
     let viewport = camera.viewport;
@@ -220,16 +306,20 @@ fn preprocess(@builtin(global_invocation_id) gid: vec3<u32>, @builtin(num_workgr
     if any(xyz < render_settings.clipping_box_min.xyz) || any(xyz > render_settings.clipping_box_max.xyz) {
         return;
     }
+    if render_settings.clip_sphere_mode != 0u {
+        let inside = distance(xyz, render_settings.clip_sphere.xyz) <= render_settings.clip_sphere.w;
+        if inside != (render_settings.clip_sphere_mode == 1u) {
+            return;
+        }
+    }
 
     var camspace = camera.view * vec4<f32>(xyz, 1.);
     let pos2d = camera.proj * camspace;
-    let bounds = 1.2 * pos2d.w;
     let z = pos2d.z / pos2d.w;
     if idx == 0u {
         atomicAdd(&sort_dispatch.dispatch_x, 1u);   // safety addition to always have an unfull block at the end of the buffer
     }
-    // frustum culling hack
-    if z < 0. || z > 1. || pos2d.x < -bounds || pos2d.x > bounds || pos2d.y < -bounds || pos2d.y > bounds {
+    if z < 0. || z > 1. {
         return;
     }
 
@@ -256,13 +346,29 @@ fn preprocess(@builtin(global_invocation_id) gid: vec3<u32>, @builtin(num_workgr
         cov1[1], cov2[1], cov3[0],
         cov2[0], cov3[0], cov3[1]
     ) * scaling * scaling;
+
+    // pos2d.w is the view depth for perspective projections and 1 for orthographic ones
+    // (camera.proj[2][3] is 1 or 0), only in the first case the screen size shrinks with depth
+    let perspective = camera.proj[2][3];
+
+    // frustum culling with the 3 sigma extent of the gaussian at the depth of its center
+    // (the trace bounds the largest variance), so partially visible gaussians are kept.
+    // Culling happens in the full image, the rendered region is culled later by the splat extent
+    let region = render_settings.region;
+    let pos_full = pos2d.xy / region.xy + region.zw * pos2d.w;
+    let extent_3d = 3. * sqrt(Vrk[0][0] + Vrk[1][1] + Vrk[2][2]) * focal / pos2d.w;
+    let bounds = (1.2 + 2. * (render_settings.cull_margin + extent_3d) / (viewport * region.xy)) * pos2d.w;
+    if any(abs(pos_full) > bounds) {
+        return;
+    }
+
     let J = mat3x3<f32>(
-        focal.x / camspace.z,
+        focal.x / pos2d.w,
         0.,
-        -(focal.x * camspace.x) / (camspace.z * camspace.z),
+        -(focal.x * camspace.x * perspective) / (pos2d.w * pos2d.w),
         0.,
-        -focal.y / camspace.z,
-        (focal.y * camspace.y) / (camspace.z * camspace.z),
+        -focal.y / pos2d.w,
+        (focal.y * camspace.y * perspective) / (pos2d.w * pos2d.w),
         0.,
         0.,
         0.
@@ -272,6 +378,11 @@ fn preprocess(@builtin(global_invocation_id) gid: vec3<u32>, @builtin(num_workgr
     let T = W * J;
     let cov = transpose(T) * Vrk * T;
 
+    // biased depth used for sorting, separates coplanar gaussians of different size
+    let size = sqrt(max(Vrk[0][0], max(Vrk[1][1], Vrk[2][2])));
+    // (orthographic projections map the view depth to [0,1])
+    let depth = pos2d.z + render_settings.depth_bias * size * select(camera.proj[2][2], 1., perspective != 0.);
+
 
     let kernel_size = render_settings.kernel_size;
     if bool(render_settings.mip_spatting) {
@@ -297,36 +408,101 @@ fn preprocess(@builtin(global_invocation_id) gid: vec3<u32>, @builtin(num_workgr
     let lambda1 = mid + max(radius, 0.1);
     let lambda2 = mid - max(radius, 0.1);
 
+    // skip faint and tiny splats (used for fast previews)
+    if opacity < render_settings.min_opacity || sqrt(2.0 * lambda1) < render_settings.min_splat_size {
+        return;
+    }
+
+    // limit the splat extent to max_splat_size pixels (0 means unlimited)
+    var max_lambda = lambda1;
+    if render_settings.max_splat_size > 0. {
+        max_lambda = 0.5 * render_settings.max_splat_size * render_settings.max_splat_size;
+    }
+
     let diagonalVector = normalize(vec2<f32>(offDiagonal, lambda1 - diagonal1));
     // scaled eigenvectors in screen space 
-    let v1 = sqrt(2.0 * lambda1) * diagonalVector;
-    let v2 = sqrt(2.0 * lambda2) * vec2<f32>(diagonalVector.y, -diagonalVector.x);
+    let v1 = sqrt(2.0 * min(lambda1, max_lambda)) * diagonalVector;
+    let v2 = sqrt(2.0 * min(lambda2, max_lambda)) * vec2<f32>(diagonalVector.y, -diagonalVector.x);
 
     let v_center = pos2d.xyzw / pos2d.w;
 
+    // skip splats whose quad (cutoff at sqrt(log(255)) standard deviations) is outside of the rendered region
+    let extent = 2. * 2.3539888583335364 * sqrt(dot(v1, v1) + dot(v2, v2)) / viewport;
+    if any(abs(v_center.xy) > vec2<f32>(1.) + extent) {
+        return;
+    }
+
     let camera_pos = camera.view_inv[3].xyz;
-    let dir = normalize(xyz - camera_pos);
-    let color = vec4<f32>(
-        max(vec3<f32>(0.), evaluate_sh(dir, vertex.sh_idx, render_settings.max_sh_deg)),
+    var dir = normalize(xyz - camera_pos);
+    if perspective == 0. {
+        // all view rays are parallel
+        dir = normalize(camera.view_inv[2].xyz);
+    }
+    var ao = 1.;
+    if render_settings.ambient_occlusion > 0. {
+        ao = 1. - render_settings.ambient_occlusion * ambient_occlusion(xyz);
+    }
+    var color = vec4<f32>(
+        max(vec3<f32>(0.), evaluate_sh(dir, vertex.sh_idx, render_settings.max_sh_deg)) * render_settings.exposure * ao,
         opacity
     );
+    let depth_range = render_settings.depth_range;
+    if depth_range.y > depth_range.x {
+        let t = (camspace.z - depth_range.x) / (depth_range.y - depth_range.x);
+        if render_settings.colormap == 3u {
+            color = vec4<f32>(vec3<f32>(camspace.z), opacity);
+        } else {
+            color = vec4<f32>(colormap(t, render_settings.colormap), opacity);
+        }
+    }
+    if render_settings.contribution_index != 0xffffffffu {
+        // after blending the color is the weight of the selected gaussian
+        color = vec4<f32>(vec3<f32>(f32(idx == render_settings.contribution_index)), opacity);
+    }
+
+    var color_rg = pack2x16float(color.rg);
+    if render_settings.store_index != 0u {
+        color_rg = idx;
+    }
 
-    let store_idx = atomicAdd(&sort_infos.keys_size, 1u);
     let v = vec4<f32>(v1 / viewport, v2 / viewport);
-    points_2d[store_idx] = Splat(
+    // splats are stored at the gaussian index so that a sorted order stays valid for later frames
+    points_2d[idx] = Splat(
         pack2x16float(v.xy), pack2x16float(v.zw),
         pack2x16float(v_center.xy),
-        pack2x16float(color.rg), pack2x16float(color.ba),
+        color_rg, pack2x16float(color.ba),
     );
+    if render_settings.reuse_order != 0u {
+        return;
+    }
+    let store_idx = atomicAdd(&sort_infos.keys_size, 1u);
     
     // filling the sorting buffers and the indirect sort dispatch buffer
-    let znear = -camera.proj[3][2] / camera.proj[2][2];
-    let zfar = -camera.proj[3][2] / (camera.proj[2][2] - (1.));
+    // range of pos2d.z, orthographic projections map the view depth to [0,1]
+    var znear = 0.;
+    var zfar = 1.;
+    if perspective != 0. {
+        znear = -camera.proj[3][2] / camera.proj[2][2];
+        zfar = -camera.proj[3][2] / (camera.proj[2][2] - (1.));
+    }
     // filling the sorting buffers and the indirect sort dispatch buffer
-    sort_depths[store_idx] = u32(f32(0xffffffu) - (pos2d.z - znear) / (zfar - znear) * f32(0xffffffu));
-    sort_indices[store_idx] = store_idx;
+    // NaN or infinite depths (e.g. from invalid positions or covariances) are placed at the far plane
+    var key_depth = zfar;
+    if is_finite(depth) {
+        key_depth = clamp(depth, znear, zfar);
+    }
+    let key = u32(f32(0xffffffu) - (key_depth - znear) / (zfar - znear) * f32(0xffffffu));
+    if KEY_WORDS == 2u {
+        // 64 bit keys break ties between equal depths with the gaussian index (lower word),
+        // the atomic key index differs between frames
+        sort_depths[store_idx * 2u] = idx;
+        sort_depths[store_idx * 2u + 1u] = key;
+    } else {
+        sort_depths[store_idx] = key;
+    }
+    sort_indices[store_idx] = idx;
 
-    let keys_per_wg = 256u * 15u;         // Caution: if workgroup size (256) or keys per thread (15) changes the dispatch is wrong!!
+    let keys_per_wg = KEYS_PER_WG;         // set by the sorter (workgroup size * block rows)
     if (store_idx % keys_per_wg) == 0u {
         atomicAdd(&sort_dispatch.dispatch_x, 1u);
     }
@@ -8,7 +8,8 @@
 // const histogram_wg_size
 // const rs_radix_log2
 // const rs_radix_size
-// const rs_keyval_size
+// const rs_keyval_size (number of passes, 4 for 32 bit keys and 8 for 64 bit keys)
+// const rs_key_words (number of u32 words per key, the lower word comes first)
 // const rs_histogram_block_rows
 // const rs_scatter_block_rows
 
@@ -49,34 +50,31 @@ var<storage, read_write> payload_b : array<u32>;
 fn zero_histograms(@builtin(global_invocation_id) gid : vec3<u32>, @builtin(num_workgroups) nwg: vec3<u32>) {
     if gid.x == 0u {
         infos.even_pass = 0u;
-        infos.odd_pass = 1u;    // has to be one, as on the first call to even pass + 1 % 2 is calculated
+        infos.odd_pass = rs_keyval_size / 2u - 1u;    // has to be the last odd pass, as on the first call to even pass + 1 % (passes / 2) is calculated
     }
-    // here the histograms are set to zero and the partitions are set to 0xfffffffff to avoid sorting problems
+    // the histograms and partitions are set to zero and the keys from keys_size to padded_size
+    // are filled with the maximum key, so that the padding is sorted to the end
     let scatter_wg_size = histogram_wg_size;
     let scatter_block_kvs = scatter_wg_size * rs_scatter_block_rows;
     let scatter_blocks_ru = (infos.keys_size + scatter_block_kvs - 1u) / scatter_block_kvs;
-    
+
     let histo_size = rs_radix_size;
-    var n = (rs_keyval_size + scatter_blocks_ru - 1u) * histo_size;
-    let b = n;
+    let histograms_size = (rs_keyval_size + scatter_blocks_ru - 1u) * histo_size;
+    var n = histograms_size;
     if infos.keys_size < infos.padded_size {
         n += infos.padded_size - infos.keys_size;
     }
-    
+
+    // grid stride loop, the number of workgroups does not have to match n
     let line_size = nwg.x * {histogram_wg_size}u;
-    for (var cur_index = gid.x; cur_index < n; cur_index += line_size){
-        if cur_index >= n {
-            return;
-        }
-            
-        if cur_index  < rs_keyval_size * histo_size {
+    for (var cur_index = gid.x; cur_index < n; cur_index += line_size) {
+        if cur_index < histograms_size {
             atomicStore(&histograms[cur_index], 0u);
-        }
-        else if cur_index < b {
-            atomicStore(&histograms[cur_index], 0u);
-        }
-        else {
-            keys[infos.keys_size + cur_index - b] = 0xFFFFFFFFu;
+        } else {
+            let key_idx = infos.keys_size + cur_index - histograms_size;
+            for (var w = 0u; w < rs_key_words; w++) {
+                keys[key_idx * rs_key_words + w] = 0xFFFFFFFFu;
+            }
         }
     }
 }
@@ -86,6 +84,17 @@ fn zero_histograms(@builtin(global_invocation_id) gid : vec3<u32>, @builtin(num_
 // --------------------------------------------------------------------------------------------------------------
 var<workgroup> smem : array<atomic<u32>, rs_radix_size>;
 var<private> kv : array<u32, rs_histogram_block_rows>;
+// upper words of 64 bit keys (unused for 32 bit keys)
+var<private> kv_hi : array<u32, rs_histogram_block_rows>;
+
+// radix digit of the i-th key for the given pass
+fn key_digit(i: u32, pass_: u32) -> u32 {
+    let word_passes = 32u / rs_radix_log2;
+    if pass_ < word_passes {
+        return extractBits(kv[i], pass_ * rs_radix_log2, rs_radix_log2);
+    }
+    return extractBits(kv_hi[i], (pass_ - word_passes) * rs_radix_log2, rs_radix_log2);
+}
 fn zero_smem(lid: u32) {
     if lid < rs_radix_size {
         atomicStore(&smem[lid], 0u);
@@ -96,8 +105,7 @@ fn histogram_pass(pass_: u32, lid: u32) {
     workgroupBarrier();
     
     for (var j = 0u; j < rs_histogram_block_rows; j++) {
-        let u_val = bitcast<u32>(kv[j]);
-        let digit = extractBits(u_val, pass_ * rs_radix_log2, rs_radix_log2);
+        let digit = key_digit(j, pass_);
         atomicAdd(&smem[digit], 1u);
     }
     
@@ -114,7 +122,10 @@ fn fill_kv(wid: u32, lid: u32) {
     let kv_in_offset = wid * rs_block_keyvals + lid;
     for (var i = 0u; i < rs_histogram_block_rows; i++) {
         let pos = kv_in_offset + i * histogram_wg_size;
-        kv[i] = keys[pos];
+        kv[i] = keys[pos * rs_key_words];
+        if rs_key_words == 2u {
+            kv_hi[i] = keys[pos * 2u + 1u];
+        }
     }
 }
 fn fill_kv_keys_b(wid: u32, lid: u32) {
@@ -122,7 +133,10 @@ fn fill_kv_keys_b(wid: u32, lid: u32) {
     let kv_in_offset = wid * rs_block_keyvals + lid;
     for (var i = 0u; i < rs_histogram_block_rows; i++) {
         let pos = kv_in_offset + i * histogram_wg_size;
-        kv[i] = keys_b[pos];
+        kv[i] = keys_b[pos * rs_key_words];
+        if rs_key_words == 2u {
+            kv_hi[i] = keys_b[pos * 2u + 1u];
+        }
     }
 }
 @compute @workgroup_size({histogram_wg_size})
@@ -131,14 +145,9 @@ fn calculate_histogram(@builtin(workgroup_id) wid : vec3<u32>, @builtin(local_in
     fill_kv(wid.x, lid.x);
     
     // Accumulate and store histograms for passes
-    histogram_pass(3u, lid.x);
-    histogram_pass(2u, lid.x);
-    // if infos.passes > 2u {
-        histogram_pass(1u, lid.x);
-    // }
-    // if infos.passes > 3u {
-        histogram_pass(0u, lid.x);
-    // }
+    for (var p = rs_keyval_size; p > 0u; p--) {
+        histogram_pass(p - 1u, lid.x);
+    }
 }
 
 // --------------------------------------------------------------------------------------------------------------
@@ -243,7 +252,10 @@ fn fill_kv_even(wid: u32, lid: u32) {
     let kv_in_offset = wid * rs_block_keyvals + subgroup_id * subgroup_keyvals + subgroup_invoc_id;
     for (var i = 0u; i < rs_histogram_block_rows; i++) {
         let pos = kv_in_offset + i * histogram_sg_size;
-        kv[i] = keys[pos];
+        kv[i] = keys[pos * rs_key_words];
+        if rs_key_words == 2u {
+            kv_hi[i] = keys[pos * 2u + 1u];
+        }
     }
     for (var i = 0u; i < rs_histogram_block_rows; i++) {
         let pos = kv_in_offset + i * histogram_sg_size;
@@ -258,7 +270,10 @@ fn fill_kv_odd(wid: u32, lid: u32) {
     let kv_in_offset = wid * rs_block_keyvals + subgroup_id * subgroup_keyvals + subgroup_invoc_id;
     for (var i = 0u; i < rs_histogram_block_rows; i++) {
         let pos = kv_in_offset + i * histogram_sg_size;
-        kv[i] = keys_b[pos];
+        kv[i] = keys_b[pos * rs_key_words];
+        if rs_key_words == 2u {
+            kv_hi[i] = keys_b[pos * 2u + 1u];
+        }
     }
     for (var i = 0u; i < rs_histogram_block_rows; i++) {
         let pos = kv_in_offset + i * histogram_sg_size;
@@ -281,8 +296,7 @@ fn scatter(pass_: u32, lid: vec3<u32>, gid: vec3<u32>, wid: vec3<u32>, nwg: vec3
     let subgroup_tid = lid.x - subgroup_offset;
     let subgroup_count = {scatter_wg_size}u / histogram_sg_size;
     for (var i = 0u; i < rs_scatter_block_rows; i++) {
-        let u_val = bitcast<u32>(kv[i]);
-        let digit = extractBits(u_val, pass_ * rs_radix_log2, rs_radix_log2);
+        let digit = key_digit(i, pass_);
         // smem[lid.x] = digit;
         atomicStore(&smem[lid.x], digit);
         var count = 0u;
@@ -308,8 +322,7 @@ fn scatter(pass_: u32, lid: vec3<u32>, gid: vec3<u32>, wid: vec3<u32>, nwg: vec3
     for (var i = 0u; i < subgroup_count; i++) {
         if subgroup_id == i {
             for (var j = 0u; j < rs_scatter_block_rows; j++) {
-                let v = bitcast<u32>(kv[j]);
-                let digit = extractBits(v, pass_ * rs_radix_log2, rs_radix_log2);
+                let digit = key_digit(j, pass_);
                 let prev = histogram_load(digit);
                 let rank = kr[j] & 0xFFFFu;
                 let count = kr[j] >> 16u;
@@ -396,8 +409,7 @@ fn scatter(pass_: u32, lid: vec3<u32>, gid: vec3<u32>, wid: vec3<u32>, nwg: vec3
 
     // convert keyval rank to local index, corresponds to rs_rank_to_local
     for (var i = 0u; i < rs_scatter_block_rows; i++) {
-        let v = bitcast<u32>(kv[i]);
-        let digit = extractBits(v, pass_ * rs_radix_log2, rs_radix_log2);
+        let digit = key_digit(i, pass_);
         let exc   = histogram_load(digit);
         let idx   = exc + kr[i];
         
@@ -423,6 +435,17 @@ fn scatter(pass_: u32, lid: vec3<u32>, gid: vec3<u32>, wid: vec3<u32>, nwg: vec3
             kv[j] = scatter_smem[smem_base + j * {scatter_wg_size}u];
         }
         workgroupBarrier();
+        if rs_key_words == 2u {
+            for (var j = 0u; j < rs_scatter_block_rows; j++) {
+                let smem_idx = smem_reorder_offset + (kr[j] >> 16u) - 1u;
+                scatter_smem[smem_idx] = kv_hi[j];
+            }
+            workgroupBarrier();
+            for (var j = 0u; j < rs_scatter_block_rows; j++) {
+                kv_hi[j] = scatter_smem[smem_base + j * {scatter_wg_size}u];
+            }
+            workgroupBarrier();
+        }
         // payload ----------------------------------------------
         // store payload to sorted location
         for (var j = 0u; j < rs_scatter_block_rows; j++) {
@@ -453,8 +476,7 @@ fn scatter(pass_: u32, lid: vec3<u32>, gid: vec3<u32>, wid: vec3<u32>, nwg: vec3
     
     // convert local index to a global index, corresponds to rs_local_to_global
     for (var i = 0u; i < rs_scatter_block_rows; i++) {
-        let v = bitcast<u32>(kv[i]);
-        let digit = extractBits(v, pass_ * rs_radix_log2, rs_radix_log2);
+        let digit = key_digit(i, pass_);
         let exc   = scatter_smem[digit];
 
         kr[i] += exc - 1u;
@@ -465,7 +487,7 @@ fn scatter(pass_: u32, lid: vec3<u32>, gid: vec3<u32>, wid: vec3<u32>, nwg: vec3
 @compute @workgroup_size({scatter_wg_size})
 fn scatter_even(@builtin(workgroup_id) wid: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>, @builtin(global_invocation_id) gid: vec3<u32>, @builtin(num_workgroups) nwg: vec3<u32>) {
     if gid.x == 0u {
-        infos.odd_pass = (infos.odd_pass + 1u) % 2u; // for this to work correctly the odd_pass has to start 1
+        infos.odd_pass = (infos.odd_pass + 1u) % (rs_keyval_size / 2u); // for this to work correctly the odd_pass has to start at the last odd pass
     }
     let cur_pass = infos.even_pass * 2u;
     
@@ -479,7 +501,10 @@ fn scatter_even(@builtin(workgroup_id) wid: vec3<u32>, @builtin(local_invocation
 
     // store keyvals to their new locations, corresponds to rs_store
     for (var i = 0u; i < rs_scatter_block_rows; i++) {
-        keys_b[kr[i]] = kv[i];
+        keys_b[kr[i] * rs_key_words] = kv[i];
+        if rs_key_words == 2u {
+            keys_b[kr[i] * 2u + 1u] = kv_hi[i];
+        }
     }
     for (var i = 0u; i < rs_scatter_block_rows; i++) {
         payload_b[kr[i]] = pv[i];
@@ -488,7 +513,7 @@ fn scatter_even(@builtin(workgroup_id) wid: vec3<u32>, @builtin(local_invocation
 @compute @workgroup_size({scatter_wg_size})
 fn scatter_odd(@builtin(workgroup_id) wid: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>, @builtin(global_invocation_id) gid: vec3<u32>, @builtin(num_workgroups) nwg: vec3<u32>) {
     if gid.x == 0u {
-        infos.even_pass = (infos.even_pass + 1u) % 2u; // for this to work correctly the even_pass has to start at 0
+        infos.even_pass = (infos.even_pass + 1u) % (rs_keyval_size / 2u); // for this to work correctly the even_pass has to start at 0
     }
     let cur_pass = infos.odd_pass * 2u + 1u;
 
@@ -502,7 +527,10 @@ fn scatter_odd(@builtin(workgroup_id) wid: vec3<u32>, @builtin(local_invocation_
 
     // store keyvals to their new locations, corresponds to rs_store
     for (var i = 0u; i < rs_scatter_block_rows; i++) {
-        keys[kr[i]] = kv[i];
+        keys[kr[i] * rs_key_words] = kv[i];
+        if rs_key_words == 2u {
+            keys[kr[i] * 2u + 1u] = kv_hi[i];
+        }
     }
     for (var i = 0u; i < rs_scatter_block_rows; i++) {
         payload_a[kr[i]] = pv[i];
@@ -0,0 +1,153 @@
+//! helpers shared by the unit tests
+
+use cgmath::{
+    Deg, EuclideanSpace, InnerSpace, Point3, Quaternion, Rotation, Rotation3, Vector2, Vector3,
+};
+use half::f16;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    depth_sort::CpuSorter, io::GenericGaussianPointCloud, pointcloud::Gaussian, utils::build_cov,
+    GaussianRenderer, PerspectiveCamera, PerspectiveProjection,
+};
+
+/// Creates a device on the default adapter (software rasterizers included).
+/// Returns None if there is no adapter, tests that need a device are skipped in this case.
+pub fn test_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    return create_device(true);
+}
+
+/// Same as [`test_device`] but also returns None for software adapters.
+/// The radix sort relies on forward progress between workgroups, which they do not guarantee
+/// (on llvmpipe it hangs or returns a wrong order).
+pub fn test_device_gpu_sort() -> Option<(wgpu::Device, wgpu::Queue)> {
+    return create_device(false);
+}
+
+fn create_device(allow_software: bool) -> Option<(wgpu::Device, wgpu::Queue)> {
+    return pollster::block_on(async {
+        let instance = wgpu::Instance::default();
+        let Some(adapter) = instance.request_adapter(&Default::default()).await else {
+            eprintln!("no adapter found, skipping test");
+            return None;
+        };
+        if !allow_software && adapter.get_info().device_type == wgpu::DeviceType::Cpu {
+            eprintln!("software adapter cannot run the GPU sort, skipping test");
+            return None;
+        }
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("test device"),
+                    required_features: wgpu::Features::empty(),
+                    required_limits: adapter.limits(),
+                },
+                None,
+            )
+            .await
+            .ok()?;
+        return Some((device, queue));
+    });
+}
+
+/// random point cloud with sh degree 0 within [-1,1]^3 (the same seed gives the same point cloud)
+pub fn random_point_cloud(n: usize, seed: u64) -> GenericGaussianPointCloud {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut gaussians = Vec::with_capacity(n);
+    let mut sh_coefs = Vec::with_capacity(n);
+    for _ in 0..n {
+        let xyz = Point3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        );
+        let rotation = Quaternion::new(
+            rng.gen_range(-1.0..1.0f32),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        )
+        .normalize();
+        let scale = Vector3::new(
+            rng.gen_range(0.01..0.1f32),
+            rng.gen_range(0.01..0.1),
+            rng.gen_range(0.01..0.1),
+        );
+        gaussians.push(Gaussian {
+            xyz: xyz.map(f16::from_f32),
+            opacity: f16::from_f32(rng.gen_range(0.1..1.0)),
+            cov: build_cov(rotation, scale).map(f16::from_f32),
+        });
+        let mut sh = [[f16::ZERO; 3]; 16];
+        sh[0] = [0; 3].map(|_| f16::from_f32(rng.gen_range(-1.5..1.5)));
+        sh_coefs.push(sh);
+    }
+    return GenericGaussianPointCloud::new(gaussians, sh_coefs, 0, n, None, None, None, None, None);
+}
+
+/// Point cloud of opaque or transparent splats with a color (sh degree 0).
+/// The covariance is rotated around z because the preprocessing cannot compute the
+/// screen space eigenvectors of axis aligned covariances with equal or decreasing x and y variance.
+pub fn splat_cloud(splats: &[(Point3<f32>, f32, [f32; 3])]) -> GenericGaussianPointCloud {
+    return scaled_splat_cloud(splats, Vector3::new(0.3, 0.2, 0.1));
+}
+
+/// same as [`splat_cloud`] with the standard deviations of the splats along their axes
+pub fn scaled_splat_cloud(
+    splats: &[(Point3<f32>, f32, [f32; 3])],
+    scale: Vector3<f32>,
+) -> GenericGaussianPointCloud {
+    let rotation = Quaternion::from_angle_z(Deg(30.));
+    let (gaussians, sh_coefs) = splats
+        .iter()
+        .map(|(xyz, opacity, color)| {
+            let mut sh = [[f16::ZERO; 3]; 16];
+            // dc term of the color in [0,1]
+            sh[0] = color.map(|c| f16::from_f32((c - 0.5) / 0.28209479));
+            let gaussian = Gaussian {
+                xyz: xyz.map(f16::from_f32),
+                opacity: f16::from_f32(*opacity),
+                cov: build_cov(rotation, scale).map(f16::from_f32),
+            };
+            (gaussian, sh)
+        })
+        .unzip();
+    return GenericGaussianPointCloud::new(
+        gaussians,
+        sh_coefs,
+        0,
+        splats.len(),
+        None,
+        None,
+        None,
+        None,
+        None,
+    );
+}
+
+/// camera with a 60 degree field of view on a circle with radius 4 around the origin looking at it.
+/// The angle 0 is at -z, so the camera looks along +z
+pub fn orbit_camera(angle: f32) -> PerspectiveCamera {
+    let position = Point3::new(angle.sin(), 0., -angle.cos()) * 4.;
+    return PerspectiveCamera::new(
+        position,
+        Quaternion::look_at(Point3::origin() - position, Vector3::unit_y()),
+        PerspectiveProjection::new(
+            Vector2::new(64, 64),
+            Vector2::new(Deg(60.), Deg(60.)),
+            0.1,
+            100.,
+        ),
+    );
+}
+
+/// renderer with the cpu sorter (works on software adapters) and a blendable float format
+pub fn cpu_renderer(device: &wgpu::Device) -> GaussianRenderer {
+    return GaussianRenderer::with_sorter(
+        device,
+        wgpu::TextureFormat::Rgba16Float,
+        0,
+        false,
+        Box::new(CpuSorter::new(device)),
+    );
+}
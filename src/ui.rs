@@ -97,13 +97,25 @@ pub(crate) fn ui(state: &mut WindowContext) {
                         .speed(1e-2),
                 );
                 ui.end_row();
-                ui.label("Directional Color");
-                let mut dir_color = state.splatting_args.max_sh_deg > 0;
+                ui.label("SH Degree");
                 ui.add_enabled(
                     state.pc.sh_deg() > 0,
-                    egui::Checkbox::new(&mut dir_color, ""),
+                    egui::Slider::new(
+                        &mut state.splatting_args.max_sh_deg,
+                        0..=state.pc.sh_deg(),
+                    ),
                 );
-                state.splatting_args.max_sh_deg = if dir_color { state.pc.sh_deg() } else { 0 };
+                ui.end_row();
+                ui.label("Min Opacity");
+                let mut min_opacity = state.renderer.min_opacity();
+                if ui
+                    .add(egui::Slider::new(&mut min_opacity, 0.0..=1.0))
+                    .changed()
+                {
+                    state.renderer.set_min_opacity(min_opacity);
+                    // not part of the splatting args, force a redraw
+                    state.render_settings_hash = None;
+                }
 
               
                 ui.end_row();
@@ -97,6 +97,12 @@ where
         queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.data]));
     }
 
+    /// uploads only the given byte range of the data (e.g. a single field)
+    pub fn sync_range(&mut self, queue: &wgpu::Queue, range: std::ops::Range<usize>) {
+        let bytes = bytemuck::bytes_of(&self.data);
+        queue.write_buffer(&self.buffer, range.start as u64, &bytes[range]);
+    }
+
     pub fn binding_type() -> wgpu::BindingType {
         wgpu::BindingType::Buffer {
             ty: wgpu::BufferBindingType::Uniform,
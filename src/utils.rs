@@ -1,4 +1,4 @@
-use cgmath::{BaseFloat, Matrix, Matrix3, Quaternion, SquareMatrix, Vector3};
+use cgmath::{BaseFloat, InnerSpace, Matrix, Matrix3, Quaternion, SquareMatrix, Vector3};
 use std::time::Duration;
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -175,7 +175,6 @@ where
     }
 }
 
-#[cfg(feature = "npz")]
 pub fn sh_num_coefficients(sh_deg: u32) -> u32 {
     (sh_deg + 1) * (sh_deg + 1)
 }
@@ -202,6 +201,56 @@ pub fn build_cov<T: BaseFloat>(rot: Quaternion<T>, scale: Vector3<T>) -> [T; 6]
     return [m[0][0], m[0][1], m[0][2], m[1][1], m[1][2], m[2][2]];
 }
 
+/// Inverse of [`build_cov`], returns a rotation and scale that reproduce the covariance matrix.
+/// The eigenvectors are computed with the Jacobi method, negative eigenvalues
+/// (from rounding errors) are clamped to zero.
+pub fn decompose_cov(cov: [f32; 6]) -> (Quaternion<f32>, Vector3<f32>) {
+    let c = cov.map(|v| v as f64);
+    let mut a = [[c[0], c[1], c[2]], [c[1], c[3], c[4]], [c[2], c[4], c[5]]];
+    // eigenvectors as columns
+    let mut v = [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]];
+    for _ in 0..32 {
+        let off = a[0][1] * a[0][1] + a[0][2] * a[0][2] + a[1][2] * a[1][2];
+        let diag = a[0][0] * a[0][0] + a[1][1] * a[1][1] + a[2][2] * a[2][2];
+        if off <= diag * 1e-24 {
+            break;
+        }
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == 0. {
+                continue;
+            }
+            // rotation that zeroes a[p][q]
+            let theta = (a[q][q] - a[p][p]) / (2. * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+            let cos = 1. / (t * t + 1.).sqrt();
+            let sin = t * cos;
+            for k in 0..3 {
+                let (akp, akq) = (a[k][p], a[k][q]);
+                a[k][p] = cos * akp - sin * akq;
+                a[k][q] = sin * akp + cos * akq;
+            }
+            for k in 0..3 {
+                let (apk, aqk) = (a[p][k], a[q][k]);
+                a[p][k] = cos * apk - sin * aqk;
+                a[q][k] = sin * apk + cos * aqk;
+            }
+            for k in 0..3 {
+                let (vkp, vkq) = (v[k][p], v[k][q]);
+                v[k][p] = cos * vkp - sin * vkq;
+                v[k][q] = sin * vkp + cos * vkq;
+            }
+        }
+    }
+    let column = |i: usize| Vector3::new(v[0][i], v[1][i], v[2][i]).cast::<f32>().unwrap();
+    let mut rot = Matrix3::from_cols(column(0), column(1), column(2));
+    // the quaternion needs a rotation without reflection
+    if rot.determinant() < 0. {
+        rot.z = -rot.z;
+    }
+    let scale = Vector3::new(a[0][0], a[1][1], a[2][2]).map(|l| l.max(0.).sqrt() as f32);
+    return (Quaternion::from(rot).normalize(), scale);
+}
+
 /// numerical stable sigmoid function
 pub fn sigmoid(x: f32) -> f32 {
     if x >= 0. {
@@ -210,3 +259,155 @@ pub fn sigmoid(x: f32) -> f32 {
         x.exp() / (1. + x.exp())
     }
 }
+
+/// downloads a texture with format `Rgba16Float` or `Rgba32Float` into an image.
+/// The texture needs `COPY_SRC` usage.
+/// With `max_buffer_size` the texture is downloaded in strips of rows so that the
+/// staging buffer does not exceed this many bytes.
+pub async fn download_texture(
+    texture: &wgpu::Texture,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    max_buffer_size: Option<u64>,
+) -> anyhow::Result<image::Rgba32FImage> {
+    let texture_format = texture.format();
+    if !matches!(
+        texture_format,
+        wgpu::TextureFormat::Rgba16Float | wgpu::TextureFormat::Rgba32Float
+    ) {
+        return Err(anyhow::anyhow!(
+            "texture format {:?} is not supported",
+            texture_format
+        ));
+    }
+
+    let texel_size: u32 = texture_format.block_copy_size(None).unwrap();
+    let fb_size = texture.size();
+    let align: u32 = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT - 1;
+    let bytes_per_row = (texel_size * fb_size.width) + align & !align;
+
+    let rows_per_strip = max_buffer_size.map_or(fb_size.height, |max| {
+        (max / bytes_per_row as u64).clamp(1, fb_size.height as u64) as u32
+    });
+
+    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        size: (bytes_per_row * rows_per_strip) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        label: Some("texture download buffer"),
+        mapped_at_creation: false,
+    });
+
+    let mut data = Vec::with_capacity((bytes_per_row * fb_size.height) as usize);
+    for y in (0..fb_size.height).step_by(rows_per_strip as usize) {
+        let rows = rows_per_strip.min(fb_size.height - y);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("download frame buffer encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: 0, y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &staging_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(rows),
+                },
+            },
+            wgpu::Extent3d {
+                width: fb_size.width,
+                height: rows,
+                depth_or_array_layers: 1,
+            },
+        );
+        let sub_idx = queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = staging_buffer.slice(..);
+        let (tx, rx) = futures_intrusive::channel::shared::oneshot_channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| tx.send(result).unwrap());
+        device.poll(wgpu::Maintain::WaitForSubmissionIndex(sub_idx));
+        rx.receive().await.unwrap()?;
+        data.extend_from_slice(&slice.get_mapped_range()[..(bytes_per_row * rows) as usize]);
+        staging_buffer.unmap();
+    }
+
+    let mut pixels = Vec::with_capacity((fb_size.width * fb_size.height * 4) as usize);
+    for row in data.chunks(bytes_per_row as usize) {
+        let row = &row[..(texel_size * fb_size.width) as usize];
+        if texture_format == wgpu::TextureFormat::Rgba16Float {
+            pixels.extend(
+                row.chunks_exact(2)
+                    .map(|c| half::f16::from_le_bytes([c[0], c[1]]).to_f32()),
+            );
+        } else {
+            pixels.extend(
+                row.chunks_exact(4)
+                    .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])),
+            );
+        }
+    }
+    return image::Rgba32FImage::from_raw(fb_size.width, fb_size.height, pixels)
+        .ok_or(anyhow::anyhow!("invalid texture size"));
+}
+
+/// downloads a texture with format `Rgba8Unorm` or `Rgba8UnormSrgb` into an image.
+/// The texture needs `COPY_SRC` usage.
+pub async fn download_texture_rgba8(
+    texture: &wgpu::Texture,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> anyhow::Result<image::RgbaImage> {
+    if texture.format().remove_srgb_suffix() != wgpu::TextureFormat::Rgba8Unorm {
+        return Err(anyhow::anyhow!(
+            "texture format {:?} is not supported",
+            texture.format()
+        ));
+    }
+    let size = texture.size();
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let bytes_per_row = (size.width * 4 + align - 1) / align * align;
+
+    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("texture download buffer"),
+        size: (bytes_per_row * size.height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("download texture encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &staging_buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(size.height),
+            },
+        },
+        size,
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = staging_buffer.slice(..);
+    let (tx, rx) = futures_intrusive::channel::shared::oneshot_channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| tx.send(result).unwrap());
+    device.poll(wgpu::Maintain::Wait);
+    rx.receive().await.unwrap()?;
+
+    let mut pixels = Vec::with_capacity((size.width * size.height * 4) as usize);
+    {
+        let data = slice.get_mapped_range();
+        for row in data.chunks(bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..(size.width * 4) as usize]);
+        }
+    }
+    staging_buffer.unmap();
+    return image::RgbaImage::from_raw(size.width, size.height, pixels)
+        .ok_or(anyhow::anyhow!("invalid texture size"));
+}
@@ -0,0 +1,248 @@
+use cgmath::{Point3, Vector2};
+use rayon::prelude::*;
+
+use crate::{camera::Camera, io::GenericGaussianPointCloud, PerspectiveCamera, SceneCamera};
+
+/// Sparse camera x gaussian visibility matrix in CSR layout.
+///
+/// Row `i` lists the (sorted) indices of all gaussians that pass the same frustum culling
+/// as the preprocess shader (3 sigma extent plus `cull_margin` pixels) for camera `i`
+/// and have an opacity above the threshold.
+/// Occlusion is not taken into account.
+///
+/// Memory is `O(num_cameras + visible pairs)` and computation `O(num_cameras * num_points)`.
+/// For large scenes use a `stride > 1` to only test every n-th gaussian.
+pub struct VisibilityMatrix {
+    num_points: usize,
+    stride: usize,
+    indptr: Vec<u64>,
+    indices: Vec<u32>,
+}
+
+impl VisibilityMatrix {
+    pub fn compute(
+        pc: &GenericGaussianPointCloud,
+        cameras: &[SceneCamera],
+        min_opacity: f32,
+        cull_margin: f32,
+        stride: usize,
+    ) -> anyhow::Result<Self> {
+        if stride == 0 {
+            return Err(anyhow::anyhow!("stride must be at least 1"));
+        }
+        let gaussians = pc.gaussians()?;
+        // index, position and standard deviation along the largest axis (bounded by the trace)
+        let points: Vec<(u32, Point3<f32>, f32)> = gaussians
+            .iter()
+            .enumerate()
+            .step_by(stride)
+            .filter(|(_, g)| g.opacity.to_f32() >= min_opacity)
+            .map(|(i, g)| {
+                let trace = g.cov[0].to_f32() + g.cov[3].to_f32() + g.cov[5].to_f32();
+                (i as u32, g.xyz.map(|v| v.to_f32()), trace.max(0.).sqrt())
+            })
+            .collect();
+
+        let rows: Vec<Vec<u32>> = cameras
+            .par_iter()
+            .map(|c| {
+                let mut camera: PerspectiveCamera = c.clone().into();
+                camera.fit_near_far(&pc.aabb);
+                let view_proj = camera.proj_matrix() * camera.view_matrix();
+                let viewport = Vector2::new(c.width, c.height);
+                let focal = camera.projection.focal(viewport);
+                let viewport = viewport.cast::<f32>().unwrap();
+                points
+                    .iter()
+                    .filter(|(_, p, std)| {
+                        // same frustum test as in preprocess.wgsl
+                        let pos2d = view_proj * p.to_homogeneous();
+                        let z = pos2d.z / pos2d.w;
+                        if !(z > 0. && z < 1.) {
+                            return false;
+                        }
+                        let extent = focal * 3. * *std / pos2d.w;
+                        let bounds_x = (1.2 + 2. * (cull_margin + extent.x) / viewport.x) * pos2d.w;
+                        let bounds_y = (1.2 + 2. * (cull_margin + extent.y) / viewport.y) * pos2d.w;
+                        pos2d.x.abs() <= bounds_x && pos2d.y.abs() <= bounds_y
+                    })
+                    .map(|(i, _, _)| *i)
+                    .collect()
+            })
+            .collect();
+
+        let mut indptr = Vec::with_capacity(rows.len() + 1);
+        indptr.push(0);
+        for r in rows.iter() {
+            indptr.push(indptr.last().unwrap() + r.len() as u64);
+        }
+        return Ok(Self {
+            num_points: gaussians.len(),
+            stride,
+            indptr,
+            indices: rows.concat(),
+        });
+    }
+
+    pub fn num_cameras(&self) -> usize {
+        self.indptr.len() - 1
+    }
+
+    pub fn num_points(&self) -> usize {
+        self.num_points
+    }
+
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// gaussians visible from the camera with the given row index
+    pub fn visible_from(&self, camera: usize) -> &[u32] {
+        &self.indices[self.indptr[camera] as usize..self.indptr[camera + 1] as usize]
+    }
+
+    pub fn is_visible(&self, camera: usize, gaussian: u32) -> bool {
+        self.visible_from(camera).binary_search(&gaussian).is_ok()
+    }
+
+    /// number of visible gaussians per camera
+    pub fn counts(&self) -> Vec<usize> {
+        self.indptr
+            .windows(2)
+            .map(|w| (w[1] - w[0]) as usize)
+            .collect()
+    }
+
+    /// saves the matrix as a compressed npz file
+    /// (compatible with `scipy.sparse.csr_matrix((data, indices, indptr), shape)`)
+    #[cfg(feature = "npz")]
+    pub fn save_npz<P: AsRef<std::path::Path>>(&self, path: P) -> anyhow::Result<()> {
+        use npyz::WriterBuilder;
+
+        let options = npyz::zip::write::FileOptions::default()
+            .compression_method(npyz::zip::CompressionMethod::Deflated);
+        let mut npz = npyz::npz::NpzWriter::create(path)?;
+
+        let mut writer = npz
+            .array::<u64>("indptr", options)?
+            .default_dtype()
+            .shape(&[self.indptr.len() as u64])
+            .begin_nd()?;
+        writer.extend(self.indptr.iter().copied())?;
+        writer.finish()?;
+
+        let mut writer = npz
+            .array::<u32>("indices", options)?
+            .default_dtype()
+            .shape(&[self.indices.len() as u64])
+            .begin_nd()?;
+        writer.extend(self.indices.iter().copied())?;
+        writer.finish()?;
+
+        let mut writer = npz
+            .array::<u8>("data", options)?
+            .default_dtype()
+            .shape(&[self.indices.len() as u64])
+            .begin_nd()?;
+        writer.extend(std::iter::repeat(1u8).take(self.indices.len()))?;
+        writer.finish()?;
+
+        let mut writer = npz
+            .array::<u64>("shape", options)?
+            .default_dtype()
+            .shape(&[2])
+            .begin_nd()?;
+        writer.extend([self.num_cameras() as u64, self.num_points as u64])?;
+        writer.finish()?;
+
+        let mut writer = npz
+            .array::<u64>("stride", options)?
+            .default_dtype()
+            .shape(&[1])
+            .begin_nd()?;
+        writer.push(&(self.stride as u64))?;
+        writer.finish()?;
+
+        npz.zip_writer().finish()?;
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, Quaternion, Vector3};
+    use half::f16;
+
+    use super::*;
+    use crate::{camera::fov2focal, pointcloud::Gaussian, utils::build_cov};
+
+    fn camera(id: usize, z: f32, rotation: [[f32; 3]; 3]) -> SceneCamera {
+        let focal = fov2focal(Deg(60.).into(), 64.);
+        SceneCamera {
+            id,
+            img_name: id.to_string(),
+            width: 64,
+            height: 64,
+            position: [0., 0., z],
+            rotation,
+            fx: focal,
+            fy: focal,
+            split: Default::default(),
+            overrides: Default::default(),
+        }
+    }
+
+    #[test]
+    fn gaussians_are_visible_from_cameras_that_see_them() {
+        let points = [
+            // behind camera 0
+            ([0., 0., -5.], 0.01),
+            // behind camera 1
+            ([0., 0., 5.], 0.01),
+            // in front of both
+            ([0., 0., 0.], 0.01),
+            // beside the image
+            ([3.5, 0., 0.], 0.01),
+            // center beside the image but large enough to reach into it
+            ([3.5, 0., 0.], 0.5),
+        ];
+        let gaussians: Vec<Gaussian> = points
+            .iter()
+            .map(|(xyz, scale)| Gaussian {
+                xyz: Point3::from(*xyz).map(f16::from_f32),
+                opacity: f16::ONE,
+                cov: build_cov(
+                    Quaternion::new(1., 0., 0., 0.),
+                    Vector3::new(1., 1., 1.) * *scale,
+                )
+                .map(f16::from_f32),
+            })
+            .collect();
+        let n = gaussians.len();
+        let pc = GenericGaussianPointCloud::new(
+            gaussians,
+            vec![[[f16::ZERO; 3]; 16]; n],
+            0,
+            n,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        // camera 0 looks along +z, camera 1 along -z
+        let cameras = [
+            camera(0, -4., [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]]),
+            camera(1, 4., [[-1., 0., 0.], [0., 1., 0.], [0., 0., -1.]]),
+        ];
+
+        let matrix = VisibilityMatrix::compute(&pc, &cameras, 0.5, 0., 1).unwrap();
+        assert_eq!(matrix.num_cameras(), 2);
+        assert_eq!(matrix.visible_from(0), [1, 2, 4]);
+        assert_eq!(matrix.visible_from(1), [0, 2, 4]);
+
+        // a margin of 20 pixels reaches the small gaussian beside the image
+        let matrix = VisibilityMatrix::compute(&pc, &cameras, 0.5, 20., 1).unwrap();
+        assert_eq!(matrix.visible_from(0), [1, 2, 3, 4]);
+    }
+}